@@ -0,0 +1,691 @@
+"""Type stubs for the `code_nav_devon` extension module.
+
+Keep in sync with the `#[pyfunction]`s of `src/lib.rs` and the classes of `src/python.rs`.
+"""
+
+from typing import Any, Dict, List, Literal, Optional, Sequence, Tuple, Union
+
+FORMAT_VERSION: int
+"""The index format written by this version, see `index_format_version`."""
+
+Format = Literal["text", "json", "markdown"]
+"""How the string returned by the search and navigation functions is rendered."""
+
+OccurrenceKind = Literal["definition", "reference"]
+"""The occurrences `go_to` and `occurrences` keep, both kinds if `None`."""
+
+class CodeNavError(RuntimeError):
+    """An error caused by the arguments of a call, with the details to correct them."""
+
+    code: Literal["invalid_query", "invalid_range", "document_not_found", "incompatible_index", "stale_edit", "invalid_continuation"]
+    position: Optional[int]
+    """For queries, the offset of the offending character. For ranges, the offending line or column."""
+    suggestion: Optional[str]
+
+class Range:
+    """A span of a file, with the lines and columns of both ends and their byte offsets."""
+
+    @property
+    def start_line(self) -> int: ...
+    @property
+    def start_column(self) -> int: ...
+    @property
+    def end_line(self) -> int: ...
+    @property
+    def end_column(self) -> int: ...
+    @property
+    def start_byte(self) -> int: ...
+    @property
+    def end_byte(self) -> int: ...
+
+class SearchResult:
+    """A line matching a search."""
+
+    @property
+    def path(self) -> str:
+        """The absolute path of the file."""
+    @property
+    def relative_path(self) -> str:
+        """`path` relative to the root it was indexed from."""
+    @property
+    def line_number(self) -> int: ...
+    @property
+    def column(self) -> int: ...
+    @property
+    def context(self) -> str: ...
+    @property
+    def context_start_line(self) -> int:
+        """Line number of the first line of `context`, counted from `line_base()`."""
+    @property
+    def term(self) -> str:
+        """The query term that matched, the query text itself unless it had several terms."""
+    @property
+    def is_minified(self) -> bool: ...
+    @property
+    def is_generated(self) -> bool: ...
+    @property
+    def symbols(self) -> List[DefinedSymbol]:
+        """The symbols defined on the matching line, to go to without another query."""
+    @property
+    def score(self) -> Optional[float]:
+        """The score of the document, with `explain=True`."""
+    @property
+    def explanation(self) -> Optional[str]:
+        """How tantivy computed `score`, as JSON."""
+    def to_json(self) -> str:
+        """The result as a JSON object of its attributes, `explanation` nested rather than a string."""
+
+class DefinedSymbol:
+    """A symbol defined on the line of a search result."""
+
+    @property
+    def name(self) -> str: ...
+    @property
+    def kind(self) -> str:
+        """e.g. `function` or `class`, as named by the language."""
+    @property
+    def range(self) -> Range:
+        """The range of the name."""
+
+class Occurrence:
+    """A definition or reference of the symbol under a position."""
+
+    @property
+    def path(self) -> str: ...
+    @property
+    def kind(self) -> Literal["definition", "reference"]: ...
+    @property
+    def range(self) -> Range:
+        """Lines are counted from `line_base()`, as in `go_to`."""
+    @property
+    def snippet(self) -> str: ...
+    @property
+    def external(self) -> bool:
+        """Whether the occurrence comes from a dependency index rather than the repository."""
+    @property
+    def reference_count(self) -> Optional[int]:
+        """For definitions, the number of references found along with them."""
+    def to_json(self) -> str:
+        """The occurrence as a JSON object of its attributes."""
+
+class CursorToken:
+    """The token under a position, see `token_at`."""
+
+    @property
+    def text(self) -> str: ...
+    @property
+    def kind(self) -> str:
+        """The kind of its syntax node, e.g. `field_identifier`."""
+    @property
+    def classification(self) -> Literal["identifier", "keyword", "literal", "comment", "punctuation", "other"]: ...
+    @property
+    def line(self) -> int:
+        """With `start_index` and `end_index`, the position to pass to `go_to`."""
+    @property
+    def start_index(self) -> int: ...
+    @property
+    def end_index(self) -> int: ...
+    @property
+    def range(self) -> Range:
+        """Lines are counted from `line_base()`, columns in bytes as in `hoverable_ranges`."""
+    def to_json(self) -> str:
+        """The token as a JSON object of its attributes."""
+
+class Edit:
+    """A replacement of the text of a range of a file, see `plan_replace`."""
+
+    @property
+    def path(self) -> str: ...
+    @property
+    def range(self) -> Range:
+        """Lines are counted from `line_base()`, columns are in bytes."""
+    @property
+    def old(self) -> str:
+        """The text of the range, before the edit."""
+    @property
+    def new(self) -> str: ...
+
+class RenameViolation:
+    """A problem with the edits of a rename, see `verify_rename`."""
+
+    @property
+    def path(self) -> str: ...
+    @property
+    def kind(self) -> Literal["stray_reference", "unintended_rename", "collision"]: ...
+    @property
+    def range(self) -> Range:
+        """In the file as edited, except for an unintended rename, the range of its edit."""
+    @property
+    def text(self) -> str: ...
+
+class SelfTestCheck:
+    """One step of `self_test`."""
+
+    @property
+    def name(self) -> Literal["index", "search", "go_to"]: ...
+    @property
+    def passed(self) -> bool: ...
+    @property
+    def detail(self) -> str:
+        """What was found, or why the step failed or was skipped."""
+
+class SelfTest:
+    """The outcome of `self_test`, true if every check passed."""
+
+    @property
+    def passed(self) -> bool: ...
+    @property
+    def version(self) -> str: ...
+    @property
+    def format_version(self) -> int: ...
+    @property
+    def checks(self) -> List[SelfTestCheck]: ...
+    def __bool__(self) -> bool: ...
+
+class IndexReport:
+    """What an indexing run did, true if it changed the index."""
+
+    @property
+    def added(self) -> List[str]: ...
+    @property
+    def updated(self) -> List[str]: ...
+    @property
+    def deleted(self) -> List[str]: ...
+    @property
+    def renamed(self) -> List[Tuple[str, str]]:
+        """`(from, to)` paths."""
+    @property
+    def skipped(self) -> List[Tuple[str, int]]:
+        """`(path, size)` of the files over the size cap."""
+    @property
+    def errors(self) -> List[Tuple[str, str]]:
+        """`(path, error)` of the entries that couldn't be read."""
+    @property
+    def evicted(self) -> List[Tuple[str, Literal["generated", "vendored", "minified"], int]]:
+        """`(path, category, size)` of the files evicted to fit in the size budget."""
+    @property
+    def duration_ms(self) -> float: ...
+    @property
+    def changed(self) -> bool: ...
+    def __bool__(self) -> bool: ...
+
+class IndexEvent:
+    """Something that happened to the index of an `IndexHandle`."""
+
+    @property
+    def kind(self) -> Literal["indexed", "removed", "error"]: ...
+    @property
+    def path(self) -> Optional[str]:
+        """`None` for the error of a whole refresh."""
+    @property
+    def message(self) -> Optional[str]:
+        """The error, for errors."""
+
+class IndexEvents:
+    """The events of an `IndexHandle`: iterating blocks until the next one, and ends once the handle is closed."""
+
+    def __iter__(self) -> IndexEvents: ...
+    def __next__(self) -> IndexEvent: ...
+    def poll(self, timeout: float = 0.0) -> Optional[IndexEvent]:
+        """The next event, waiting at most `timeout` seconds for it."""
+
+class SyncHandle:
+    """The progress of a refresh started with `IndexHandle.start_refresh()`."""
+
+    @property
+    def state(self) -> Literal["queued", "walking", "parsing", "committing", "done", "failed"]: ...
+    @property
+    def walked(self) -> int:
+        """The files the walk found so far."""
+    @property
+    def parsed(self) -> int:
+        """The files parsed so far, those new or changed."""
+    @property
+    def error(self) -> Optional[str]:
+        """Why the refresh failed."""
+    @property
+    def done(self) -> bool: ...
+    def wait(self, timeout: Optional[float] = None) -> Optional[IndexReport]:
+        """What the refresh changed once it is over, `None` if it still runs after `timeout` seconds."""
+
+class IndexHandle:
+    """A repository index kept open across calls.
+
+    One handle can be shared by any number of threads: every method releases the GIL
+    and runs on its own snapshot of the index, so queries never wait on each other.
+    """
+
+    def __init__(
+        self,
+        root_path_str: str,
+        index_path_str: str,
+        max_heavy_queries: int = 2,
+        index_missing_files: bool = False,
+        line_base: Optional[Literal[0, 1]] = None,
+    ) -> None:
+        """With `line_base`, the lines of its queries are numbered from it instead of from `line_base()`."""
+    def refresh(self, background: bool = False) -> IndexReport: ...
+    def start_refresh(self, background: bool = False) -> SyncHandle:
+        """Start a refresh without waiting for it."""
+    def start_watching(self, debounce_ms: Optional[int] = None) -> None:
+        """Keep the index up to date, reindexing the files that change, until `stop_watching()`."""
+    def stop_watching(self) -> None: ...
+    @property
+    def watching(self) -> bool: ...
+    def stats(self) -> Dict[Literal["max_running", "running", "queued"], int]:
+        """The heavy queries running, waiting for a turn, and the limit."""
+    def health(self) -> Dict[Literal["num_docs", "num_deleted_docs", "num_segments"], int]: ...
+    def compact(self) -> None: ...
+    def search(self, query: Union[str, Sequence[str]], **options: Any) -> str: ...
+    def search_results(self, query: Union[str, Sequence[str]], **options: Any) -> List[SearchResult]: ...
+    def text_search(
+        self,
+        query: str,
+        case_sensitive: bool,
+        context: Optional[str] = None,
+        include_flagged: bool = False,
+        scope: Optional[str] = None,
+        format: Optional[Format] = None,
+    ) -> str: ...
+    def fuzzy_search(
+        self,
+        query: str,
+        max_distance: int,
+        include_flagged: bool = False,
+        scope: Optional[str] = None,
+        format: Optional[Format] = None,
+        transpositions: bool = True,
+    ) -> str: ...
+    def regex_search(
+        self,
+        pattern: str,
+        case_sensitive: bool,
+        context: Optional[str] = None,
+        include_flagged: bool = False,
+        scope: Optional[str] = None,
+        format: Optional[Format] = None,
+    ) -> str: ...
+    def go_to(
+        self,
+        relative_path: str,
+        line: int,
+        start_index: int,
+        end_index: int,
+        context: Optional[str] = None,
+        format: Optional[Format] = None,
+        kind: Optional[OccurrenceKind] = None,
+        path_glob: Optional[str] = None,
+        lang: Optional[str] = None,
+    ) -> str: ...
+    def go_to_definition(
+        self,
+        relative_path: str,
+        line: int,
+        start_index: int,
+        end_index: int,
+        context: Optional[str] = None,
+        format: Optional[Format] = None,
+        path_glob: Optional[str] = None,
+        lang: Optional[str] = None,
+    ) -> str: ...
+    def find_references(
+        self,
+        relative_path: str,
+        line: int,
+        start_index: int,
+        end_index: int,
+        include_declaration: bool = False,
+        directory: Optional[str] = None,
+        context: Optional[str] = None,
+        format: Optional[Format] = None,
+        lang: Optional[str] = None,
+    ) -> str: ...
+    def occurrences(
+        self,
+        relative_path: str,
+        line: int,
+        start_index: int,
+        end_index: int,
+        context: Optional[str] = None,
+        kind: Optional[OccurrenceKind] = None,
+        path_glob: Optional[str] = None,
+        lang: Optional[str] = None,
+    ) -> List[Occurrence]: ...
+    def hoverable_ranges(
+        self,
+        relative_path: str,
+        start_line: Optional[int] = None,
+        end_line: Optional[int] = None,
+        lang: Optional[str] = None,
+    ) -> List[Range]: ...
+    def hoverable_ranges_bulk(self, relative_paths: List[str], lang: Optional[str] = None) -> Dict[str, List[Range]]: ...
+    def get_hoverable_ranges(
+        self,
+        relative_path: str,
+        start_line: Optional[int] = None,
+        end_line: Optional[int] = None,
+        lang: Optional[str] = None,
+    ) -> str: ...
+    def get_hoverable_ranges_bulk(self, relative_paths: List[str], lang: Optional[str] = None) -> str: ...
+    def token_at(self, relative_path: str, line: int, column: int) -> Optional[CursorToken]: ...
+    def events(self) -> IndexEvents:
+        """The events of the refreshes made from now on."""
+
+CodeNavSession = IndexHandle
+
+def go_to(
+    root_path_str: str,
+    index_path_str: str,
+    relative_path: str,
+    line: int,
+    start_index: int,
+    end_index: int,
+    context: Optional[str] = None,
+    dependencies: Optional[Sequence[Tuple[str, str]]] = None,
+    format: Optional[Format] = None,
+    kind: Optional[OccurrenceKind] = None,
+    path_glob: Optional[str] = None,
+    lang: Optional[str] = None,
+) -> str: ...
+def go_to_continue(continuation: str, format: Optional[Format] = None) -> str:
+    """The next occurrences of a `go_to` response cut by the response limits, from its continuation token."""
+def go_to_definition(
+    root_path_str: str,
+    index_path_str: str,
+    relative_path: str,
+    line: int,
+    start_index: int,
+    end_index: int,
+    context: Optional[str] = None,
+    dependencies: Optional[Sequence[Tuple[str, str]]] = None,
+    format: Optional[Format] = None,
+    path_glob: Optional[str] = None,
+    lang: Optional[str] = None,
+) -> str:
+    """Like `go_to` with `kind="definition"`: the definitions of the token, without its references."""
+def find_references(
+    root_path_str: str,
+    index_path_str: str,
+    relative_path: str,
+    line: int,
+    start_index: int,
+    end_index: int,
+    include_declaration: bool = False,
+    directory: Optional[str] = None,
+    context: Optional[str] = None,
+    dependencies: Optional[Sequence[Tuple[str, str]]] = None,
+    format: Optional[Format] = None,
+    lang: Optional[str] = None,
+) -> str:
+    """The references to the token, with its definitions if `include_declaration`, in the files under `directory` if given."""
+
+def occurrences(
+    root_path_str: str,
+    index_path_str: str,
+    relative_path: str,
+    line: int,
+    start_index: int,
+    end_index: int,
+    context: Optional[str] = None,
+    dependencies: Optional[Sequence[Tuple[str, str]]] = None,
+    kind: Optional[OccurrenceKind] = None,
+    path_glob: Optional[str] = None,
+    lang: Optional[str] = None,
+) -> List[Occurrence]: ...
+def text_search(
+    root_path_str: str,
+    index_path_str: str,
+    query: str,
+    case_sensitive: bool,
+    context: Optional[str] = None,
+    include_flagged: bool = False,
+    scope: Optional[str] = None,
+    format: Optional[Format] = None,
+) -> str: ...
+def fuzzy_search(
+    root_path_str: str,
+    index_path_str: str,
+    query: str,
+    max_distance: int,
+    include_flagged: bool = False,
+    scope: Optional[str] = None,
+    format: Optional[Format] = None,
+    transpositions: bool = True,
+) -> str:
+    """Lines with a term at most `max_distance` edits away, 0 to 2, see `search`."""
+def regex_search(
+    root_path_str: str,
+    index_path_str: str,
+    pattern: str,
+    case_sensitive: bool,
+    context: Optional[str] = None,
+    include_flagged: bool = False,
+    scope: Optional[str] = None,
+    format: Optional[Format] = None,
+) -> str:
+    """The lines matching a regex, such as `fn\\s+\\w+_handler`, matched line by line."""
+def multi_term_search(
+    root_path_str: str,
+    index_path_str: str,
+    terms: Sequence[str],
+    case_sensitive: bool,
+    context: Optional[str] = None,
+    include_flagged: bool = False,
+    scope: Optional[str] = None,
+    format: Optional[Format] = None,
+) -> str: ...
+def wildcard_search(
+    root_path_str: str,
+    index_path_str: str,
+    pattern: str,
+    case_sensitive: bool,
+    allow_leading_wildcard: bool = False,
+    context: Optional[str] = None,
+    include_flagged: bool = False,
+    scope: Optional[str] = None,
+    format: Optional[Format] = None,
+) -> str: ...
+def complete_symbol(
+    root_path_str: str, index_path_str: str, prefix: str, limit: int = 20, format: Optional[Format] = None
+) -> str: ...
+def fuzzy_symbol_search(
+    root_path_str: str,
+    index_path_str: str,
+    query: str,
+    max_distance: int = 2,
+    limit: int = 20,
+    format: Optional[Format] = None,
+) -> str: ...
+def search(root_path_str: str, index_path_str: str, query: Union[str, Sequence[str]], **options: Any) -> str: ...
+def search_results(
+    root_path_str: str, index_path_str: str, query: Union[str, Sequence[str]], **options: Any
+) -> List[SearchResult]: ...
+def diff_context(
+    root_path_str: str,
+    index_path_str: str,
+    changed_files: Sequence[str],
+    query: Union[str, Sequence[str]],
+    base: Optional[str] = None,
+    **options: Any,
+) -> str:
+    """Like `search`, restricted to `changed_files` and annotated against their diff to `base`."""
+def diff_go_to(
+    root_path_str: str,
+    index_path_str: str,
+    changed_files: Sequence[str],
+    relative_path: str,
+    line: int,
+    start_index: int,
+    end_index: int,
+    base: Optional[str] = None,
+    context: Optional[str] = None,
+    format: Optional[Format] = None,
+) -> str:
+    """Like `go_to`, restricted to `changed_files` and annotated against their diff to `base`."""
+def index_revision(root_path_str: str, index_path_str: str, revision: str) -> str:
+    """Indexes the repository as it was at `revision`, read from git, returning its commit hash."""
+def indexed_revisions(index_path_str: str) -> List[str]: ...
+def revision_search(
+    root_path_str: str,
+    index_path_str: str,
+    query: Union[str, Sequence[str]],
+    revisions: Optional[Sequence[str]] = None,
+    **options: Any,
+) -> str:
+    """Like `search`, over the indexed `revisions`, every indexed one by default, newest first."""
+def sharded_search(
+    root_path_str: str,
+    index_path_str: str,
+    query: Union[str, Sequence[str]],
+    shard_by: Literal["language", "directory"],
+    **options: Any,
+) -> str: ...
+def reindex_shard(
+    root_path_str: str, index_path_str: str, shard_by: Literal["language", "directory"], shard: str
+) -> None: ...
+def set_response_limits(max_results: Optional[int] = 100, max_response_bytes: Optional[int] = 524288) -> None:
+    """Bounds every response of the process, `None` lifts a limit."""
+def response_limits() -> Dict[Literal["max_results", "max_response_bytes"], Optional[int]]: ...
+def set_query_log(enabled: bool) -> None:
+    """Logs the queries of every index opened from now on to `queries.jsonl` in its directory."""
+def query_log_enabled() -> bool: ...
+def replay_query_log(index_path_str: str, log_path_str: Optional[str] = None) -> str:
+    """Runs the logged queries again, returning their latencies and result counts then and now as JSON."""
+def set_line_base(base: Literal[0, 1]) -> None:
+    """Numbers the lines of the inputs and outputs of the calls made on the calling thread from `base`."""
+def line_base() -> Literal[0, 1]:
+    """The base of the calling thread, 1 unless set otherwise."""
+def capabilities() -> Dict[str, Any]:
+    """`version`, `languages`, `features` (name to enabled), `index_format_version` and `debug_build`."""
+def repo_fingerprint(root_path_str: str) -> Optional[str]:
+    """`None` outside a git repository."""
+def index_fingerprint(index_path_str: str) -> Optional[str]: ...
+def index_format_version(index_path_str: str) -> Optional[int]: ...
+def index_health(index_path_str: str) -> Dict[Literal["num_docs", "num_deleted_docs", "num_segments"], int]: ...
+def index_repository(
+    root_path_str: str,
+    index_path_str: str,
+    dry_run: bool = False,
+    background: bool = False,
+    max_index_bytes: Optional[int] = None,
+) -> IndexReport:
+    """Brings the index up to date, as every query does first, and returns what changed.
+
+    With `background`, indexes on a single thread, pausing after every file read. With
+    `max_index_bytes`, evicts generated, vendored and minified files until the index
+    fits, on this run and the next ones; 0 lifts the budget.
+    """
+def index_documents(
+    index_path_str: str, documents: Sequence[Tuple[str, str]], dry_run: bool = False
+) -> IndexReport:
+    """Indexes `(path, content)` pairs of files that needn't exist on disk, stored under their path."""
+def index_archive(index_path_str: str, archive_path_str: str, dry_run: bool = False) -> IndexReport:
+    """Indexes the files of a zip or tar archive under its path, without extracting it."""
+def compact_index(index_path_str: str) -> None: ...
+def self_test() -> SelfTest:
+    """Indexes, searches and navigates a built-in fixture in a temporary directory."""
+def get_hoverable_ranges(
+    root_path_str: str,
+    index_path_str: str,
+    relative_path: str,
+    start_line: Optional[int] = None,
+    end_line: Optional[int] = None,
+    lang: Optional[str] = None,
+) -> str:
+    """Only the ranges touching the lines from `start_line` to `end_line`, both included, if given.
+
+    Lines are counted from `line_base()`, 1 by default; they used to count from 0.
+    """
+def get_hoverable_ranges_bulk(
+    root_path_str: str, index_path_str: str, relative_paths: List[str], lang: Optional[str] = None
+) -> str:
+    """A JSON object of the ranges of every path, as `get_hoverable_ranges` returns them."""
+def hoverable_ranges(
+    root_path_str: str,
+    index_path_str: str,
+    relative_path: str,
+    start_line: Optional[int] = None,
+    end_line: Optional[int] = None,
+    lang: Optional[str] = None,
+) -> List[Range]:
+    """Lines are counted from `line_base()`, as in `get_hoverable_ranges`."""
+def token_at(
+    root_path_str: str, index_path_str: str, relative_path: str, line: int, column: int
+) -> Optional[CursorToken]:
+    """The token at the char `column` of `line`, or the one ending there, `None` between tokens."""
+def plan_replace(
+    root_path_str: str,
+    index_path_str: str,
+    pattern: str,
+    replacement: str,
+    regex: bool = False,
+    case_sensitive: bool = True,
+    lang: Optional[str] = None,
+    path_glob: Optional[str] = None,
+    include_flagged: bool = False,
+    scope: Optional[str] = None,
+) -> List[Edit]:
+    """Every edit of the replacement, planned from the index without touching the disk."""
+def verify_rename(
+    root_path_str: str,
+    index_path_str: str,
+    edits: List[Edit],
+    old_name: str,
+    new_name: str,
+) -> List[RenameViolation]:
+    """The problems with renaming `old_name` to `new_name` with the edits, before they are applied."""
+def apply_edits(index_path_str: str, edits: List[Edit]) -> IndexReport:
+    """Apply the edits to the files on disk, then reindex the files they change.
+
+    Nothing is written if the text of an edit is no longer in its file, which raises a
+    `CodeNavError` with the code `stale_edit`.
+    """
+def doc_search(
+    root_path_str: str,
+    index_path_str: str,
+    query: str,
+    limit: int = 10,
+    include_flagged: bool = False,
+    scope: Optional[str] = None,
+    format: Optional[Format] = None,
+) -> str:
+    """The symbols whose doc comment or docstring best matches the natural-language query."""
+def tests_for(
+    root_path_str: str,
+    index_path_str: str,
+    path: Optional[str] = None,
+    symbol: Optional[str] = None,
+    include_flagged: bool = False,
+    scope: Optional[str] = None,
+    format: Optional[Format] = None,
+) -> str:
+    """The files likely to test the file or the symbol, either of which must be given, and their test functions."""
+def hybrid_search(
+    root_path_str: str,
+    index_path_str: str,
+    query: str,
+    limit: int = 20,
+    include_flagged: bool = False,
+    scope: Optional[str] = None,
+    format: Optional[Format] = None,
+) -> str:
+    """The symbols, files and lines matching the query, from every search, in one ranked list."""
+def public_api(
+    root_path_str: str,
+    index_path_str: str,
+    lang: Optional[str] = None,
+    path_glob: Optional[str] = None,
+    format: Optional[Format] = None,
+) -> str: ...
+def file_summary(root_path_str: str, index_path_str: str, relative_path: str, format: Optional[Format] = None) -> str: ...
+def document_symbols(root_path_str: str, index_path_str: str, relative_path: str, format: Optional[Format] = None) -> str:
+    """The symbols the file defines, in the order of the file."""
+def workspace_symbols(
+    root_path_str: str, index_path_str: str, query: str, limit: int = 50, format: Optional[Format] = None
+) -> str:
+    """The symbols of the repository whose name holds `query`, ignoring case."""
+def package_info(root_path_str: str, index_path_str: str, dir: str, format: Optional[Format] = None) -> str: ...
+def owners_of(root_path_str: str, index_path_str: str, path: str) -> List[str]:
+    """The owners of the file in the `CODEOWNERS` of the repository, none if no rule matches it."""
+def language_breakdown(root_path_str: str, index_path_str: str, format: Optional[Format] = None) -> str:
+    """The indexed files, lines and share of the bytes of every language, largest first."""
@@ -0,0 +1,28 @@
+//! Indexes a repository, then finds the symbols, files and lines matching a query:
+//!
+//! ```sh
+//! cargo run --example find -- /path/to/repo /path/to/index parse_config
+//! ```
+
+use anyhow::Context;
+use code_nav_devon::CodeNav;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let usage = "usage: find <repo> <index> <query>";
+    let (root, index, query) = (args.next().context(usage)?, args.next().context(usage)?, args.next().context(usage)?);
+
+    std::fs::create_dir_all(&index)?;
+    let nav = CodeNav::new(&root, &index);
+    let session = nav.session().await?;
+    for hit in session.find(&query)? {
+        let location = match hit.line {
+            Some(line) => format!("{}:{line}", hit.relative_path),
+            None => hit.relative_path.clone(),
+        };
+        let sources = hit.sources.iter().map(|source| source.as_str()).collect::<Vec<_>>().join(", ");
+        println!("{location} ({sources}) {}", hit.symbol.or(hit.text).unwrap_or_default());
+    }
+    Ok(())
+}
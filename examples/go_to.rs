@@ -0,0 +1,34 @@
+//! Indexes a repository, then lists the definitions and references of the token at a
+//! line and char column of one of its files:
+//!
+//! ```sh
+//! cargo run --example go_to -- /path/to/repo /path/to/index src/main.rs 12 8
+//! ```
+
+use anyhow::Context;
+use code_nav_devon::CodeNav;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let [root, index, path, line, column] = args.as_slice() else {
+        anyhow::bail!("usage: go_to <repo> <index> <path> <line> <column>");
+    };
+    let (line, column) = (line.parse()?, column.parse()?);
+
+    std::fs::create_dir_all(index)?;
+    let nav = CodeNav::new(root, index);
+    let session = nav.session().await?;
+    let token = session
+        .searcher()
+        .token_at(path, line, column)?
+        .with_context(|| format!("no token at {path}:{line}:{column}"))?;
+    println!("{} ({})", token.text, token.classification.as_str());
+
+    for file in session.go_to(path, token.line, token.start_index, token.end_index)? {
+        for occurrence in &file.data {
+            println!("{:?} {}:{}", occurrence.kind, file.file, occurrence.range.start.line);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//! The crate from Rust, without knowing how it is put together: a [`CodeNav`] is a
+//! repository and its index, opened on first use with the defaults the python
+//! functions use, and a [`SearchSession`] queries it, refreshed when asked.
+//!
+//! ```no_run
+//! use code_nav_devon::api::CodeNav;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let nav = CodeNav::new("/path/to/repo", "/path/to/index");
+//! let mut session = nav.session().await?;
+//! for hit in session.find("parse_config")? {
+//!     println!("{}:{:?} {:?}", hit.relative_path, hit.line, hit.sources);
+//! }
+//!
+//! // after the files changed
+//! let report = session.refresh().await?;
+//! println!("{} files reindexed", report.added.len() + report.updated.len());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The python functions are built on these types too, opening a [`CodeNav`] per call.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use tokio::sync::OnceCell;
+
+use crate::budget::SizeBudget;
+use crate::edit::Edit;
+use crate::file::File;
+use crate::format::OnIncompatible;
+use crate::hybrid::HybridHit;
+use crate::indexes::{IndexReport, Indexes, Priority};
+use crate::intelligence::code_navigation::FileSymbols;
+use crate::package::Scope;
+use crate::query::Query;
+use crate::search::{SearchResponse, SearchResult, Searcher, SymbolCompletion};
+use crate::source::Archive;
+
+/// The memory of every writer thread, in bytes, unless set otherwise
+pub const DEFAULT_BUFFER_SIZE: usize = 15_000_000;
+
+/// The writer threads indexing uses, unless set otherwise
+pub const DEFAULT_THREADS: usize = 4;
+
+/// The most hits [`SearchSession::find`] returns
+pub const DEFAULT_FIND_LIMIT: usize = 20;
+
+/// A repository and the index of its files
+pub struct CodeNav {
+    /// `None` for an index of documents and archives only, see [`Self::index_only`]
+    root: Option<PathBuf>,
+    index_path: PathBuf,
+    file: File,
+    buffer_size: usize,
+    threads: usize,
+    on_incompatible: OnIncompatible,
+    /// `None` to keep the budget recorded in the index
+    size_budget: Option<Option<SizeBudget>>,
+    priority: Priority,
+    indexes: OnceCell<Indexes>,
+}
+
+impl CodeNav {
+    /// The repository at `root` and its index at `index_path`, which is created or
+    /// brought up to date when first needed.
+    pub fn new(root: impl AsRef<Path>, index_path: impl AsRef<Path>) -> Self {
+        Self {
+            root: Some(root.as_ref().to_path_buf()),
+            ..Self::index_only(index_path)
+        }
+    }
+
+    /// The index at `index_path`, without a repository to refresh it from, to index
+    /// documents and archives into or to search.
+    pub fn index_only(index_path: impl AsRef<Path>) -> Self {
+        Self {
+            root: None,
+            index_path: index_path.as_ref().to_path_buf(),
+            file: File::new(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            threads: DEFAULT_THREADS,
+            on_incompatible: OnIncompatible::Rebuild,
+            size_budget: None,
+            priority: Priority::Normal,
+            indexes: OnceCell::new(),
+        }
+    }
+
+    /// Index with `file` rather than [`File::new`], e.g. one with a redactor or a
+    /// [`File::dry_run`] one.
+    pub fn with_source(mut self, file: File) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Index with `threads` writer threads of `buffer_size` bytes each.
+    pub fn threads(mut self, threads: usize, buffer_size: usize) -> Self {
+        self.threads = threads;
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// What happens to an index this version can't use, rebuilt by default.
+    pub fn on_incompatible(mut self, on_incompatible: OnIncompatible) -> Self {
+        self.on_incompatible = on_incompatible;
+        self
+    }
+
+    /// Keep the index under `budget`, or lift its budget if `None`, see
+    /// [`Indexes::size_budget`].
+    pub fn size_budget(mut self, budget: Option<SizeBudget>) -> Self {
+        self.size_budget = Some(budget);
+        self
+    }
+
+    /// Refresh at `priority`, [`Priority::Normal`] by default.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    pub fn index_path(&self) -> &Path {
+        &self.index_path
+    }
+
+    /// The indexes, opened on first use.
+    pub async fn indexes(&self) -> Result<&Indexes> {
+        self.indexes
+            .get_or_try_init(|| async {
+                let indexes = Indexes::open(self.file.clone(), &self.index_path, self.buffer_size, self.threads, self.on_incompatible).await?;
+                Ok(match self.size_budget {
+                    Some(budget) => indexes.size_budget(budget),
+                    None => indexes,
+                })
+            })
+            .await
+    }
+
+    /// Bring the index up to date with the repository, returning what changed.
+    pub async fn refresh(&self) -> Result<IndexReport> {
+        let Some(root) = &self.root else {
+            anyhow::bail!("the index at {} has no repository to refresh from", self.index_path.display());
+        };
+        self.indexes().await?.index_at(root, self.priority).await
+    }
+
+    /// Index `documents`, `(path, content)` pairs of files that needn't exist on disk,
+    /// see [`Indexes::index_documents`].
+    pub async fn index_documents(&self, documents: Vec<(String, String)>) -> Result<IndexReport> {
+        self.indexes().await?.index_documents(documents).await
+    }
+
+    /// Apply `edits` to the files on disk and reindex those they change, see
+    /// [`Indexes::apply_edits`].
+    pub async fn apply_edits(&self, edits: &[Edit]) -> Result<IndexReport> {
+        self.indexes().await?.apply_edits(edits).await
+    }
+
+    /// Index the files of the zip or tar archive at `archive_path` under its path.
+    pub async fn index_archive(&self, archive_path: &Path) -> Result<IndexReport> {
+        self.indexes().await?.index_source(Archive::new(), archive_path).await
+    }
+
+    /// Merge the index into a single segment without deleted documents.
+    pub async fn compact(&self) -> Result<()> {
+        self.indexes().await?.compact().await
+    }
+
+    /// A session over the index brought up to date with the repository first, if there
+    /// is one.
+    pub async fn session(&self) -> Result<SearchSession<'_>> {
+        if self.root.is_some() {
+            self.refresh().await?;
+        }
+        SearchSession::open(self)
+    }
+}
+
+/// Queries of the index of a [`CodeNav`], all against the index as of the last time
+/// the session was opened or refreshed
+pub struct SearchSession<'a> {
+    nav: &'a CodeNav,
+    searcher: Searcher,
+    include_flagged: bool,
+    scope: Option<Scope>,
+}
+
+impl<'a> SearchSession<'a> {
+    /// A session over the index of `nav` as it is, without refreshing it.
+    pub fn open(nav: &'a CodeNav) -> Result<Self> {
+        Ok(Self {
+            nav,
+            searcher: Searcher::new(&nav.index_path)?,
+            include_flagged: false,
+            scope: None,
+        })
+    }
+
+    /// Return results from minified and generated files too.
+    pub fn include_flagged(mut self, include_flagged: bool) -> Self {
+        self.include_flagged = include_flagged;
+        self.searcher = self.searcher.include_flagged(include_flagged);
+        self
+    }
+
+    /// Restrict results to `scope`, or lift the restriction if `None`.
+    pub fn scope(mut self, scope: Option<Scope>) -> Result<Self> {
+        self.searcher = self.searcher.scope(scope.clone())?;
+        self.scope = scope;
+        Ok(self)
+    }
+
+    /// Bring the index up to date with the repository, then search it as it is now,
+    /// with the same options.
+    pub async fn refresh(&mut self) -> Result<IndexReport> {
+        let report = self.nav.refresh().await?;
+        if report.changed() {
+            self.searcher = Searcher::new(&self.nav.index_path)?
+                .include_flagged(self.include_flagged)
+                .scope(self.scope.clone())?;
+        }
+        Ok(report)
+    }
+
+    /// The searcher the session queries, for what the session has no method for.
+    pub fn searcher(&self) -> &Searcher {
+        &self.searcher
+    }
+
+    pub fn into_searcher(self) -> Searcher {
+        self.searcher
+    }
+
+    /// The symbols, files and lines matching `text`, best first, see
+    /// [`Searcher::hybrid_search`]: the search to start with.
+    pub fn find(&self, text: &str) -> Result<Vec<HybridHit>> {
+        self.searcher.hybrid_search(text, DEFAULT_FIND_LIMIT)
+    }
+
+    /// Run any kind of search, see [`Query`].
+    pub fn search(&self, query: &Query) -> Result<SearchResponse> {
+        self.searcher.search(query)
+    }
+
+    /// The lines containing `text`, ignoring case.
+    pub fn text_search(&self, text: &str) -> Result<Vec<SearchResult>> {
+        self.searcher.text_search(text, false, None)
+    }
+
+    /// The definitions and references of the token at `start_index..end_index` of
+    /// `line` of the file at `relative_path`, as the python `go_to` takes them.
+    pub fn go_to(&self, relative_path: &str, line: usize, start_index: usize, end_index: usize) -> Result<Vec<FileSymbols>> {
+        self.searcher.token_info(relative_path, line, start_index, end_index, None)
+    }
+
+    /// The names of the defined symbols starting with `prefix`, at most `limit`.
+    pub fn complete_symbol(&self, prefix: &str, limit: usize) -> Result<Vec<SymbolCompletion>> {
+        self.searcher.complete_symbol(prefix, limit)
+    }
+}
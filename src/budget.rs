@@ -0,0 +1,229 @@
+//! Keeping an index under a size budget, so that sandboxes with little disk stay
+//! functional on giant repositories.
+//!
+//! When indexing leaves the index over its [`SizeBudget`], the documents of the files
+//! least worth searching, generated, vendored and minified ones, are evicted, biggest
+//! first, until the index fits, and listed in the `evicted` files of the report. The
+//! evicted files are recorded next to the index, in [`EVICTED_FILE`], along with the
+//! budget, which keeps applying to the next runs until another one is set. They are
+//! left out of those runs for as long as the budget stays the same. Other files are
+//! never evicted: an index still over its budget once none are left stays as it is,
+//! with a warning.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tantivy::collector::DocSetCollector;
+use tantivy::directory::error::OpenReadError;
+use tantivy::directory::Directory;
+use tantivy::query::AllQuery;
+use tantivy::{Index, Term};
+
+use crate::file::File;
+use crate::file_heuristics::FileFlags;
+use crate::indexes::IndexWriteHandle;
+use crate::package::PackageInfo;
+
+/// Name of the file, inside the index directory, that lists the evicted files
+pub const EVICTED_FILE: &str = "evicted.json";
+
+/// Directories holding the sources of dependencies checked into the repository
+pub const VENDORED_DIRS: &[&str] = &["vendor", "vendored", "third_party", "third-party", "external"];
+
+/// The most bytes the segments of an index may take on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeBudget {
+    pub max_bytes: u64,
+}
+
+impl SizeBudget {
+    pub fn new(max_bytes: u64) -> Self {
+        Self { max_bytes }
+    }
+}
+
+/// Why a file may be evicted, in the order they are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionCategory {
+    Generated,
+    /// In one of the [`VENDORED_DIRS`]
+    Vendored,
+    Minified,
+}
+
+impl EvictionCategory {
+    /// The category of the file at `relative_path`, relative to its root, `None` if it
+    /// is worth keeping.
+    pub fn of(relative_path: &str, content: &str) -> Option<Self> {
+        let flags = FileFlags::classify(relative_path, content);
+        let dirs = relative_path.rsplit_once('/').map_or("", |(dirs, _)| dirs);
+        if flags.is_generated {
+            Some(Self::Generated)
+        } else if dirs.split('/').any(|dir| VENDORED_DIRS.contains(&dir)) {
+            Some(Self::Vendored)
+        } else if flags.is_minified {
+            Some(Self::Minified)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Generated => "generated",
+            Self::Vendored => "vendored",
+            Self::Minified => "minified",
+        }
+    }
+}
+
+/// A file whose document was evicted to keep the index under its budget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvictedFile {
+    pub path: String,
+    pub category: EvictionCategory,
+    /// Of its content, in bytes
+    pub size: u64,
+}
+
+/// The budget and the files evicted for it, as recorded in [`EVICTED_FILE`]
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Evicted {
+    max_bytes: Option<u64>,
+    paths: Vec<String>,
+}
+
+/// The bytes the searchable segments of `index` take on disk.
+pub fn index_bytes(index: &Index) -> Result<u64> {
+    let directory = index.directory();
+    let mut bytes = 0;
+    for meta in index.searchable_segment_metas()? {
+        for path in meta.list_files() {
+            match directory.open_read(&path) {
+                Ok(file) => bytes += file.len() as u64,
+                // e.g. the positions of a segment without text
+                Err(OpenReadError::FileDoesNotExist(_)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// The paths of the files evicted from `index`, left out of indexing.
+pub(crate) fn evicted_paths(index: &Index) -> Result<HashSet<String>> {
+    Ok(stored(index)?.paths.into_iter().collect())
+}
+
+/// The budget recorded in `index` by the last run, if any.
+pub fn stored_budget(index: &Index) -> Result<Option<SizeBudget>> {
+    Ok(stored(index)?.max_bytes.map(SizeBudget::new))
+}
+
+/// Record `budget` in `index`, forgetting the files evicted for another one, so that
+/// they are indexed again, and evicted again if they still don't fit.
+pub(crate) fn prepare(index: &Index, budget: Option<SizeBudget>) -> Result<()> {
+    let max_bytes = budget.map(|budget| budget.max_bytes);
+    if stored(index)?.max_bytes == max_bytes {
+        return Ok(());
+    }
+    record(index, &Evicted { max_bytes, paths: Vec::new() })
+}
+
+/// Evict the low-value documents stored under `roots` from the index of `writer`,
+/// biggest first, until it fits in `budget`, then compact it to reclaim their space.
+pub(crate) fn evict(writer: &mut IndexWriteHandle<'_>, file: &File, roots: &[&Path], budget: SizeBudget) -> Result<Vec<EvictedFile>> {
+    let index = writer.writer().index().clone();
+    let bytes = index_bytes(&index)?;
+    if bytes <= budget.max_bytes {
+        return Ok(Vec::new());
+    }
+
+    let roots = roots
+        .iter()
+        .map(|root| Ok(format!("{}/", root.canonicalize()?.to_string_lossy().replace('\\', "/"))))
+        .collect::<Result<Vec<_>>>()?;
+    let searcher = index.reader()?.searcher();
+    let mut content_bytes = 0;
+    let mut candidates = Vec::new();
+    for address in searcher.search(&AllQuery, &DocSetCollector)? {
+        let doc = searcher.doc(address)?;
+        let text = |field| doc.get_first(field).and_then(|value| value.as_text()).unwrap_or_default();
+        let (path, content) = (text(file.path_field), text(file.content_field));
+        content_bytes += content.len() as u64;
+        // virtual documents and those of other sources aren't walked again
+        let Some(relative_path) = roots.iter().find_map(|root| path.strip_prefix(root.as_str())) else {
+            continue;
+        };
+        // package files are read on every run, to list the packages
+        if PackageInfo::is_package_file(relative_path.rsplit('/').next().unwrap_or_default()) {
+            continue;
+        }
+        if let Some(category) = EvictionCategory::of(relative_path, content) {
+            candidates.push(EvictedFile { path: path.to_owned(), category, size: content.len() as u64 });
+        }
+    }
+    candidates.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+    // the share of the index a document takes, estimated from the size of its content
+    let bytes_per_byte = bytes as f64 / content_bytes.max(1) as f64;
+    let mut excess = (bytes - budget.max_bytes) as f64;
+    let mut evicted = Vec::new();
+    for candidate in candidates {
+        if excess <= 0.0 {
+            break;
+        }
+        excess -= candidate.size as f64 * bytes_per_byte;
+        writer.writer().delete_term(Term::from_field_text(file.path_field, &candidate.path));
+        evicted.push(candidate);
+    }
+
+    if !evicted.is_empty() {
+        writer.commit()?;
+        writer.compact()?;
+        let mut recorded = stored(&index)?;
+        recorded.max_bytes = Some(budget.max_bytes);
+        recorded.paths.extend(evicted.iter().map(|evicted| evicted.path.clone()));
+        recorded.paths.sort();
+        recorded.paths.dedup();
+        record(&index, &recorded)?;
+    }
+    let bytes = index_bytes(&index)?;
+    if bytes > budget.max_bytes {
+        eprintln!(
+            "Warning: the index takes {bytes} bytes, over its budget of {}, and has no generated, vendored or minified files left to evict",
+            budget.max_bytes
+        );
+    }
+    Ok(evicted)
+}
+
+fn stored(index: &Index) -> Result<Evicted> {
+    match index.directory().atomic_read(Path::new(EVICTED_FILE)) {
+        Ok(stored) => serde_json::from_slice(&stored).context("invalid evicted files list"),
+        Err(OpenReadError::FileDoesNotExist(_)) => Ok(Evicted::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn record(index: &Index, evicted: &Evicted) -> Result<()> {
+    Ok(index
+        .directory()
+        .atomic_write(Path::new(EVICTED_FILE), &serde_json::to_vec(evicted)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_value_categories() {
+        assert_eq!(EvictionCategory::of("src/api.pb.go", "package api\n"), Some(EvictionCategory::Generated));
+        assert_eq!(EvictionCategory::of("third_party/zlib/inflate.c", "int x;\n"), Some(EvictionCategory::Vendored));
+        assert_eq!(EvictionCategory::of("static/app.min.js", "var a;\n"), Some(EvictionCategory::Minified));
+        assert_eq!(EvictionCategory::of("src/vendor.rs", "fn vendor() {}\n"), None);
+    }
+}
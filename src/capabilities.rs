@@ -0,0 +1,62 @@
+//! What this build of the library supports, for callers that adapt to the wheel they
+//! are running on: the grammars compiled in, the optional Cargo features it was built
+//! with, and the version of the index format it reads and writes.
+//!
+//! Everything is known at compile time. A feature missing from [`FEATURES`] doesn't
+//! exist in this version, which callers can treat as disabled.
+
+use serde::Serialize;
+
+use crate::format::FORMAT_VERSION;
+use crate::intelligence::ALL_LANGUAGES;
+
+/// The optional Cargo features, and whether this build enabled them
+pub const FEATURES: &[(&str, bool)] = &[
+    ("console-subscriber", cfg!(feature = "console-subscriber")),
+    ("histogram", cfg!(feature = "histogram")),
+];
+
+/// The capabilities of this build
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// The version of the package
+    pub version: &'static str,
+    /// The canonical names of the languages with a compiled grammar, which
+    /// navigation and hoverable ranges support
+    pub languages: Vec<&'static str>,
+    pub features: Vec<(&'static str, bool)>,
+    /// See [`FORMAT_VERSION`]
+    pub index_format_version: u32,
+    /// Whether it was built without optimizations
+    pub debug_build: bool,
+}
+
+impl Capabilities {
+    pub fn of_build() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            languages: ALL_LANGUAGES.iter().map(|language| language.name()).collect(),
+            features: FEATURES.to_vec(),
+            index_format_version: FORMAT_VERSION,
+            debug_build: cfg!(debug_assertions),
+        }
+    }
+
+    /// Whether the feature `name` is enabled, `false` if it doesn't exist.
+    pub fn has_feature(&self, name: &str) -> bool {
+        self.features.iter().any(|&(feature, enabled)| feature == name && enabled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_the_compiled_grammars() {
+        let capabilities = Capabilities::of_build();
+        assert!(capabilities.languages.contains(&"rust"));
+        assert!(capabilities.languages.contains(&"python"));
+        assert!(!capabilities.has_feature("embeddings"));
+    }
+}
@@ -0,0 +1,91 @@
+//! Deleted documents left behind by reindexing, and merging them away.
+//!
+//! Every reindexed file deletes its previous document, which tantivy only marks as
+//! deleted: the document stays in its segment, and iterating the doc store still has to
+//! skip over it. Compacting merges the segments into one holding only the live
+//! documents.
+
+use serde::Serialize;
+
+/// How many documents the index holds, and how many of them are deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct IndexHealth {
+    /// Documents that are searchable
+    pub num_docs: u64,
+    /// Documents deleted but still stored in their segment
+    pub num_deleted_docs: u64,
+    pub num_segments: usize,
+}
+
+impl IndexHealth {
+    pub fn of(searcher: &tantivy::Searcher) -> Self {
+        let segments = searcher.segment_readers();
+        Self {
+            num_docs: segments.iter().map(|segment| segment.num_docs() as u64).sum(),
+            num_deleted_docs: segments.iter().map(|segment| segment.num_deleted_docs() as u64).sum(),
+            num_segments: segments.len(),
+        }
+    }
+
+    /// The share of the stored documents that are deleted, from 0 to 1.
+    pub fn deleted_ratio(&self) -> f64 {
+        let stored = self.num_docs + self.num_deleted_docs;
+        if stored == 0 {
+            0.0
+        } else {
+            self.num_deleted_docs as f64 / stored as f64
+        }
+    }
+}
+
+/// When indexing compacts the index on its own: once at least `min_deleted_docs` are
+/// deleted, and they are more than `max_deleted_ratio` of the stored documents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionPolicy {
+    pub min_deleted_docs: u64,
+    pub max_deleted_ratio: f64,
+}
+
+impl CompactionPolicy {
+    pub const DEFAULT: Self = Self {
+        min_deleted_docs: 1_000,
+        max_deleted_ratio: 0.2,
+    };
+
+    pub fn should_compact(&self, health: &IndexHealth) -> bool {
+        health.num_deleted_docs >= self.min_deleted_docs.max(1) && health.deleted_ratio() > self.max_deleted_ratio
+    }
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds() {
+        let health = |num_docs, num_deleted_docs| IndexHealth {
+            num_docs,
+            num_deleted_docs,
+            num_segments: 1,
+        };
+        let policy = CompactionPolicy::DEFAULT;
+
+        assert_eq!(health(0, 0).deleted_ratio(), 0.0);
+        assert!(!policy.should_compact(&health(10, 900)), "too few deleted docs");
+        assert!(!policy.should_compact(&health(10_000, 1_500)), "too small a share");
+        assert!(policy.should_compact(&health(1_000, 1_000)));
+
+        let eager = CompactionPolicy {
+            min_deleted_docs: 0,
+            max_deleted_ratio: 0.0,
+        };
+        assert!(!eager.should_compact(&health(10, 0)));
+        assert!(eager.should_compact(&health(10, 1)));
+    }
+}
@@ -1,4 +1,11 @@
-use crate::{intelligence::TreeSitterFile, symbol::SymbolLocations, text_range::TextRange};
+use std::borrow::Cow;
+
+use crate::{
+    intelligence::{TreeSitterFile, PLAINTEXT},
+    regions::{self, Region},
+    symbol::{LazySymbolLocations, SymbolLocations},
+    text_range::TextRange,
+};
 
 #[derive(Debug, Clone)]
 pub struct ContentDocument {
@@ -6,15 +13,79 @@ pub struct ContentDocument {
     pub lang: Option<String>,
     pub relative_path: String,
     pub line_end_indices: Vec<u32>,
-    pub symbol_locations: SymbolLocations,
+    pub symbol_locations: LazySymbolLocations,
+    /// The regions of a template, see [`crate::regions`]
+    pub regions: Vec<Region>,
 }
 
 impl ContentDocument {
     pub fn hoverable_ranges(&self) -> Option<Vec<TextRange>> {
-        TreeSitterFile::try_build(self.content.as_bytes(), self.lang.as_ref()?)
+        if self.is_template() {
+            let mut ranges = self
+                .by_language()
+                .iter()
+                .filter_map(|doc| doc.hoverable_ranges())
+                .flatten()
+                .collect::<Vec<_>>();
+            ranges.sort_by_key(|range| range.start.byte);
+            return Some(ranges);
+        }
+        TreeSitterFile::try_build(self.source().as_bytes(), self.lang.as_ref()?)
             .and_then(TreeSitterFile::hoverable_ranges)
             .ok()
     }
+
+    pub fn function_ranges(&self) -> Option<Vec<std::ops::Range<usize>>> {
+        TreeSitterFile::try_build(self.source().as_bytes(), self.lang.as_ref()?)
+            .and_then(TreeSitterFile::function_ranges)
+            .ok()
+    }
+
+    /// What the parser of the document's language reads: its content, but for the
+    /// regions of another language, which are blanked out.
+    pub fn source(&self) -> Cow<'_, str> {
+        match self.lang.as_deref() {
+            Some(lang) if !self.regions.is_empty() => Cow::Owned(regions::mask(&self.content, &self.regions, lang)),
+            _ => Cow::Borrowed(&self.content),
+        }
+    }
+
+    /// A template, stored as plaintext with the regions of the languages it embeds.
+    pub fn is_template(&self) -> bool {
+        !self.regions.is_empty() && self.lang.as_deref().map_or(true, |lang| lang == PLAINTEXT)
+    }
+
+    /// The region holding the byte `offset`, if any.
+    pub fn region_at(&self, offset: usize) -> Option<&Region> {
+        self.regions.iter().find(|region| region.range.contains(&offset))
+    }
+
+    /// The document as read in each of its languages: a template once for every
+    /// language of its regions, any other document as it is.
+    pub fn by_language(&self) -> Vec<Cow<'_, ContentDocument>> {
+        if !self.is_template() {
+            return vec![Cow::Borrowed(self)];
+        }
+        let mut langs = self.regions.iter().map(|region| region.lang.as_str()).collect::<Vec<_>>();
+        langs.sort_unstable();
+        langs.dedup();
+        langs.into_iter().map(|lang| Cow::Owned(self.clone().read_as(lang))).collect()
+    }
+
+    /// The document read as `lang` instead of the language it was indexed as, its
+    /// symbols resolved again from a parse in `lang`. A template keeps only its regions
+    /// of `lang`.
+    pub fn read_as(mut self, lang: &str) -> Self {
+        if self.lang.as_deref() == Some(lang) {
+            return self;
+        }
+        self.lang = Some(lang.to_owned());
+        let symbol_locations = TreeSitterFile::try_build(self.source().as_bytes(), lang)
+            .and_then(TreeSitterFile::scope_graph)
+            .map_or(SymbolLocations::Empty, SymbolLocations::TreeSitter);
+        self.symbol_locations = symbol_locations.into();
+        self
+    }
 }
 
 impl std::hash::Hash for ContentDocument {
@@ -0,0 +1,56 @@
+//! The content hashes stored with every document, which tell whether a file changed
+//! since it was indexed.
+//!
+//! Hashes are stored as hex, prefixed with the name of their algorithm, except for
+//! SHA-256 ones, which indexes wrote before the algorithm could be chosen. A stored
+//! document is compared with the algorithm it was hashed with, so that changing it
+//! doesn't reindex every file: documents move to the new one as their files change.
+
+use sha2::{Digest, Sha256};
+
+/// How file contents are hashed, see [`crate::File::with_hash`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// Several times faster than SHA-256 on large files
+    #[default]
+    Blake3,
+    /// What indexes used before BLAKE3
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// The hash of `content`, as stored.
+    pub fn hash(self, content: &[u8]) -> String {
+        match self {
+            Self::Blake3 => format!("blake3:{}", blake3::hash(content).to_hex()),
+            Self::Sha256 => format!("{:x}", Sha256::digest(content)),
+        }
+    }
+
+    /// The algorithm of the stored hash `hash`.
+    pub fn of(hash: &str) -> Self {
+        if hash.starts_with("blake3:") {
+            Self::Blake3
+        } else {
+            Self::Sha256
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_tell_their_algorithm() {
+        for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+            let hash = algorithm.hash(b"fn main() {}\n");
+            assert_eq!(HashAlgorithm::of(&hash), algorithm);
+        }
+        // as stored before the algorithm could be chosen
+        assert_eq!(
+            HashAlgorithm::Sha256.hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
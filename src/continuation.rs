@@ -0,0 +1,148 @@
+//! Continuation tokens for the occurrences of `go_to`, so that a client can page
+//! through the references of a much-used symbol without resolving it again.
+//!
+//! When the occurrences of a token don't all fit in a response, the occurrences are
+//! kept in [`Continuations::global`] and the response carries a continuation token:
+//! the id of the kept occurrences and the offset of the first one left out. The next
+//! page is read from them, as they were on the searcher that resolved the token,
+//! however much the index changed since. A token can be used any number of times, but
+//! only the [`MAX_KEPT`] most recently used lookups are kept.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+
+use crate::error::{ErrorCode, NavError};
+use crate::intelligence::code_navigation::FileSymbols;
+
+/// The number of lookups whose occurrences are kept for their continuation tokens
+pub const MAX_KEPT: usize = 32;
+
+static CONTINUATIONS: Continuations = Continuations::new();
+
+/// The occurrences of recent lookups, by id, least recently used first
+pub struct Continuations {
+    kept: Mutex<VecDeque<(u64, Arc<Vec<FileSymbols>>)>>,
+}
+
+impl Continuations {
+    pub const fn new() -> Self {
+        Self {
+            kept: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The continuations of the python functions.
+    pub fn global() -> &'static Self {
+        &CONTINUATIONS
+    }
+
+    /// Keep `results`, returning the token continuing them from the `offset`th
+    /// occurrence on.
+    pub fn keep(&self, results: Arc<Vec<FileSymbols>>, offset: usize) -> String {
+        let mut kept = self.kept.lock().unwrap_or_else(|e| e.into_inner());
+        let id = match kept.iter().position(|(_, kept)| Arc::ptr_eq(kept, &results)) {
+            Some(position) => {
+                let entry = kept.remove(position).expect("position is in bounds");
+                let id = entry.0;
+                kept.push_back(entry);
+                id
+            }
+            None => {
+                let id = rand::random();
+                kept.push_back((id, results));
+                if kept.len() > MAX_KEPT {
+                    kept.pop_front();
+                }
+                id
+            }
+        };
+        format!("{id:016x}-{offset}")
+    }
+
+    /// The occurrences continued by `token`, and the offset to continue them from.
+    pub fn resume(&self, token: &str) -> Result<(Arc<Vec<FileSymbols>>, usize)> {
+        let invalid = |message: &str| {
+            NavError::new(ErrorCode::InvalidContinuation, format!("{message}: {token:?}"))
+                .suggest("run go_to again for a fresh continuation token")
+        };
+        let (id, offset) = token
+            .split_once('-')
+            .and_then(|(id, offset)| Some((u64::from_str_radix(id, 16).ok()?, offset.parse::<usize>().ok()?)))
+            .ok_or_else(|| invalid("Invalid continuation token"))?;
+
+        let mut kept = self.kept.lock().unwrap_or_else(|e| e.into_inner());
+        let position = kept
+            .iter()
+            .position(|(kept_id, _)| *kept_id == id)
+            .ok_or_else(|| invalid("Unknown or expired continuation token"))?;
+        let entry = kept.remove(position).expect("position is in bounds");
+        let results = Arc::clone(&entry.1);
+        kept.push_back(entry);
+        Ok((results, offset))
+    }
+}
+
+impl Default for Continuations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The occurrences of `results` from the `offset`th on, still grouped by file.
+pub fn occurrences_from(results: &[FileSymbols], mut offset: usize) -> Vec<FileSymbols> {
+    let mut rest = Vec::new();
+    for file_symbols in results {
+        if offset >= file_symbols.data.len() {
+            offset -= file_symbols.data.len();
+            continue;
+        }
+        let data = file_symbols.data[offset..].to_vec();
+        offset = 0;
+        rest.push(FileSymbols { data, ..file_symbols.clone() });
+    }
+    rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intelligence::code_navigation::Occurrence;
+    use crate::text_range::{Point, TextRange};
+
+    fn file_symbols(file: &str, lines: &[usize]) -> FileSymbols {
+        let data = lines
+            .iter()
+            .map(|&line| Occurrence {
+                kind: Default::default(),
+                range: TextRange::new(Point::new(0, line, 0), Point::new(1, line, 1)),
+                snippet: Default::default(),
+                reference_count: None,
+            })
+            .collect();
+        FileSymbols { file: file.to_owned(), data, external: false }
+    }
+
+    #[test]
+    fn resumes_where_the_page_stopped() {
+        let results = Arc::new(vec![file_symbols("a.rs", &[1, 2]), file_symbols("b.rs", &[3, 4, 5])]);
+        let continuations = Continuations::new();
+        let token = continuations.keep(Arc::clone(&results), 3);
+        // the same lookup keeps its id
+        assert_eq!(continuations.keep(Arc::clone(&results), 3), token);
+
+        let (resumed, offset) = continuations.resume(&token).unwrap();
+        assert!(Arc::ptr_eq(&resumed, &results));
+        let rest = occurrences_from(&resumed, offset);
+        let lines = rest
+            .iter()
+            .map(|file_symbols| (file_symbols.file.as_str(), file_symbols.data.iter().map(|o| o.range.start.line).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [("b.rs", vec![4, 5])]);
+
+        let error = continuations.resume("0123456789abcdef-3").unwrap_err();
+        assert_eq!(error.downcast_ref::<NavError>().map(|e| e.code), Some(ErrorCode::InvalidContinuation));
+        assert!(continuations.resume("not a token").is_err());
+    }
+}
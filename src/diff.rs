@@ -0,0 +1,397 @@
+//! Search results and occurrences restricted to the files of a change, grouped by
+//! file and annotated with the hunks of its diff, for reviewing the change.
+//!
+//! Hunks come from `git diff` against a base revision, `HEAD` by default, so they
+//! cover staged and unstaged changes. Files git doesn't track are new: all their lines
+//! are changed.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+
+use crate::fingerprint::git;
+use crate::intelligence::code_navigation::{FileSymbols, OccurrenceKind};
+use crate::query::OutputFormat;
+use crate::search::TermMatch;
+use crate::snippet::Snippet;
+use crate::text_range::LineBase;
+
+/// Lines of the new version of a file that a diff changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hunk {
+    /// 0-indexed first line, or for a deletion the line following the deleted ones
+    pub start: usize,
+    /// The number of lines added or changed, 0 for a deletion
+    pub lines: usize,
+}
+
+impl Hunk {
+    pub fn contains(&self, line: usize) -> bool {
+        (self.start..self.start + self.lines).contains(&line)
+    }
+
+    fn describe(&self) -> String {
+        let base = LineBase::current();
+        match self.lines {
+            0 => format!("deletion before line {}", base.number(self.start)),
+            1 => format!("line {}", base.number(self.start)),
+            lines => format!("lines {}-{}", base.number(self.start), base.number(self.start + lines - 1)),
+        }
+    }
+}
+
+impl Serialize for Hunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Hunk", 2)?;
+        state.serialize_field("start_line", &LineBase::current().number(self.start))?;
+        state.serialize_field("line_count", &self.lines)?;
+        state.end()
+    }
+}
+
+/// A file of the change, with its hunks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedFile {
+    /// The absolute path, as the index stores it
+    pub path: String,
+    /// The path relative to the root of the repository
+    pub relative_path: String,
+    pub hunks: Vec<Hunk>,
+    /// Whether git doesn't track the file, whose lines are then all new
+    pub untracked: bool,
+}
+
+impl ChangedFile {
+    /// Whether the change added or modified the 0-indexed `line`.
+    pub fn is_changed(&self, line: usize) -> bool {
+        self.untracked || self.hunks.iter().any(|hunk| hunk.contains(line))
+    }
+}
+
+/// The `files` of the repository at `root`, e.g. as listed by `git diff --name-only`,
+/// with their hunks against `base`.
+///
+/// Files are relative to `root` or absolute. A file the diff doesn't mention is kept,
+/// without hunks.
+pub fn changed_files(root: &Path, files: &[String], base: Option<&str>) -> Result<Vec<ChangedFile>> {
+    let root = root.canonicalize()?;
+    let prefix = format!("{}/", root.to_string_lossy().replace('\\', "/").trim_end_matches('/'));
+    let relative = files
+        .iter()
+        .map(|file| {
+            let file = file.replace('\\', "/");
+            match file.strip_prefix(&prefix) {
+                Some(relative) => relative.to_owned(),
+                None => file.trim_start_matches("./").to_owned(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let base = base.unwrap_or("HEAD");
+    let mut diff_args = vec!["diff", "--relative", "--unified=0", "--no-color", "--no-ext-diff", "--no-prefix", base, "--"];
+    diff_args.extend(relative.iter().map(String::as_str));
+    let Some(diff) = git(&root, &diff_args)? else {
+        bail!("`git diff {base}` failed in {}: is it a git repository, and `{base}` a revision?", root.display());
+    };
+    let mut hunks = parse_hunks(&diff);
+
+    let mut untracked_args = vec!["ls-files", "--others", "--exclude-standard", "-z", "--"];
+    untracked_args.extend(relative.iter().map(String::as_str));
+    let untracked = git(&root, &untracked_args)?.unwrap_or_default();
+    let untracked = untracked.split('\0').filter(|path| !path.is_empty()).collect::<HashSet<_>>();
+
+    Ok(relative
+        .into_iter()
+        .map(|relative_path| {
+            let absolute = root.join(&relative_path);
+            let absolute = absolute.canonicalize().unwrap_or(absolute);
+            ChangedFile {
+                path: absolute.to_string_lossy().replace('\\', "/"),
+                hunks: hunks.remove(&relative_path).unwrap_or_default(),
+                untracked: untracked.contains(relative_path.as_str()),
+                relative_path,
+            }
+        })
+        .collect())
+}
+
+/// The hunks of every file of a `git diff --unified=0 --no-prefix` patch, by path.
+fn parse_hunks(diff: &str) -> HashMap<String, Vec<Hunk>> {
+    let mut hunks: HashMap<String, Vec<Hunk>> = HashMap::new();
+    let mut file = None;
+
+    for line in diff.lines() {
+        if let Some(path) = line.strip_prefix("+++ ") {
+            // a deleted file has no new version
+            file = (path != "/dev/null").then(|| path.trim_end_matches('\t').to_owned());
+        } else if let (Some(header), Some(file)) = (line.strip_prefix("@@ "), &file) {
+            // `@@ -a,b +c,d @@`, where `c` is 1-indexed and `d` defaults to 1
+            let Some(new) = header.split(' ').find_map(|range| range.strip_prefix('+')) else {
+                continue;
+            };
+            let (start, lines) = new.split_once(',').unwrap_or((new, "1"));
+            let (Ok(start), Ok(lines)) = (start.parse::<usize>(), lines.parse::<usize>()) else {
+                continue;
+            };
+            // a deletion starts after line `c`, others on it
+            let start = if lines == 0 { start } else { start.saturating_sub(1) };
+            hunks.entry(file.clone()).or_default().push(Hunk { start, lines });
+        }
+    }
+    hunks
+}
+
+/// A search hit or an occurrence in a changed file
+#[derive(Debug, Clone)]
+pub struct DiffHit {
+    /// `match` for search hits, or the kind of the occurrence
+    pub kind: &'static str,
+    /// For search hits, the query term that matched
+    pub term: Option<String>,
+    /// 0-indexed
+    pub line: usize,
+    pub column: usize,
+    /// Whether the change added or modified the line of the hit
+    pub changed: bool,
+    pub snippet: Snippet,
+    /// 0-indexed lines of the snippet that the change added or modified
+    pub changed_lines: Vec<usize>,
+}
+
+impl Serialize for DiffHit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let base = LineBase::current();
+        let changed_lines = self.changed_lines.iter().map(|&line| base.number(line)).collect::<Vec<_>>();
+
+        let mut state = serializer.serialize_struct("DiffHit", 7)?;
+        state.serialize_field("kind", &self.kind)?;
+        state.serialize_field("term", &self.term)?;
+        state.serialize_field("line_number", &base.number(self.line))?;
+        state.serialize_field("column", &self.column)?;
+        state.serialize_field("changed", &self.changed)?;
+        state.serialize_field("snippet", &self.snippet)?;
+        state.serialize_field("changed_lines", &changed_lines)?;
+        state.end()
+    }
+}
+
+/// A changed file, with its hunks and the hits found in it
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffFile {
+    pub path: String,
+    pub relative_path: String,
+    pub hunks: Vec<Hunk>,
+    pub untracked: bool,
+    /// In the order they were found
+    pub hits: Vec<DiffHit>,
+}
+
+impl DiffFile {
+    fn new(file: &ChangedFile) -> Self {
+        Self {
+            path: file.path.clone(),
+            relative_path: file.relative_path.clone(),
+            hunks: file.hunks.clone(),
+            untracked: file.untracked,
+            hits: Vec::new(),
+        }
+    }
+}
+
+/// Every changed file of `changed`, in order, with the search `hits` found in it.
+/// Hits in other files are dropped.
+pub fn group_hits(changed: &[ChangedFile], hits: Vec<TermMatch>) -> Vec<DiffFile> {
+    let base = LineBase::current();
+    group(changed, hits.into_iter().map(|hit| {
+        let hit_in = DiffHit {
+            kind: "match",
+            term: Some(hit.term),
+            line: base.index(hit.result.line_number).unwrap_or_default(),
+            column: hit.result.column,
+            changed: false,
+            snippet: hit.result.snippet,
+            changed_lines: Vec::new(),
+        };
+        (hit.result.path, hit_in)
+    }))
+}
+
+/// Every changed file of `changed`, in order, with the occurrences found in it, as
+/// returned by `token_info`. Occurrences in other files are dropped.
+pub fn group_occurrences(changed: &[ChangedFile], results: Vec<FileSymbols>) -> Vec<DiffFile> {
+    let base = LineBase::current();
+    group(changed, results.into_iter().flat_map(|file_symbols| {
+        let FileSymbols { file, data, .. } = file_symbols;
+        data.into_iter().map(move |occurrence| {
+            let hit_in = DiffHit {
+                kind: match occurrence.kind {
+                    OccurrenceKind::Definition => "definition",
+                    OccurrenceKind::Reference => "reference",
+                },
+                term: None,
+                line: base.index(occurrence.range.start.line).unwrap_or_default(),
+                column: occurrence.range.start.column,
+                changed: false,
+                snippet: occurrence.snippet,
+                changed_lines: Vec::new(),
+            };
+            (file.clone(), hit_in)
+        })
+    }))
+}
+
+/// Add every hit of `hits`, by path, to its changed file, annotated against its hunks.
+fn group(changed: &[ChangedFile], hits: impl Iterator<Item = (String, DiffHit)>) -> Vec<DiffFile> {
+    let mut files = changed.iter().map(DiffFile::new).collect::<Vec<_>>();
+    let by_path = changed
+        .iter()
+        .enumerate()
+        .map(|(i, file)| (file.path.as_str(), i))
+        .collect::<HashMap<_, _>>();
+
+    for (path, mut hit) in hits {
+        let Some(&i) = by_path.get(path.as_str()) else {
+            continue;
+        };
+        hit.changed = changed[i].is_changed(hit.line);
+        hit.changed_lines = changed_lines(&changed[i], &hit.snippet);
+        files[i].hits.push(hit);
+    }
+    files
+}
+
+/// The 0-indexed lines of `snippet` the change to `file` added or modified.
+fn changed_lines(file: &ChangedFile, snippet: &Snippet) -> Vec<usize> {
+    let lines = snippet.line_range.start..snippet.line_range.start + snippet.data.split('\n').count();
+    lines.filter(|&line| file.is_changed(line)).collect()
+}
+
+/// The first `n` hits of `files`, keeping every file.
+pub fn first_hits(files: &[DiffFile], mut n: usize) -> Vec<DiffFile> {
+    files
+        .iter()
+        .map(|file| {
+            let hits = file.hits.iter().take(n).cloned().collect::<Vec<_>>();
+            n -= hits.len();
+            DiffFile { hits, ..file.clone() }
+        })
+        .collect()
+}
+
+/// Render `files` in `format`. Text and markdown mark the changed lines of every
+/// snippet with `+`, like a diff.
+pub fn format_diff_context(files: &[DiffFile], format: OutputFormat) -> Result<String> {
+    if format == OutputFormat::Json {
+        return Ok(serde_json::to_string(&serde_json::json!({ "files": files }))?);
+    }
+
+    let mut formatted = String::new();
+    for file in files {
+        let hunks = if file.untracked {
+            "new file".to_owned()
+        } else if file.hunks.is_empty() {
+            "no changes".to_owned()
+        } else {
+            file.hunks.iter().map(Hunk::describe).collect::<Vec<_>>().join(", ")
+        };
+
+        match format {
+            OutputFormat::Markdown => formatted.push_str(&format!("### `{}` ({hunks})\n\n", file.relative_path)),
+            _ => formatted.push_str(&format!("File: {} ({hunks})\n", file.relative_path)),
+        }
+        if file.hits.is_empty() {
+            formatted.push_str("No results\n\n");
+        }
+
+        for hit in &file.hits {
+            let base = LineBase::current();
+            let label = match &hit.term {
+                Some(term) => format!("{} `{term}`", hit.kind),
+                None => hit.kind.to_owned(),
+            };
+            let changed = if hit.changed { ", changed" } else { "" };
+            let lines = marked_lines(hit);
+
+            match format {
+                OutputFormat::Markdown => formatted.push_str(&format!(
+                    "`{}:{}:{}` {label}{changed}\n```diff\n{lines}\n```\n\n",
+                    file.relative_path,
+                    base.number(hit.line),
+                    hit.column
+                )),
+                _ => formatted.push_str(&format!(
+                    "Line: {}, Column: {}, {label}{changed}\n{lines}\n\n",
+                    base.number(hit.line),
+                    hit.column
+                )),
+            }
+        }
+    }
+    Ok(formatted)
+}
+
+/// The numbered lines of the snippet of `hit`, the changed ones marked with `+`.
+fn marked_lines(hit: &DiffHit) -> String {
+    let base = LineBase::current();
+    hit.snippet
+        .lines()
+        .map(|(number, line)| {
+            let changed = base.index(number).is_some_and(|line| hit.changed_lines.contains(&line));
+            format!("{}{number:>5} | {line}", if changed { '+' } else { ' ' })
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hunks_of_a_patch() {
+        let diff = "\
+diff --git src/app.py src/app.py
+index 1111111..2222222 100644
+--- src/app.py
++++ src/app.py
+@@ -3 +3,2 @@ def main():
+-    run()
++    setup()
++    run()
+@@ -10,2 +11,0 @@ def main():
+-    a
+-    b
+@@ -20 +19 @@
+-x
++y
+diff --git gone.py gone.py
+deleted file mode 100644
+--- gone.py
++++ /dev/null
+@@ -1 +0,0 @@
+-print()
+";
+        let hunks = parse_hunks(diff);
+        assert_eq!(
+            hunks["src/app.py"],
+            [
+                Hunk { start: 2, lines: 2 },
+                Hunk { start: 11, lines: 0 },
+                Hunk { start: 18, lines: 1 },
+            ]
+        );
+        assert!(!hunks.contains_key("gone.py"));
+
+        let file = ChangedFile {
+            path: "/repo/src/app.py".to_owned(),
+            relative_path: "src/app.py".to_owned(),
+            hunks: hunks["src/app.py"].clone(),
+            untracked: false,
+        };
+        let changed = (0..20).filter(|&line| file.is_changed(line)).collect::<Vec<_>>();
+        assert_eq!(changed, [2, 3, 18]);
+    }
+}
@@ -0,0 +1,353 @@
+//! Edits of the indexed files planned from the index, for callers to review and apply:
+//! the indexing analog of `sed`, without touching the disk.
+//!
+//! An [`Edit`] replaces the text of a range of a file, and keeps the text it replaces
+//! so that it can be checked against the file before it is applied: [`apply`] writes
+//! nothing unless every edit still applies.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ErrorCode, NavError};
+use crate::intelligence::{TokenClass, TreeSitterFile};
+use crate::text_range::{LineBase, TextRange};
+
+/// A replacement of the text of a range of a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Edit {
+    /// As stored in the index, absolute
+    pub path: String,
+    /// Numbered from the [`LineBase::current`], with byte columns and offsets
+    pub range: TextRange,
+    /// The text of the range, before the edit
+    pub old: String,
+    pub new: String,
+}
+
+/// What [`crate::Searcher::plan_replace`] replaces, and where
+#[derive(Debug, Clone)]
+pub struct Replace {
+    pub pattern: String,
+    /// Expanded with the groups of the match, `$1` or `${name}`, for regex patterns
+    pub replacement: String,
+    /// Whether `pattern` is a regex rather than literal text
+    pub regex: bool,
+    /// `true` unless set
+    pub case_sensitive: bool,
+    /// Only in the files of this language
+    pub lang: Option<String>,
+    /// Only in the files matching this glob, see [`crate::search::path_matcher`]
+    pub path_glob: Option<String>,
+}
+
+impl Replace {
+    /// Replace the text `pattern` with `replacement`.
+    pub fn literal(pattern: &str, replacement: &str) -> Self {
+        Self {
+            pattern: pattern.to_owned(),
+            replacement: replacement.to_owned(),
+            regex: false,
+            case_sensitive: true,
+            lang: None,
+            path_glob: None,
+        }
+    }
+
+    /// Replace the matches of the regex `pattern` with `replacement`, expanded.
+    pub fn regex(pattern: &str, replacement: &str) -> Self {
+        Self {
+            regex: true,
+            ..Self::literal(pattern, replacement)
+        }
+    }
+
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    pub fn lang(mut self, lang: Option<&str>) -> Self {
+        self.lang = lang.map(str::to_owned);
+        self
+    }
+
+    pub fn path_glob(mut self, path_glob: Option<&str>) -> Self {
+        self.path_glob = path_glob.map(str::to_owned);
+        self
+    }
+
+    /// The regex matching what is replaced.
+    pub(crate) fn compiled(&self) -> Result<Regex> {
+        if self.pattern.is_empty() {
+            anyhow::bail!("nothing to replace, the pattern is empty");
+        }
+        let pattern = if self.regex { self.pattern.clone() } else { regex::escape(&self.pattern) };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()
+            .map_err(|e| anyhow::anyhow!("invalid regex `{}`: {e}", self.pattern))
+    }
+
+    /// The edits replacing every match of `regex`, compiled from this replacement, in
+    /// `content`, the content of the file stored under `path` whose lines end at
+    /// `line_end_indices`. Empty matches are left alone.
+    pub(crate) fn edits_in(&self, regex: &Regex, path: &str, content: &str, line_end_indices: &[u32]) -> Vec<Edit> {
+        let base = LineBase::current();
+        regex
+            .captures_iter(content)
+            .filter_map(|captures| {
+                let matched = captures.get(0)?;
+                if matched.is_empty() {
+                    return None;
+                }
+                let new = if self.regex {
+                    let mut new = String::new();
+                    captures.expand(&self.replacement, &mut new);
+                    new
+                } else {
+                    self.replacement.clone()
+                };
+                Some(Edit {
+                    path: path.to_owned(),
+                    range: base.numbered(TextRange::from_byte_range(matched.range(), line_end_indices)),
+                    old: matched.as_str().to_owned(),
+                    new,
+                })
+            })
+            .filter(|edit| edit.old != edit.new)
+            .collect()
+    }
+}
+
+/// Apply `edits` to the files on disk, returning the path and new content of every
+/// file they change, sorted by path. Every file is read and checked first: nothing is
+/// written if an edit no longer applies, with a `stale_edit` error, or overlaps
+/// another one. Each file is then written aside and renamed over the old one, so
+/// that readers never see half of it.
+pub fn apply(edits: &[Edit]) -> Result<Vec<(String, String)>> {
+    let mut changed = Vec::new();
+    for (path, edits) in by_path(edits) {
+        let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+        let (content, _) = edited(path, &content, &edits)?;
+        changed.push((path.to_owned(), content));
+    }
+    for (path, content) in &changed {
+        write_atomically(Path::new(path), content).with_context(|| format!("failed to write {path}"))?;
+    }
+    Ok(changed)
+}
+
+/// `edits` by path, sorted by position.
+pub(crate) fn by_path(edits: &[Edit]) -> BTreeMap<&str, Vec<&Edit>> {
+    let mut by_path = BTreeMap::<&str, Vec<&Edit>>::new();
+    for edit in edits {
+        by_path.entry(&edit.path).or_default().push(edit);
+    }
+    for edits in by_path.values_mut() {
+        edits.sort_by_key(|edit| (edit.range.start.byte, edit.range.end.byte));
+    }
+    by_path
+}
+
+/// `content` with `edits`, sorted, applied, and the byte ranges of their new text in it.
+fn edited(path: &str, content: &str, edits: &[&Edit]) -> Result<(String, Vec<Range<usize>>)> {
+    let mut edited = String::with_capacity(content.len());
+    let mut replaced = Vec::with_capacity(edits.len());
+    let mut end = 0;
+    for edit in edits {
+        let range = edit.range.start.byte..edit.range.end.byte;
+        if range.start < end {
+            let message = format!("the edits of {path} overlap at line {}", edit.range.start.line);
+            return Err(NavError::new(ErrorCode::InvalidRange, message).at(edit.range.start.line).into());
+        }
+        if content.get(range.clone()) != Some(edit.old.as_str()) {
+            let message = format!("{path} changed since the edit of `{}` at line {} was planned", edit.old, edit.range.start.line);
+            return Err(NavError::new(ErrorCode::StaleEdit, message).suggest("plan the edits again").into());
+        }
+        edited.push_str(&content[end..range.start]);
+        replaced.push(edited.len()..edited.len() + edit.new.len());
+        edited.push_str(&edit.new);
+        end = range.end;
+    }
+    edited.push_str(&content[end..]);
+    Ok((edited, replaced))
+}
+
+/// What is wrong with a rename
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    /// A reference to the old name the edits leave
+    StrayReference,
+    /// An edit of something else than a reference to the old name, such as a word of
+    /// a comment or a longer name containing it
+    UnintendedRename,
+    /// A reference to the new name that was already there, which the renamed
+    /// references now collide with
+    Collision,
+}
+
+impl ViolationKind {
+    /// Its name, as serialized
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::StrayReference => "stray_reference",
+            Self::UnintendedRename => "unintended_rename",
+            Self::Collision => "collision",
+        }
+    }
+}
+
+/// A problem with the edits of a rename, see [`crate::Searcher::verify_rename`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenameViolation {
+    pub path: String,
+    pub kind: ViolationKind,
+    /// Numbered from the [`LineBase::current`], in the file as edited, except for an
+    /// unintended rename, which is the range of its edit
+    pub range: TextRange,
+    /// The text of the range
+    pub text: String,
+}
+
+/// The violations of renaming `old_name` to `new_name` with `edits`, sorted, in
+/// `content`, the content of `path` in `lang`: the identifiers of both names left
+/// or found in the edited content, and the edits of anything but those identifiers.
+pub(crate) fn rename_violations(
+    path: &str,
+    lang: &str,
+    content: &str,
+    edits: &[&Edit],
+    old_name: &str,
+    new_name: &str,
+) -> Result<Vec<RenameViolation>> {
+    let (after, replaced) = edited(path, content, edits)?;
+    let identifiers = |content: &str| -> Result<Vec<TextRange>> {
+        let file = TreeSitterFile::try_build(content.as_bytes(), lang)
+            .map_err(|e| anyhow::anyhow!("failed to parse {path}: {e:?}"))?;
+        Ok(file
+            .tokens()
+            .into_iter()
+            .filter(|token| token.class == TokenClass::Identifier)
+            .map(|token| token.range)
+            .collect())
+    };
+    let text = |content: &str, range: &TextRange| content[range.start.byte..range.end.byte].to_owned();
+    let base = LineBase::current();
+    let mut violations = Vec::new();
+
+    let renamed = identifiers(content)?
+        .into_iter()
+        .filter(|range| text(content, range) == old_name)
+        .map(|range| (range.start.byte, range.end.byte))
+        .collect::<HashSet<_>>();
+    for edit in edits.iter().filter(|edit| !renamed.contains(&(edit.range.start.byte, edit.range.end.byte))) {
+        violations.push(RenameViolation {
+            path: path.to_owned(),
+            kind: ViolationKind::UnintendedRename,
+            range: edit.range,
+            text: edit.old.clone(),
+        });
+    }
+
+    for range in identifiers(&after)? {
+        let kind = match text(&after, &range) {
+            name if name == old_name => ViolationKind::StrayReference,
+            name if name == new_name && !replaced.contains(&(range.start.byte..range.end.byte)) => ViolationKind::Collision,
+            _ => continue,
+        };
+        violations.push(RenameViolation {
+            path: path.to_owned(),
+            kind,
+            range: base.numbered(range),
+            text: text(&after, &range),
+        });
+    }
+    violations.sort_by_key(|violation| violation.range.start.byte);
+    Ok(violations)
+}
+
+/// Replace the file at `path` with `content`, keeping its permissions.
+fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let dir = path.parent().context("no parent directory")?;
+    let name = path.file_name().context("no file name")?.to_string_lossy();
+    let partial = dir.join(format!(".{name}.{}.edit", std::process::id()));
+    let written = fs::write(&partial, content)
+        .and_then(|()| fs::set_permissions(&partial, fs::metadata(path)?.permissions()))
+        .and_then(|()| fs::rename(&partial, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    Ok(written?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_range::line_end_indices;
+
+    #[test]
+    fn plans_literal_and_regex_replacements() -> Result<()> {
+        let content = "let old_name = 1;\nprint(old_name, OLD_NAME)\n";
+        let ends = line_end_indices(content);
+        let edits_of = |replace: Replace| -> Result<Vec<Edit>> { Ok(replace.edits_in(&replace.compiled()?, "/repo/a.py", content, &ends)) };
+
+        let edits = edits_of(Replace::literal("old_name", "new_name"))?;
+        assert_eq!(edits.len(), 2);
+        assert_eq!((edits[1].range.start.line, edits[1].range.start.column), (2, 6));
+        assert_eq!((edits[1].old.as_str(), edits[1].new.as_str()), ("old_name", "new_name"));
+
+        let edits = edits_of(Replace::regex(r"(?i)old_(\w+)", "new_$1"))?;
+        assert_eq!(edits.iter().map(|edit| edit.new.as_str()).collect::<Vec<_>>(), ["new_name", "new_name", "new_NAME"]);
+
+        assert!(edits_of(Replace::regex("x*", "y"))?.is_empty());
+        assert!(edits_of(Replace::regex("(", "y")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn finds_the_violations_of_a_rename() -> Result<()> {
+        let content = "fn old() {}\n// old one\nfn new() {}\nfn main() { old(); }\n";
+        let replace = Replace::regex(r"\bold\b", "new");
+        let edits = replace.edits_in(&replace.compiled()?, "/repo/a.rs", content, &line_end_indices(content));
+        assert_eq!(edits.len(), 3);
+
+        let kinds_of = |edits: &[Edit]| -> Result<Vec<(ViolationKind, usize)>> {
+            let edits = edits.iter().collect::<Vec<_>>();
+            let violations = rename_violations("/repo/a.rs", "rust", content, &edits, "old", "new")?;
+            Ok(violations.iter().map(|violation| (violation.kind, violation.range.start.line)).collect())
+        };
+        // the comment, then the function already named `new`
+        assert_eq!(kinds_of(&edits)?, [(ViolationKind::UnintendedRename, 2), (ViolationKind::Collision, 3)]);
+        let without_the_call = [edits[0].clone(), edits[1].clone()];
+        assert!(kinds_of(&without_the_call)?.contains(&(ViolationKind::StrayReference, 4)));
+        Ok(())
+    }
+
+    #[test]
+    fn applies_edits_unless_stale() -> Result<()> {
+        let dir = tempdir::TempDir::new("edits")?;
+        let path = dir.path().join("a.py");
+        let content = "let old_name = 1;\nprint(old_name)\n";
+        fs::write(&path, content)?;
+        let path = path.to_string_lossy().into_owned();
+        let replace = Replace::literal("old_name", "new_name");
+        let edits = replace.edits_in(&replace.compiled()?, &path, content, &line_end_indices(content));
+
+        assert_eq!(apply(&edits)?, [(path.clone(), "let new_name = 1;\nprint(new_name)\n".to_owned())]);
+        assert_eq!(fs::read_to_string(&path)?, "let new_name = 1;\nprint(new_name)\n");
+
+        // already applied
+        let error = apply(&edits).unwrap_err();
+        assert_eq!(error.downcast_ref::<NavError>().map(|e| e.code), Some(ErrorCode::StaleEdit));
+        assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+}
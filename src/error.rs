@@ -0,0 +1,108 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// What a [`NavError`] is about, as a stable identifier callers can match on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The query text couldn't be parsed
+    InvalidQuery,
+    /// A line or column is outside of the file
+    InvalidRange,
+    /// The path isn't in the index
+    DocumentNotFound,
+    /// The index was written in a format this version can't use, see [`crate::format`]
+    IncompatibleIndex,
+    /// The text an edit replaces is no longer in the file, which changed since the edit
+    /// was planned
+    StaleEdit,
+    /// The continuation token is malformed, or its lookup is no longer kept, see
+    /// [`crate::continuation`]
+    InvalidContinuation,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::InvalidQuery => "invalid_query",
+            Self::InvalidRange => "invalid_range",
+            Self::DocumentNotFound => "document_not_found",
+            Self::IncompatibleIndex => "incompatible_index",
+            Self::StaleEdit => "stale_edit",
+            Self::InvalidContinuation => "invalid_continuation",
+        }
+    }
+}
+
+/// An error caused by the caller's input, with enough detail to retry with a
+/// corrected one.
+///
+/// It travels inside `anyhow::Error` like any other error; the python module
+/// downcasts it to raise a `CodeNavError` with the same fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NavError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// For queries, the char offset of the offending character. For ranges, the
+    /// offending line or column, as given by the caller.
+    pub position: Option<usize>,
+    /// What to do instead
+    pub suggestion: Option<String>,
+}
+
+impl NavError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            position: None,
+            suggestion: None,
+        }
+    }
+
+    pub fn at(mut self, position: usize) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    pub fn suggest(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+}
+
+impl fmt::Display for NavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(position) = self.position {
+            write!(f, " (at {position})")?;
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ". {suggestion}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for NavError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downcast_through_anyhow() {
+        let error: anyhow::Error = NavError::new(ErrorCode::InvalidRange, "Invalid line number")
+            .at(12)
+            .suggest("The file has 10 lines")
+            .into();
+
+        assert_eq!(error.to_string(), "Invalid line number (at 12). The file has 10 lines");
+
+        let error = error.context("Error retrieving token info");
+        let nav_error = error.downcast_ref::<NavError>().unwrap();
+        assert_eq!(nav_error.code, ErrorCode::InvalidRange);
+        assert_eq!(nav_error.position, Some(12));
+    }
+}
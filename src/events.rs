@@ -0,0 +1,150 @@
+//! What happens to an index kept open by an [`crate::IndexHandle`], as a stream of
+//! events, so that callers can react when the repository changes underneath them,
+//! e.g. by invalidating their own caches.
+//!
+//! Every refresh of the handle publishes an event per file it indexed or removed, and
+//! per error it ran into. A subscriber receives the events published after it
+//! subscribed, in order. Subscribers that are gone are dropped on the next publish.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::indexes::IndexReport;
+
+/// Something that happened to the index
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IndexEvent {
+    /// The file was indexed, for the first time, again with a new content, or under a
+    /// new path
+    Indexed { path: String },
+    /// The documents of the file were deleted or moved to another path
+    Removed { path: String },
+    /// A file couldn't be read, or the refresh failed, without a path
+    Error { path: Option<String>, message: String },
+}
+
+impl IndexEvent {
+    /// The name of its kind, as serialized
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Indexed { .. } => "indexed",
+            Self::Removed { .. } => "removed",
+            Self::Error { .. } => "error",
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::Indexed { path } | Self::Removed { path } => Some(path),
+            Self::Error { path, .. } => path.as_deref(),
+        }
+    }
+
+    /// The events of an indexing run, removals first, so that a rename ends with its
+    /// new path. Evicted files are removed too.
+    pub fn of_report(report: &IndexReport) -> Vec<Self> {
+        let removed = report
+            .deleted
+            .iter()
+            .chain(report.renamed.iter().map(|rename| &rename.from))
+            .chain(report.evicted.iter().map(|evicted| &evicted.path));
+        let indexed = report.added.iter().chain(&report.updated).chain(report.renamed.iter().map(|rename| &rename.to));
+        removed
+            .map(|path| Self::Removed { path: path.clone() })
+            .chain(indexed.map(|path| Self::Indexed { path: path.clone() }))
+            .chain(report.errors.iter().map(|error| Self::Error {
+                path: Some(error.path.clone()),
+                message: error.error.clone(),
+            }))
+            .collect()
+    }
+}
+
+/// The subscribers to the events of an index
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<IndexEvent>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Events {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner()).push(sender);
+        Events { receiver }
+    }
+
+    pub fn publish(&self, events: impl IntoIterator<Item = IndexEvent>) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        for event in events {
+            subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+}
+
+/// The index of [`Events`] was closed, no more events will come
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+/// The events published to one subscriber. Iterating blocks until the next event, and
+/// ends once the index is closed.
+pub struct Events {
+    receiver: Receiver<IndexEvent>,
+}
+
+impl Events {
+    /// The next event, waiting at most `timeout` for it to come. `Err` once the index
+    /// is closed.
+    pub fn next_timeout(&self, timeout: Duration) -> Result<Option<IndexEvent>, Closed> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => Ok(Some(event)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(Closed),
+        }
+    }
+
+    /// The next event if one is already there.
+    pub fn try_next(&self) -> Option<IndexEvent> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Iterator for Events {
+    type Item = IndexEvent;
+
+    fn next(&mut self) -> Option<IndexEvent> {
+        self.receiver.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexes::Rename;
+
+    #[test]
+    fn subscribers_receive_the_events_published_after_they_subscribed() {
+        let bus = EventBus::default();
+        bus.publish([IndexEvent::Indexed { path: "/repo/early.rs".to_owned() }]);
+        let events = bus.subscribe();
+
+        let report = IndexReport {
+            updated: vec!["/repo/a.rs".to_owned()],
+            renamed: vec![Rename { from: "/repo/b.rs".to_owned(), to: "/repo/c.rs".to_owned() }],
+            ..IndexReport::default()
+        };
+        bus.publish(IndexEvent::of_report(&report));
+        let received = std::iter::from_fn(|| events.try_next()).map(|event| (event.kind(), event.path().unwrap().to_owned()));
+        assert_eq!(
+            received.collect::<Vec<_>>(),
+            [("removed", "/repo/b.rs".to_owned()), ("indexed", "/repo/a.rs".to_owned()), ("indexed", "/repo/c.rs".to_owned())]
+        );
+
+        assert_eq!(events.next_timeout(Duration::ZERO), Ok(None));
+        drop(bus);
+        assert_eq!(events.next_timeout(Duration::ZERO), Err(Closed));
+    }
+}
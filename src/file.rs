@@ -1,29 +1,76 @@
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use ignore::gitignore::{Gitignore, GitignoreBuilder};
-use ignore::WalkBuilder;
-use tantivy::{schema::Schema, IndexWriter, doc, Term};
+use tantivy::{collector::TopDocs, directory::Directory, query::TermQuery, schema::{IndexRecordOption, Schema}, IndexWriter, doc, Term};
 use anyhow::Result;
 use async_trait::async_trait;
 use tokio::fs;
 use tokio::task::spawn_blocking;
 use futures::future::BoxFuture;
 use std::collections::{HashSet, HashMap};
-use crate::indexes::Indexable;
-use crate::intelligence::{TreeSitterFile, TSLanguage};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::content_hash::HashAlgorithm;
+use crate::indexes::{EntryError, IndexReport, Indexable, Rename, SkippedFile};
+use crate::intelligence::symbol_docs::symbol_docs;
+use crate::intelligence::{TreeSitterFile, TSLanguage, PLAINTEXT};
 use crate::symbol::SymbolLocations;
+use crate::sync_handle::SyncHandle;
+use crate::file_heuristics::FileFlags;
+use crate::file_stats::{FileStat, FileStats};
+use crate::budget;
+use crate::owners::{self, CodeOwners};
+use crate::package::{PackageInfo, PACKAGES_FILE};
+use crate::path_prefix;
+use crate::redaction::Redactor;
+use crate::regions;
+use crate::repository::{self, Gitignores, Repository};
+use crate::roots;
 use crate::schema::build_schema;
-use sha2::{Sha256, Digest};
-
+use crate::text_range;
+
+/// Decides which files an index holds, see [`File::with_path_filter`]
+pub type PathFilter = dyn Fn(&Path) -> bool + Send + Sync;
+
+/// Directories never walked, wherever they are, unless a `.gitignore` re-includes
+/// them: version control internals, virtual environments, dependencies and build
+/// outputs. See [`File::with_excludes`].
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    ".git",
+    ".hg",
+    ".svn",
+    ".venv",
+    "venv",
+    "__pycache__",
+    "node_modules",
+    "target",
+];
+
+/// Files larger than this, in bytes, aren't indexed, see [`File::with_max_file_size`]
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Clone)]
 pub struct File {
     pub schema: Schema,
     pub path_field: tantivy::schema::Field,
     pub content_field: tantivy::schema::Field,
     pub symbol_locations_field: tantivy::schema::Field,
     pub symbols_field: tantivy::schema::Field,
-    pub line_end_indices_field: tantivy::schema::Field,
+    pub line_ends_field: tantivy::schema::Field,
     pub lang_field: tantivy::schema::Field,
     pub hash_field: tantivy::schema::Field,
-    content_insensitive_field: tantivy::schema::Field
+    pub symbol_docs_field: tantivy::schema::Field,
+    pub regions_field: tantivy::schema::Field,
+    pub path_prefixes_field: tantivy::schema::Field,
+    pub flags_field: tantivy::schema::Field,
+    pub(crate) redactor: Option<Arc<dyn Redactor>>,
+    pub(crate) path_filter: Option<Arc<PathFilter>>,
+    pub(crate) excludes: Vec<String>,
+    pub(crate) hash: HashAlgorithm,
+    pub(crate) max_file_size: Option<u64>,
+    pub(crate) strict: bool,
+    pub(crate) dry_run: bool,
+    pub(crate) throttle: Option<Duration>,
+    pub(crate) progress: Option<SyncHandle>,
 }
 
 impl File {
@@ -33,10 +80,13 @@ impl File {
         let content_field = schema.get_field("content").unwrap();
         let symbol_locations_field = schema.get_field("symbol_locations").unwrap();
         let symbols_field = schema.get_field("symbols").unwrap();
-        let line_end_indices_field = schema.get_field("line_end_indices").unwrap();
+        let line_ends_field = schema.get_field("line_ends").unwrap();
         let lang_field = schema.get_field("lang").unwrap();
         let hash_field = schema.get_field("hash").unwrap();
-        let content_insensitive_field = schema.get_field("content_insensitive").unwrap();
+        let symbol_docs_field = schema.get_field("symbol_docs").unwrap();
+        let regions_field = schema.get_field("regions").unwrap();
+        let path_prefixes_field = schema.get_field("path_prefixes").unwrap();
+        let flags_field = schema.get_field("flags").unwrap();
 
         Self {
             schema,
@@ -44,229 +94,837 @@ impl File {
             content_field,
             symbol_locations_field,
             symbols_field,
-            line_end_indices_field,
+            line_ends_field,
             lang_field,
             hash_field,
-            content_insensitive_field
+            symbol_docs_field,
+            regions_field,
+            path_prefixes_field,
+            flags_field,
+            redactor: None,
+            path_filter: None,
+            excludes: DEFAULT_EXCLUDES.iter().map(|name| name.to_string()).collect(),
+            hash: HashAlgorithm::default(),
+            max_file_size: Some(DEFAULT_MAX_FILE_SIZE),
+            strict: false,
+            dry_run: false,
+            throttle: None,
+            progress: None,
         }
     }
 
-    fn detect_language(path: &Path) -> &'static str {
-        let extension = path.extension().and_then(std::ffi::OsStr::to_str).unwrap_or("");
-        TSLanguage::from_extension(extension).unwrap_or("plaintext")
+    /// Run every file through `redactor` before it is indexed.
+    pub fn with_redactor(mut self, redactor: Arc<dyn Redactor>) -> Self {
+        self.redactor = Some(redactor);
+        self
     }
-}
 
-#[async_trait]
-impl Indexable for File {
-    async fn index_repository(&self, root_path: &Path, writer: &IndexWriter) -> Result<()> {
-        let existing_docs = load_existing_docs(writer, &self.hash_field, &self.path_field)?;
-        let gitignore_manager = GitignoreManager::new(root_path.to_path_buf()).await?;
+    /// Only index the files for which `filter` returns `true`. Package manifests and
+    /// READMEs are still read, so that every index knows the packages of the repository.
+    pub fn with_path_filter(mut self, filter: Arc<PathFilter>) -> Self {
+        self.path_filter = Some(filter);
+        self
+    }
 
-        traverse_and_index_files(
-            root_path, writer, self.path_field, self.content_field,
-            self.symbol_locations_field, self.symbols_field, self.line_end_indices_field,
-            self.lang_field, self.hash_field, self.content_insensitive_field, 
-            &existing_docs, &gitignore_manager).await
+    /// Skip the directories named one of `names` rather than the [`DEFAULT_EXCLUDES`],
+    /// none if `names` is empty. A negated `.gitignore` pattern such as `!target`
+    /// re-includes an excluded directory; the `.gitignore` files inside excluded
+    /// directories are not read.
+    pub fn with_excludes<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.excludes = names.into_iter().map(Into::into).collect();
+        self
     }
 
-    fn schema(&self) -> Schema {
-        self.schema.clone()
+    /// Hash new and changed files with `algorithm` rather than BLAKE3. Indexed files
+    /// keep the hash they were stored with until they change.
+    pub fn with_hash(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash = algorithm;
+        self
     }
-}
 
-fn load_existing_docs(writer: &IndexWriter, hash_field: &tantivy::schema::Field, path_field: &tantivy::schema::Field) -> Result<HashMap<String, String>> {
-    let searcher = writer.index().reader()?.searcher();
-    let mut existing_docs = HashMap::new();
+    /// Leave out the files larger than `max_size` bytes rather than
+    /// [`DEFAULT_MAX_FILE_SIZE`], none if `None`. Skipped files are listed in the
+    /// [`IndexReport`], and their documents deleted if they were indexed before.
+    pub fn with_max_file_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_file_size = max_size;
+        self
+    }
 
-    for segment_reader in searcher.segment_readers() {
-        let store_reader = segment_reader.get_store_reader(0)?;
-        let alive_bitset = segment_reader.alive_bitset();
+    /// Fail the run on the first file or directory that can't be read, rather than
+    /// listing it in the [`IndexReport`] and indexing the others.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
 
-        for doc in store_reader.iter(alive_bitset) {
-            let doc = doc?;
-            let path = doc.get_first(*path_field).unwrap().as_text().unwrap().to_string();
-            let hash = doc.get_first(*hash_field).unwrap().as_text().unwrap().to_string();
-            existing_docs.insert(path, hash);
-        }
+    /// Walk, read and hash the files, and report what indexing them would add, update
+    /// and delete, without writing to the index or next to it, see
+    /// [`crate::Indexes::index`].
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
     }
 
-    Ok(existing_docs)
-}
+    /// Pause for `pause` after every file read, so that indexing in the background
+    /// leaves the disk and the CPU to the other processes. Not at all if `None`, the
+    /// default. See [`crate::Priority::Background`].
+    pub fn with_throttle(mut self, pause: Option<Duration>) -> Self {
+        self.throttle = pause;
+        self
+    }
 
-struct GitignoreManager {
-    root_path: PathBuf,
-    gitignores: Vec<(PathBuf, Gitignore)>,
-}
+    /// Count the files walked and parsed into `progress`, see [`SyncHandle`].
+    pub fn with_progress(mut self, progress: Option<SyncHandle>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// The document of a file of `lang` stored under `path`, with `content` hashed as
+    /// `hash`: its scope graph, the names it defines, its line ends, the directories it
+    /// is in, whether it is minified or generated and the regions of a template along
+    /// with it.
+    pub(crate) fn document(&self, path: String, content: String, lang: &str, hash: String) -> tantivy::Document {
+        if let Some(progress) = &self.progress {
+            progress.parsing();
+        }
+        let symbol_locations = match TreeSitterFile::try_build(content.as_bytes(), lang).and_then(TreeSitterFile::scope_graph) {
+            Ok(graph) => SymbolLocations::TreeSitter(graph),
+            Err(_) => SymbolLocations::Empty,
+        };
 
-impl GitignoreManager {
-    async fn new(root_path: PathBuf) -> Result<Self> {
-        let mut manager = GitignoreManager {
-            root_path,
-            gitignores: Vec::new(),
+        // Flatten the list of symbols into a string with just text
+        let symbols = symbol_locations.defined_names(&content).join("\n");
+        let symbol_docs = match symbol_locations.scope_graph() {
+            Some(graph) => symbol_docs(lang, &content, graph).into_iter().map(|doc| doc.doc).collect::<Vec<_>>().join("\n\n"),
+            None => String::new(),
         };
-        manager.load_gitignores().await?;
-        Ok(manager)
+
+        let line_ends = text_range::encode_line_ends(&text_range::line_end_indices(&content));
+        let regions = regions::regions(Path::new(&path), &content);
+        let flags = FileFlags::classify(&path, &content);
+
+        let mut doc = tantivy::doc!(
+            self.path_field => path.clone(),
+            self.content_field => content,
+            self.symbol_locations_field => bincode::serialize(&symbol_locations).unwrap(),
+            self.symbols_field => symbols,
+            self.line_ends_field => line_ends,
+            self.lang_field => lang.to_string(),
+            self.hash_field => hash,
+            self.symbol_docs_field => symbol_docs,
+            self.regions_field => bincode::serialize(&regions).unwrap(),
+        );
+        self.add_path_prefixes(&mut doc, &path);
+        self.add_flags(&mut doc, flags);
+        doc
     }
 
-    async fn load_gitignores(&mut self) -> Result<()> {
-        let walk = WalkBuilder::new(&self.root_path)
-            .hidden(false)
-            .git_ignore(false)
-            .build();
+    /// A fingerprint of the settings the stored contents depend on, see [`FileStats`].
+    fn settings(&self) -> String {
+        let redactor = self.redactor.as_deref().map(Redactor::fingerprint);
+        format!("hash {:?} max size {:?} redactor {:?}", self.hash, self.max_file_size, redactor)
+    }
 
-        for entry in walk {
-            let entry = entry?;
-            let path = entry.path();
-            if path.file_name() == Some(".gitignore".as_ref()) {
-                let gitignore_dir = path.parent().unwrap().to_path_buf();
-                let mut builder = GitignoreBuilder::new(&gitignore_dir);
-                builder.add(path);
-                match builder.build() {
-                    Ok(gitignore) => {
-                        self.gitignores.push((gitignore_dir, gitignore));
-                    },
-                    Err(err) => {
-                        eprintln!("Error building gitignore for {:?}: {}", path, err);
-                        // Optionally, you can choose to return the error or continue
-                        // return Err(err.into());
-                    }
+    /// Index the directories of `path` in `doc`, which aren't stored and so aren't
+    /// carried over by the documents of moved files.
+    pub(crate) fn add_path_prefixes(&self, doc: &mut tantivy::Document, path: &str) {
+        for prefix in path_prefix::prefixes(path) {
+            doc.add_text(self.path_prefixes_field, prefix);
+        }
+    }
+
+    /// Index `flags` in `doc`, so that searches leave minified and generated files out
+    /// in the query rather than after it. Not stored either, as a move can change them.
+    pub(crate) fn add_flags(&self, doc: &mut tantivy::Document, flags: FileFlags) {
+        for term in flags.terms() {
+            doc.add_text(self.flags_field, term);
+        }
+    }
+
+    /// Index `documents`, pairs of a path and the content of a file that needn't exist
+    /// on disk, stored under that path. They are indexed whatever their language, and
+    /// only the redactor and the size cap apply.
+    pub(crate) fn index_documents(&self, documents: Vec<(String, String)>, writer: &IndexWriter) -> Result<IndexReport> {
+        if documents.iter().any(|(path, _)| path.is_empty()) {
+            anyhow::bail!("every document needs a path");
+        }
+        let existing_docs = ExistingDocs::load(writer, self.hash_field, self.path_field, self.lang_field)?;
+        let report = Mutex::new(IndexReport::default());
+        self.index_contents(documents, writer, &existing_docs, None, &report)?;
+        Ok(report.into_inner().unwrap())
+    }
+
+    /// Index the files at `paths`, under `root_path`, on their own, as a traversal of the
+    /// root would find them but without walking it, e.g. the files a
+    /// [`crate::watcher::Watcher`] saw change. The documents of the paths that are gone,
+    /// or that a traversal would now leave out, such as newly ignored files or files no
+    /// longer UTF-8, are deleted, along with those of the files under them if they were
+    /// directories. The paths that are directories, such as one moved into the root,
+    /// are walked as by a traversal.
+    pub(crate) async fn index_paths(&self, root_path: &Path, paths: &[PathBuf], writer: &IndexWriter) -> Result<IndexReport> {
+        let repository = Repository::from_path(root_path)?;
+        let existing_docs = ExistingDocs::load(writer, self.hash_field, self.path_field, self.lang_field)?;
+        let gitignores = repository.gitignores_of(paths, &self.excludes);
+        let report = Mutex::new(IndexReport::default());
+
+        let mut documents = Vec::new();
+        let mut dirs = Vec::new();
+        for path in paths {
+            let root_path = &repository.disk_path;
+            if !path.starts_with(root_path) || path == root_path {
+                continue;
+            }
+            let path_str = path.to_string_lossy().replace('\\', "/");
+            if self.is_left_out(path, root_path, &gitignores) {
+                self.delete_under(&path_str, writer, &existing_docs, &report);
+                continue;
+            }
+            let metadata = match fs::symlink_metadata(path).await {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    self.delete_under(&path_str, writer, &existing_docs, &report);
+                    continue;
+                }
+                Err(e) => {
+                    entry_failed(self.strict, &report, path, e.into())?;
+                    continue;
                 }
+            };
+
+            if metadata.is_dir() {
+                // only walked if a traversal would walk it
+                if !(is_excluded(path, &self.excludes) && !gitignores.is_whitelisted(path)) && !is_index_dir(path) {
+                    dirs.push(path.clone());
+                }
+                continue;
+            }
+
+            let lang = File::detect_language(path);
+            let in_index = self.path_filter.as_deref().map_or(true, |filter| filter(path)) && (lang != PLAINTEXT || regions::is_template(path));
+            if !metadata.is_file() || !in_index || existing_docs.is_evicted(&path_str) {
+                continue;
+            }
+            if self.max_file_size.is_some_and(|max_size| metadata.len() > max_size) {
+                skip_too_large(self, writer, &existing_docs, &report, path_str, metadata.len());
+                continue;
             }
+            match fs::read(path).await.map(String::from_utf8) {
+                Ok(Ok(content)) => documents.push((path_str, content)),
+                // files that aren't UTF-8 are left out, as by a traversal
+                Ok(Err(_)) => self.delete_under(&path_str, writer, &existing_docs, &report),
+                Err(e) => entry_failed(self.strict, &report, path, e.into())?,
+            }
+        }
+
+        // A directory moved or copied in comes without an event for each of its files,
+        // and the files of those walked are indexed by the walk
+        dirs.sort();
+        dirs.dedup_by(|dir, outer| dir.starts_with(outer));
+        documents.retain(|(path, _)| !dirs.iter().any(|dir| Path::new(path).starts_with(dir)));
+        self.index_contents(documents, writer, &existing_docs, None, &report)?;
+        let (file_stats, packages) = (FileStats::default(), Mutex::new(HashMap::new()));
+        for dir in &dirs {
+            traverse_and_index_files(self, dir, writer, &existing_docs, &file_stats, &gitignores, &[], &packages, &report).await?;
         }
+        Ok(report.into_inner().unwrap())
+    }
+
+    /// Whether a traversal of `root_path` would leave out `path`: ignored, or in an
+    /// excluded directory or the directory of an index.
+    fn is_left_out(&self, path: &Path, root_path: &Path, gitignores: &Gitignores) -> bool {
+        path.ancestors().take_while(|entry| *entry != root_path).any(|entry| {
+            gitignores.is_ignored(entry)
+                || (entry != path
+                    && ((is_excluded(entry, &self.excludes) && !gitignores.is_whitelisted(entry)) || is_index_dir(entry)))
+        })
+    }
 
-        // Sort gitignores from most specific (deepest) to least specific (root)
-        self.gitignores.sort_by(|a, b| b.0.components().count().cmp(&a.0.components().count()));
+    /// Delete the documents of the file at `path`, or of the files under it if it was a
+    /// directory.
+    fn delete_under(&self, path: &str, writer: &IndexWriter, existing_docs: &ExistingDocs, report: &Mutex<IndexReport>) {
+        let dir = format!("{path}/");
+        let mut deleted = existing_docs
+            .paths()
+            .filter(|stored| *stored == path || stored.starts_with(&dir))
+            .cloned()
+            .collect::<Vec<_>>();
+        deleted.sort();
+        for stored in &deleted {
+            if !self.dry_run {
+                writer.delete_term(Term::from_field_text(self.path_field, stored));
+            }
+            existing_docs.claim(stored);
+        }
+        report.lock().unwrap().deleted.extend(deleted);
+    }
 
+    /// Delete the documents of the files of `repositories` that their traversal didn't
+    /// find, because they were deleted or are now left out. Documents stored under the
+    /// root of a [`crate::source::Archive`], or under a directory that couldn't be read,
+    /// are kept.
+    fn delete_stale(&self, repositories: &[Repository], writer: &IndexWriter, existing_docs: &ExistingDocs, report: &Mutex<IndexReport>) -> Result<()> {
+        let mut report = report.lock().unwrap();
+        let mut kept = roots::repositories(writer.index())?
+            .into_iter()
+            .filter(|archive| !archive.disk_path.is_dir())
+            .map(|archive| archive.root().to_owned())
+            .collect::<Vec<_>>();
+        kept.extend(report.errors.iter().map(|error| error.path.clone()));
+
+        let stale = existing_docs.stale(repositories, &kept);
+        if !self.dry_run {
+            for path in &stale {
+                writer.delete_term(Term::from_field_text(self.path_field, path));
+            }
+        }
+        report.deleted.extend(stale);
         Ok(())
     }
 
-    fn is_ignored(&self, path: &Path) -> bool {
-        for (dir, gitignore) in &self.gitignores {
-            if path.starts_with(dir) {
-                let relative_path = path.strip_prefix(dir).unwrap();
-                match gitignore.matched(relative_path, false) {
-                    ignore::Match::Ignore(_) => return true,
-                    ignore::Match::Whitelist(_) => return false,
-                    ignore::Match::None => continue,
+    /// Index the `documents` whose content changed since they were stored, see
+    /// [`Self::index_documents`]. With `gone`, telling which stored paths no longer
+    /// exist, a document with the content of one of those is a rename of it, see
+    /// [`Self::store`]. The paths of `documents` are seen by `existing_docs`, their
+    /// documents being indexed or deleted here.
+    pub(crate) fn index_contents(
+        &self,
+        documents: Vec<(String, String)>,
+        writer: &IndexWriter,
+        existing_docs: &ExistingDocs,
+        gone: Option<&dyn Fn(&str) -> bool>,
+        report: &Mutex<IndexReport>,
+    ) -> Result<()> {
+        // the last content given for a path wins
+        let mut given = HashSet::new();
+        let mut documents = documents
+            .into_iter()
+            .rev()
+            .map(|(path, content)| (path.replace('\\', "/"), content))
+            .filter(|(path, _)| given.insert(path.clone()))
+            .collect::<Vec<_>>();
+        documents.reverse();
+
+        for (path, content) in documents {
+            existing_docs.see(&path);
+            let size = content.len() as u64;
+            if self.max_file_size.is_some_and(|max_size| size > max_size) {
+                skip_too_large(self, writer, existing_docs, report, path, size);
+                continue;
+            }
+            let content = match self.redactor.as_deref() {
+                Some(redactor) => match redactor.redact(Path::new(&path), content) {
+                    Some(content) => content,
+                    // the document of a file the redactor now drops goes too
+                    None => {
+                        self.delete_under(&path, writer, existing_docs, report);
+                        continue;
+                    }
+                },
+                None => content,
+            };
+
+            let lang = File::detect_language(Path::new(&path));
+            self.store(path, content, lang, writer, existing_docs, gone, report)?;
+        }
+        Ok(())
+    }
+
+    /// Store the document of the file at `path`, whose `content` is accepted for the
+    /// index, unless the stored one has the same content. Without a stored document, a
+    /// file with the content of one whose path is `gone` is a rename of it. Returns the
+    /// hash of the content.
+    #[allow(clippy::too_many_arguments)]
+    fn store(
+        &self,
+        path: String,
+        content: String,
+        lang: &'static str,
+        writer: &IndexWriter,
+        existing_docs: &ExistingDocs,
+        gone: Option<&dyn Fn(&str) -> bool>,
+        report: &Mutex<IndexReport>,
+    ) -> Result<String> {
+        let path_field = self.path_field;
+
+        // Compared with the algorithm the stored document was hashed with, and
+        // rehashed with the configured one if it changed
+        let stored_hash = existing_docs.hash(&path);
+        let stored_algorithm = stored_hash.map_or(self.hash, HashAlgorithm::of);
+        let mut hash = stored_algorithm.hash(content.as_bytes());
+        if stored_hash != Some(hash.as_str()) && stored_algorithm != self.hash {
+            hash = self.hash.hash(content.as_bytes());
+        }
+
+        let updated = stored_hash.is_some();
+        match stored_hash {
+            // File has not changed, skip reindexing
+            Some(existing_hash) if existing_hash == hash => return Ok(hash),
+            Some(_) if self.dry_run => {}
+            Some(_) => {
+                // Delete the old document
+                writer.delete_term(Term::from_field_text(path_field, &path));
+            }
+            None => {
+                // A moved file keeps everything derived from its content, only its path
+                // changes
+                let renamed = match gone {
+                    Some(gone) => existing_docs.renamed_to(&path, &hash, lang, gone)?,
+                    None => None,
+                };
+                if let Some((from, moved)) = renamed {
+                    if !self.dry_run {
+                        let mut doc = tantivy::Document::new();
+                        doc.add_text(path_field, &path);
+                        self.add_path_prefixes(&mut doc, &path);
+                        let moved_content = moved.get_first(self.content_field).and_then(|value| value.as_text()).unwrap_or_default();
+                        self.add_flags(&mut doc, FileFlags::classify(&path, moved_content));
+                        for field_value in moved.field_values().iter().filter(|value| value.field() != path_field) {
+                            doc.add_field_value(field_value.field(), field_value.value().clone());
+                        }
+                        writer.delete_term(Term::from_field_text(path_field, &from));
+                        writer.add_document(doc)?;
+                    }
+                    report.lock().unwrap().renamed.push(Rename { from, to: path });
+                    return Ok(hash);
                 }
             }
         }
-        false
+
+        if !self.dry_run {
+            println!("{}", path);
+            writer.add_document(self.document(path.clone(), content, lang, hash.clone()))?;
+        }
+        let mut report = report.lock().unwrap();
+        let indexed = if updated { &mut report.updated } else { &mut report.added };
+        indexed.push(path);
+        Ok(hash)
+    }
+
+    pub(crate) fn detect_language(path: &Path) -> &'static str {
+        TSLanguage::of_path(path)
     }
 }
 
+#[async_trait]
+impl Indexable for File {
+    async fn index_repository(&self, root_path: &Path, writer: &IndexWriter) -> Result<IndexReport> {
+        self.index_roots(&[root_path], writer).await
+    }
+
+    /// Every file is indexed once, as part of the innermost root holding it, and only the
+    /// `.gitignore` files of that root apply: a root nested in another one is indexed
+    /// even if the outer root ignores it.
+    async fn index_roots(&self, roots: &[&Path], writer: &IndexWriter) -> Result<IndexReport> {
+        let existing_docs = ExistingDocs::load(writer, self.hash_field, self.path_field, self.lang_field)?;
+        let file_stats = FileStats::load(writer.index().directory(), &self.settings());
+        let repositories = distinct_repositories(roots)?;
+        let packages = Mutex::new(HashMap::new());
+        let report = Mutex::new(IndexReport::default());
+
+        for repository in &repositories {
+            let root = &repository.disk_path;
+            let nested_roots = repositories
+                .iter()
+                .map(|other| &other.disk_path)
+                .filter(|other| *other != root && other.starts_with(root))
+                .cloned()
+                .collect::<Vec<_>>();
+            let gitignores = repository.gitignores(&self.excludes);
+
+            traverse_and_index_files(
+                self, root, writer, &existing_docs, &file_stats, &gitignores, &nested_roots, &packages, &report,
+            ).await?;
+        }
+        self.delete_stale(&repositories, writer, &existing_docs, &report)?;
+
+        if self.dry_run {
+            return Ok(report.into_inner().unwrap());
+        }
+        let roots = repositories.iter().map(|repository| &repository.disk_path).collect::<Vec<_>>();
+        file_stats.save(writer.index().directory(), &roots)?;
+
+        // Package metadata lives next to the index rather than in it
+        let mut packages = packages.into_inner().unwrap().into_values().collect::<Vec<PackageInfo>>();
+        packages.sort_by(|a, b| a.dir.cmp(&b.dir));
+        writer
+            .index()
+            .directory()
+            .atomic_write(Path::new(PACKAGES_FILE), &serde_json::to_vec(&packages)?)?;
+
+        let codeowners = roots.iter().map(|root| CodeOwners::read(root)).collect::<Result<Vec<_>>>()?;
+        let roots = repositories.iter().map(|repository| repository.root().to_owned()).collect::<Vec<_>>();
+        owners::record(writer.index(), &roots, codeowners.into_iter().flatten().collect())?;
+
+        Ok(report.into_inner().unwrap())
+    }
+
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+}
+
+/// The repositories at `roots`, without duplicates, outer roots first.
+fn distinct_repositories(roots: &[&Path]) -> Result<Vec<Repository>> {
+    let mut distinct = roots
+        .iter()
+        .map(|root| Repository::from_path(root))
+        .collect::<Result<Vec<_>>>()?;
+    distinct.sort();
+    distinct.dedup();
+    Ok(distinct)
+}
+
+/// The documents in the index before indexing starts
+pub(crate) struct ExistingDocs {
+    searcher: tantivy::Searcher,
+    path_field: tantivy::schema::Field,
+    /// Content hash and language of every stored path
+    docs: HashMap<String, (String, String)>,
+    /// Paths of the stored documents by content hash
+    by_hash: HashMap<String, Vec<String>>,
+    /// Paths already claimed by a renamed file, or deleted
+    moved: Mutex<HashSet<String>>,
+    /// Paths of the files a traversal found, see [`Self::stale`]
+    seen: Mutex<HashSet<String>>,
+    /// Paths evicted to keep the index under its size budget, see [`crate::budget`]
+    evicted: HashSet<String>,
+}
+
+impl ExistingDocs {
+    pub(crate) fn load(writer: &IndexWriter, hash_field: tantivy::schema::Field, path_field: tantivy::schema::Field, lang_field: tantivy::schema::Field) -> Result<Self> {
+        let searcher = writer.index().reader()?.searcher();
+        let mut docs = HashMap::new();
+        let mut by_hash: HashMap<String, Vec<String>> = HashMap::new();
+
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader.get_store_reader(0)?;
+            let alive_bitset = segment_reader.alive_bitset();
+
+            for doc in store_reader.iter(alive_bitset) {
+                let doc = doc?;
+                let text = |field| doc.get_first(field).and_then(|f| f.as_text()).unwrap_or_default().to_string();
+                let (path, hash) = (text(path_field), text(hash_field));
+                by_hash.entry(hash.clone()).or_default().push(path.clone());
+                docs.insert(path, (hash, text(lang_field)));
+            }
+        }
+
+        Ok(Self {
+            searcher,
+            path_field,
+            docs,
+            by_hash,
+            moved: Mutex::new(HashSet::new()),
+            seen: Mutex::new(HashSet::new()),
+            evicted: budget::evicted_paths(writer.index())?,
+        })
+    }
+
+    pub(crate) fn hash(&self, path: &str) -> Option<&str> {
+        self.docs.get(path).map(|(hash, _)| hash.as_str())
+    }
+
+    /// The stored paths, in no particular order
+    pub(crate) fn paths(&self) -> impl Iterator<Item = &String> {
+        self.docs.keys()
+    }
+
+    /// Whether the file at `path` was evicted, and is left out until the budget changes.
+    pub(crate) fn is_evicted(&self, path: &str) -> bool {
+        self.evicted.contains(path)
+    }
+
+    /// Record that the document of `path` is deleted, so that no file is a rename of it.
+    fn claim(&self, path: &str) {
+        self.moved.lock().unwrap().insert(path.to_owned());
+    }
+
+    /// Record that a traversal found the file at `path`, which is then not stale.
+    fn see(&self, path: &str) {
+        self.seen.lock().unwrap().insert(path.to_owned());
+    }
+
+    /// The stored paths starting with `prefix` that weren't seen nor renamed, sorted.
+    pub(crate) fn unseen_under(&self, prefix: &str) -> Vec<String> {
+        let (seen, moved) = (self.seen.lock().unwrap(), self.moved.lock().unwrap());
+        let mut unseen = self
+            .docs
+            .keys()
+            .filter(|path| path.starts_with(prefix) && !seen.contains(*path) && !moved.contains(*path))
+            .cloned()
+            .collect::<Vec<_>>();
+        unseen.sort();
+        unseen
+    }
+
+    /// The stored paths of the files of `repositories` that a traversal didn't find,
+    /// sorted, but for those under one of the `kept` paths and those renamed already.
+    fn stale(&self, repositories: &[Repository], kept: &[String]) -> Vec<String> {
+        let (seen, moved) = (self.seen.lock().unwrap(), self.moved.lock().unwrap());
+        let mut stale = self
+            .docs
+            .keys()
+            .filter(|path| repositories.iter().any(|repository| repository.contains(path)))
+            .filter(|path| !seen.contains(*path) && !moved.contains(*path))
+            .filter(|path| !kept.iter().any(|dir| repository::is_under(path, dir)))
+            .cloned()
+            .collect::<Vec<_>>();
+        stale.sort();
+        stale
+    }
+
+    /// The stored document of a file of `lang` with the content `hash` whose path is
+    /// `gone`, which `path` is then a rename of. Each document is claimed only once.
+    fn renamed_to(&self, path: &str, hash: &str, lang: &str, gone: &dyn Fn(&str) -> bool) -> Result<Option<(String, tantivy::Document)>> {
+        let Some(candidates) = self.by_hash.get(hash) else {
+            return Ok(None);
+        };
+        let mut moved = self.moved.lock().unwrap();
+        let Some(from) = candidates.iter().find(|from| {
+            *from != path
+                && !moved.contains(*from)
+                && TSLanguage::normalize(&self.docs[*from].1) == lang
+                && gone(from)
+        }) else {
+            return Ok(None);
+        };
+
+        let query = TermQuery::new(Term::from_field_text(self.path_field, from), IndexRecordOption::Basic);
+        let Some((_, address)) = self.searcher.search(&query, &TopDocs::with_limit(1))?.into_iter().next() else {
+            return Ok(None);
+        };
+        moved.insert(from.clone());
+        Ok(Some((from.clone(), self.searcher.doc(address)?)))
+    }
+}
+
+/// Whether `path` is the directory of a tantivy index, such as the one being written
+/// when it is kept inside the repository: that one holds the writer's lock, and may
+/// not list its managed files yet.
+pub(crate) fn is_index_dir(path: &Path) -> bool {
+    path.join("meta.json").is_file()
+        && (path.join(".managed.json").is_file() || path.join(".tantivy-writer.lock").is_file())
+}
+
+/// Whether `path` is a directory named one of `excludes`.
+pub(crate) fn is_excluded(path: &Path, excludes: &[String]) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| excludes.iter().any(|exclude| exclude == name))
+        && path.is_dir()
+}
+
 fn traverse_and_index_files<'a>(
+    file: &'a File,
     path: &'a Path,
     writer: &'a IndexWriter,
-    path_field: tantivy::schema::Field,
-    content_field: tantivy::schema::Field,
-    symbol_locations_field: tantivy::schema::Field,
-    symbols_field: tantivy::schema::Field,
-    line_end_indices_field: tantivy::schema::Field,
-    lang_field: tantivy::schema::Field,
-    hash_field: tantivy::schema::Field,
-    content_insensitive_field: tantivy::schema::Field,
-    existing_docs: &'a HashMap<String, String>,
-    gitignore_manager: &'a GitignoreManager,
+    existing_docs: &'a ExistingDocs,
+    file_stats: &'a FileStats,
+    gitignores: &'a Gitignores,
+    // indexed on their own
+    nested_roots: &'a [PathBuf],
+    packages: &'a Mutex<HashMap<String, PackageInfo>>,
+    report: &'a Mutex<IndexReport>,
 ) -> BoxFuture<'a, Result<()>> {
     Box::pin(async move {
-        let mut entries = fs::read_dir(path).await?;
-        while let Some(entry) = entries.next_entry().await? {
+        let (strict, max_file_size) = (file.strict, file.max_file_size);
+        let mut entries = match fs::read_dir(path).await {
+            Ok(entries) => entries,
+            Err(e) => return entry_failed(strict, report, path, e.into()),
+        };
+        loop {
+            let entry = match entries.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(e) => return entry_failed(strict, report, path, e.into()),
+            };
             let path = entry.path();
     
-            if gitignore_manager.is_ignored(&path) {
+            if gitignores.is_ignored(&path) || nested_roots.contains(&path) {
                 continue;
             }
+
+            // Targets of links inside the repository are indexed on their own, and links
+            // to directories can form cycles
+            match entry.file_type().await {
+                Ok(file_type) if file_type.is_symlink() => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    entry_failed(strict, report, &path, e.into())?;
+                    continue;
+                }
+            }
     
-            if path.is_dir() {                
+            if path.is_dir() {
+                if is_excluded(&path, &file.excludes) && !gitignores.is_whitelisted(&path) {
+                    continue;
+                }
+                // indexing the index would grow it with every run
+                if is_index_dir(&path) {
+                    eprintln!("Warning: not indexing {}, it holds a search index", path.display());
+                    continue;
+                }
                 traverse_and_index_files(
-                    &path, writer, path_field, content_field, symbol_locations_field,
-                    symbols_field, line_end_indices_field, lang_field, hash_field, content_insensitive_field, 
-                    existing_docs, gitignore_manager).await?;
+                    file, &path, writer, existing_docs, file_stats, gitignores, nested_roots, packages, report,
+                ).await?;
             } else if path.is_file() {
+                let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+                let is_package = PackageInfo::is_package_file(file_name);
+                let lang_str = File::detect_language(&path);
+                let in_index = file.path_filter.as_deref().map_or(true, |filter| filter(&path)) && (lang_str != PLAINTEXT || regions::is_template(&path));
+                if !in_index && !is_package {
+                    continue;
+                }
+
+                let absolute_path = match path.canonicalize() {
+                    Ok(absolute_path) => absolute_path,
+                    Err(e) => {
+                        entry_failed(strict, report, &path, e.into())?;
+                        continue;
+                    }
+                };
+                let absolute_path_str = absolute_path.to_string_lossy().replace("\\", "/");
+                existing_docs.see(&absolute_path_str);
+                if let Some(progress) = &file.progress {
+                    progress.walked();
+                }
+                if existing_docs.is_evicted(&absolute_path_str) {
+                    continue;
+                }
+
+                let metadata = fs::metadata(&path).await.ok();
+                let size = metadata.as_ref().map_or(0, |metadata| metadata.len());
+                if max_file_size.is_some_and(|max_size| size > max_size) {
+                    skip_too_large(file, writer, existing_docs, report, absolute_path_str, size);
+                    continue;
+                }
+
+                // Package files are read on every run, to list the packages
+                let stat = metadata.as_ref().and_then(FileStat::of);
+                if let Some(stat) = stat.filter(|_| !is_package) {
+                    if let Some(hash) = file_stats.unchanged(&absolute_path_str, stat, existing_docs.hash(&absolute_path_str)) {
+                        file_stats.see(&absolute_path_str, stat, hash);
+                        continue;
+                    }
+                }
+
                 let path_clone = path.clone();
-                let content = spawn_blocking(move || std::fs::read(&path_clone)).await??;
+                let content = match spawn_blocking(move || read_capped(&path_clone, max_file_size)).await? {
+                    Ok(Some(content)) => content,
+                    Ok(None) => {
+                        // grew past the cap since its size was read
+                        let size = size.max(max_file_size.unwrap_or_default() + 1);
+                        skip_too_large(file, writer, existing_docs, report, absolute_path_str, size);
+                        continue;
+                    }
+                    Err(e) => {
+                        entry_failed(strict, report, &path, e.into())?;
+                        continue;
+                    }
+                };
 
+                if let Some(pause) = file.throttle {
+                    tokio::time::sleep(pause).await;
+                }
+
+                // Files that aren't valid UTF-8, or that the redactor drops, aren't
+                // indexed, and lose the document they had
                 let content_str = match String::from_utf8(content) {
                     Ok(content_str) => content_str,
-                    Err(_) => continue, // Skip if the content is not valid UTF-8
+                    Err(_) => {
+                        delete_stored(file, writer, existing_docs, report, absolute_path_str);
+                        continue;
+                    }
                 };
 
-                // Compute the hash of the content
-                let mut hasher = Sha256::new();
-                hasher.update(&content_str);
-                let hash = format!("{:x}", hasher.finalize());
-                
-                let absolute_path = path.canonicalize()?;
-                let absolute_path_str = absolute_path.to_string_lossy().replace("\\", "/");
-
-                let path_str = absolute_path_str.clone();
-                    if let Some(existing_hash) = existing_docs.get(&path_str) {
-                        if existing_hash == &hash {
-                            // File has not changed, skip reindexing
+                // Strip secrets before anything is hashed or stored
+                let content_str = match file.redactor.as_deref() {
+                    Some(redactor) => match redactor.redact(&path, content_str) {
+                        Some(content_str) => content_str,
+                        None => {
+                            delete_stored(file, writer, existing_docs, report, absolute_path_str);
                             continue;
-                        } else {
-                            // Delete the old document
-                            writer.delete_term(Term::from_field_text(path_field, &path_str));
                         }
-                    }
+                    },
+                    None => content_str,
+                };
 
-                let lang_str = File::detect_language(&path);
+                if is_package {
+                    let dir = absolute_path
+                        .parent()
+                        .map(|dir| dir.to_string_lossy().replace("\\", "/"))
+                        .unwrap_or_default();
+                    packages
+                        .lock()
+                        .unwrap()
+                        .entry(dir.clone())
+                        .or_insert_with(|| PackageInfo { dir, ..Default::default() })
+                        .update(file_name, &content_str);
+                }
 
-                if lang_str == "plaintext" {
+                if !in_index {
                     continue;
                 }
 
-                let symbol_locations: SymbolLocations = {
-                    let scope_graph = TreeSitterFile::try_build(content_str.as_bytes(), lang_str)
-                        .and_then(TreeSitterFile::scope_graph);
-
-                    match scope_graph {
-                        Ok(graph) => SymbolLocations::TreeSitter(graph),
-                        Err(_) => SymbolLocations::Empty,
-                    }
-                };
-
-                // Flatten the list of symbols into a string with just text
-                let symbols = symbol_locations
-                    .list()
-                    .iter()
-                    .map(|sym| content_str[sym.range.start.byte..sym.range.end.byte].to_owned())
-                    .collect::<HashSet<_>>()
-                    .into_iter()
-                    .collect::<Vec<_>>()
-                    .join("\n");
-
-                // Collect line end indices as bytes
-                let mut line_end_indices = content_str
-                    .match_indices('\n')
-                    .flat_map(|(i, _)| u32::to_le_bytes(i as u32))
-                    .collect::<Vec<_>>();
-
-                // Add the byte index of the last character to the line_end_indices vector
-                let last_char_byte_index = content_str.chars().map(|c| c.len_utf8()).sum::<usize>();
-                line_end_indices.extend_from_slice(&u32::to_le_bytes(last_char_byte_index as u32));
-
-                // Convert content to lower case for case-insensitive search
-                let content_insensitive = content_str.to_lowercase();
-
-                println!("{}", absolute_path_str);
-
-                let doc = tantivy::doc!(
-                    path_field => path_str,
-                    content_field => content_str,
-                    content_insensitive_field => content_insensitive,  // Add case-insensitive content
-                    symbol_locations_field => bincode::serialize(&symbol_locations).unwrap(),
-                    symbols_field => symbols,
-                    line_end_indices_field => line_end_indices,
-                    lang_field => lang_str.to_string(),
-                    hash_field => hash,
-                );
-
-                writer.add_document(doc)?;
+                // files moved within the filesystem are gone from their stored path
+                let gone = |from: &str| !Path::new(from).exists();
+                let hash = file.store(absolute_path_str.clone(), content_str, lang_str, writer, existing_docs, Some(&gone), report)?;
+                if let Some(stat) = stat {
+                    file_stats.see(&absolute_path_str, stat, &hash);
+                }
             }
         }
         Ok(())
     })
 }
+
+/// The content of the file at `path`, `None` if it holds more than `max_size` bytes.
+/// Reading stops past the cap, so that a file that grew since its size was read isn't
+/// loaded whole.
+fn read_capped(path: &Path, max_size: Option<u64>) -> std::io::Result<Option<Vec<u8>>> {
+    let file = std::fs::File::open(path)?;
+    let mut content = Vec::new();
+    match max_size {
+        Some(max_size) => {
+            file.take(max_size + 1).read_to_end(&mut content)?;
+            Ok((content.len() as u64 <= max_size).then_some(content))
+        }
+        None => {
+            (&file).read_to_end(&mut content)?;
+            Ok(Some(content))
+        }
+    }
+}
+
+/// Report the file at `path` as too large to index, deleting its document if it was
+/// indexed while smaller.
+fn skip_too_large(file: &File, writer: &IndexWriter, existing_docs: &ExistingDocs, report: &Mutex<IndexReport>, path: String, size: u64) {
+    delete_stored(file, writer, existing_docs, report, path.clone());
+    eprintln!("Warning: not indexing {path}, its {size} bytes are over the size cap");
+    report.lock().unwrap().skipped.push(SkippedFile { path, size });
+}
+
+/// Delete the document of the file at `path`, found by a traversal but no longer
+/// indexed, if it has one.
+fn delete_stored(file: &File, writer: &IndexWriter, existing_docs: &ExistingDocs, report: &Mutex<IndexReport>, path: String) {
+    if existing_docs.hash(&path).is_some() {
+        if !file.dry_run {
+            writer.delete_term(Term::from_field_text(file.path_field, &path));
+        }
+        report.lock().unwrap().deleted.push(path);
+    }
+}
+
+/// Report that `path` couldn't be read because of `error` and move on, or fail with it
+/// in `strict` mode.
+fn entry_failed(strict: bool, report: &Mutex<IndexReport>, path: &Path, error: anyhow::Error) -> Result<()> {
+    if strict {
+        return Err(error.context(format!("failed to read {}", path.display())));
+    }
+    eprintln!("Warning: not indexing {}: {error:#}", path.display());
+    report.lock().unwrap().errors.push(EntryError {
+        path: path.to_string_lossy().replace('\\', "/"),
+        error: format!("{error:#}"),
+    });
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+/// Markers in the first lines of a file that announce it as machine generated. A bare
+/// "do not edit" isn't one, hand-written files say so too.
+const GENERATED_MARKERS: &[&str] = &[
+    "@generated",
+    "code generated",
+    "auto-generated",
+    "autogenerated",
+    "automatically generated",
+];
+
+/// Path fragments of well-known generated files. Build output such as `dist/` is left
+/// to the gitignores, it holds hand-written sources in some repositories.
+const GENERATED_PATHS: &[&str] = &[
+    ".pb.go",
+    "_pb2.py",
+    ".pb.cc",
+    ".pb.h",
+    ".generated.",
+    ".g.cs",
+    ".designer.cs",
+];
+
+/// Files with an average line length above this are considered minified
+const MINIFIED_AVERAGE_LINE_LENGTH: usize = 300;
+
+/// Files with a line longer than this are considered minified
+const MINIFIED_MAX_LINE_LENGTH: usize = 5_000;
+
+/// Properties of a file that make its contents low-value in search results
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileFlags {
+    pub is_minified: bool,
+    pub is_generated: bool,
+}
+
+impl FileFlags {
+    /// Classify a file from its path and contents.
+    pub fn classify(path: &str, content: &str) -> Self {
+        Self {
+            is_minified: is_minified(path, content),
+            is_generated: is_generated(path, content),
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.is_minified || self.is_generated
+    }
+
+    /// The terms of the `flags` field indexed for them, see [`Self::TERMS`].
+    pub fn terms(&self) -> impl Iterator<Item = &'static str> {
+        [self.is_minified, self.is_generated]
+            .into_iter()
+            .zip(Self::TERMS)
+            .filter_map(|(flagged, term)| flagged.then_some(term))
+    }
+
+    /// The terms of the `flags` field, `minified` and `generated`
+    pub const TERMS: [&'static str; 2] = ["minified", "generated"];
+}
+
+fn is_minified(path: &str, content: &str) -> bool {
+    if path.contains(".min.") {
+        return true;
+    }
+
+    let line_count = crate::text_range::line_count(content).max(1);
+    let longest_line = content.lines().map(str::len).max().unwrap_or_default();
+
+    content.len() / line_count > MINIFIED_AVERAGE_LINE_LENGTH
+        || longest_line > MINIFIED_MAX_LINE_LENGTH
+}
+
+fn is_generated(path: &str, content: &str) -> bool {
+    if GENERATED_PATHS.iter().any(|fragment| path.contains(fragment)) {
+        return true;
+    }
+
+    content
+        .lines()
+        .take(5)
+        .map(str::to_lowercase)
+        .any(|line| GENERATED_MARKERS.iter().any(|marker| line.contains(marker)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_source() {
+        let flags = FileFlags::classify("/repo/src/main.rs", "fn main() {\n    println!(\"hi\");\n}\n");
+        assert_eq!(flags, FileFlags::default());
+    }
+
+    #[test]
+    fn minified() {
+        assert!(FileFlags::classify("/repo/app.min.js", "a").is_minified);
+
+        let bundle = "var a=1;".repeat(100);
+        assert!(FileFlags::classify("/repo/app.js", &bundle).is_minified);
+    }
+
+    #[test]
+    fn generated() {
+        assert!(FileFlags::classify("/repo/api.pb.go", "package api\n").is_generated);
+
+        let src = "// Code generated by protoc-gen-go. DO NOT EDIT.\npackage api\n";
+        assert!(FileFlags::classify("/repo/api.go", src).is_generated);
+
+        // hand-written, though they look like build output
+        assert!(!FileFlags::classify("/repo/src/locale.rs", "// Do not edit without updating the docs\n").is_generated);
+        assert!(!FileFlags::classify("/repo/dist/bundle.js", "export const a = 1;\n").any());
+        assert_eq!(FileFlags::classify("/repo/api.min.pb.go", "package api\n").terms().collect::<Vec<_>>(), FileFlags::TERMS);
+    }
+}
@@ -0,0 +1,142 @@
+//! The modification time and size of every indexed file, persisted next to the index
+//! so that files unchanged since the last run are neither read nor hashed again.
+//!
+//! A file is skipped when its metadata matches the recorded one and the index still
+//! holds the content hash recorded with it. Anything else, including a missing or
+//! unreadable stats file, falls back to reading and hashing the file. So does every
+//! file when the settings its stored content depends on, such as the redactor, changed
+//! since the stats were recorded.
+
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tantivy::directory::Directory;
+
+pub const FILE_STATS_FILE: &str = "file_stats";
+
+/// Metadata written within this long before it is read isn't trusted: the file could
+/// change again within the resolution of its modification time, keeping it the same.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// The metadata of a file that tells it changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStat {
+    /// Nanoseconds since the Unix epoch
+    pub mtime_ns: u128,
+    pub size: u64,
+}
+
+impl FileStat {
+    /// The stat of a file with `metadata`, `None` if its modification time is unknown
+    /// or too recent to be trusted.
+    pub fn of(metadata: &Metadata) -> Option<Self> {
+        let modified = metadata.modified().ok()?;
+        if SystemTime::now().duration_since(modified).map_or(true, |age| age < RACY_WINDOW) {
+            return None;
+        }
+        Some(Self {
+            mtime_ns: modified.duration_since(UNIX_EPOCH).ok()?.as_nanos(),
+            size: metadata.len(),
+        })
+    }
+}
+
+/// The stats of a run, as written to the stats file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Recorded {
+    /// The fingerprint of the indexing settings of the run
+    settings: String,
+    stats: HashMap<String, (FileStat, String)>,
+}
+
+/// The stats recorded by the last run, and those seen by this one
+#[derive(Debug, Default)]
+pub struct FileStats {
+    settings: String,
+    recorded: HashMap<String, (FileStat, String)>,
+    seen: Mutex<HashMap<String, (FileStat, String)>>,
+}
+
+impl FileStats {
+    /// The stats recorded in `directory` by a run with the same `settings`, a
+    /// fingerprint of the indexing settings, none if there are none or they can't be
+    /// read.
+    pub fn load(directory: &dyn Directory, settings: &str) -> Self {
+        let recorded = match directory.atomic_read(Path::new(FILE_STATS_FILE)) {
+            Ok(bytes) => bincode::deserialize::<Recorded>(&bytes).unwrap_or_default(),
+            Err(_) => Recorded::default(),
+        };
+        Self {
+            settings: settings.to_owned(),
+            recorded: if recorded.settings == settings { recorded.stats } else { HashMap::new() },
+            seen: Mutex::default(),
+        }
+    }
+
+    /// The hash of the file at `path` if it is unchanged since it was recorded with
+    /// `stat`, and the index still holds that version, whose hash is `indexed_hash`.
+    pub fn unchanged(&self, path: &str, stat: FileStat, indexed_hash: Option<&str>) -> Option<&str> {
+        let (recorded_stat, hash) = self.recorded.get(path)?;
+        (*recorded_stat == stat && Some(hash.as_str()) == indexed_hash).then_some(hash.as_str())
+    }
+
+    /// Record that the file at `path`, with `stat`, has the content `hash`.
+    pub fn see(&self, path: &str, stat: FileStat, hash: &str) {
+        self.seen.lock().unwrap().insert(path.to_owned(), (stat, hash.to_owned()));
+    }
+
+    /// Write the stats seen by this run to `directory`, keeping the recorded ones of
+    /// files outside `roots`, which this run didn't walk.
+    pub fn save(self, directory: &dyn Directory, roots: &[impl AsRef<Path>]) -> Result<()> {
+        let mut stats = self.seen.into_inner().unwrap();
+        for (path, recorded) in self.recorded {
+            if !roots.iter().any(|root| Path::new(&path).starts_with(root)) {
+                stats.entry(path).or_insert(recorded);
+            }
+        }
+        let recorded = Recorded { settings: self.settings, stats };
+        directory.atomic_write(Path::new(FILE_STATS_FILE), &bincode::serialize(&recorded)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::directory::RamDirectory;
+
+    use super::*;
+
+    #[test]
+    fn skips_only_files_indexed_as_recorded() -> Result<()> {
+        let directory = RamDirectory::create();
+        let stat = FileStat { mtime_ns: 1, size: 10 };
+
+        let stats = FileStats::load(&directory, "blake3");
+        assert_eq!(stats.unchanged("/repo/a.rs", stat, Some("h1")), None);
+        stats.see("/repo/a.rs", stat, "h1");
+        stats.see("/other/b.rs", stat, "h2");
+        stats.save(&directory, &["/repo", "/other"])?;
+
+        let stats = FileStats::load(&directory, "blake3");
+        assert_eq!(stats.unchanged("/repo/a.rs", stat, Some("h1")), Some("h1"));
+        // touched, or reindexed since
+        assert_eq!(stats.unchanged("/repo/a.rs", FileStat { mtime_ns: 2, size: 10 }, Some("h1")), None);
+        assert_eq!(stats.unchanged("/repo/a.rs", stat, Some("h3")), None);
+
+        // files of roots this run didn't walk are kept, the others only if seen again
+        stats.save(&directory, &["/repo"])?;
+        let stats = FileStats::load(&directory, "blake3");
+        assert_eq!(stats.unchanged("/repo/a.rs", stat, Some("h1")), None);
+        assert_eq!(stats.unchanged("/other/b.rs", stat, Some("h2")), Some("h2"));
+
+        // nor are the stats of a run with other settings
+        let stats = FileStats::load(&directory, "blake3 redacted");
+        assert_eq!(stats.unchanged("/other/b.rs", stat, Some("h2")), None);
+        Ok(())
+    }
+}
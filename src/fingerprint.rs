@@ -0,0 +1,205 @@
+//! Identifies the state of a git repository, so that an index built from it can be
+//! reused wherever the same state is checked out.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use tantivy::directory::error::{DeleteError, OpenReadError};
+use tantivy::Index;
+
+/// The fingerprint of the repository an index was built from, stored in the index
+pub const FINGERPRINT_FILE: &str = "fingerprint";
+
+/// The commit checked out, plus a hash of the uncommitted changes if there are some.
+///
+/// Written `<commit>` for a clean worktree, `<commit>+<changes hash>` otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    pub head: String,
+    /// Hash of the paths and contents of the modified, deleted and untracked files
+    pub changes: Option<String>,
+}
+
+impl Fingerprint {
+    /// The fingerprint of the repository at `root`, or `None` if it isn't a git
+    /// repository with a commit, or git isn't installed.
+    pub fn of(root: &Path) -> Result<Option<Self>> {
+        let Some(head) = git(root, &["rev-parse", "--verify", "HEAD"])? else {
+            return Ok(None);
+        };
+        let Some(status) = git(root, &["status", "--porcelain=v1", "-z", "--no-renames", "--untracked-files=all"])? else {
+            return Ok(None);
+        };
+
+        // entries are `XY path`, the paths relative to the root of the worktree
+        let mut paths = status
+            .split('\0')
+            .filter_map(|entry| entry.get(3..))
+            .filter(|path| !path.is_empty())
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        paths.dedup();
+
+        let changes = if paths.is_empty() {
+            None
+        } else {
+            let toplevel = git(root, &["rev-parse", "--show-toplevel"])?.context("no worktree")?;
+            let mut hasher = blake3::Hasher::new();
+            for path in paths {
+                hasher.update(path.as_bytes());
+                hasher.update(b"\0");
+                match fs::read(Path::new(toplevel.trim()).join(path)) {
+                    Ok(content) => hasher.update(&content),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => hasher.update(b"\0deleted"),
+                    // e.g. a modified submodule, which is a directory
+                    Err(_) => hasher.update(b"\0unreadable"),
+                };
+                hasher.update(b"\0");
+            }
+            Some(hasher.finalize().to_hex()[..32].to_owned())
+        };
+
+        Ok(Some(Self {
+            head: head.trim().to_owned(),
+            changes,
+        }))
+    }
+
+    /// The fingerprint recorded in the index at `index_path` when it was last built,
+    /// read without opening the index.
+    pub fn stored_in(index_path: &Path) -> Result<Option<Self>> {
+        match fs::read_to_string(index_path.join(FINGERPRINT_FILE)) {
+            Ok(stored) => Ok(Some(stored.parse()?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The fingerprint recorded in `index`.
+    pub fn stored(index: &Index) -> Result<Option<Self>> {
+        match index.directory().atomic_read(Path::new(FINGERPRINT_FILE)) {
+            Ok(stored) => Ok(Some(String::from_utf8(stored)?.parse()?)),
+            Err(OpenReadError::FileDoesNotExist(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record `fingerprint` as the state `index` was built from, or forget the stored
+    /// one if there is none.
+    pub fn record(index: &Index, fingerprint: Option<&Self>) -> Result<()> {
+        let path = Path::new(FINGERPRINT_FILE);
+        match fingerprint {
+            Some(fingerprint) => index.directory().atomic_write(path, fingerprint.to_string().as_bytes())?,
+            None => match index.directory().delete(path) {
+                Ok(()) | Err(DeleteError::FileDoesNotExist(_)) => {}
+                Err(e) => return Err(e.into()),
+            },
+        }
+        Ok(())
+    }
+}
+
+/// The output of `git -C root args`, `None` if it fails.
+pub(crate) fn git(root: &Path, args: &[&str]) -> Result<Option<String>> {
+    let output = match Command::new("git").arg("-C").arg(root).args(args).output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("failed to run git"),
+    };
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8(output.stdout).context("git output is not UTF-8")?))
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.changes {
+            Some(changes) => write!(f, "{}+{}", self.head, changes),
+            None => write!(f, "{}", self.head),
+        }
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(anyhow::anyhow!("empty repository fingerprint"));
+        }
+        Ok(match s.split_once('+') {
+            Some((head, changes)) => Self {
+                head: head.to_owned(),
+                changes: Some(changes.to_owned()),
+            },
+            None => Self {
+                head: s.to_owned(),
+                changes: None,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn run_git(root: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?}");
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        for fingerprint in ["0123abcd", "0123abcd+ffee"] {
+            assert_eq!(fingerprint.parse::<Fingerprint>().unwrap().to_string(), fingerprint);
+        }
+        assert!("".parse::<Fingerprint>().is_err());
+    }
+
+    #[test]
+    fn tracks_uncommitted_changes() -> Result<()> {
+        let dir = TempDir::new("fingerprint")?;
+        let root = dir.path();
+        if git(root, &["--version"])?.is_none() {
+            return Ok(());
+        }
+        assert_eq!(Fingerprint::of(root)?, None);
+
+        run_git(root, &["init", "-q"]);
+        fs::write(root.join("main.rs"), "fn main() {}\n")?;
+        run_git(root, &["add", "."]);
+        run_git(root, &["commit", "-q", "-m", "init"]);
+
+        let clean = Fingerprint::of(root)?.unwrap();
+        assert_eq!(clean.changes, None);
+
+        fs::write(root.join("main.rs"), "fn main() { todo!() }\n")?;
+        let modified = Fingerprint::of(root)?.unwrap();
+        assert_eq!(modified.head, clean.head);
+        assert!(modified.changes.is_some());
+
+        fs::write(root.join("main.rs"), "fn main() { unimplemented!() }\n")?;
+        assert_ne!(Fingerprint::of(root)?, Some(modified));
+
+        fs::write(root.join("main.rs"), "fn main() {}\n")?;
+        assert_eq!(Fingerprint::of(root)?, Some(clean));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+//! The version of the layout of an index on disk, and what opening an index of
+//! another version does.
+//!
+//! Indexes record the [`FORMAT_VERSION`] that wrote them. Indexes written before
+//! versions were recorded are version 1.
+//!
+//! - Indexes of this version, or of an older one down to [`MIN_READABLE_VERSION`] with
+//!   the same schema, are read as they are. Writing to an older one upgrades it: its
+//!   documents stay, new ones are written in the current format, and readers cope with
+//!   both.
+//! - Indexes of an older version that can't be read, or whose schema differs, are
+//!   rebuilt or refused for writing depending on [`OnIncompatible`], and refused for
+//!   reading.
+//! - Indexes of a newer version are never touched: they are refused for reading and
+//!   for writing.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use tantivy::directory::error::OpenReadError;
+use tantivy::schema::Schema;
+use tantivy::Index;
+
+use crate::budget::EVICTED_FILE;
+use crate::error::{ErrorCode, NavError};
+use crate::file_stats::FILE_STATS_FILE;
+use crate::fingerprint::FINGERPRINT_FILE;
+use crate::owners::OWNERS_FILE;
+use crate::package::PACKAGES_FILE;
+use crate::roots::ROOTS_FILE;
+use crate::symbol_cache::SYMBOL_CACHE_FILE;
+
+/// The format version an index was written with, stored in the index
+pub const FORMAT_VERSION_FILE: &str = "format_version";
+
+/// The format written by this version of the crate.
+///
+/// - 1: indexes without a recorded version
+/// - 2: canonical language names, and the `symbols` field listing each name once
+/// - 3: the `symbol_docs` field, see [`crate::Searcher::doc_search`]
+/// - 4: the delta-encoded `line_ends` field in place of `line_end_indices`, see
+///   [`crate::text_range::encode_line_ends`]
+/// - 5: the `regions` field of templates, see [`crate::regions`]
+/// - 6: the `path_prefixes` field, see [`crate::path_prefix`]
+/// - 7: the `flags` field of minified and generated files, see
+///   [`crate::file_heuristics::FileFlags`]
+pub const FORMAT_VERSION: u32 = 7;
+
+/// The oldest format this version reads
+pub const MIN_READABLE_VERSION: u32 = 1;
+
+/// What opening an index for writing does when it can't be upgraded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnIncompatible {
+    /// Delete the index files and index from scratch, leaving any other file of the
+    /// directory alone
+    #[default]
+    Rebuild,
+    /// Fail with an `incompatible_index` error
+    Fail,
+}
+
+/// How an existing index compares to this version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Compatibility {
+    /// There is no index yet
+    Missing,
+    /// Readable as it is, from `version`
+    Readable { version: u32 },
+    /// Older and unreadable, or with another schema
+    Outdated { version: u32, reason: String },
+    /// Written by a newer version of the crate
+    Newer { version: u32 },
+}
+
+impl Compatibility {
+    /// Compare the index at `path` to this version, which writes with `schema`.
+    pub fn of(path: &Path, schema: &Schema) -> Result<Self> {
+        if !path.join("meta.json").is_file() {
+            return Ok(Self::Missing);
+        }
+        let compatibility = Self::of_version(stored_in(path)?.unwrap_or(1));
+        if let Self::Readable { version } = compatibility {
+            if Index::open_in_dir(path)?.schema() != *schema {
+                return Ok(Self::Outdated {
+                    version,
+                    reason: "its schema differs from this version's".to_owned(),
+                });
+            }
+        }
+        Ok(compatibility)
+    }
+
+    fn of_version(version: u32) -> Self {
+        if version > FORMAT_VERSION {
+            Self::Newer { version }
+        } else if version < MIN_READABLE_VERSION {
+            Self::Outdated {
+                version,
+                reason: format!("format {version} is older than the oldest readable, {MIN_READABLE_VERSION}"),
+            }
+        } else {
+            Self::Readable { version }
+        }
+    }
+
+    /// The error for using the index anyway.
+    pub fn error(&self) -> Option<NavError> {
+        let error = |message: String| NavError::new(ErrorCode::IncompatibleIndex, message);
+        match self {
+            Self::Missing | Self::Readable { .. } => None,
+            Self::Outdated { reason, .. } => Some(
+                error(format!("The index can't be used: {reason}"))
+                    .suggest("Reindex it with `OnIncompatible::Rebuild`, or delete it"),
+            ),
+            Self::Newer { version } => Some(
+                error(format!(
+                    "The index was written by a newer version, in format {version}, this one writes format {FORMAT_VERSION}"
+                ))
+                .suggest("Upgrade code_nav_devon, or index into another directory"),
+            ),
+        }
+    }
+}
+
+/// The format version recorded in the index at `path`, if there is one.
+pub fn stored_in(path: &Path) -> Result<Option<u32>> {
+    match fs::read_to_string(path.join(FORMAT_VERSION_FILE)) {
+        Ok(stored) => Ok(Some(stored.trim().parse()?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The format version recorded in `index`, 1 if there is none.
+pub fn stored(index: &Index) -> Result<u32> {
+    match index.directory().atomic_read(Path::new(FORMAT_VERSION_FILE)) {
+        Ok(stored) => Ok(String::from_utf8(stored)?.trim().parse()?),
+        Err(OpenReadError::FileDoesNotExist(_)) => Ok(1),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Refuse to read `index` if this version can't.
+pub fn check_readable(index: &Index) -> Result<()> {
+    match Compatibility::of_version(stored(index)?).error() {
+        Some(error) => Err(error.into()),
+        None => Ok(()),
+    }
+}
+
+/// Record that `index` is in the current format.
+pub fn record(index: &Index) -> Result<()> {
+    Ok(index
+        .directory()
+        .atomic_write(Path::new(FORMAT_VERSION_FILE), FORMAT_VERSION.to_string().as_bytes())?)
+}
+
+/// Delete the files of the index at `path`: the segments tantivy lists as its own, its
+/// metadata and the files this crate keeps next to them.
+pub fn delete_index_files(path: &Path) -> Result<()> {
+    let managed: Vec<PathBuf> = match fs::read(path.join(".managed.json")) {
+        Ok(managed) => serde_json::from_slice(&managed)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let own = [
+        "meta.json",
+        ".managed.json",
+        FORMAT_VERSION_FILE,
+        FINGERPRINT_FILE,
+        FILE_STATS_FILE,
+        PACKAGES_FILE,
+        OWNERS_FILE,
+        EVICTED_FILE,
+        ROOTS_FILE,
+        SYMBOL_CACHE_FILE,
+    ];
+
+    for file in managed.iter().map(PathBuf::as_path).chain(own.iter().map(Path::new)) {
+        match fs::remove_file(path.join(file)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::schema::build_schema;
+
+    #[test]
+    fn versions() -> Result<()> {
+        let dir = TempDir::new("format")?;
+        let path = dir.path();
+        let schema = build_schema();
+        assert_eq!(Compatibility::of(path, &schema)?, Compatibility::Missing);
+
+        let index = Index::create_in_dir(path, schema.clone())?;
+        index.writer_with_num_threads(1, 15_000_000)?.commit()?;
+        // written before versions were recorded
+        assert_eq!(Compatibility::of(path, &schema)?, Compatibility::Readable { version: 1 });
+
+        record(&index)?;
+        assert_eq!(stored(&index)?, FORMAT_VERSION);
+        assert_eq!(Compatibility::of(path, &schema)?, Compatibility::Readable { version: FORMAT_VERSION });
+        check_readable(&index)?;
+
+        fs::write(path.join(FORMAT_VERSION_FILE), "99")?;
+        let newer = Compatibility::of(path, &schema)?;
+        assert_eq!(newer, Compatibility::Newer { version: 99 });
+        assert_eq!(newer.error().unwrap().code, ErrorCode::IncompatibleIndex);
+        assert!(check_readable(&index).is_err());
+
+        fs::write(path.join("notes.txt"), "not part of the index")?;
+        delete_index_files(path)?;
+        assert_eq!(Compatibility::of(path, &schema)?, Compatibility::Missing);
+        assert!(path.join("notes.txt").exists());
+        Ok(())
+    }
+}
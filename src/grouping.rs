@@ -0,0 +1,138 @@
+//! Search hits aggregated by directory, package or language, for an overview of where
+//! a pattern concentrates in a large repository before looking at single hits.
+//!
+//! Every group counts its hits and the files they are in, and keeps its first
+//! [`TOP_HITS`] hits. Groups are sorted by their number of hits, most first.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::package::PackageInfo;
+use crate::query::OutputFormat;
+use crate::search::{Searcher, TermMatch};
+
+/// The hits kept in every group
+pub const TOP_HITS: usize = 3;
+
+/// The documents searched by a grouped query, rather than the top 10, so that the
+/// counts cover most of the repository
+pub const GROUPED_DOC_LIMIT: usize = 1000;
+
+/// What search hits are grouped by, see [`crate::Query::group_by`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// The directory of the file, relative to its root
+    Dir,
+    /// The package the file belongs to, see [`PackageInfo::find`]
+    Package,
+    Lang,
+}
+
+impl FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "dir" => Ok(Self::Dir),
+            "package" => Ok(Self::Package),
+            "lang" => Ok(Self::Lang),
+            s => Err(anyhow::anyhow!("invalid group_by `{s}`, expected `dir`, `package` or `lang`")),
+        }
+    }
+}
+
+/// The hits of a group
+#[derive(Debug, Clone, Serialize)]
+pub struct HitGroup {
+    /// The directory, package name or language
+    pub key: String,
+    pub hits: usize,
+    pub files: usize,
+    /// The first [`TOP_HITS`] hits
+    pub top_hits: Vec<TermMatch>,
+}
+
+/// Group `hits` by `group_by`, packages looked up in `packages`.
+pub fn group(hits: Vec<TermMatch>, group_by: GroupBy, packages: &[PackageInfo]) -> Vec<HitGroup> {
+    let mut groups = Vec::<HitGroup>::new();
+    let mut positions = HashMap::new();
+    let mut files = HashSet::new();
+    for hit in hits {
+        let key = key_of(&hit, group_by, packages);
+        let position = *positions.entry(key.clone()).or_insert_with(|| {
+            groups.push(HitGroup {
+                key: key.clone(),
+                hits: 0,
+                files: 0,
+                top_hits: Vec::new(),
+            });
+            groups.len() - 1
+        });
+
+        let group = &mut groups[position];
+        group.hits += 1;
+        if files.insert(hit.result.path.clone()) {
+            group.files += 1;
+        }
+        if group.top_hits.len() < TOP_HITS {
+            group.top_hits.push(hit);
+        }
+    }
+
+    // stable, so that groups with as many hits keep the order of their best hit
+    groups.sort_by(|a, b| b.hits.cmp(&a.hits));
+    groups
+}
+
+fn key_of(hit: &TermMatch, group_by: GroupBy, packages: &[PackageInfo]) -> String {
+    let parent = |path: &str| match Path::new(path).parent().and_then(Path::to_str) {
+        Some("") | None => ".".to_owned(),
+        Some(dir) => dir.to_owned(),
+    };
+    match group_by {
+        GroupBy::Dir => parent(&hit.result.relative_path),
+        GroupBy::Package => match PackageInfo::find(packages, &parent(&hit.result.path)) {
+            Some(package) => package.name.clone().unwrap_or_else(|| package.dir.clone()),
+            None => "(no package)".to_owned(),
+        },
+        GroupBy::Lang => hit.result.snippet.lang.clone().unwrap_or_else(|| "unknown".to_owned()),
+    }
+}
+
+/// Render `groups` in `format`: a header per group with its counts, followed by its
+/// top hits.
+pub fn format_groups(groups: &[HitGroup], format: OutputFormat) -> Result<String> {
+    if format == OutputFormat::Json {
+        return Ok(serde_json::to_string(groups)?);
+    }
+    if groups.is_empty() {
+        return Ok("No results found".to_owned());
+    }
+
+    let mut formatted = String::new();
+    for group in groups {
+        let counts = format!(
+            "{} hit{} in {} file{}",
+            group.hits,
+            if group.hits == 1 { "" } else { "s" },
+            group.files,
+            if group.files == 1 { "" } else { "s" }
+        );
+        match format {
+            OutputFormat::Markdown => {
+                formatted.push_str(&format!("### `{}` ({counts})\n\n", group.key));
+                formatted.push_str(&Searcher::format_markdown_search_results(group.top_hits.clone(), false));
+            }
+            _ => {
+                formatted.push_str(&format!("{}: {counts}\n", group.key));
+                let results = group.top_hits.iter().map(|hit| hit.result.clone()).collect();
+                formatted.push_str(&Searcher::format_search_results(results));
+            }
+        }
+    }
+    Ok(formatted)
+}
@@ -0,0 +1,252 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::compaction::IndexHealth;
+use crate::error::{ErrorCode, NavError};
+use crate::events::{EventBus, Events, IndexEvent};
+use crate::indexes::{IndexReport, Indexes, Priority};
+use crate::limiter::{LimiterStats, QueryLimiter};
+use crate::search::Searcher;
+use crate::sync_handle::SyncHandle;
+use crate::text_range::LineBase;
+use crate::watcher::Watcher;
+
+/// A long-lived handle on the index of one repository, shared by every thread that
+/// queries it.
+///
+/// Cloning is cheap and every clone refers to the same index. Queries run against a
+/// [`snapshot`](Self::snapshot) of the searcher taken at the start of the call, so
+/// they never wait on each other, nor on a [`refresh`](Self::refresh) in progress:
+/// the refreshed searcher is swapped in for the calls that start after it.
+///
+/// Heavy queries run through [`query`](Self::query) are bounded by a
+/// [`QueryLimiter`], shared by the clones made after it is set.
+///
+/// What every refresh changes is published to its [`events`](Self::events).
+#[derive(Clone)]
+pub struct IndexHandle {
+    inner: Arc<Inner>,
+    limiter: Arc<QueryLimiter>,
+    index_missing_files: bool,
+    line_base: Option<LineBase>,
+}
+
+struct Inner {
+    root_path: PathBuf,
+    index_path: PathBuf,
+    indexes: Indexes,
+    searcher: RwLock<Arc<Searcher>>,
+    events: EventBus,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl IndexHandle {
+    /// Index the repository at `root_path` into `index_path`, then open a searcher
+    /// over it. Must not be called from within a tokio runtime.
+    pub fn open(root_path: &Path, index_path: &Path, buffer_size: usize, threads: usize) -> Result<Self> {
+        let runtime = tokio::runtime::Runtime::new().context("failed to create tokio runtime")?;
+
+        let indexes = runtime.block_on(async {
+            let indexes = Indexes::new(index_path, buffer_size, threads).await?;
+            indexes.index(root_path).await?;
+            anyhow::Ok(indexes)
+        })?;
+        let searcher = Searcher::new(index_path)?;
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                root_path: root_path.to_path_buf(),
+                index_path: index_path.to_path_buf(),
+                indexes,
+                searcher: RwLock::new(Arc::new(searcher)),
+                events: EventBus::default(),
+                runtime,
+            }),
+            limiter: Arc::new(QueryLimiter::default()),
+            index_missing_files: false,
+            line_base: None,
+        })
+    }
+
+    /// Let at most `max_running` heavy queries run at once. Defaults to
+    /// [`crate::limiter::DEFAULT_HEAVY_QUERIES`].
+    pub fn heavy_query_limit(mut self, max_running: usize) -> Self {
+        self.limiter = Arc::new(QueryLimiter::new(max_running));
+        self
+    }
+
+    /// Index the files that [`query_file`](Self::query_file) queries miss on the fly,
+    /// if they exist under the root. Off by default, for clones made after it is set.
+    pub fn index_missing_files(mut self, enabled: bool) -> Self {
+        self.index_missing_files = enabled;
+        self
+    }
+
+    /// Number the lines of the queries run through this handle from `base`, whatever
+    /// the calling thread's [`LineBase::current`], for clones made after it is set.
+    pub fn line_base(mut self, base: LineBase) -> Self {
+        self.line_base = Some(base);
+        self
+    }
+
+    /// Run `f` with the lines numbered like the queries of this handle: from its
+    /// [`line_base`](Self::line_base) if set, else the calling thread's.
+    pub fn numbered<T>(&self, f: impl FnOnce() -> T) -> T {
+        match self.line_base {
+            Some(base) => base.scope(f),
+            None => f(),
+        }
+    }
+
+    /// Run `f` on a snapshot, waiting for a turn first if the query is `heavy`.
+    pub fn query<T>(&self, heavy: bool, f: impl FnOnce(&Searcher) -> T) -> T {
+        self.numbered(|| self.limiter.run(heavy, || f(&self.snapshot())))
+    }
+
+    /// Like [`Self::query`], for a query of the file at `path`. When the file isn't
+    /// indexed but exists under the root, such as one created since the last refresh,
+    /// and [`index_missing_files`](Self::index_missing_files) is set, it is indexed on
+    /// its own, as a refresh would index it, then the query runs again. A relative
+    /// `path` is relative to the root.
+    pub fn query_file<T>(&self, heavy: bool, path: &str, f: impl Fn(&Searcher) -> Result<T>) -> Result<T> {
+        match self.query(heavy, &f) {
+            Err(e) if self.index_missing_files && is_document_not_found(&e) && self.is_under_root(path) => {
+                self.index_file(path)?;
+                self.query(heavy, f)
+            }
+            result => result,
+        }
+    }
+
+    /// How many heavy queries are running, and how many are queued behind them.
+    pub fn stats(&self) -> LimiterStats {
+        self.limiter.stats()
+    }
+
+    pub fn root_path(&self) -> &Path {
+        &self.inner.root_path
+    }
+
+    pub fn index_path(&self) -> &Path {
+        &self.inner.index_path
+    }
+
+    /// The current searcher. It keeps seeing the index as it was when the snapshot
+    /// was taken, however long the caller holds on to it.
+    pub fn snapshot(&self) -> Arc<Searcher> {
+        Arc::clone(&self.inner.searcher.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Bring the index up to date with the repository, returning what changed.
+    /// Concurrent refreshes are serialised by the index writer lock; queries carry on
+    /// meanwhile.
+    pub fn refresh(&self) -> Result<IndexReport> {
+        self.refresh_at(Priority::Normal)
+    }
+
+    /// Like [`Self::refresh`], running at `priority`, e.g. in the background.
+    pub fn refresh_at(&self, priority: Priority) -> Result<IndexReport> {
+        let refreshed = self.inner.runtime.block_on(self.inner.indexes.index_at(&self.inner.root_path, priority));
+        self.reopened(refreshed)
+    }
+
+    /// Start a refresh at `priority` on a thread of its own, returning its progress right
+    /// away, see [`SyncHandle`]. It is done once the refreshed searcher is swapped in.
+    pub fn start_refresh(&self, priority: Priority) -> SyncHandle {
+        let progress = SyncHandle::new();
+        let (handle, tracked) = (self.clone(), progress.clone());
+        std::thread::spawn(move || {
+            let refreshed = handle
+                .inner
+                .runtime
+                .block_on(handle.inner.indexes.index_roots_with(&[handle.inner.root_path.as_path()], priority, Some(&tracked)));
+            tracked.finish(&handle.reopened(refreshed));
+        });
+        progress
+    }
+
+    /// Index the file at `path`, relative to the root unless absolute, on its own, see
+    /// [`Indexes::index_file`], publishing what changed like a refresh.
+    pub fn index_file(&self, path: &str) -> Result<IndexReport> {
+        let indexed = self.inner.runtime.block_on(self.inner.indexes.index_file(&self.inner.root_path, Path::new(path)));
+        self.reopened(indexed)
+    }
+
+    /// Index the files at `paths` on their own, see [`Indexes::index_paths`], publishing
+    /// what changed like a refresh.
+    pub fn index_paths(&self, paths: &[PathBuf]) -> Result<IndexReport> {
+        let indexed = self.inner.runtime.block_on(self.inner.indexes.index_paths(&self.inner.root_path, paths));
+        self.reopened(indexed)
+    }
+
+    /// Keep the index up to date with the repository until the returned watcher is
+    /// dropped, reindexing the files that change, see [`Watcher`].
+    pub fn watch(&self, debounce: Duration) -> Result<Watcher> {
+        Watcher::start(self.clone(), debounce)
+    }
+
+    /// Publish an error that happened outside of a refresh, such as a failed watch.
+    pub(crate) fn publish_error(&self, message: String) {
+        self.inner.events.publish([IndexEvent::Error { path: None, message }]);
+    }
+
+    /// The events of the refreshes made from now on, by any clone of the handle.
+    pub fn events(&self) -> Events {
+        self.inner.events.subscribe()
+    }
+
+    /// Live and deleted documents of the index as of the last refresh.
+    pub fn health(&self) -> IndexHealth {
+        self.inner.indexes.health()
+    }
+
+    /// Merge away the deleted documents of the index, see [`Indexes::compact`].
+    pub fn compact(&self) -> Result<()> {
+        self.inner.runtime.block_on(self.inner.indexes.compact())?;
+        self.reopen()
+    }
+
+    /// Swap in a searcher seeing the changes of `indexed`, then publish them.
+    fn reopened(&self, indexed: Result<IndexReport>) -> Result<IndexReport> {
+        let indexed = indexed.and_then(|report| self.reopen().map(|()| report));
+        match &indexed {
+            Ok(report) => self.inner.events.publish(IndexEvent::of_report(report)),
+            Err(e) => self.inner.events.publish([IndexEvent::Error { path: None, message: format!("{e:#}") }]),
+        }
+        indexed
+    }
+
+    /// Whether `path` is a file under the root of the repository.
+    fn is_under_root(&self, path: &str) -> bool {
+        let path = self.inner.root_path.join(path);
+        let (Ok(path), Ok(root)) = (path.canonicalize(), self.inner.root_path.canonicalize()) else {
+            return false;
+        };
+        path.is_file() && path.starts_with(root)
+    }
+
+    fn reopen(&self) -> Result<()> {
+        let searcher = Arc::new(Searcher::new(&self.inner.index_path)?);
+        *self.inner.searcher.write().unwrap_or_else(|e| e.into_inner()) = searcher;
+        Ok(())
+    }
+}
+
+fn is_document_not_found(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<NavError>().is_some_and(|e| e.code == ErrorCode::DocumentNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_is_shareable() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<IndexHandle>();
+        assert_send_sync::<Searcher>();
+    }
+}
@@ -0,0 +1,210 @@
+//! One ranked list out of the symbol, path and content searches, for agents that just
+//! want to find something without picking the right search first.
+//!
+//! Every source ranks its own hits: symbols defined with the name of the query, then
+//! with a name starting with it, files whose name or path contains it, and the lines
+//! containing it, most relevant documents first. The lists are fused by reciprocal
+//! rank, each hit scoring the weight of its source over [`RANK_OFFSET`] plus its rank,
+//! so that the first hits of every source come before the tail of any of them, and
+//! definitions before mere mentions. A location found by several sources, such as the
+//! line defining a symbol, sums their scores.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// Added to the ranks of hits before fusing them, the higher the less the first hits
+/// of a source lead over the next ones
+pub const RANK_OFFSET: f32 = 10.0;
+
+/// The number of hits taken from every source before fusing them
+pub const SOURCE_LIMIT: usize = 50;
+
+/// The search a hit comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HybridSource {
+    /// A definition of a symbol named like the query
+    Symbol,
+    /// A file whose path contains the query
+    Path,
+    /// A line containing the query
+    Content,
+}
+
+impl HybridSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Symbol => "symbol",
+            Self::Path => "path",
+            Self::Content => "content",
+        }
+    }
+
+    /// How much its hits weigh against those of the other sources.
+    pub fn weight(self) -> f32 {
+        match self {
+            Self::Symbol => 1.0,
+            Self::Path => 0.8,
+            Self::Content => 0.5,
+        }
+    }
+}
+
+/// A hit of [`crate::Searcher::hybrid_search`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HybridHit {
+    pub path: String,
+    /// `path` relative to the root it was indexed from
+    pub relative_path: String,
+    /// Numbered from the [`crate::LineBase::current`], `None` for a file as a whole
+    pub line: Option<usize>,
+    /// The sources that found it, best first
+    pub sources: Vec<HybridSource>,
+    /// The name and kind of the symbol defined there, for symbol hits
+    pub symbol: Option<String>,
+    pub kind: Option<String>,
+    /// The line, trimmed
+    pub text: Option<String>,
+    pub score: f32,
+}
+
+impl HybridHit {
+    /// A hit of `source`, to be scored by [`fuse`].
+    pub(crate) fn new(source: HybridSource, path: String, relative_path: String, line: Option<usize>) -> Self {
+        Self {
+            path,
+            relative_path,
+            line,
+            sources: vec![source],
+            symbol: None,
+            kind: None,
+            text: None,
+            score: 0.0,
+        }
+    }
+}
+
+/// How well the symbol `name` matches `query`, lower is better: exactly, ignoring
+/// case, starting with it, then containing it. `None` if it doesn't.
+pub(crate) fn symbol_rank(name: &str, query: &str) -> Option<u8> {
+    let (name_lower, query_lower) = (name.to_lowercase(), query.to_lowercase());
+    if name == query {
+        Some(0)
+    } else if name_lower == query_lower {
+        Some(1)
+    } else if name_lower.starts_with(&query_lower) {
+        Some(2)
+    } else if name_lower.contains(&query_lower) {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// How well the file at `relative_path` matches `query`, ignoring case, lower is
+/// better: named after it, with a name starting with it or containing it, then in a
+/// directory containing it. `None` if it doesn't.
+pub(crate) fn path_rank(relative_path: &str, query: &str) -> Option<u8> {
+    let (path, query) = (relative_path.to_lowercase(), query.to_lowercase());
+    let name = path.rsplit('/').next().unwrap_or_default();
+    let stem = name.split('.').next().unwrap_or_default();
+    if stem == query || name == query {
+        Some(0)
+    } else if name.starts_with(&query) {
+        Some(1)
+    } else if name.contains(&query) {
+        Some(2)
+    } else if path.contains(&query) {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// A regex matching the stored paths containing `query`, ignoring case, for the
+/// regex queries of tantivy, which have no case-insensitive flag.
+pub(crate) fn path_pattern(query: &str) -> String {
+    let chars = query.chars().map(|c| {
+        let (lower, upper) = (c.to_lowercase().collect::<String>(), c.to_uppercase().collect::<String>());
+        if lower == upper {
+            regex::escape(&c.to_string())
+        } else {
+            format!("[{}{}]", regex::escape(&lower), regex::escape(&upper))
+        }
+    });
+    format!(".*{}.*", chars.collect::<String>())
+}
+
+/// The hits of every source, each list ranked best first, fused into one list of at
+/// most `limit`, best first.
+pub(crate) fn fuse(ranked: Vec<Vec<HybridHit>>, limit: usize) -> Vec<HybridHit> {
+    let mut fused = Vec::<HybridHit>::new();
+    let mut positions = HashMap::<(String, Option<usize>), usize>::new();
+    for hits in ranked {
+        for (rank, hit) in hits.into_iter().enumerate() {
+            let score = hit.sources[0].weight() / (RANK_OFFSET + rank as f32 + 1.0);
+            match positions.get(&(hit.path.clone(), hit.line)) {
+                Some(&position) => {
+                    let found = &mut fused[position];
+                    found.score += score;
+                    found.sources.extend(hit.sources);
+                    found.sources.sort();
+                    found.sources.dedup();
+                    found.symbol = found.symbol.take().or(hit.symbol);
+                    found.kind = found.kind.take().or(hit.kind);
+                    found.text = found.text.take().or(hit.text);
+                }
+                None => {
+                    positions.insert((hit.path.clone(), hit.line), fused.len());
+                    fused.push(HybridHit { score, ..hit });
+                }
+            }
+        }
+    }
+
+    fused.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.path.cmp(&b.path))
+            .then(a.line.cmp(&b.line))
+    });
+    fused.truncate(limit);
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuses_sources_by_rank() {
+        assert_eq!(symbol_rank("parse_config", "parse_config"), Some(0));
+        assert_eq!(symbol_rank("ParseConfig", "parseconfig"), Some(1));
+        assert_eq!(symbol_rank("parse_config_file", "parse_config"), Some(2));
+        assert_eq!(symbol_rank("config", "parse_config"), None);
+        assert_eq!(path_rank("src/config.rs", "Config"), Some(0));
+        assert_eq!(path_rank("src/config/mod.rs", "config"), Some(3));
+        assert!(regex::Regex::new(&format!("^{}$", path_pattern("a.B"))).unwrap().is_match("/repo/x/A.b.rs"));
+
+        let hit = |source, line| HybridHit::new(source, "/repo/src/config.rs".to_owned(), "src/config.rs".to_owned(), line);
+        let other = HybridHit::new(HybridSource::Content, "/repo/README.md".to_owned(), "README.md".to_owned(), Some(3));
+        let fused = fuse(
+            vec![
+                vec![hit(HybridSource::Symbol, Some(12))],
+                vec![hit(HybridSource::Path, None)],
+                vec![other, hit(HybridSource::Content, Some(12))],
+            ],
+            10,
+        );
+        let ranked = fused.iter().map(|hit| (hit.relative_path.as_str(), hit.line, hit.sources.clone())).collect::<Vec<_>>();
+        assert_eq!(
+            ranked,
+            [
+                ("src/config.rs", Some(12), vec![HybridSource::Symbol, HybridSource::Content]),
+                ("src/config.rs", None, vec![HybridSource::Path]),
+                ("README.md", Some(3), vec![HybridSource::Content]),
+            ]
+        );
+    }
+}
@@ -1,13 +1,114 @@
-use std::{fs, path::Path};
+use std::{fs, path::{Path, PathBuf}};
+use std::time::{Duration, Instant};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use tantivy::{schema::Schema, Index, IndexReader, IndexWriter};
 use tokio::sync::Mutex;
+use crate::budget::{self, EvictedFile, SizeBudget};
+use crate::compaction::{CompactionPolicy, IndexHealth};
+use crate::edit::{self, Edit};
 use crate::file::File;
+use crate::fingerprint::Fingerprint;
+use crate::format::{self, Compatibility, OnIncompatible};
+use crate::roots;
+use crate::source::{FileSource, Sourced};
+use crate::symbol_cache;
+use crate::sync_handle::{SyncHandle, SyncState};
+
+/// The pause after every file read by a [`Priority::Background`] run
+pub const BACKGROUND_PAUSE: Duration = Duration::from_millis(5);
+
+/// How much of the machine an indexing run may take
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// All the writer threads of the indexes, as fast as the disk allows
+    #[default]
+    Normal,
+    /// A single writer thread, pausing for [`BACKGROUND_PAUSE`] after every file read,
+    /// for reindexing that mustn't compete with builds and tests running next to it
+    Background,
+}
+
+/// What an indexing run did, for callers to tell whether anything changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexReport {
+    /// Files indexed for the first time
+    pub added: Vec<String>,
+    /// Files whose content changed since they were indexed
+    pub updated: Vec<String>,
+    /// Files whose documents were deleted, because they no longer exist, are left out
+    /// or grew past the size cap
+    pub deleted: Vec<String>,
+    /// Files found under a new path with the content and language of a file that is
+    /// gone, whose documents were moved rather than rebuilt
+    pub renamed: Vec<Rename>,
+    /// Files larger than the size cap, left out of the index, see
+    /// [`crate::File::with_max_file_size`]
+    pub skipped: Vec<SkippedFile>,
+    /// Files and directories that couldn't be read, left out of the run unless it is
+    /// strict, see [`crate::File::strict`]
+    pub errors: Vec<EntryError>,
+    /// Files whose documents were deleted to keep the index under its size budget,
+    /// see [`Indexes::size_budget`]
+    pub evicted: Vec<EvictedFile>,
+    /// How long the run took, committing and refreshing the symbol cache included
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedFile {
+    pub path: String,
+    /// In bytes
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryError {
+    pub path: String,
+    pub error: String,
+}
+
+impl IndexReport {
+    /// Whether the run changed the documents of the index.
+    pub fn changed(&self) -> bool {
+        !(self.added.is_empty()
+            && self.updated.is_empty()
+            && self.deleted.is_empty()
+            && self.renamed.is_empty()
+            && self.evicted.is_empty())
+    }
+
+    fn merge(&mut self, other: IndexReport) {
+        self.added.extend(other.added);
+        self.updated.extend(other.updated);
+        self.deleted.extend(other.deleted);
+        self.renamed.extend(other.renamed);
+        self.skipped.extend(other.skipped);
+        self.errors.extend(other.errors);
+        self.evicted.extend(other.evicted);
+        self.duration += other.duration;
+    }
+}
 
 #[async_trait]
 pub trait Indexable: Send + Sync {
-    async fn index_repository(&self, root_path: &Path, writer: &IndexWriter) -> Result<()>;
+    async fn index_repository(&self, root_path: &Path, writer: &IndexWriter) -> Result<IndexReport>;
+
+    /// Index several repositories, or several trees of a workspace, in one go.
+    async fn index_roots(&self, roots: &[&Path], writer: &IndexWriter) -> Result<IndexReport> {
+        let mut report = IndexReport::default();
+        for root in roots {
+            report.merge(self.index_repository(root, writer).await?);
+        }
+        Ok(report)
+    }
+
     fn schema(&self) -> Schema;
 }
 
@@ -18,16 +119,36 @@ pub struct IndexWriteHandle<'a> {
 }
 
 impl<'a> IndexWriteHandle<'a> {
-    pub async fn index(&self, root_path: &Path) -> Result<()> {
+    pub async fn index(&self, root_path: &Path) -> Result<IndexReport> {
         self.source.index_repository(root_path, &self.writer).await
     }
 
+    pub async fn index_roots(&self, roots: &[&Path]) -> Result<IndexReport> {
+        self.source.index_roots(roots, &self.writer).await
+    }
+
+    pub(crate) fn writer(&self) -> &IndexWriter {
+        &self.writer
+    }
+
     pub fn commit(&mut self) -> Result<()> {
         self.writer.commit()?;
         self.reader.reload()?;
         Ok(())
     }
 
+    /// Merge every segment into one, dropping the deleted documents.
+    pub fn compact(&mut self) -> Result<()> {
+        let segments = self.writer.index().searchable_segment_ids()?;
+        if segments.is_empty() {
+            return Ok(());
+        }
+        self.writer.merge(&segments).wait()?;
+        self.writer.garbage_collect_files().wait()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
     pub fn rollback(&mut self) -> Result<()> {
         self.writer.rollback()?;
         Ok(())
@@ -43,11 +164,22 @@ pub struct Indexer<T> {
 }
 
 impl<T: Indexable> Indexer<T> {
-    fn write_handle(&self) -> Result<IndexWriteHandle<'_>> {
+    pub(crate) fn write_handle(&self) -> Result<IndexWriteHandle<'_>> {
+        self.write_handle_with(&self.source, self.threads)
+    }
+
+    /// A write handle indexing the files of `source` rather than those of this
+    /// indexer's source, which must share its schema.
+    pub(crate) fn write_handle_for<'a>(&'a self, source: &'a dyn Indexable) -> Result<IndexWriteHandle<'a>> {
+        self.write_handle_with(source, self.threads)
+    }
+
+    /// Like [`Self::write_handle_for`], with a writer of `threads` threads.
+    pub(crate) fn write_handle_with<'a>(&'a self, source: &'a dyn Indexable, threads: usize) -> Result<IndexWriteHandle<'a>> {
         Ok(IndexWriteHandle {
-            source: &self.source,
+            source,
             reader: &self.reader,
-            writer: self.index.writer_with_num_threads(self.threads, self.buffer_size * self.threads)?,
+            writer: self.index.writer_with_num_threads(threads, self.buffer_size * threads)?,
         })
     }
 
@@ -59,33 +191,35 @@ impl<T: Indexable> Indexer<T> {
     }
 
     pub fn create(source: T, path: &Path, buffer_size: usize, threads: usize) -> Result<Self> {
-        match Self::init_index(source.schema(), path, threads) {
-            Ok(index) => {
-                let reader = index.reader()?;
-                Ok(Self {
-                    reader,
-                    index,
-                    source,
-                    threads,
-                    buffer_size,
-                })
-            },
-            Err(e) if e.to_string().contains("Schema error: 'An index exists but the schema does not match.'") => {
-                // Delete the index directory
-                fs::remove_dir_all(path)?;
-                // Retry creating the Indexer instance
-                let index = Self::init_index(source.schema(), path, threads)?;
-                let reader = index.reader()?;
-                Ok(Self {
-                    reader,
-                    index,
-                    source,
-                    threads,
-                    buffer_size,
-                })
-            },
-            Err(e) => Err(e),
+        Self::open(source, path, buffer_size, threads, OnIncompatible::Rebuild)
+    }
+
+    /// Open or create the index at `path`, upgrading an older one this version can
+    /// read, and rebuilding or refusing one it can't, see [`crate::format`].
+    pub fn open(source: T, path: &Path, buffer_size: usize, threads: usize, on_incompatible: OnIncompatible) -> Result<Self> {
+        let compatibility = Compatibility::of(path, &source.schema())?;
+        match (&compatibility, on_incompatible) {
+            (Compatibility::Missing | Compatibility::Readable { .. }, _) => {}
+            (Compatibility::Outdated { version, reason }, OnIncompatible::Rebuild) => {
+                eprintln!("Rebuilding the index at {} (format {version}): {reason}", path.display());
+                format::delete_index_files(path)?;
+            }
+            (Compatibility::Outdated { .. } | Compatibility::Newer { .. }, _) => {
+                let error = compatibility.error().expect("incompatible indexes have an error");
+                return Err(anyhow::Error::from(error).context(format!("Failed to open the index at {}", path.display())));
+            }
         }
+
+        let index = Self::init_index(source.schema(), path, threads)?;
+        format::record(&index)?;
+        let reader = index.reader()?;
+        Ok(Self {
+            reader,
+            index,
+            source,
+            threads,
+            buffer_size,
+        })
     }
 }
 
@@ -93,21 +227,244 @@ impl<T: Indexable> Indexer<T> {
 pub struct Indexes {
     pub file: Indexer<File>,
     pub write_mutex: Mutex<()>,
+    compaction: Option<CompactionPolicy>,
+    /// `None` to keep the budget recorded in the index
+    size_budget: Option<Option<SizeBudget>>,
 }
 
 impl Indexes {
     pub async fn new(index_path: &Path, buffer_size: usize, threads: usize) -> Result<Self> {
+        Self::with_source(File::new(), index_path, buffer_size, threads).await
+    }
+
+    /// Open the indexes with a customised file source, e.g. one with a redactor.
+    pub async fn with_source(file: File, index_path: &Path, buffer_size: usize, threads: usize) -> Result<Self> {
+        Self::open(file, index_path, buffer_size, threads, OnIncompatible::Rebuild).await
+    }
+
+    /// Open the indexes, choosing what happens to an index this version can't use
+    /// rather than rebuilding it.
+    pub async fn open(file: File, index_path: &Path, buffer_size: usize, threads: usize, on_incompatible: OnIncompatible) -> Result<Self> {
         Ok(Self {
-            file: Indexer::create(File::new(), index_path, buffer_size, threads)?,
+            file: Indexer::open(file, index_path, buffer_size, threads, on_incompatible)?,
             write_mutex: Mutex::new(()),
+            compaction: Some(CompactionPolicy::DEFAULT),
+            size_budget: None,
         })
     }
 
-    pub async fn index(&self, root_path: &Path) -> Result<()> {
+    /// Compact the index after indexing when `policy` says so, or never if `None`.
+    /// Defaults to [`CompactionPolicy::DEFAULT`].
+    pub fn compaction(mut self, policy: Option<CompactionPolicy>) -> Self {
+        self.compaction = policy;
+        self
+    }
+
+    /// Keep the index under `budget` after indexing, by evicting its generated,
+    /// vendored and minified files, or lift the budget if `None`, see [`budget`]. The
+    /// budget is recorded in the index: without this, the one set last applies.
+    pub fn size_budget(mut self, budget: Option<SizeBudget>) -> Self {
+        self.size_budget = Some(budget);
+        self
+    }
+
+    /// The documents of the index as of the last commit, deleted ones included.
+    pub fn health(&self) -> IndexHealth {
+        IndexHealth::of(&self.file.reader.searcher())
+    }
+
+    /// Merge the index into a single segment without deleted documents.
+    pub async fn compact(&self) -> Result<()> {
         let _write_lock = self.write_mutex.lock().await;
+        self.file.write_handle()?.compact()?;
+        symbol_cache::refresh(&self.file.index, self.file.source.symbols_field, self.file.source.path_field)
+    }
+
+    /// Open the index at `index_path` only if it was built from the repository state
+    /// identified by `fingerprint`, so that it can be used without indexing.
+    pub async fn open_if_matching(index_path: &Path, fingerprint: &Fingerprint, buffer_size: usize, threads: usize) -> Result<Option<Self>> {
+        if Fingerprint::stored_in(index_path)?.as_ref() != Some(fingerprint) {
+            return Ok(None);
+        }
+        Self::new(index_path, buffer_size, threads).await.map(Some)
+    }
+
+    /// The fingerprint of the repository state the index was last built from.
+    pub fn fingerprint(&self) -> Result<Option<Fingerprint>> {
+        Fingerprint::stored(&self.file.index)
+    }
+
+    /// Whether the index was built from the repository at `root_path` as it is now.
+    pub fn is_up_to_date(&self, root_path: &Path) -> Result<bool> {
+        let Some(fingerprint) = Fingerprint::of(root_path)? else {
+            return Ok(false);
+        };
+        Ok(self.fingerprint()? == Some(fingerprint))
+    }
+
+    /// Bring the index up to date with the repository at `root_path`, returning what
+    /// changed. With a [`File::dry_run`] source, only report what would change.
+    pub async fn index(&self, root_path: &Path) -> Result<IndexReport> {
+        self.index_roots(&[root_path]).await
+    }
+
+    /// Index the files of every root of `roots` into one index, e.g. the members of a
+    /// workspace. Overlapping roots are indexed once, see [`File`]'s `index_roots`.
+    pub async fn index_roots(&self, roots: &[&Path]) -> Result<IndexReport> {
+        self.index_roots_at(roots, Priority::Normal).await
+    }
+
+    /// Like [`Self::index`], running at `priority`.
+    pub async fn index_at(&self, root_path: &Path, priority: Priority) -> Result<IndexReport> {
+        self.index_roots_at(&[root_path], priority).await
+    }
+
+    /// Like [`Self::index_roots`], running at `priority`.
+    pub async fn index_roots_at(&self, roots: &[&Path], priority: Priority) -> Result<IndexReport> {
+        self.index_roots_with(roots, priority, None).await
+    }
+
+    /// Like [`Self::index_at`], following the run with `progress` until it is done,
+    /// see [`SyncHandle`].
+    pub async fn index_tracked(&self, root_path: &Path, priority: Priority, progress: &SyncHandle) -> Result<IndexReport> {
+        let indexed = self.index_roots_with(&[root_path], priority, Some(progress)).await;
+        progress.finish(&indexed);
+        indexed
+    }
+
+    /// Like [`Self::index_roots_at`], counting what the run does into `progress`, which
+    /// is left [`SyncState::Committing`] for the caller to finish.
+    pub(crate) async fn index_roots_with(&self, roots: &[&Path], priority: Priority, progress: Option<&SyncHandle>) -> Result<IndexReport> {
+        let _write_lock = self.write_mutex.lock().await;
+        if let Some(progress) = progress {
+            progress.enter(SyncState::Walking);
+        }
+        let started = Instant::now();
+        // taken first: changes made while indexing may be missed
+        let fingerprint = common_fingerprint(roots)?;
+        let budget = match self.size_budget {
+            Some(budget) => budget,
+            None => budget::stored_budget(&self.file.index)?,
+        };
+        if !self.file.source.dry_run {
+            budget::prepare(&self.file.index, budget)?;
+        }
+        let source = self.file.source.clone().with_progress(progress.cloned());
+        let (source, threads) = match priority {
+            Priority::Normal => (source, self.file.threads),
+            Priority::Background => (source.with_throttle(Some(BACKGROUND_PAUSE)), 1),
+        };
+        let mut writer = self.file.write_handle_with(&source, threads)?;
+        let mut report = writer.index_roots(roots).await?;
+        if let Some(progress) = progress {
+            progress.enter(SyncState::Committing);
+        }
+        if self.file.source.dry_run {
+            writer.rollback()?;
+            report.duration = started.elapsed();
+            return Ok(report);
+        }
+        writer.commit()?;
+        if self.compaction.is_some_and(|policy| policy.should_compact(&self.health())) {
+            writer.compact()?;
+        }
+        if let Some(budget) = budget {
+            report.evicted = budget::evict(&mut writer, &self.file.source, roots, budget)?;
+        }
+        symbol_cache::refresh(&self.file.index, self.file.source.symbols_field, self.file.source.path_field)?;
+        Fingerprint::record(&self.file.index, fingerprint.as_ref())?;
+        roots::record(&self.file.index, roots)?;
+        report.duration = started.elapsed();
+        Ok(report)
+    }
+}
+
+impl Indexes {
+    /// Index `documents`, pairs of a path and a content, as files stored under that
+    /// path that needn't exist on disk, e.g. rendered templates or generated code.
+    /// They are searched and navigated like the others, and kept until indexed again
+    /// with another content. See [`File`]'s `index_documents`.
+    pub async fn index_documents(&self, documents: Vec<(String, String)>) -> Result<IndexReport> {
+        let _write_lock = self.write_mutex.lock().await;
+        let started = Instant::now();
         let mut writer = self.file.write_handle()?;
-        writer.index( root_path).await?;
+        let mut report = self.file.source.index_documents(documents, writer.writer())?;
+        if self.file.source.dry_run {
+            writer.rollback()?;
+            report.duration = started.elapsed();
+            return Ok(report);
+        }
         writer.commit()?;
-        Ok(())
+        symbol_cache::refresh(&self.file.index, self.file.source.symbols_field, self.file.source.path_field)?;
+        report.duration = started.elapsed();
+        Ok(report)
+    }
+
+    /// Apply `edits` to the files on disk, see [`edit::apply`], then reindex the files
+    /// they change, and only those, so that the index stays consistent with them.
+    pub async fn apply_edits(&self, edits: &[Edit]) -> Result<IndexReport> {
+        let changed = edit::apply(edits)?;
+        self.index_documents(changed).await
+    }
+
+    /// Index the file at `path`, relative to `root_path` unless absolute, on its own, e.g.
+    /// one created since the last refresh that a query needs right away. It is stored
+    /// under its canonical path, and left out as by a traversal of the root, see
+    /// [`Self::index_paths`]. The next refresh takes it as it finds it.
+    pub async fn index_file(&self, root_path: &Path, path: &Path) -> Result<IndexReport> {
+        let path = root_path.join(path);
+        let path = tokio::fs::canonicalize(&path).await.unwrap_or(path);
+        self.index_paths(root_path, &[path]).await
+    }
+
+    /// Index the files at `paths` under `root_path`, and only those, rather than walking
+    /// the root, e.g. the files a [`crate::watcher::Watcher`] saw change. The documents
+    /// of the paths that are gone are deleted. See [`File`]'s `index_paths`.
+    pub async fn index_paths(&self, root_path: &Path, paths: &[PathBuf]) -> Result<IndexReport> {
+        let _write_lock = self.write_mutex.lock().await;
+        let started = Instant::now();
+        let mut writer = self.file.write_handle()?;
+        let mut report = self.file.source.index_paths(root_path, paths, writer.writer()).await?;
+        // nothing to commit, which would write to the index, and wake up its watcher
+        if self.file.source.dry_run || !report.changed() {
+            writer.rollback()?;
+            report.duration = started.elapsed();
+            return Ok(report);
+        }
+        writer.commit()?;
+        symbol_cache::refresh(&self.file.index, self.file.source.symbols_field, self.file.source.path_field)?;
+        report.duration = started.elapsed();
+        Ok(report)
+    }
+
+    /// Index the files `source` holds at `root_path`, such as a [`crate::source::Archive`] at that
+    /// path, with the settings of the [`File`] source of the indexes. Documents stored
+    /// under `root_path` that `source` no longer holds are deleted.
+    pub async fn index_source(&self, source: impl FileSource, root_path: &Path) -> Result<IndexReport> {
+        let _write_lock = self.write_mutex.lock().await;
+        let started = Instant::now();
+        let sourced = Sourced::new(source).with_file(self.file.source.clone());
+        let mut writer = self.file.write_handle_for(&sourced)?;
+        let mut report = writer.index(root_path).await?;
+        if self.file.source.dry_run {
+            writer.rollback()?;
+            report.duration = started.elapsed();
+            return Ok(report);
+        }
+        writer.commit()?;
+        symbol_cache::refresh(&self.file.index, self.file.source.symbols_field, self.file.source.path_field)?;
+        roots::record(&self.file.index, &[root_path])?;
+        report.duration = started.elapsed();
+        Ok(report)
     }
 }
+
+/// The fingerprint shared by all the `roots`, which are then in the same state of the
+/// same repository.
+fn common_fingerprint(roots: &[&Path]) -> Result<Option<Fingerprint>> {
+    let fingerprints = roots.iter().map(|root| Fingerprint::of(root)).collect::<Result<Vec<_>>>()?;
+    let Some(Some(first)) = fingerprints.first() else {
+        return Ok(None);
+    };
+    Ok(fingerprints.iter().all(|other| other.as_ref() == Some(first)).then(|| first.clone()))
+}
@@ -1,11 +1,15 @@
 mod language;
 mod namespace;
 mod scope_resolution;
+pub mod builtins;
 pub mod code_navigation;
+pub mod file_summary;
+pub mod public_api;
+pub mod symbol_docs;
 
 
 pub use {
-    language::{Language, MemoizedQuery, TSLanguage, TSLanguageConfig, ALL_LANGUAGES},
+    language::{Language, MemoizedQuery, TSLanguage, TSLanguageConfig, ALL_LANGUAGES, PLAINTEXT},
     namespace::*,
     scope_resolution::{NodeKind, ScopeGraph},
 };
@@ -13,6 +17,29 @@ pub use {
 use scope_resolution::ResolutionMethod;
 use tree_sitter::{Parser, Tree};
 
+/// Syntax node kinds, across all supported grammars, that delimit a function body.
+const FUNCTION_NODE_KINDS: &[&str] = &[
+    // c, c++, python, r
+    "function_definition",
+    // rust
+    "function_item",
+    // go, javascript, typescript
+    "function_declaration",
+    "method_declaration",
+    "func_literal",
+    "generator_function_declaration",
+    "function",
+    "function_expression",
+    "arrow_function",
+    "method_definition",
+    // java, c#
+    "constructor_declaration",
+    "local_function_statement",
+    // ruby
+    "method",
+    "singleton_method",
+];
+
 /// A tree-sitter representation of a file
 pub struct TreeSitterFile<'a> {
     /// The original source that was used to generate this file.
@@ -83,6 +110,34 @@ impl<'a> TreeSitterFile<'a> {
             .collect::<Vec<_>>())
     }
 
+    /// Produce the byte ranges of every function-like node in this file.
+    pub fn function_ranges(self) -> Result<Vec<std::ops::Range<usize>>, TreeSitterFileError> {
+        let mut ranges = Vec::new();
+        let mut cursor = self.tree.walk();
+
+        'walk: loop {
+            let node = cursor.node();
+            if FUNCTION_NODE_KINDS.contains(&node.kind()) {
+                ranges.push(node.byte_range());
+            }
+
+            if cursor.goto_first_child() || cursor.goto_next_sibling() {
+                continue;
+            }
+
+            loop {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+                if cursor.goto_next_sibling() {
+                    continue 'walk;
+                }
+            }
+        }
+
+        Ok(ranges)
+    }
+
     /// Produce a lexical scope-graph for this TreeSitterFile.
     pub fn scope_graph(self) -> Result<ScopeGraph, TreeSitterFileError> {
         let query = self
@@ -94,4 +149,131 @@ impl<'a> TreeSitterFile<'a> {
 
         Ok(ResolutionMethod::Generic.build_scope(query, root_node, self.src, self.language))
     }
+
+    /// The token at the byte `byte`, or the one ending there when there is none or only
+    /// punctuation, under a cursor placed right after a word. `None` between tokens.
+    pub fn token_at(&self, byte: usize) -> Option<SyntaxToken> {
+        let token_on = |byte: usize| {
+            let node = self.tree.root_node().descendant_for_byte_range(byte, byte + 1)?;
+            if !node.byte_range().contains(&byte) {
+                return None;
+            }
+            Some(SyntaxToken {
+                kind: node.kind(),
+                class: TokenClass::of(node)?,
+                range: node.range().into(),
+            })
+        };
+
+        let at = token_on(byte);
+        if at.as_ref().is_some_and(|token| token.class != TokenClass::Punctuation) {
+            return at;
+        }
+        let before = byte
+            .checked_sub(1)
+            .filter(|&before| self.src.get(before) != Some(&b'\n'))
+            .and_then(token_on)
+            .filter(|token| token.class != TokenClass::Punctuation);
+        before.or(at)
+    }
+
+    /// Every token of the file, in order. Literals and comments are single tokens,
+    /// whatever their parts.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        let mut cursor = self.tree.walk();
+
+        'walk: loop {
+            let node = cursor.node();
+            match TokenClass::of(node) {
+                Some(class) => tokens.push(SyntaxToken {
+                    kind: node.kind(),
+                    class,
+                    range: node.range().into(),
+                }),
+                None if cursor.goto_first_child() => continue,
+                None => {}
+            }
+            if cursor.goto_next_sibling() {
+                continue;
+            }
+
+            loop {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+                if cursor.goto_next_sibling() {
+                    continue 'walk;
+                }
+            }
+        }
+        tokens
+    }
+}
+
+/// A token of a [`TreeSitterFile`]
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    /// The kind of its syntax node, e.g. `field_identifier`
+    pub kind: &'static str,
+    pub class: TokenClass,
+    pub range: crate::text_range::TextRange,
+}
+
+/// What a token is, across grammars
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenClass {
+    Identifier,
+    Keyword,
+    /// Strings, numbers, booleans and null values, and the parts of a string
+    Literal,
+    Comment,
+    Punctuation,
+    /// Other named leaves, such as primitive types or `self`
+    Other,
+}
+
+impl TokenClass {
+    /// Its name, as serialized
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Identifier => "identifier",
+            Self::Keyword => "keyword",
+            Self::Literal => "literal",
+            Self::Comment => "comment",
+            Self::Punctuation => "punctuation",
+            Self::Other => "other",
+        }
+    }
+
+    /// The class of `node`, `None` unless it is a token: a leaf, or a whole literal
+    /// or comment.
+    fn of(node: tree_sitter::Node<'_>) -> Option<Self> {
+        const LITERAL_KINDS: &[&str] = &[
+            "string", "char", "number", "integer", "float", "literal", "boolean", "true", "false", "null", "nil", "none",
+        ];
+        let kind = node.kind();
+        let in_literal = node.parent().is_some_and(|parent| {
+            let kind = parent.kind();
+            parent.is_named() && (kind.contains("string") || kind.contains("literal"))
+        });
+
+        let class = if kind.contains("comment") {
+            Self::Comment
+        } else if node.is_named() && kind.contains("identifier") {
+            Self::Identifier
+        } else if in_literal || (node.is_named() && LITERAL_KINDS.iter().any(|literal| kind.contains(literal))) {
+            Self::Literal
+        } else if node.child_count() > 0 {
+            return None;
+        } else if node.is_named() {
+            Self::Other
+        } else if kind.chars().all(|c| c.is_alphabetic() || c == '_') {
+            Self::Keyword
+        } else {
+            Self::Punctuation
+        };
+        Some(class)
+    }
 }
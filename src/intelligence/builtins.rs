@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+
+use crate::intelligence::TSLanguage;
+
+/// A symbol defined outside the repository, by the language itself or its standard library
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalSymbol {
+    pub language: String,
+    /// Module providing the symbol, e.g. `std::collections` or `builtins`
+    pub module: String,
+    pub name: String,
+}
+
+type Modules = &'static [(&'static str, &'static [&'static str])];
+
+const RUST: Modules = &[
+    ("core::primitive", &[
+        "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32",
+        "i64", "i128", "isize", "f32", "f64",
+    ]),
+    ("std::prelude", &[
+        "Option", "Some", "None", "Result", "Ok", "Err", "Vec", "String", "Box", "ToString",
+        "ToOwned", "Clone", "Copy", "Default", "Drop", "Eq", "PartialEq", "Ord", "PartialOrd",
+        "Iterator", "IntoIterator", "Extend", "FromIterator", "From", "Into", "TryFrom",
+        "TryInto", "AsRef", "AsMut", "Fn", "FnMut", "FnOnce", "Send", "Sync", "Sized", "Unpin",
+        "drop",
+    ]),
+    ("std::collections", &["HashMap", "HashSet", "BTreeMap", "BTreeSet", "VecDeque", "BinaryHeap"]),
+    ("std::rc", &["Rc"]),
+    ("std::sync", &["Arc", "Mutex", "RwLock"]),
+    ("std::cell", &["Cell", "RefCell"]),
+    ("std::fmt", &["Debug", "Display"]),
+    ("std::hash", &["Hash"]),
+    ("std::macros", &[
+        "println", "print", "eprintln", "eprint", "format", "write", "writeln", "vec", "panic",
+        "assert", "assert_eq", "assert_ne", "debug_assert", "unreachable", "todo",
+        "unimplemented", "matches", "dbg",
+    ]),
+];
+
+const PYTHON: Modules = &[
+    ("builtins", &[
+        "print", "len", "range", "enumerate", "zip", "map", "filter", "sorted", "reversed",
+        "sum", "min", "max", "abs", "any", "all", "open", "isinstance", "issubclass", "type",
+        "super", "object", "int", "float", "str", "bytes", "bool", "list", "dict", "set",
+        "tuple", "frozenset", "iter", "next", "getattr", "setattr", "hasattr", "delattr",
+        "repr", "hash", "id", "input", "round", "divmod", "callable", "vars", "dir",
+        "staticmethod", "classmethod", "property", "Exception", "BaseException",
+        "ValueError", "TypeError", "KeyError", "IndexError", "AttributeError",
+        "RuntimeError", "StopIteration", "NotImplementedError", "OSError", "None", "True",
+        "False",
+    ]),
+    ("os", &["os"]),
+    ("sys", &["sys"]),
+    ("typing", &["Any", "Optional", "Union", "List", "Dict", "Tuple", "Callable", "Iterable"]),
+];
+
+const JAVASCRIPT: Modules = &[
+    ("globals", &[
+        "console", "Object", "Array", "String", "Number", "Boolean", "Symbol", "BigInt",
+        "Math", "JSON", "Date", "RegExp", "Error", "TypeError", "RangeError", "Promise", "Map",
+        "Set", "WeakMap", "WeakSet", "Proxy", "Reflect", "parseInt", "parseFloat", "isNaN",
+        "setTimeout", "setInterval", "clearTimeout", "clearInterval", "fetch", "undefined",
+        "globalThis", "window", "document", "require", "module", "exports",
+    ]),
+];
+
+const TYPESCRIPT: Modules = &[
+    ("lib.es5", &[
+        "Partial", "Required", "Readonly", "Record", "Pick", "Omit", "Exclude", "Extract",
+        "NonNullable", "ReturnType", "Parameters", "InstanceType", "Awaited",
+    ]),
+];
+
+const GO: Modules = &[
+    ("builtin", &[
+        "append", "cap", "close", "copy", "delete", "len", "make", "new", "panic", "print",
+        "println", "recover", "error", "string", "bool", "byte", "rune", "int", "int8", "int16",
+        "int32", "int64", "uint", "uint8", "uint16", "uint32", "uint64", "uintptr", "float32",
+        "float64", "complex64", "complex128", "any", "nil", "true", "false", "iota",
+    ]),
+    ("fmt", &["fmt"]),
+];
+
+const JAVA: Modules = &[
+    ("java.lang", &[
+        "Object", "String", "StringBuilder", "Integer", "Long", "Double", "Float", "Boolean",
+        "Character", "Byte", "Short", "Math", "System", "Thread", "Runnable", "Exception",
+        "RuntimeException", "IllegalArgumentException", "IllegalStateException",
+        "NullPointerException", "Override", "Deprecated", "Iterable", "Comparable",
+    ]),
+    ("java.util", &[
+        "List", "ArrayList", "LinkedList", "Map", "HashMap", "TreeMap", "Set", "HashSet",
+        "TreeSet", "Optional", "Collections", "Arrays", "Objects",
+    ]),
+];
+
+const C_SHARP: Modules = &[
+    ("System", &[
+        "Console", "String", "Object", "Math", "Exception", "ArgumentException",
+        "InvalidOperationException", "DateTime", "TimeSpan", "Guid", "Int32", "Int64",
+    ]),
+    ("System.Collections.Generic", &["List", "Dictionary", "HashSet", "IEnumerable", "IList"]),
+    ("System.Threading.Tasks", &["Task"]),
+];
+
+const C: Modules = &[
+    ("stdio.h", &["printf", "fprintf", "sprintf", "snprintf", "scanf", "fopen", "fclose", "fread", "fwrite", "puts", "FILE"]),
+    ("stdlib.h", &["malloc", "calloc", "realloc", "free", "exit", "abort", "atoi", "qsort"]),
+    ("string.h", &["memcpy", "memmove", "memset", "memcmp", "strlen", "strcmp", "strncmp", "strcpy", "strncpy", "strcat"]),
+    ("stddef.h", &["size_t", "NULL"]),
+    ("stdint.h", &["int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t"]),
+];
+
+const CPP: Modules = &[
+    ("std", &[
+        "std", "string", "vector", "map", "unordered_map", "set", "unordered_set", "pair",
+        "tuple", "optional", "unique_ptr", "shared_ptr", "make_unique", "make_shared", "move",
+        "forward", "cout", "cerr", "endl", "sort", "find",
+    ]),
+];
+
+const RUBY: Modules = &[
+    ("Kernel", &["puts", "print", "p", "require", "require_relative", "raise", "loop", "lambda", "proc", "attr_accessor", "attr_reader", "attr_writer"]),
+    ("core", &["String", "Integer", "Float", "Array", "Hash", "Symbol", "Object", "StandardError", "nil", "true", "false"]),
+];
+
+const R: Modules = &[
+    ("base", &["c", "list", "vector", "length", "print", "paste", "paste0", "cat", "function", "library", "require", "data.frame", "matrix", "apply", "lapply", "sapply", "nrow", "ncol"]),
+];
+
+fn modules_of(lang: &str) -> Modules {
+    match lang {
+        "rust" => RUST,
+        "python" => PYTHON,
+        "javascript" => JAVASCRIPT,
+        "typescript" => TYPESCRIPT,
+        "go" => GO,
+        "java" => JAVA,
+        "c#" => C_SHARP,
+        "c" => C,
+        "c++" => CPP,
+        "ruby" => RUBY,
+        "r" => R,
+        _ => &[],
+    }
+}
+
+/// Recognise `name` as a well-known builtin or standard library symbol of `lang`.
+pub fn lookup(lang: &str, name: &str) -> Option<ExternalSymbol> {
+    let lang = TSLanguage::normalize(lang);
+
+    // TypeScript code uses the JavaScript globals as well, and C++ the C library
+    let fallback = match lang.as_str() {
+        "typescript" => JAVASCRIPT,
+        "c++" => C,
+        _ => &[],
+    };
+
+    modules_of(&lang)
+        .iter()
+        .chain(fallback)
+        .find(|(_, names)| names.contains(&name))
+        .map(|(module, _)| ExternalSymbol {
+            language: lang.clone(),
+            module: module.to_string(),
+            name: name.to_owned(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_builtins() {
+        let symbol = lookup("Rust", "HashMap").unwrap();
+        assert_eq!(symbol.module, "std::collections");
+        assert_eq!(symbol.language, "rust");
+
+        assert_eq!(lookup("python", "isinstance").unwrap().module, "builtins");
+        assert_eq!(lookup("TypeScript", "Promise").unwrap().module, "globals");
+        assert_eq!(lookup("C++", "memcpy").unwrap().module, "string.h");
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_eq!(lookup("rust", "my_function"), None);
+        assert_eq!(lookup("python", "HashMap"), None);
+        assert_eq!(lookup("cobol", "print"), None);
+    }
+}
@@ -1,27 +1,72 @@
-use std::{collections::HashSet, ops::Not};
+use std::{collections::HashSet, ops::Not, str::FromStr};
 
-use serde::Serialize;
+use anyhow::Result;
+use globset::GlobMatcher;
+use rayon::prelude::*;
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 
 use crate::{
     content_document::ContentDocument,
     snippet::{Snipper, Snippet},
     text_range::TextRange,
-    intelligence::NodeKind,
+    intelligence::{NodeKind, TSLanguage},
 };
 
-#[derive(Debug, Serialize)]
+/// Repository-wide lookups keep the occurrences of this many files, the first by path,
+/// which bounds the responses about very common identifiers.
+pub const MAX_REPO_WIDE_FILES: usize = 200;
+
+/// The occurrences of a token, see [`CodeNavigationContext::token_occurrences`]
+#[derive(Debug, Clone, Default)]
+pub struct TokenOccurrences {
+    pub files: Vec<FileSymbols>,
+    /// Whether a repository-wide lookup found occurrences in more than
+    /// [`MAX_REPO_WIDE_FILES`] files, and kept only the first of them
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct FileSymbols {
     /// The file to which the following occurrences belong
     pub file: String,
     /// A collection of symbol locations with context in this file
     pub data: Vec<Occurrence>,
+    /// Whether the file comes from a read-only dependency index rather than the repository
+    pub external: bool,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Debug, Clone)]
 pub struct Occurrence {
     pub kind: OccurrenceKind,
     pub range: TextRange,
     pub snippet: Snippet,
+    /// For definitions, the number of references found along with them
+    pub reference_count: Option<usize>,
+}
+
+/// Along with the `range`, both ends are spelled out as `start_line`, `start_column`,
+/// `start_byte`, `end_line`, `end_column` and `end_byte`, for callers editing or
+/// highlighting the occurrence.
+impl Serialize for Occurrence {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (start, end) = (self.range.start, self.range.end);
+        let fields = 9 + usize::from(self.reference_count.is_some());
+
+        let mut state = serializer.serialize_struct("Occurrence", fields)?;
+        state.serialize_field("kind", &self.kind)?;
+        state.serialize_field("range", &self.range)?;
+        state.serialize_field("start_line", &start.line)?;
+        state.serialize_field("start_column", &start.column)?;
+        state.serialize_field("start_byte", &start.byte)?;
+        state.serialize_field("end_line", &end.line)?;
+        state.serialize_field("end_column", &end.column)?;
+        state.serialize_field("end_byte", &end.byte)?;
+        state.serialize_field("snippet", &self.snippet)?;
+        if let Some(reference_count) = self.reference_count {
+            state.serialize_field("reference_count", &reference_count)?;
+        }
+        state.end()
+    }
 }
 
 impl Occurrence {
@@ -30,7 +75,7 @@ impl Occurrence {
     }
 }
 
-#[derive(Serialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum OccurrenceKind {
     #[default]
@@ -38,6 +83,71 @@ pub enum OccurrenceKind {
     Definition,
 }
 
+impl FromStr for OccurrenceKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "definition" => Ok(Self::Definition),
+            "reference" => Ok(Self::Reference),
+            _ => anyhow::bail!("Invalid occurrence kind `{s}`, expected `definition` or `reference`"),
+        }
+    }
+}
+
+/// The occurrences a lookup returns, checked while resolving it, so that the files
+/// it rules out are never searched for the token, and the language it reads them in
+#[derive(Debug, Clone, Default)]
+pub struct OccurrenceFilter {
+    /// Only occurrences of this kind, both if `None`
+    pub kind: Option<OccurrenceKind>,
+    /// Only occurrences in the files whose stored path matches
+    pub paths: Option<GlobMatcher>,
+    /// The language of the file of the token, and of the files searched for it, instead
+    /// of the language of its extension
+    pub lang: Option<&'static str>,
+}
+
+impl OccurrenceFilter {
+    /// Only the definitions if `kind` is [`OccurrenceKind::Definition`], only the
+    /// references otherwise.
+    pub fn kind(mut self, kind: OccurrenceKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Only the occurrences in files matching `glob`, anchored like the globs of
+    /// [`crate::search::path_matcher`].
+    pub fn path_glob(mut self, glob: &str) -> Result<Self> {
+        self.paths = Some(crate::search::path_matcher(glob)?);
+        Ok(self)
+    }
+
+    /// Only the occurrences in the files under `dir`, see [`Self::path_glob`].
+    pub fn directory(self, dir: &str) -> Result<Self> {
+        self.path_glob(&crate::path_prefix::dir_glob(dir))
+    }
+
+    /// Read the file of the token as `lang`, e.g. a `.h` header of a C++ project as
+    /// `c++` rather than C. Any name of [`TSLanguage::canonical_name`] will do.
+    pub fn lang(mut self, lang: &str) -> Result<Self> {
+        self.lang = Some(TSLanguage::supported_name(lang)?);
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kind.is_none() && self.paths.is_none() && self.lang.is_none()
+    }
+
+    pub fn wants(&self, kind: OccurrenceKind) -> bool {
+        self.kind.map_or(true, |wanted| wanted == kind)
+    }
+
+    pub fn includes(&self, path: &str) -> bool {
+        self.paths.as_ref().map_or(true, |paths| paths.is_match(path))
+    }
+}
+
 pub enum CodeNavigationError {}
 
 pub struct CodeNavigationContext<'a, 'b> {
@@ -45,6 +155,7 @@ pub struct CodeNavigationContext<'a, 'b> {
     pub all_docs: &'b [ContentDocument],
     pub source_document_idx: usize,
     pub snipper: Option<Snipper>,
+    pub filter: OccurrenceFilter,
 }
 
 impl<'a, 'b> CodeNavigationContext<'a, 'b> {
@@ -71,6 +182,7 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
                 all_docs
                     .iter()
                     .filter(|doc| doc.relative_path != source_doc.relative_path)
+                    .filter(|doc| Self::mentions(doc, active_token_text))
                     .filter(|doc| {
                         let Some(scope_graph) = doc.symbol_locations.scope_graph() else {
                             return false;
@@ -112,6 +224,7 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
                         end_byte: source_sg.graph[idx].range().end.byte,
                     },
                     snipper: None,
+                    filter: OccurrenceFilter::default(),
                 }
                 .local_definitions()
                 .is_none()
@@ -127,6 +240,7 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
                 all_docs
                     .iter()
                     .filter(|doc| doc.relative_path != source_doc.relative_path)
+                    .filter(|doc| Self::mentions(doc, active_token_text))
                     .filter(|doc| {
                         let Some(scope_graph) = doc.symbol_locations.scope_graph() else {
                             return false;
@@ -154,6 +268,7 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
             source_document_idx: 0,
             token,
             snipper: None,
+            filter: OccurrenceFilter::default(),
         }
     }
 
@@ -162,54 +277,79 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
     }
 
     pub fn token_info(&self) -> Vec<FileSymbols> {
+        self.token_occurrences().files
+    }
+
+    /// The occurrences of the token, and whether the repository-wide lookups kept only
+    /// some of them.
+    pub fn token_occurrences(&self) -> TokenOccurrences {
+        use OccurrenceKind::{Definition, Reference};
+
         if self.is_definition() {
-            let local_references = self.local_references();
-            let repo_wide_references = self
-                .is_top_level()
+            let local_references = self.wants_local(Reference).then(|| self.local_references()).flatten();
+            let repo_wide_references = (self.is_top_level() && self.filter.wants(Reference))
                 .then(|| self.repo_wide_references())
                 .unwrap_or_default();
 
-            local_references
-                .into_iter()
-                .chain(repo_wide_references)
-                .collect()
+            TokenOccurrences {
+                files: local_references
+                    .into_iter()
+                    .chain(repo_wide_references.files)
+                    .collect(),
+                truncated: repo_wide_references.truncated,
+            }
         } else if self.is_reference() {
-            
+            // Local definitions are looked up even when the filter drops them, since they
+            // decide whether to search the repository.
             let local_definitions = self.local_definitions();
-            
-            let repo_wide_definitions = local_definitions
-                .is_none()
+            let repo_wide_definitions = (local_definitions.is_none() && self.filter.wants(Definition))
                 .then(|| self.repo_wide_definitions())
                 .unwrap_or_default();
 
-            let local_references = self.local_references();
-            let repo_wide_references = local_definitions
-                .is_none()
+            let local_references = self.wants_local(Reference).then(|| self.local_references()).flatten();
+            let repo_wide_references = (local_definitions.is_none() && self.filter.wants(Reference))
                 .then(|| self.repo_wide_references())
                 .unwrap_or_default();
 
             let imports = self.imports();
 
-            local_definitions
-                .or(imports)
-                .into_iter()
-                .chain(repo_wide_definitions)
-                .chain(local_references)
-                .chain(repo_wide_references)
-                .collect()
+            TokenOccurrences {
+                truncated: repo_wide_definitions.truncated || repo_wide_references.truncated,
+                files: local_definitions
+                    .or(imports)
+                    .filter(|_| self.wants_local(Definition))
+                    .into_iter()
+                    .chain(repo_wide_definitions.files)
+                    .chain(local_references)
+                    .chain(repo_wide_references.files)
+                    .collect(),
+            }
         } else if self.is_import() {
-            let local_references = self.local_references();
-            let repo_wide_definitions = self.repo_wide_definitions();
+            let local_references = self.wants_local(Reference).then(|| self.local_references()).flatten();
+            let repo_wide_definitions = self
+                .filter
+                .wants(Definition)
+                .then(|| self.repo_wide_definitions())
+                .unwrap_or_default();
 
-            repo_wide_definitions
-                .into_iter()
-                .chain(local_references)
-                .collect()
+            TokenOccurrences {
+                files: repo_wide_definitions
+                    .files
+                    .into_iter()
+                    .chain(local_references)
+                    .collect(),
+                truncated: repo_wide_definitions.truncated,
+            }
         } else {
-            Vec::new()
+            TokenOccurrences::default()
         }
     }
 
+    /// Whether the filter keeps the occurrences of `kind` in the source document.
+    fn wants_local(&self, kind: OccurrenceKind) -> bool {
+        self.filter.wants(kind) && self.filter.includes(self.token.relative_path)
+    }
+
     fn is_definition(&self) -> bool {
         self.source_document()
             .symbol_locations
@@ -254,10 +394,35 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
             .unwrap_or_default()
     }
 
-    fn non_source_documents(&self) -> impl Iterator<Item = &ContentDocument> {
+    /// The other documents that mention the active token and pass the filter, visited
+    /// in parallel.
+    fn candidate_documents(&self) -> impl ParallelIterator<Item = &ContentDocument> {
         self.all_docs
-            .iter()
+            .par_iter()
             .filter(|doc| doc.relative_path != self.source_document().relative_path)
+            .filter(|doc| self.filter.includes(&doc.relative_path))
+            .filter(|doc| Self::mentions(doc, self.active_token_text()))
+    }
+
+    /// Collect the occurrences found by `f` in every candidate document, sorted by path,
+    /// and keep those of the first [`MAX_REPO_WIDE_FILES`] files. Which files are kept
+    /// doesn't depend on the order the documents are visited in.
+    fn repo_wide<F>(&self, f: F) -> TokenOccurrences
+    where
+        F: Fn(&ContentDocument) -> Option<FileSymbols> + Sync + Send,
+    {
+        let mut files = self.candidate_documents().filter_map(f).collect::<Vec<_>>();
+
+        files.sort_by(|a, b| a.file.cmp(&b.file));
+        let truncated = files.len() > MAX_REPO_WIDE_FILES;
+        files.truncate(MAX_REPO_WIDE_FILES);
+        TokenOccurrences { files, truncated }
+    }
+
+    /// Whether the text of a token appears in `doc` at all. Checked before opening the
+    /// scope graph of `doc`, which is far more expensive.
+    fn mentions(doc: &ContentDocument, token_text: &str) -> bool {
+        doc.content.contains(token_text)
     }
 
     pub fn active_token_range(&self) -> std::ops::Range<usize> {
@@ -281,6 +446,7 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
                     scope_graph.graph[idx].range(),
                     self.snipper,
                 ),
+                reference_count: None,
             })
             .collect::<Vec<_>>();
 
@@ -289,40 +455,41 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
         data.is_empty().not().then(|| FileSymbols {
             file: self.token.relative_path.to_owned(),
             data,
+            external: false,
         })
     }
 
-    fn repo_wide_definitions(&self) -> Vec<FileSymbols> {
-        self.non_source_documents()
-            .filter_map(|doc| {
-                let scope_graph = doc.symbol_locations.scope_graph()?;
-                let content = doc.content.as_bytes();
-                let mut data = scope_graph
-                    .graph
-                    .node_indices()
-                    .filter(|idx| scope_graph.is_top_level(*idx))
-                    .filter(|idx| {
-                        if let Some(NodeKind::Def(d)) = scope_graph.get_node(*idx) {
-                            d.name(content) == self.active_token_text().as_bytes()
-                        } else {
-                            false
-                        }
-                    })
-                    .map(|idx| Occurrence {
-                        kind: OccurrenceKind::Definition,
-                        range: scope_graph.graph[idx].range(),
-                        snippet: to_occurrence(doc, scope_graph.graph[idx].range(), self.snipper),
-                    })
-                    .collect::<Vec<_>>();
+    fn repo_wide_definitions(&self) -> TokenOccurrences {
+        self.repo_wide(|doc| {
+            let scope_graph = doc.symbol_locations.scope_graph()?;
+            let content = doc.content.as_bytes();
+            let mut data = scope_graph
+                .graph
+                .node_indices()
+                .filter(|idx| scope_graph.is_top_level(*idx))
+                .filter(|idx| {
+                    if let Some(NodeKind::Def(d)) = scope_graph.get_node(*idx) {
+                        d.name(content) == self.active_token_text().as_bytes()
+                    } else {
+                        false
+                    }
+                })
+                .map(|idx| Occurrence {
+                    kind: OccurrenceKind::Definition,
+                    range: scope_graph.graph[idx].range(),
+                    snippet: to_occurrence(doc, scope_graph.graph[idx].range(), self.snipper),
+                    reference_count: None,
+                })
+                .collect::<Vec<_>>();
 
-                data.sort_by_key(|occurrence| occurrence.range.start.byte);
+            data.sort_by_key(|occurrence| occurrence.range.start.byte);
 
-                data.is_empty().not().then(|| FileSymbols {
-                    file: doc.relative_path.to_owned(),
-                    data,
-                })
+            data.is_empty().not().then(|| FileSymbols {
+                file: doc.relative_path.to_owned(),
+                data,
+                external: false,
             })
-            .collect()
+        })
     }
 
     fn local_references(&self) -> Option<FileSymbols> {
@@ -341,6 +508,7 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
                     scope_graph.graph[idx].range(),
                     self.snipper,
                 ),
+                reference_count: None,
             })
             .collect::<Vec<_>>();
 
@@ -351,41 +519,42 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
         data.is_empty().not().then(|| FileSymbols {
             file: self.token.relative_path.to_owned(),
             data,
+            external: false,
         })
     }
 
-    fn repo_wide_references(&self) -> Vec<FileSymbols> {
-        self.non_source_documents()
-            .filter_map(|doc| {
-                let scope_graph = doc.symbol_locations.scope_graph()?;
-                let content = doc.content.as_bytes();
-                let mut data = scope_graph
-                    .graph
-                    .node_indices()
-                    .filter(|idx| scope_graph.is_top_level(*idx))
-                    .filter(|idx| match scope_graph.get_node(*idx).unwrap() {
-                        NodeKind::Def(n) => n.name(content) == self.active_token_text().as_bytes(),
-                        NodeKind::Import(n) => {
-                            n.name(content) == self.active_token_text().as_bytes()
-                        }
-                        _ => false,
-                    })
-                    .flat_map(|idx| scope_graph.references(idx))
-                    .map(|idx| Occurrence {
-                        kind: OccurrenceKind::Reference,
-                        range: scope_graph.graph[idx].range(),
-                        snippet: to_occurrence(doc, scope_graph.graph[idx].range(), self.snipper),
-                    })
-                    .collect::<Vec<_>>();
+    fn repo_wide_references(&self) -> TokenOccurrences {
+        self.repo_wide(|doc| {
+            let scope_graph = doc.symbol_locations.scope_graph()?;
+            let content = doc.content.as_bytes();
+            let mut data = scope_graph
+                .graph
+                .node_indices()
+                .filter(|idx| scope_graph.is_top_level(*idx))
+                .filter(|idx| match scope_graph.get_node(*idx).unwrap() {
+                    NodeKind::Def(n) => n.name(content) == self.active_token_text().as_bytes(),
+                    NodeKind::Import(n) => {
+                        n.name(content) == self.active_token_text().as_bytes()
+                    }
+                    _ => false,
+                })
+                .flat_map(|idx| scope_graph.references(idx))
+                .map(|idx| Occurrence {
+                    kind: OccurrenceKind::Reference,
+                    range: scope_graph.graph[idx].range(),
+                    snippet: to_occurrence(doc, scope_graph.graph[idx].range(), self.snipper),
+                    reference_count: None,
+                })
+                .collect::<Vec<_>>();
 
-                data.sort_by_key(|occurrence| occurrence.range.start.byte);
+            data.sort_by_key(|occurrence| occurrence.range.start.byte);
 
-                data.is_empty().not().then(|| FileSymbols {
-                    file: doc.relative_path.to_owned(),
-                    data,
-                })
+            data.is_empty().not().then(|| FileSymbols {
+                file: doc.relative_path.to_owned(),
+                data,
+                external: false,
             })
-            .collect()
+        })
     }
 
     fn imports(&self) -> Option<FileSymbols> {
@@ -401,6 +570,7 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
                     scope_graph.graph[idx].range(),
                     self.snipper,
                 ),
+                reference_count: None,
             })
             .collect::<Vec<_>>();
 
@@ -409,10 +579,28 @@ impl<'a, 'b> CodeNavigationContext<'a, 'b> {
         data.is_empty().not().then(|| FileSymbols {
             file: self.token.relative_path.to_owned(),
             data,
+            external: false,
         })
     }
 }
 
+/// Set the `reference_count` of every definition in `results` to the number of
+/// references in `results`, which are all references to the same symbol. Repo-wide
+/// lookups keep [`MAX_REPO_WIDE_FILES`] files, past which the count is a lower bound.
+pub fn count_references(results: &mut [FileSymbols]) {
+    let references = results
+        .iter()
+        .flat_map(|file_symbols| &file_symbols.data)
+        .filter(|occurrence| !occurrence.is_definition())
+        .count();
+
+    for occurrence in results.iter_mut().flat_map(|file_symbols| &mut file_symbols.data) {
+        if occurrence.is_definition() {
+            occurrence.reference_count = Some(references);
+        }
+    }
+}
+
 pub struct Token<'a> {
     pub relative_path: &'a str,
     pub start_byte: usize,
@@ -427,6 +615,7 @@ fn to_occurrence(doc: &ContentDocument, range: TextRange, snipper: Option<Snippe
         .unwrap_or_default()
         .expand(highlight, src, line_end_indices)
         .reify(src, &[])
+        .with_origin(&doc.relative_path, doc.lang.as_deref())
 }
 
 // ranges of defs in related_file_document used in source_document
@@ -0,0 +1,157 @@
+use serde::Serialize;
+
+use crate::content_document::ContentDocument;
+use crate::text_range;
+
+/// Symbol kinds, across all supported languages, counted as classes
+pub(crate) const CLASS_KINDS: &[&str] = &["class", "struct", "interface", "enum", "union", "record"];
+
+/// Symbol kinds, across all supported languages, counted as functions
+pub(crate) const FUNCTION_KINDS: &[&str] = &["function", "method", "func", "generator"];
+
+/// What a file is about: its header comment and how much it defines
+#[derive(Debug, Serialize)]
+pub struct FileSummary {
+    pub file: String,
+    pub lang: Option<String>,
+    pub lines: usize,
+    /// The module docstring, or the comment the file starts with, without its markers
+    pub header: Option<String>,
+    pub classes: usize,
+    pub functions: usize,
+}
+
+/// Summarize `doc` from its leading comment and the definitions of its scope graph.
+///
+/// Definitions nested in other definitions, like methods, are counted too.
+pub fn summarize(doc: &ContentDocument) -> FileSummary {
+    let lang = doc.lang.clone();
+    let content = doc.content.as_str();
+
+    let (mut classes, mut functions) = (0, 0);
+    if let Some(scope_graph) = doc.symbol_locations.scope_graph() {
+        for idx in scope_graph.graph.node_indices().filter(|&idx| scope_graph.is_definition(idx)) {
+            match scope_graph.symbol_name_of(idx) {
+                Some(kind) if CLASS_KINDS.contains(&kind) => classes += 1,
+                Some(kind) if FUNCTION_KINDS.contains(&kind) => functions += 1,
+                _ => {}
+            }
+        }
+    }
+
+    FileSummary {
+        file: doc.relative_path.clone(),
+        header: header_comment(lang.as_deref().unwrap_or_default(), content),
+        lang,
+        lines: text_range::line_count(content),
+        classes,
+        functions,
+    }
+}
+
+/// The module docstring of a Python file, or else the comment block a file starts
+/// with. A `#!` line is skipped, and so are the blank lines around the comment.
+fn header_comment(lang: &str, content: &str) -> Option<String> {
+    let mut rest = content.trim_start_matches('\u{feff}');
+    if rest.starts_with("#!") {
+        rest = rest.split_once('\n').map_or("", |(_, rest)| rest);
+    }
+    let rest = rest.trim_start();
+
+    let lines = match lang {
+        "python" => docstring(rest).or_else(|| line_comments(rest, &["#"]))?,
+        "ruby" | "r" => line_comments(rest, &["#"])?,
+        // `//!` documents the module, a plain comment above it is usually a license
+        "rust" => line_comments(rest, &["//!"])
+            .or_else(|| block_comment(rest, "/*!"))
+            .or_else(|| line_comments(rest, &["//"]))
+            .or_else(|| block_comment(rest, "/*"))?,
+        _ => line_comments(rest, &["//"]).or_else(|| block_comment(rest, "/*"))?,
+    };
+
+    let lines = lines.iter().map(|line| line.trim_end()).collect::<Vec<_>>();
+    let start = lines.iter().position(|line| !line.trim().is_empty())?;
+    let end = lines.iter().rposition(|line| !line.trim().is_empty())?;
+    Some(dedent(&lines[start..=end]))
+}
+
+/// The consecutive lines at the start of `content` that are comments with one of
+/// `markers`, without it.
+fn line_comments<'a>(content: &'a str, markers: &[&str]) -> Option<Vec<&'a str>> {
+    let lines = content
+        .lines()
+        .map_while(|line| {
+            let line = line.trim_start();
+            markers.iter().find_map(|marker| line.strip_prefix(marker))
+        })
+        // `///` documents the item that follows, `//!` is matched as its own marker
+        .take_while(|line| !markers.contains(&"//") || !line.starts_with(['/', '!']))
+        .collect::<Vec<_>>();
+    (!lines.is_empty()).then_some(lines)
+}
+
+/// The lines of the `/* .. */` comment `content` starts with, opened by `opening`,
+/// without the leading `*` of each line.
+fn block_comment<'a>(content: &'a str, opening: &str) -> Option<Vec<&'a str>> {
+    let body = content.strip_prefix(opening)?;
+    let body = &body[..body.find("*/")?];
+    Some(
+        body.lines()
+            .map(|line| {
+                let trimmed = line.trim_start();
+                match trimmed.strip_prefix('*') {
+                    Some(rest) => rest,
+                    None => line,
+                }
+            })
+            .collect(),
+    )
+}
+
+/// The lines of the string literal a Python file starts with.
+pub(crate) fn docstring(content: &str) -> Option<Vec<&str>> {
+    let body = content.trim_start_matches(['r', 'R', 'u', 'U']);
+    let quotes = ["\"\"\"", "'''", "\"", "'"].into_iter().find(|quotes| body.starts_with(quotes))?;
+    let body = &body[quotes.len()..];
+    Some(body[..body.find(quotes)?].lines().collect())
+}
+
+/// Join `lines`, removing the indentation they all share.
+pub(crate) fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or(line.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_comments() {
+        let python = "#!/usr/bin/env python\n\"\"\"Parse the config.\n\n    Keys are case-sensitive.\n\"\"\"\nimport os\n";
+        assert_eq!(
+            header_comment("python", python).as_deref(),
+            Some("Parse the config.\n\n    Keys are case-sensitive.")
+        );
+        assert_eq!(header_comment("python", "# Helpers\n# for tests\nx = 1\n").as_deref(), Some("Helpers\nfor tests"));
+
+        let rust = "//! Search the index.\n//!\n//! With tantivy.\n\nuse std::fs;\n";
+        assert_eq!(header_comment("rust", rust).as_deref(), Some("Search the index.\n\nWith tantivy."));
+        assert_eq!(header_comment("rust", "/// Documents `main`\nfn main() {}\n"), None);
+
+        let go = "/*\n * Package cache keeps recent results.\n */\npackage cache\n";
+        assert_eq!(header_comment("go", go).as_deref(), Some("Package cache keeps recent results."));
+
+        assert_eq!(header_comment("javascript", "const a = 1; // not a header\n"), None);
+    }
+}
@@ -13,6 +13,8 @@ mod typescript;
 #[cfg(test)]
 mod test_utils;
 
+use std::path::Path;
+
 use once_cell::sync::OnceCell;
 
 use super::NameSpaces;
@@ -95,11 +97,36 @@ impl MemoizedQuery {
 
 pub type TSLanguage = Language<TSLanguageConfig>;
 
+/// The name stored for files of no supported language
+pub const PLAINTEXT: &str = "plaintext";
+
+/// Canonical language names, with the other names each language goes by. Canonical
+/// names are the lowercase first `language_ids` of the languages, and what indexes store.
+static LANGUAGE_NAMES: &[(&str, &[&str])] = &[
+    ("c", &[]),
+    ("c++", &["cpp", "cxx", "cc", "hpp"]),
+    ("c#", &["csharp", "c_sharp", "cs"]),
+    ("go", &["golang"]),
+    ("java", &[]),
+    ("javascript", &["js", "jsx", "node"]),
+    ("python", &["py", "python3"]),
+    ("r", &[]),
+    ("ruby", &["rb"]),
+    ("rust", &["rs"]),
+    ("typescript", &["ts", "tsx"]),
+    (PLAINTEXT, &["text", "txt", "plain"]),
+];
+
 impl TSLanguageConfig {
+    /// The canonical name of the language, see [`TSLanguage::canonical_name`].
+    pub fn name(&self) -> &'static str {
+        TSLanguage::canonical_name(self.language_ids[0]).expect("every language has a canonical name")
+    }
+
     pub fn from_extension(extension: &str) -> Option<&'static str> {
         for lang in ALL_LANGUAGES.iter() {
             if lang.file_extensions.contains(&extension) {
-                return Some(lang.language_ids[0]);
+                return Some(lang.name());
             }
         }
         None
@@ -107,27 +134,61 @@ impl TSLanguageConfig {
 }
 
 impl TSLanguage {
-    /// Find a tree-sitter language configuration from a language identifier
+    /// Find a tree-sitter language configuration from a language identifier, or any
+    /// name accepted by [`canonical_name`](Self::canonical_name).
     ///
     /// See [0] for a list of valid language identifiers.
     ///
     /// [0]: https://github.com/monkslc/hyperpolyglot/blob/master/src/codegen/languages.rs
     pub fn from_id(lang_id: &str) -> Self {
+        let Some(name) = Self::canonical_name(lang_id) else {
+            return Language::Unsupported;
+        };
         ALL_LANGUAGES
             .iter()
             .copied()
-            .find(|target| {
-                target
-                    .language_ids
-                    .iter()
-                    .any(|&id| id.to_lowercase() == lang_id.to_lowercase())
-            })
+            .find(|target| target.name() == name)
             .map_or(Language::Unsupported, Language::Supported)
     }
 
     pub fn from_extension(extension: &str) -> Option<&'static str> {
         TSLanguageConfig::from_extension(extension)
     }
+
+    /// The canonical name of the language of the file at `path`, [`PLAINTEXT`] if it
+    /// isn't a supported one.
+    pub fn of_path(path: &Path) -> &'static str {
+        let extension = path.extension().and_then(std::ffi::OsStr::to_str).unwrap_or("");
+        Self::from_extension(extension).unwrap_or(PLAINTEXT)
+    }
+
+    /// The canonical name of the language called `name`, ignoring case: `C++`, `cpp`
+    /// and `cxx` are all `c++`, `golang` is `go`. `None` for unknown languages.
+    pub fn canonical_name(name: &str) -> Option<&'static str> {
+        let name = name.trim().to_lowercase();
+        LANGUAGE_NAMES
+            .iter()
+            .find(|(canonical, aliases)| *canonical == name || aliases.contains(&name.as_str()))
+            .map(|(canonical, _)| *canonical)
+    }
+
+    /// `name` as it is stored and compared: its canonical name if the language is
+    /// known, lowercase otherwise.
+    pub fn normalize(name: &str) -> String {
+        Self::canonical_name(name).map_or_else(|| name.trim().to_lowercase(), str::to_owned)
+    }
+
+    /// The canonical name of the supported language called `name`, for callers naming
+    /// the language of a file rather than leaving it to its extension.
+    pub fn supported_name(name: &str) -> anyhow::Result<&'static str> {
+        match Self::from_id(name) {
+            Language::Supported(config) => Ok(config.name()),
+            Language::Unsupported => {
+                let names = ALL_LANGUAGES.iter().map(|config| config.name()).collect::<Vec<_>>();
+                anyhow::bail!("Unsupported language `{name}`, expected one of {}", names.join(", "))
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -160,6 +221,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn language_names() {
+        for language in ALL_LANGUAGES {
+            for id in language.language_ids {
+                let Language::Supported(config) = TSLanguage::from_id(id) else {
+                    panic!("{id} has no canonical name");
+                };
+                assert_eq!(config.name(), language.name(), "{id}");
+            }
+        }
+
+        assert_eq!(TSLanguage::canonical_name("C++"), Some("c++"));
+        assert_eq!(TSLanguage::canonical_name("cpp"), Some("c++"));
+        assert_eq!(TSLanguage::canonical_name("golang"), Some("go"));
+        assert_eq!(TSLanguage::canonical_name(" TSX "), Some("typescript"));
+        assert_eq!(TSLanguage::canonical_name("cobol"), None);
+        assert_eq!(TSLanguage::normalize("COBOL"), "cobol");
+        assert_eq!(TSLanguage::from_extension("rs"), Some("rust"));
+        assert_eq!(TSLanguage::supported_name("cpp").unwrap(), "c++");
+        assert!(TSLanguage::supported_name("plaintext").is_err());
+    }
+
     fn has_valid_symbol_kinds(query: &Query, kinds: Vec<&str>) -> bool {
         let query_file_symbol_names = query
             .capture_names()
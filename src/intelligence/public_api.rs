@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{content_document::ContentDocument, intelligence::TSLanguage, text_range::TextRange};
+
+/// A symbol that a file exposes to the outside world
+#[derive(Debug, Serialize)]
+pub struct PublicSymbol {
+    pub name: String,
+    pub kind: Option<String>,
+    pub range: TextRange,
+}
+
+/// The public surface of a single file
+#[derive(Debug, Serialize)]
+pub struct PublicApi {
+    pub file: String,
+    pub symbols: Vec<PublicSymbol>,
+}
+
+/// Collect the top-level definitions of `doc` that are visible outside of it.
+///
+/// Visibility is decided per language with cheap textual heuristics on the line
+/// that introduces the definition:
+///
+/// - Rust: unrestricted `pub` items
+/// - Python: names listed in `__all__`, or every name not starting with `_`
+/// - JavaScript/TypeScript: `export` declarations and `export { .. }` lists
+/// - Go: capitalized names
+/// - Java/C#: `public` declarations
+/// - C/C++: everything that is not `static`
+/// - everything else: all top-level definitions
+pub fn public_symbols(doc: &ContentDocument) -> Vec<PublicSymbol> {
+    let Some(scope_graph) = doc.symbol_locations.scope_graph() else {
+        return Vec::new();
+    };
+
+    let lang = TSLanguage::normalize(doc.lang.as_deref().unwrap_or_default());
+    let content = doc.content.as_str();
+    let exported_names = exported_names(&lang, content);
+
+    let mut symbols = scope_graph
+        .graph
+        .node_indices()
+        .filter(|&idx| scope_graph.is_definition(idx) && scope_graph.is_top_level(idx))
+        .filter_map(|idx| {
+            let range = scope_graph.graph[idx].range();
+            let name = content.get(range.start.byte..range.end.byte)?;
+            let line_prefix = &content[line_start(content, range.start.byte)..range.start.byte];
+
+            is_public(&lang, name, line_prefix, &exported_names).then(|| PublicSymbol {
+                name: name.to_owned(),
+                kind: scope_graph.symbol_name_of(idx).map(ToOwned::to_owned),
+                range,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    symbols.sort_by_key(|symbol| symbol.range.start.byte);
+    symbols
+}
+
+fn is_public(
+    lang: &str,
+    name: &str,
+    line_prefix: &str,
+    exported_names: &Option<HashSet<String>>,
+) -> bool {
+    let has_keyword = |keyword: &str| {
+        line_prefix
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .any(|word| word == keyword)
+    };
+
+    match lang {
+        // `pub(crate)` and friends are not part of the public surface
+        "rust" => has_keyword("pub") && !line_prefix.contains("pub("),
+        "python" => match exported_names {
+            Some(names) => names.contains(name),
+            None => !name.starts_with('_'),
+        },
+        "javascript" | "typescript" => {
+            has_keyword("export")
+                || exported_names
+                    .as_ref()
+                    .is_some_and(|names| names.contains(name))
+        }
+        "go" => name.chars().next().is_some_and(char::is_uppercase),
+        "java" | "c#" => has_keyword("public"),
+        "c" | "c++" => !has_keyword("static"),
+        _ => true,
+    }
+}
+
+/// Names explicitly exported by a file-level listing, such as Python's `__all__`
+/// or JavaScript's `export { a, b as c }`.
+///
+/// Returns `None` if the file has no such listing.
+fn exported_names(lang: &str, content: &str) -> Option<HashSet<String>> {
+    let (listing, item) = match lang {
+        "python" => (
+            Regex::new(r"(?m)^__all__\s*=\s*[\[\(]([^\]\)]*)[\]\)]").unwrap(),
+            Regex::new(r#"["']([A-Za-z_][A-Za-z0-9_]*)["']"#).unwrap(),
+        ),
+        "javascript" | "typescript" => (
+            Regex::new(r"export\s*\{([^}]*)\}").unwrap(),
+            Regex::new(r"(?:^|,)\s*([A-Za-z_$][A-Za-z0-9_$]*)").unwrap(),
+        ),
+        _ => return None,
+    };
+
+    let names = listing
+        .captures_iter(content)
+        .filter_map(|c| c.get(1))
+        .flat_map(|m| {
+            item.captures_iter(m.as_str())
+                .filter_map(|c| c.get(1))
+                .map(|n| n.as_str().to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect::<HashSet<_>>();
+
+    (!names.is_empty()).then_some(names)
+}
+
+fn line_start(content: &str, byte: usize) -> usize {
+    content[..byte].rfind('\n').map(|i| i + 1).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{intelligence::TreeSitterFile, symbol::SymbolLocations};
+
+    fn document(src: &str, lang: &str) -> ContentDocument {
+        let scope_graph = TreeSitterFile::try_build(src.as_bytes(), lang)
+            .and_then(TreeSitterFile::scope_graph)
+            .unwrap();
+
+        ContentDocument {
+            content: src.to_owned(),
+            lang: Some(TSLanguage::normalize(lang)),
+            relative_path: "test".to_owned(),
+            line_end_indices: crate::text_range::line_end_indices(src),
+            symbol_locations: SymbolLocations::TreeSitter(scope_graph).into(),
+            regions: Vec::new(),
+        }
+    }
+
+    fn names(doc: &ContentDocument) -> Vec<String> {
+        public_symbols(doc).into_iter().map(|s| s.name).collect()
+    }
+
+    #[test]
+    fn rust_pub_items() {
+        let doc = document("pub fn a() {}\nfn b() {}\npub(crate) struct C;\n", "Rust");
+        assert_eq!(names(&doc), vec!["a"]);
+    }
+
+    #[test]
+    fn python_dunder_all() {
+        let doc = document("__all__ = ['b']\n\ndef a():\n    pass\n\ndef b():\n    pass\n", "Python");
+        assert_eq!(names(&doc), vec!["b"]);
+
+        let doc = document("def a():\n    pass\n\ndef _b():\n    pass\n", "Python");
+        assert_eq!(names(&doc), vec!["a"]);
+    }
+
+    #[test]
+    fn javascript_exports() {
+        let doc = document(
+            "export function a() {}\nfunction b() {}\nfunction c() {}\nexport { c };\n",
+            "JavaScript",
+        );
+        assert_eq!(names(&doc), vec!["a", "c"]);
+    }
+}
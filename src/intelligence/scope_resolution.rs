@@ -409,6 +409,19 @@ impl ScopeGraph {
             .collect()
     }
 
+    /// The ranges of the definitions that have a symbol kind, the ones listed by
+    /// [`symbols`](Self::symbols), without allocating their kinds.
+    pub fn symbol_ranges(&self) -> impl Iterator<Item = TextRange> + '_ {
+        self.graph.node_weights().filter_map(|weight| match weight {
+            NodeKind::Def(LocalDef {
+                range,
+                symbol_id: Some(_),
+                ..
+            }) => Some(*range),
+            _ => None,
+        })
+    }
+
     // produce a stringified name of a def/ref's symbol
     pub fn symbol_name_of(&self, idx: NodeIndex) -> Option<&'static str> {
         let namespaces = ALL_LANGUAGES[self.lang_id].namespaces;
@@ -0,0 +1,124 @@
+use serde::Serialize;
+
+use crate::intelligence::file_summary::{dedent, docstring, CLASS_KINDS, FUNCTION_KINDS};
+use crate::intelligence::ScopeGraph;
+use crate::text_range::{self, TextRange};
+
+/// The documentation of a symbol
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SymbolDoc {
+    pub name: String,
+    pub kind: Option<String>,
+    /// The range of the name
+    pub range: TextRange,
+    /// Without its comment markers
+    pub doc: String,
+}
+
+/// The documented definitions of a file of `lang`: its top-level definitions, and the
+/// classes and functions nested in it, along with the comment right above them, past
+/// their attributes and decorators, or in Python their docstring.
+pub fn symbol_docs(lang: &str, content: &str, scope_graph: &ScopeGraph) -> Vec<SymbolDoc> {
+    let lines = content.lines().collect::<Vec<_>>();
+    let line_end_indices = text_range::line_end_indices(content);
+
+    let mut docs = scope_graph
+        .graph
+        .node_indices()
+        .filter(|&idx| scope_graph.is_definition(idx))
+        .filter_map(|idx| {
+            let kind = scope_graph.symbol_name_of(idx);
+            let documentable = kind.is_some_and(|kind| CLASS_KINDS.contains(&kind) || FUNCTION_KINDS.contains(&kind));
+            if !documentable && !scope_graph.is_top_level(idx) {
+                return None;
+            }
+            let range = scope_graph.graph[idx].range();
+            let name = content.get(range.start.byte..range.end.byte)?;
+            let doc = match lang {
+                "python" => body_docstring(content, &lines, &line_end_indices, range.start.line),
+                _ => None,
+            }
+            .or_else(|| comment_above(lang, &lines, range.start.line))?;
+
+            Some(SymbolDoc {
+                name: name.to_owned(),
+                kind: kind.map(str::to_owned),
+                range,
+                doc,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    docs.sort_by_key(|doc| doc.range.start.byte);
+    docs
+}
+
+/// The comment block ending on the line above `line`, or above the attributes and
+/// decorators there.
+fn comment_above(lang: &str, lines: &[&str], line: usize) -> Option<String> {
+    let hash_comments = matches!(lang, "python" | "ruby" | "r");
+    let is_attribute = |line: &str| line.starts_with('@') || (lang == "rust" && line.starts_with("#["));
+    let mut above = lines.get(..line)?.iter().rev().map(|line| line.trim()).peekable();
+    while above.next_if(|line| is_attribute(line)).is_some() {}
+
+    let mut comment = Vec::new();
+    if !hash_comments && above.peek().is_some_and(|line| line.ends_with("*/")) {
+        for line in above {
+            let opening = line.starts_with("/*");
+            let line = line.trim_end_matches("*/").trim_start_matches("/*").trim_start_matches(['*', '!']);
+            comment.push(line.trim());
+            if opening {
+                break;
+            }
+        }
+    } else {
+        let marker = if hash_comments { "#" } else { "//" };
+        // `//!` and `#!` lines document the file rather than what follows
+        let lines = above.map_while(|line| line.strip_prefix(marker)).take_while(|line| !line.starts_with('!'));
+        comment.extend(lines.map(|line| line.trim_start_matches(['/', '#']).trim()));
+    }
+    comment.reverse();
+
+    let start = comment.iter().position(|line| !line.is_empty())?;
+    let end = comment.iter().rposition(|line| !line.is_empty())?;
+    Some(comment[start..=end].join("\n"))
+}
+
+/// The docstring of the Python definition on `line`: the string its body starts with.
+fn body_docstring(content: &str, lines: &[&str], line_end_indices: &[u32], line: usize) -> Option<String> {
+    // the signature may span a few lines
+    let colon = (line..lines.len().min(line + 20)).find(|&i| lines[i].trim_end().ends_with(':'))?;
+    let body = (colon + 1..lines.len()).find(|&i| !lines[i].trim().is_empty())?;
+    let rest = content[text_range::line_start(body, line_end_indices)..].trim_start();
+    // the first line starts right after the quotes, the others are indented
+    let (first, others) = docstring(rest)?.split_first().map(|(first, others)| (first.trim(), dedent(others)))?;
+    let doc = format!("{first}\n{others}");
+    let doc = doc.trim();
+    (!doc.is_empty()).then(|| doc.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_above_definitions() {
+        let rust = "/// Parses the config.\n///\n/// Keys are case-sensitive.\n#[inline]\nfn parse() {}\n";
+        let lines = rust.lines().collect::<Vec<_>>();
+        assert_eq!(
+            comment_above("rust", &lines, 4).as_deref(),
+            Some("Parses the config.\n\nKeys are case-sensitive.")
+        );
+        assert_eq!(comment_above("rust", &lines, 0), None);
+
+        let js = "/**\n * Retries the request.\n */\nfunction retry() {}\n";
+        assert_eq!(comment_above("javascript", &js.lines().collect::<Vec<_>>(), 3).as_deref(), Some("Retries the request."));
+
+        let python = "def load(path):\n    \"\"\"Load the cache\n    from disk.\"\"\"\n    pass\n";
+        let lines = python.lines().collect::<Vec<_>>();
+        assert_eq!(
+            body_docstring(python, &lines, &text_range::line_end_indices(python), 0).as_deref(),
+            Some("Load the cache\nfrom disk.")
+        );
+    }
+}
@@ -1,72 +1,758 @@
+pub mod api;
 pub mod file;
+pub mod budget;
+pub mod capabilities;
+pub mod compaction;
+pub mod content_hash;
+pub mod continuation;
+pub mod file_heuristics;
+pub mod file_stats;
+pub mod fingerprint;
+pub mod format;
+pub mod grouping;
 pub mod indexes;
 pub mod intelligence;
+pub mod package;
+pub mod path_prefix;
+pub mod query;
+pub mod query_log;
+pub mod redaction;
+pub mod regions;
+pub mod remote;
 pub mod repository;
+pub mod revision;
+pub mod roots;
 pub mod sync_handle;
 pub mod symbol;
+pub mod symbol_cache;
 pub mod text_range;
 pub mod search;
+pub mod self_test;
+pub mod shard;
 pub mod schema;
 pub mod snippet;
+pub mod source;
 pub mod content_document;
+pub mod diff;
+pub mod edit;
+pub mod handle;
+pub mod hybrid;
+pub mod limiter;
+pub mod response_limits;
+pub mod error;
+pub mod events;
+pub mod owners;
+pub mod test_discovery;
+pub mod python;
+pub mod watcher;
 
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
 
+pub use api::{CodeNav, SearchSession};
+pub use budget::{EvictedFile, EvictionCategory, SizeBudget};
+pub use compaction::{CompactionPolicy, IndexHealth};
+pub use content_hash::HashAlgorithm;
+pub use continuation::Continuations;
+pub use edit::{Edit, RenameViolation, Replace, ViolationKind};
+pub use error::{ErrorCode, NavError};
+pub use events::{Events, IndexEvent};
 pub use file::File;
-pub use indexes::{Indexes, Indexable};
+pub use fingerprint::Fingerprint;
+pub use format::OnIncompatible;
+pub use handle::IndexHandle;
+pub use hybrid::{HybridHit, HybridSource};
+pub use indexes::{EntryError, IndexReport, Indexes, Indexable, Priority, Rename, SkippedFile};
+pub use package::{PackageInfo, Scope};
+pub use query::{OutputFormat, Query, QueryKind};
+pub use redaction::{Redactor, RegexRedactor};
 pub use repository::Repository;
-pub use search::Searcher;
-pub use sync_handle::SyncHandle;
+pub use response_limits::ResponseLimits;
+pub use search::{SearchResponse, Searcher};
+pub use shard::{ShardBy, ShardedIndexes, ShardedSearcher};
+pub use source::{Archive, ArchiveFormat, FileSource, Sourced};
+pub use sync_handle::{SyncHandle, SyncState, SyncStatus};
+pub use test_discovery::{TestEvidence, TestMatch, TestTarget};
+pub use text_range::LineBase;
+pub use snippet::ContextMode;
+
+use intelligence::code_navigation::{FileSymbols, OccurrenceFilter};
+use python::{health_dict, PyCursorToken, PyDefinedSymbol, PyEdit, PyIndexEvent, PyIndexEvents, PyIndexHandle, PyRenameViolation, PyIndexReport, PyOccurrence, PyRange, PySearchResult, PySelfTest, PySelfTestCheck, PySyncHandle};
+use api::{DEFAULT_BUFFER_SIZE, DEFAULT_THREADS};
+use response_limits::{DEFAULT_MAX_RESPONSE_BYTES, DEFAULT_MAX_RESULTS};
 
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use serde_json::json;
 
 
 /// Formats the sum of two numbers as string.
 #[pyfunction]
-fn go_to(root_path_str: &str, index_path_str: &str, relative_path: &str, line: usize, start_index: usize, end_index: usize) -> PyResult<String> {
-    let root_path = Path::new(root_path_str);
+#[pyo3(signature = (root_path_str, index_path_str, relative_path, line, start_index, end_index, context=None, dependencies=None, format=None, kind=None, path_glob=None, lang=None))]
+fn go_to(root_path_str: &str, index_path_str: &str, relative_path: &str, line: usize, start_index: usize, end_index: usize, context: Option<&str>, dependencies: Option<Vec<(String, String)>>, format: Option<&str>, kind: Option<&str>, path_glob: Option<&str>, lang: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+    let filter = parse_occurrence_filter(kind, path_glob, lang)?;
+    let (searcher, result) = resolve_token(root_path_str, index_path_str, relative_path, line, start_index, end_index, context, dependencies, &filter)?;
 
-    if !root_path.exists() {
-        return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Root path does not exist"));
+    format_go_to(&searcher, result, relative_path, line, start_index, end_index, filter.lang, format)
+        .map_err(|e| operation_error("Error retrieving token info", e))
+}
+
+/// Like `go_to` with `kind="definition"`: the definitions of the token, without its
+/// references.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, relative_path, line, start_index, end_index, context=None, dependencies=None, format=None, path_glob=None, lang=None))]
+fn go_to_definition(root_path_str: &str, index_path_str: &str, relative_path: &str, line: usize, start_index: usize, end_index: usize, context: Option<&str>, dependencies: Option<Vec<(String, String)>>, format: Option<&str>, path_glob: Option<&str>, lang: Option<&str>) -> PyResult<String> {
+    go_to(root_path_str, index_path_str, relative_path, line, start_index, end_index, context, dependencies, format, Some("definition"), path_glob, lang)
+}
+
+/// Like `go_to` with `kind="reference"`, unless `include_declaration`: the references to
+/// the token, in the files under `directory` if given.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, relative_path, line, start_index, end_index, include_declaration=false, directory=None, context=None, dependencies=None, format=None, lang=None))]
+fn find_references(root_path_str: &str, index_path_str: &str, relative_path: &str, line: usize, start_index: usize, end_index: usize, include_declaration: bool, directory: Option<&str>, context: Option<&str>, dependencies: Option<Vec<(String, String)>>, format: Option<&str>, lang: Option<&str>) -> PyResult<String> {
+    let kind = (!include_declaration).then_some("reference");
+    let path_glob = directory.map(path_prefix::dir_glob);
+    go_to(root_path_str, index_path_str, relative_path, line, start_index, end_index, context, dependencies, format, kind, path_glob.as_deref(), lang)
+}
+
+/// Formats the result of `token_info` in `format`, within the response limits,
+/// falling back to describing the token as a builtin symbol of the file's language, or
+/// of `lang`, when it resolves to nothing.
+fn format_go_to(searcher: &Searcher, result: Vec<FileSymbols>, relative_path: &str, line: usize, start_index: usize, end_index: usize, lang: Option<&str>, format: OutputFormat) -> anyhow::Result<String> {
+    let limits = ResponseLimits::global();
+
+    if result.is_empty() {
+        if let Some(symbol) = searcher.builtin_symbol(relative_path, line, start_index, end_index, lang)? {
+            return limits.fit(0, format, |_| {
+                Ok(match format {
+                    OutputFormat::Json => serde_json::to_string(&symbol)?,
+                    OutputFormat::Text | OutputFormat::Markdown => search::Searcher::format_external_symbol(symbol.clone()),
+                })
+            });
+        }
     }
 
-    let index_path = Path::new(index_path_str);
-    
-    if !index_path.exists() {
-        return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Index path does not exist"));
+    format_occurrence_page(Arc::new(result), 0, format)
+}
+
+/// Formats the occurrences of `results` from the `offset`th on in `format`, within the
+/// response limits, with a continuation token for those that don't fit.
+fn format_occurrence_page(results: Arc<Vec<FileSymbols>>, offset: usize, format: OutputFormat) -> anyhow::Result<String> {
+    let rest = continuation::occurrences_from(&results, offset);
+    let total = rest.iter().map(|file_symbols| file_symbols.data.len()).sum();
+    let (mut response, returned) = ResponseLimits::global()
+        .fit_counted(total, format, |n| search::Searcher::format_token_info_as(first_occurrences(&rest, n), format))?;
+
+    // a JSON response too big for a single occurrence has none to continue from
+    let continuation = (returned > 0 && returned < total).then(|| Continuations::global().keep(Arc::clone(&results), offset + returned));
+    match format {
+        OutputFormat::Json => {
+            let mut value: serde_json::Value = serde_json::from_str(&response)?;
+            if let Some(object) = value.as_object_mut() {
+                object.insert("continuation".to_owned(), json!(continuation));
+            }
+            Ok(serde_json::to_string(&value)?)
+        }
+        OutputFormat::Text | OutputFormat::Markdown => {
+            if let Some(token) = continuation {
+                response.push_str(&format!("[Continuation: pass \"{token}\" to go_to_continue for the next results]\n"));
+            }
+            Ok(response)
+        }
     }
-    
-    let buffer_size_per_thread = 15_000_000;
-    let num_threads = 4;
+}
+
+/// Returns the next occurrences of a `go_to` response cut by the response limits, from
+/// the continuation token it ended with, without resolving the token again.
+#[pyfunction]
+#[pyo3(signature = (continuation, format=None))]
+fn go_to_continue(py: Python<'_>, continuation: &str, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+
+    py.allow_threads(|| {
+        let (results, offset) = Continuations::global().resume(continuation)?;
+        format_occurrence_page(results, offset, format)
+    })
+    .map_err(|e| operation_error("Error retrieving token info", e))
+}
+
+/// The first `n` occurrences of `results`, still grouped by file.
+fn first_occurrences(results: &[FileSymbols], mut n: usize) -> Vec<FileSymbols> {
+    let mut first = Vec::new();
+    for file_symbols in results {
+        if n == 0 {
+            break;
+        }
+        let data = file_symbols.data.iter().take(n).cloned().collect::<Vec<_>>();
+        n -= data.len();
+        first.push(FileSymbols { data, ..file_symbols.clone() });
+    }
+    first
+}
+
+/// Formats the response to a query of `kind` in `format`, within the response limits.
+fn format_search(kind: &QueryKind, format: OutputFormat, response: SearchResponse) -> anyhow::Result<String> {
+    let SearchResponse { hits, suggestions } = response;
+
+    ResponseLimits::global().fit(hits.len(), format, |n| {
+        let response = SearchResponse {
+            hits: hits[..n].to_vec(),
+            suggestions: suggestions.clone(),
+        };
+        search::Searcher::format_response(kind, format, response)
+    })
+}
+
+/// Like `go_to`, returning `Occurrence` objects instead of a listing.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, relative_path, line, start_index, end_index, context=None, dependencies=None, kind=None, path_glob=None, lang=None))]
+fn occurrences(root_path_str: &str, index_path_str: &str, relative_path: &str, line: usize, start_index: usize, end_index: usize, context: Option<&str>, dependencies: Option<Vec<(String, String)>>, kind: Option<&str>, path_glob: Option<&str>, lang: Option<&str>) -> PyResult<Vec<PyOccurrence>> {
+    let filter = parse_occurrence_filter(kind, path_glob, lang)?;
+    let (_, result) = resolve_token(root_path_str, index_path_str, relative_path, line, start_index, end_index, context, dependencies, &filter)?;
+    Ok(ResponseLimits::global().truncate(PyOccurrence::from_file_symbols(result)))
+}
+
+/// Brings the index up to date, then resolves the token at the given position across
+/// the repository and its `dependencies`, keeping the occurrences passing `filter`.
+/// The searcher is returned for fallbacks.
+fn resolve_token(root_path_str: &str, index_path_str: &str, relative_path: &str, line: usize, start_index: usize, end_index: usize, context: Option<&str>, dependencies: Option<Vec<(String, String)>>, filter: &OccurrenceFilter) -> PyResult<(Searcher, Vec<FileSymbols>)> {
+    let context_mode = parse_context(context)?;
+
+    let nav = code_nav(root_path_str, index_path_str)?;
+    let rt = runtime()?;
 
-    let rt = tokio::runtime::Runtime::new().map_err(|e| {
-        pyo3::exceptions::PyRuntimeError::new_err(format!("Internal error: Failed to create Tokio runtime: {}", e))
-    })?;
-    
     rt.block_on(async {
-        let indexes = Indexes::new(&index_path, buffer_size_per_thread, num_threads).await.map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create indexes: {}", e))
-        })?;
+        let mut searcher = refreshed_session(&nav).await?.into_searcher();
+
+        for (dependency_root, dependency_index) in dependencies.unwrap_or_default() {
+            let external = open_dependency(&dependency_root, &dependency_index).await.map_err(|e| {
+                pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to open dependency index {}: {}", dependency_index, e))
+            })?;
+            searcher = searcher.with_external(external);
+        }
         
-        indexes.index(root_path).await.map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to index repository: {}", e))
-        })?;
+        let result = searcher.token_info_filtered(relative_path, line, start_index, end_index, context_mode, filter).map_err(|e| operation_error("Error retrieving token info", e))?;
+
+        Ok((searcher, result))
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, query, case_sensitive, context=None, include_flagged=false, scope=None, format=None))]
+fn text_search(root_path_str: &str, index_path_str: &str, query: &str, case_sensitive: bool, context: Option<&str>, include_flagged: bool, scope: Option<&str>, format: Option<&str>) -> PyResult<String> {
+    let context_mode = parse_context(context)?;
+    let scope = parse_scope(scope)?;
+    let format = parse_format(format)?;
+
+    let nav = code_nav(root_path_str, index_path_str)?;
+    let rt = runtime()?;
 
-        let searcher = Searcher::new(&index_path).map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create searcher: {}", e))
+    rt.block_on(async {
+        let searcher = refreshed_session(&nav).await?.into_searcher();
+        
+        let searcher = searcher.include_flagged(include_flagged).scope(scope).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(e.to_string())
         })?;
+
+        let result = searcher.text_search(query, case_sensitive, context_mode).map_err(|e| operation_error("Error performing text search", e))?;
+
+        let suggestions = if result.is_empty() {
+            searcher.did_you_mean(query, 3).map_err(|e| operation_error("Error performing text search", e))?
+        } else {
+            Vec::new()
+        };
+        
+        let response = SearchResponse::labelled(query, result, suggestions);
+        format_search(&QueryKind::Text, format, response).map_err(|e| operation_error("Error performing text search", e))
+    })
+    // Ok("dsf");
+}
+
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, query, max_distance, include_flagged=false, scope=None, format=None, transpositions=true))]
+fn fuzzy_search(root_path_str: &str, index_path_str: &str, query: &str, max_distance: u8, include_flagged: bool, scope: Option<&str>, format: Option<&str>, transpositions: bool) -> PyResult<String> {
+    let scope = parse_scope(scope)?;
+    let format = parse_format(format)?;
+
+    let nav = code_nav(root_path_str, index_path_str)?;
+    let rt = runtime()?;
+
+    rt.block_on(async {
+        let searcher = refreshed_session(&nav).await?.into_searcher();
         
-        let result = searcher.token_info(relative_path, line, start_index, end_index).map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Error retrieving token info: {}", e))
+        let searcher = searcher.include_flagged(include_flagged).scope(scope).map_err(|e| {
+            pyo3::exceptions::PyValueError::new_err(e.to_string())
         })?;
+
+        let result = searcher.fuzzy_search(query, max_distance, transpositions).map_err(|e| operation_error("Error performing fuzzy search", e))?;
         
-        Ok(search::Searcher::format_token_info(result))
+        let response = SearchResponse::labelled(query, result, Vec::new());
+        format_search(&QueryKind::Fuzzy { max_distance, transpositions }, format, response).map_err(|e| operation_error("Error performing fuzzy search", e))
     })
 }
 
+
+/// The ranges of `relative_path` that `go_to` resolves, as JSON, only those touching
+/// the lines from `start_line` to `end_line` if given, e.g. the visible lines of an
+/// editor. The file is parsed as `lang` if given.
 #[pyfunction]
-fn text_search(root_path_str: &str, index_path_str: &str, query: &str, case_sensitive: bool) -> PyResult<String> {
+#[pyo3(signature = (root_path_str, index_path_str, relative_path, start_line=None, end_line=None, lang=None))]
+fn get_hoverable_ranges(root_path_str: &str, index_path_str: &str, relative_path: &str, start_line: Option<usize>, end_line: Option<usize>, lang: Option<&str>) -> PyResult<String> {
+    let lang = parse_lang(lang)?;
+    let nav = code_nav(root_path_str, index_path_str)?;
+    let rt = runtime()?;
+
+    rt.block_on(async {
+        let searcher = refreshed_session(&nav).await?.into_searcher();
+        
+        let ranges = searcher
+            .hoverable_tokens_in(relative_path, start_line, end_line, lang)
+            .map_err(|e| operation_error("Error retrieving hoverable ranges", e))?;
+        
+        let formatted_ranges = search::Searcher::format_hoverable_ranges(ranges);
+        
+        Ok(json!(formatted_ranges).to_string())
+    })
+}
+
+/// The hoverable ranges of every file of `relative_paths`, as a JSON object of the
+/// ranges of each path, in the format of `get_hoverable_ranges`.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, relative_paths, lang=None))]
+fn get_hoverable_ranges_bulk(root_path_str: &str, index_path_str: &str, relative_paths: Vec<String>, lang: Option<&str>) -> PyResult<String> {
+    let lang = parse_lang(lang)?;
+    with_searcher(root_path_str, index_path_str, "Error retrieving hoverable ranges", |searcher| {
+        let ranges = searcher
+            .hoverable_tokens_bulk(&relative_paths, lang)?
+            .into_iter()
+            .map(|(path, ranges)| (path, json!(search::Searcher::format_hoverable_ranges(ranges))))
+            .collect::<serde_json::Map<_, _>>();
+        Ok(serde_json::Value::Object(ranges).to_string())
+    })
+}
+
+/// Like `get_hoverable_ranges`, returning `Range` objects instead of JSON.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, relative_path, start_line=None, end_line=None, lang=None))]
+fn hoverable_ranges(root_path_str: &str, index_path_str: &str, relative_path: &str, start_line: Option<usize>, end_line: Option<usize>, lang: Option<&str>) -> PyResult<Vec<PyRange>> {
+    let lang = parse_lang(lang)?;
+    with_searcher(root_path_str, index_path_str, "Error retrieving hoverable ranges", |searcher| {
+        Ok(searcher
+            .hoverable_tokens_in(relative_path, start_line, end_line, lang)?
+            .into_iter()
+            .map(|token| PyRange::from(token.range))
+            .collect())
+    })
+}
+
+/// The token at the char `column` of `line` of `relative_path`, or the one ending
+/// there, with its range and class, `None` between tokens.
+#[pyfunction]
+fn token_at(root_path_str: &str, index_path_str: &str, relative_path: &str, line: usize, column: usize) -> PyResult<Option<PyCursorToken>> {
+    with_searcher(root_path_str, index_path_str, "Error resolving token", |searcher| {
+        Ok(searcher.token_at(relative_path, line, column)?.map(PyCursorToken::from))
+    })
+}
+
+/// Every edit replacing `pattern` with `replacement` in the indexed files, planned
+/// from the index without touching the disk, for the caller to review and apply.
+/// With `regex`, `pattern` is a regex and `$1` or `${name}` in `replacement` expand
+/// to its groups.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, pattern, replacement, regex=false, case_sensitive=true, lang=None, path_glob=None, include_flagged=false, scope=None))]
+fn plan_replace(root_path_str: &str, index_path_str: &str, pattern: &str, replacement: &str, regex: bool, case_sensitive: bool, lang: Option<&str>, path_glob: Option<&str>, include_flagged: bool, scope: Option<&str>) -> PyResult<Vec<PyEdit>> {
+    let scope = parse_scope(scope)?;
+    let replace = if regex { Replace::regex(pattern, replacement) } else { Replace::literal(pattern, replacement) }
+        .case_sensitive(case_sensitive)
+        .lang(lang)
+        .path_glob(path_glob);
+
+    with_searcher(root_path_str, index_path_str, "Error planning the replacement", |searcher| {
+        let edits = searcher.include_flagged(include_flagged).scope(scope)?.plan_replace(&replace)?;
+        Ok(edits.into_iter().map(PyEdit::from).collect())
+    })
+}
+
+/// The symbols whose doc comment or docstring best matches the natural-language
+/// `query`, e.g. "retry failed requests", at most `limit`.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, query, limit=10, include_flagged=false, scope=None, format=None))]
+fn doc_search(root_path_str: &str, index_path_str: &str, query: &str, limit: usize, include_flagged: bool, scope: Option<&str>, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+    let scope = parse_scope(scope)?;
+    with_searcher(root_path_str, index_path_str, "Error searching the documentation", |searcher| {
+        let matches = searcher.include_flagged(include_flagged).scope(scope)?.doc_search(query, limit)?;
+        Ok(ResponseLimits::global().fit_text(search::Searcher::format_doc_search(&matches, format)?))
+    })
+}
+
+/// The files likely to test the file at `path` or the symbol `symbol`, one of which
+/// must be given, and their test functions concerned: what to run after editing it.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, path=None, symbol=None, include_flagged=false, scope=None, format=None))]
+fn tests_for(root_path_str: &str, index_path_str: &str, path: Option<&str>, symbol: Option<&str>, include_flagged: bool, scope: Option<&str>, format: Option<&str>) -> PyResult<String> {
+    let target = match (path, symbol) {
+        (Some(path), None) => TestTarget::Path(path.to_owned()),
+        (None, Some(symbol)) => TestTarget::Symbol(symbol.to_owned()),
+        _ => return Err(pyo3::exceptions::PyValueError::new_err("Expected either a path or a symbol")),
+    };
+    let format = parse_format(format)?;
+    let scope = parse_scope(scope)?;
+    with_searcher(root_path_str, index_path_str, "Error finding tests", |searcher| {
+        let matches = searcher.include_flagged(include_flagged).scope(scope)?.tests_for(&target)?;
+        Ok(ResponseLimits::global().fit_text(search::Searcher::format_tests_for(&matches, format)?))
+    })
+}
+
+/// The symbols, files and lines matching `query`, found by the symbol, path and
+/// content searches at once and ranked in one list, at most `limit`.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, query, limit=20, include_flagged=false, scope=None, format=None))]
+fn hybrid_search(root_path_str: &str, index_path_str: &str, query: &str, limit: usize, include_flagged: bool, scope: Option<&str>, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+    let scope = parse_scope(scope)?;
+    with_searcher(root_path_str, index_path_str, "Error searching", |searcher| {
+        let hits = searcher.include_flagged(include_flagged).scope(scope)?.hybrid_search(query, limit)?;
+        Ok(ResponseLimits::global().fit_text(search::Searcher::format_hybrid_search(&hits, format)?))
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, lang=None, path_glob=None, format=None))]
+fn public_api(root_path_str: &str, index_path_str: &str, lang: Option<&str>, path_glob: Option<&str>, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error retrieving public API", |searcher| {
+        let api = searcher.public_api(lang, path_glob)?;
+        if format == OutputFormat::Json {
+            return ResponseLimits::global().fit(api.len(), format, |n| Ok(serde_json::to_string(&api[..n])?));
+        }
+        Ok(ResponseLimits::global().fit_text(search::Searcher::format_public_api(api)))
+    })
+}
+
+/// The symbols a file defines, in the order of the file, as an outline of it.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, relative_path, format=None))]
+fn document_symbols(root_path_str: &str, index_path_str: &str, relative_path: &str, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error listing symbols", |searcher| {
+        let symbols = searcher.document_symbols(relative_path)?;
+        if format == OutputFormat::Json {
+            return ResponseLimits::global().fit(symbols.len(), format, |n| Ok(serde_json::to_string(&symbols[..n])?));
+        }
+        Ok(ResponseLimits::global().fit_text(search::Searcher::format_document_symbols(relative_path, &symbols)))
+    })
+}
+
+/// The symbols defined in the repository whose name holds `query`, the names equal to
+/// it or starting with it first.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, query, limit=50, format=None))]
+fn workspace_symbols(root_path_str: &str, index_path_str: &str, query: &str, limit: usize, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error looking up symbols", |searcher| {
+        let symbols = searcher.workspace_symbols(query, limit)?;
+        if format == OutputFormat::Json {
+            return ResponseLimits::global().fit(symbols.len(), format, |n| Ok(serde_json::to_string(&symbols[..n])?));
+        }
+        Ok(ResponseLimits::global().fit_text(search::Searcher::format_workspace_symbols(&symbols)))
+    })
+}
+
+/// The header comment of a file and how many classes and functions it defines.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, relative_path, format=None))]
+fn file_summary(root_path_str: &str, index_path_str: &str, relative_path: &str, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error summarizing file", |searcher| {
+        let summary = searcher.file_summary(relative_path)?;
+        let summary = match format {
+            OutputFormat::Json => serde_json::to_string(&summary)?,
+            _ => search::Searcher::format_file_summary(&summary),
+        };
+        Ok(ResponseLimits::global().fit_text(summary))
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, terms, case_sensitive, context=None, include_flagged=false, scope=None, format=None))]
+fn multi_term_search(root_path_str: &str, index_path_str: &str, terms: Vec<String>, case_sensitive: bool, context: Option<&str>, include_flagged: bool, scope: Option<&str>, format: Option<&str>) -> PyResult<String> {
+    let context_mode = parse_context(context)?;
+    let scope = parse_scope(scope)?;
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error performing multi-term search", |searcher| {
+        let hits = searcher.include_flagged(include_flagged).scope(scope)?.multi_term_search(&terms, case_sensitive, context_mode)?;
+        let response = SearchResponse { hits, suggestions: Vec::new() };
+        format_search(&QueryKind::MultiTerm(terms), format, response)
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, prefix, limit=20, format=None))]
+fn complete_symbol(root_path_str: &str, index_path_str: &str, prefix: &str, limit: usize, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error completing symbol", |searcher| {
+        let completions = searcher.complete_symbol(prefix, limit)?;
+        if format == OutputFormat::Json {
+            return ResponseLimits::global().fit(completions.len(), format, |n| Ok(serde_json::to_string(&completions[..n])?));
+        }
+        if completions.is_empty() {
+            return Ok(search::Searcher::format_did_you_mean(searcher.did_you_mean(prefix, 3)?));
+        }
+        Ok(ResponseLimits::global().fit_text(search::Searcher::format_symbol_completions(completions)))
+    })
+}
+
+/// The symbol names a few edits away from `query`, the names starting like it first.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, query, max_distance=2, limit=20, format=None))]
+fn fuzzy_symbol_search(root_path_str: &str, index_path_str: &str, query: &str, max_distance: u8, limit: usize, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error looking up symbols", |searcher| {
+        let symbols = searcher.fuzzy_symbols(query, max_distance, limit)?;
+        if format == OutputFormat::Json {
+            return ResponseLimits::global().fit(symbols.len(), format, |n| Ok(serde_json::to_string(&symbols[..n])?));
+        }
+        Ok(ResponseLimits::global().fit_text(search::Searcher::format_symbol_completions(symbols)))
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, pattern, case_sensitive, allow_leading_wildcard=false, context=None, include_flagged=false, scope=None, format=None))]
+fn wildcard_search(root_path_str: &str, index_path_str: &str, pattern: &str, case_sensitive: bool, allow_leading_wildcard: bool, context: Option<&str>, include_flagged: bool, scope: Option<&str>, format: Option<&str>) -> PyResult<String> {
+    let context_mode = parse_context(context)?;
+    let scope = parse_scope(scope)?;
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error performing wildcard search", |searcher| {
+        let result = searcher.include_flagged(include_flagged).scope(scope)?.wildcard_search(pattern, case_sensitive, allow_leading_wildcard, context_mode)?;
+        let response = SearchResponse::labelled(pattern, result, Vec::new());
+        format_search(&QueryKind::Wildcard { allow_leading_wildcard }, format, response)
+    })
+}
+
+/// The lines matching a regex, such as `fn\s+\w+_handler`, matched line by line.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, pattern, case_sensitive, context=None, include_flagged=false, scope=None, format=None))]
+fn regex_search(root_path_str: &str, index_path_str: &str, pattern: &str, case_sensitive: bool, context: Option<&str>, include_flagged: bool, scope: Option<&str>, format: Option<&str>) -> PyResult<String> {
+    let context_mode = parse_context(context)?;
+    let scope = parse_scope(scope)?;
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error performing regex search", |searcher| {
+        let result = searcher.include_flagged(include_flagged).scope(scope)?.regex_search(pattern, case_sensitive, context_mode)?;
+        let response = SearchResponse::labelled(pattern, result, Vec::new());
+        format_search(&QueryKind::Regex, format, response)
+    })
+}
+
+/// The indexed files, lines and share of the bytes of every language, largest first.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, format=None))]
+fn language_breakdown(root_path_str: &str, index_path_str: &str, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error computing the language breakdown", |searcher| {
+        let breakdown = search::Searcher::format_language_breakdown(&searcher.language_breakdown()?, format)?;
+        Ok(ResponseLimits::global().fit_text(breakdown))
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, dir, format=None))]
+fn package_info(root_path_str: &str, index_path_str: &str, dir: &str, format: Option<&str>) -> PyResult<String> {
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error retrieving package info", |searcher| {
+        let info = searcher.package_info(dir)?;
+        let info = match format {
+            OutputFormat::Json => serde_json::to_string(&info)?,
+            _ => search::Searcher::format_package_info(info),
+        };
+        Ok(ResponseLimits::global().fit_text(info))
+    })
+}
+
+/// The owners of the file at `path` in the `CODEOWNERS` of the repository, none if no
+/// rule matches it.
+#[pyfunction]
+fn owners_of(root_path_str: &str, index_path_str: &str, path: &str) -> PyResult<Vec<String>> {
+    with_searcher(root_path_str, index_path_str, "Error retrieving owners", |searcher| searcher.owners_of(path))
+}
+
+/// Opens the read-only index of a dependency's sources at `index_path_str`, indexing
+/// `root_path_str` only if the index is still empty. Dependency checkouts don't change
+/// once downloaded, so they are not re-scanned on every call.
+async fn open_dependency(root_path_str: &str, index_path_str: &str) -> anyhow::Result<Searcher> {
+    let nav = CodeNav::new(root_path_str, index_path_str);
+    nav.indexes().await?;
+    if SearchSession::open(&nav)?.searcher().num_docs() == 0 {
+        nav.refresh().await?;
+    }
+
+    Ok(SearchSession::open(&nav)?.into_searcher())
+}
+
+/// Runs any kind of search. `query` is the search text, a list of terms for
+/// `kind="multi_term"` or an operator such as `near(foo, bar, 3)` for `kind="proximity"`; every other option is a keyword argument, see `QUERY_OPTIONS`.
+#[pyfunction]
+#[pyo3(name = "search", signature = (root_path_str, index_path_str, query, **options))]
+fn search_query(root_path_str: &str, index_path_str: &str, query: &Bound<'_, PyAny>, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let query = build_query(query, options)?;
+
+    with_searcher(root_path_str, index_path_str, "Error performing search", |searcher| {
+        let response = searcher.search(&query)?;
+        format_query_response(searcher, &query, response)
+    })
+}
+
+/// Formats the response to `query` within the response limits, its hits grouped if
+/// the query groups them.
+fn format_query_response(searcher: &Searcher, query: &Query, response: SearchResponse) -> anyhow::Result<String> {
+    match query.group_by {
+        // without hits, the suggestions are more useful than no groups
+        Some(group_by) if !response.hits.is_empty() || query.format == OutputFormat::Json => {
+            let groups = searcher.group_hits(response.hits, group_by)?;
+            ResponseLimits::global().fit(groups.len(), query.format, |n| grouping::format_groups(&groups[..n], query.format))
+        }
+        _ => format_search(&query.kind, query.format, response),
+    }
+}
+
+/// Like `search`, returning `SearchResult` objects instead of a listing. The `format`
+/// option is ignored, and no suggestions are made when nothing matches.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, query, **options))]
+fn search_results(root_path_str: &str, index_path_str: &str, query: &Bound<'_, PyAny>, options: Option<&Bound<'_, PyDict>>) -> PyResult<Vec<PySearchResult>> {
+    let query = build_query(query, options)?;
+
+    with_searcher(root_path_str, index_path_str, "Error performing search", |searcher| {
+        let hits = ResponseLimits::global().truncate(searcher.search(&query)?.hits);
+        Ok(hits.into_iter().map(PySearchResult::from).collect())
+    })
+}
+
+/// Like `search`, restricted to the `changed_files` of the repository and grouped by
+/// file, with every snippet annotated against the hunks of the file's diff to `base`
+/// (`HEAD` by default).
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, changed_files, query, base=None, **options))]
+fn diff_context(root_path_str: &str, index_path_str: &str, changed_files: Vec<String>, query: &Bound<'_, PyAny>, base: Option<&str>, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let query = build_query(query, options)?;
+
+    with_searcher(root_path_str, index_path_str, "Error performing diff search", |searcher| {
+        let changed = diff::changed_files(Path::new(root_path_str), &changed_files, base)?;
+        let files = diff::group_hits(&changed, searcher.search(&query)?.hits);
+        format_diff_context(files, query.format)
+    })
+}
+
+/// Like `go_to`, restricted to the `changed_files` of the repository, as `diff_context`.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, changed_files, relative_path, line, start_index, end_index, base=None, context=None, format=None))]
+fn diff_go_to(root_path_str: &str, index_path_str: &str, changed_files: Vec<String>, relative_path: &str, line: usize, start_index: usize, end_index: usize, base: Option<&str>, context: Option<&str>, format: Option<&str>) -> PyResult<String> {
+    let context_mode = parse_context(context)?;
+    let format = parse_format(format)?;
+
+    with_searcher(root_path_str, index_path_str, "Error retrieving token info", |searcher| {
+        let changed = diff::changed_files(Path::new(root_path_str), &changed_files, base)?;
+        let result = searcher.token_info(relative_path, line, start_index, end_index, context_mode)?;
+        format_diff_context(diff::group_occurrences(&changed, result), format)
+    })
+}
+
+/// Formats the hits of changed `files` in `format`, within the response limits.
+fn format_diff_context(files: Vec<diff::DiffFile>, format: OutputFormat) -> anyhow::Result<String> {
+    let total = files.iter().map(|file| file.hits.len()).sum();
+    ResponseLimits::global().fit(total, format, |n| diff::format_diff_context(&diff::first_hits(&files, n), format))
+}
+
+/// Indexes the repository at `root_path_str` as it was at `revision`, a commit, branch
+/// or tag, next to the index at `index_path_str`, and returns the commit's hash. The
+/// files are read from git, without checking the commit out; a commit already indexed
+/// is left as it is.
+#[pyfunction]
+fn index_revision(py: Python<'_>, root_path_str: &str, index_path_str: &str, revision: &str) -> PyResult<String> {
+    py.allow_threads(|| {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(revision::index(Path::new(root_path_str), Path::new(index_path_str), revision, DEFAULT_BUFFER_SIZE, DEFAULT_THREADS))
+    })
+    .map_err(|e| operation_error("Failed to index the revision", e))
+}
+
+/// The commits indexed next to the index at `index_path_str` by `index_revision`.
+#[pyfunction]
+fn indexed_revisions(index_path_str: &str) -> PyResult<Vec<String>> {
+    revision::indexed(Path::new(index_path_str)).map_err(|e| operation_error("Failed to list the indexed revisions", e))
+}
+
+/// Like `search`, over the indexes of `revisions`, every indexed one by default, newest
+/// first. Hits are grouped by revision.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, query, revisions=None, **options))]
+fn revision_search(py: Python<'_>, root_path_str: &str, index_path_str: &str, query: &Bound<'_, PyAny>, revisions: Option<Vec<String>>, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let query = build_query(query, options)?;
+    let (root, index_path) = (Path::new(root_path_str), Path::new(index_path_str));
+
+    py.allow_threads(|| {
+        let commits = match revisions {
+            Some(revisions) => revisions.iter().map(|revision| revision::resolve(root, revision)).collect::<anyhow::Result<Vec<_>>>()?,
+            None => revision::indexed(index_path)?,
+        };
+        let found = revision::search(root, index_path, &commits, &query)?;
+        let total = found.iter().map(|revision| revision.response.hits.len()).sum();
+        ResponseLimits::global().fit(total, query.format, |n| revision::format_search(&query, revision::first_hits(&found, n)))
+    })
+    .map_err(|e| operation_error("Error searching the revisions", e))
+}
+
+/// Like `search`, over an index sharded by `shard_by` (`language` or `directory`).
+/// Shards are indexed concurrently and searched in parallel.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, query, shard_by, **options))]
+fn sharded_search(root_path_str: &str, index_path_str: &str, query: &Bound<'_, PyAny>, shard_by: &str, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let query = build_query(query, options)?;
+    let shard_by = parse_shard_by(shard_by)?;
+
+    with_sharded_indexes(root_path_str, index_path_str, shard_by, "Error performing sharded search", |indexes, root_path| async move {
+        indexes.index(root_path).await?;
+        let response = indexes.searcher()?.search(&query)?;
+        format_search(&query.kind, query.format, response)
+    })
+}
+
+/// Rebuilds a single shard of an index sharded by `shard_by`, leaving the others untouched.
+#[pyfunction]
+fn reindex_shard(root_path_str: &str, index_path_str: &str, shard_by: &str, shard: &str) -> PyResult<()> {
+    let shard_by = parse_shard_by(shard_by)?;
+
+    with_sharded_indexes(root_path_str, index_path_str, shard_by, "Error reindexing shard", |indexes, root_path| async move {
+        indexes.index_shard(root_path, shard).await
+    })
+}
+
+fn parse_shard_by(shard_by: &str) -> PyResult<ShardBy> {
+    shard_by
+        .parse()
+        .map_err(|e: anyhow::Error| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
+
+/// Runs `f` against the sharded indexes at `index_path_str`, for the repository at
+/// `root_path_str`. Errors returned by `f` are prefixed with `context`.
+fn with_sharded_indexes<'a, T, F, Fut>(root_path_str: &'a str, index_path_str: &str, shard_by: ShardBy, context: &str, f: F) -> PyResult<T>
+where
+    F: FnOnce(ShardedIndexes, &'a Path) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<T>>,
+{
     let root_path = Path::new(root_path_str);
 
     if !root_path.exists() {
@@ -78,128 +764,585 @@ fn text_search(root_path_str: &str, index_path_str: &str, query: &str, case_sens
     if !index_path.exists() {
         return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Index path does not exist"));
     }
-    
-    let buffer_size_per_thread = 15_000_000;
-    let num_threads = 4;
 
     let rt = tokio::runtime::Runtime::new().map_err(|e| {
         pyo3::exceptions::PyRuntimeError::new_err(format!("Internal error: Failed to create Tokio runtime: {}", e))
     })?;
-    
-    rt.block_on(async {
-        let indexes = Indexes::new(&index_path, buffer_size_per_thread, num_threads).await.map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create indexes: {}", e))
-        })?;
-        
-        indexes.index(root_path).await.map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to index repository: {}", e))
-        })?;
 
-        let searcher = Searcher::new(&index_path).map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create searcher: {}", e))
-        })?;
-        
-        let result = searcher.text_search(query, case_sensitive).map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Error performing text search: {}", e))
-        })?;
-        
-        Ok(search::Searcher::format_search_results(result))
+    let indexes = ShardedIndexes::new(index_path, shard_by, DEFAULT_BUFFER_SIZE, DEFAULT_THREADS);
+    rt.block_on(f(indexes, root_path)).map_err(|e| operation_error(context, e))
+}
+
+pyo3::create_exception!(
+    code_nav_devon,
+    CodeNavError,
+    pyo3::exceptions::PyRuntimeError,
+    "An error caused by the arguments of a call, with `code`, `position` and `suggestion` attributes to correct them."
+);
+
+/// Converts the error of an operation into a `CodeNavError` when it is a `NavError`,
+/// or a `RuntimeError` otherwise. The message is prefixed with `context`.
+fn operation_error(context: &str, e: anyhow::Error) -> PyErr {
+    let message = format!("{}: {}", context, e);
+    let Some(nav_error) = e.downcast_ref::<NavError>() else {
+        return pyo3::exceptions::PyRuntimeError::new_err(message);
+    };
+
+    Python::with_gil(|py| {
+        let err = CodeNavError::new_err(message);
+        let value = err.value_bound(py);
+        let attributes = value
+            .setattr("code", nav_error.code.as_str())
+            .and_then(|_| value.setattr("position", nav_error.position))
+            .and_then(|_| value.setattr("suggestion", nav_error.suggestion.as_deref()));
+
+        match attributes {
+            Ok(()) => err,
+            Err(setattr_error) => setattr_error,
+        }
     })
-    // Ok("dsf");
 }
 
+/// Sets the limits on the size of every response: the most results listed, and the
+/// most bytes returned. `None` lifts a limit.
 #[pyfunction]
-fn fuzzy_search(root_path_str: &str, index_path_str: &str, query: &str, max_distance: u8) -> PyResult<String> {
-    let root_path = Path::new(root_path_str);
+#[pyo3(signature = (max_results=Some(DEFAULT_MAX_RESULTS), max_response_bytes=Some(DEFAULT_MAX_RESPONSE_BYTES)))]
+fn set_response_limits(max_results: Option<usize>, max_response_bytes: Option<usize>) {
+    ResponseLimits::set_global(ResponseLimits { max_results, max_response_bytes });
+}
+
+/// The limits set by `set_response_limits`.
+#[pyfunction]
+#[pyo3(name = "response_limits")]
+fn get_response_limits() -> HashMap<&'static str, Option<usize>> {
+    let limits = ResponseLimits::global();
+    HashMap::from([
+        ("max_results", limits.max_results),
+        ("max_response_bytes", limits.max_response_bytes),
+    ])
+}
+
+/// Logs the queries of every searcher opened from now on next to its index, in
+/// `queries.jsonl`, or stops logging them.
+#[pyfunction]
+fn set_query_log(enabled: bool) {
+    query_log::set_enabled(enabled);
+}
+
+/// Whether queries are logged, `False` unless set by `set_query_log`.
+#[pyfunction]
+fn query_log_enabled() -> bool {
+    query_log::is_enabled()
+}
+
+/// Runs the queries logged for the index at `index_path_str` again, or those of the log
+/// at `log_path_str`, and returns their latencies and numbers of results then and now
+/// as JSON. The index is searched as it is, without indexing, and the replayed queries
+/// aren't logged.
+#[pyfunction]
+#[pyo3(signature = (index_path_str, log_path_str=None))]
+fn replay_query_log(py: Python<'_>, index_path_str: &str, log_path_str: Option<&str>) -> PyResult<String> {
+    let index_path = Path::new(index_path_str);
+    let log_path = log_path_str.map_or_else(|| index_path.join(query_log::QUERY_LOG_FILE), Into::into);
+
+    py.allow_threads(|| {
+        let entries = query_log::read(&log_path)?;
+        let searcher = Searcher::new(index_path)?.query_log(None);
+        Ok(serde_json::to_string(&query_log::replay(&searcher, &entries))?)
+    })
+    .map_err(|e| operation_error("Error replaying the query log", e))
+}
+
+/// The numbering of the lines counting from `base`, 0 or 1.
+pub(crate) fn line_base_from(base: usize) -> PyResult<LineBase> {
+    match base {
+        0 => Ok(LineBase::Zero),
+        1 => Ok(LineBase::One),
+        _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "Lines can be numbered from 0 or 1, not {base}"
+        ))),
+    }
+}
+
+/// Numbers the lines of the inputs and outputs of the calls made on the calling thread
+/// from `base`, 0 or 1. Other threads keep their own numbering.
+#[pyfunction]
+fn set_line_base(base: usize) -> PyResult<()> {
+    line_base_from(base)?.set_current();
+    Ok(())
+}
+
+/// The number of the first line on the calling thread, 1 unless set otherwise by
+/// `set_line_base`.
+#[pyfunction]
+#[pyo3(name = "line_base")]
+fn get_line_base() -> usize {
+    LineBase::current().first()
+}
+
+/// What this build supports: its `version`, the `languages` with a compiled grammar,
+/// the optional `features` it was built with, by name, the `index_format_version` and
+/// whether it is a `debug_build`.
+#[pyfunction]
+fn capabilities(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let capabilities = capabilities::Capabilities::of_build();
+    let dict = PyDict::new_bound(py);
+    dict.set_item("version", capabilities.version)?;
+    dict.set_item("languages", capabilities.languages)?;
+    dict.set_item("features", capabilities.features.into_iter().collect::<HashMap<_, _>>())?;
+    dict.set_item("index_format_version", capabilities.index_format_version)?;
+    dict.set_item("debug_build", capabilities.debug_build)?;
+    Ok(dict)
+}
+
+/// The fingerprint of the repository at `root_path_str`: its HEAD commit, followed by
+/// `+` and a hash of the uncommitted changes if there are some. `None` outside a git
+/// repository.
+#[pyfunction]
+fn repo_fingerprint(root_path_str: &str) -> PyResult<Option<String>> {
+    Fingerprint::of(Path::new(root_path_str))
+        .map(|fingerprint| fingerprint.map(|f| f.to_string()))
+        .map_err(|e| operation_error("Error fingerprinting repository", e))
+}
+
+/// The fingerprint of the repository the index at `index_path_str` was last built
+/// from, `None` if it wasn't built from a git repository. An index whose fingerprint
+/// is `repo_fingerprint(root)` is up to date with `root`, wherever it was built.
+#[pyfunction]
+fn index_fingerprint(index_path_str: &str) -> PyResult<Option<String>> {
+    Fingerprint::stored_in(Path::new(index_path_str))
+        .map(|fingerprint| fingerprint.map(|f| f.to_string()))
+        .map_err(|e| operation_error("Error reading index fingerprint", e))
+}
+
+/// The format version the index at `index_path_str` was written in, 1 for indexes
+/// older than versions, `None` if there is no index. Indexes newer than
+/// `FORMAT_VERSION` raise an `incompatible_index` error when opened.
+#[pyfunction]
+fn index_format_version(index_path_str: &str) -> PyResult<Option<u32>> {
+    let index_path = Path::new(index_path_str);
+    if !index_path.join("meta.json").is_file() {
+        return Ok(None);
+    }
+    format::stored_in(index_path)
+        .map(|version| Some(version.unwrap_or(1)))
+        .map_err(|e| operation_error("Error reading index format version", e))
+}
+
+/// The documents of the index at `index_path_str`, without bringing it up to date:
+/// searchable (`num_docs`), deleted but still stored (`num_deleted_docs`), and the number
+/// of segments holding them.
+#[pyfunction]
+fn index_health(index_path_str: &str) -> PyResult<HashMap<&'static str, u64>> {
+    Searcher::new(Path::new(index_path_str))
+        .map(|searcher| health_dict(searcher.health()))
+        .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create searcher: {}", e)))
+}
+
+/// Brings the index at `index_path_str` up to date with `root_path_str`, as every
+/// query does first, and returns what changed. With `dry_run`, only reports what would
+/// change, leaving the index as it is, and fails rather than rebuild an index of an
+/// older format. With `background`, indexes on a single thread, pausing after every
+/// file read, not to slow down the builds and tests running next to it. With
+/// `max_index_bytes`, evicts generated, vendored and minified files, biggest first,
+/// until the index takes at most that many bytes on disk, and keeps doing so on the
+/// next runs; 0 lifts the budget.
+#[pyfunction]
+#[pyo3(signature = (root_path_str, index_path_str, dry_run=false, background=false, max_index_bytes=None))]
+fn index_repository(py: Python<'_>, root_path_str: &str, index_path_str: &str, dry_run: bool, background: bool, max_index_bytes: Option<u64>) -> PyResult<PyIndexReport> {
+    let (root_path, index_path) = (Path::new(root_path_str), Path::new(index_path_str));
 
     if !root_path.exists() {
         return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Root path does not exist"));
     }
 
+    let priority = if background { Priority::Background } else { Priority::Normal };
+    py.allow_threads(|| {
+        let rt = match priority {
+            Priority::Normal => tokio::runtime::Runtime::new()?,
+            Priority::Background => tokio::runtime::Builder::new_current_thread().enable_all().build()?,
+        };
+        rt.block_on(async {
+            let on_incompatible = if dry_run { OnIncompatible::Fail } else { OnIncompatible::Rebuild };
+            let mut nav = CodeNav::new(root_path, index_path)
+                .with_source(File::new().dry_run(dry_run))
+                .on_incompatible(on_incompatible)
+                .priority(priority);
+            if let Some(max_bytes) = max_index_bytes {
+                nav = nav.size_budget((max_bytes > 0).then(|| SizeBudget::new(max_bytes)));
+            }
+            nav.refresh().await
+        })
+    })
+    .map(PyIndexReport::from)
+    .map_err(|e| operation_error("Failed to index repository", e))
+}
+
+/// Indexes `documents`, `(path, content)` pairs of files that needn't exist on disk,
+/// such as rendered templates, into the index at `index_path_str`, and returns what
+/// changed. They are stored under their path as given, whatever their language.
+#[pyfunction]
+#[pyo3(signature = (index_path_str, documents, dry_run=false))]
+fn index_documents(py: Python<'_>, index_path_str: &str, documents: Vec<(String, String)>, dry_run: bool) -> PyResult<PyIndexReport> {
+    let index_path = Path::new(index_path_str);
+
+    py.allow_threads(|| {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let on_incompatible = if dry_run { OnIncompatible::Fail } else { OnIncompatible::Rebuild };
+            let nav = CodeNav::index_only(index_path).with_source(File::new().dry_run(dry_run)).on_incompatible(on_incompatible);
+            nav.index_documents(documents).await
+        })
+    })
+    .map(PyIndexReport::from)
+    .map_err(|e| operation_error("Failed to index documents", e))
+}
+
+/// The problems with renaming `old_name` to `new_name` with `edits`, such as those
+/// planned by `plan_replace`, checked against the indexed files before they are
+/// applied. Empty when the rename is clean.
+#[pyfunction]
+fn verify_rename(root_path_str: &str, index_path_str: &str, edits: Vec<PyEdit>, old_name: &str, new_name: &str) -> PyResult<Vec<PyRenameViolation>> {
+    let edits = edits.into_iter().map(Edit::from).collect::<Vec<_>>();
+
+    with_searcher(root_path_str, index_path_str, "Error verifying the rename", |searcher| {
+        let violations = searcher.verify_rename(&edits, old_name, new_name)?;
+        Ok(violations.into_iter().map(PyRenameViolation::from).collect())
+    })
+}
+
+/// Applies `edits`, as planned by `plan_replace`, to the files on disk, then reindexes
+/// the files they change into the index at `index_path_str`, and returns what changed.
+/// Nothing is written if the text of an edit is no longer in its file.
+#[pyfunction]
+fn apply_edits(py: Python<'_>, index_path_str: &str, edits: Vec<PyEdit>) -> PyResult<PyIndexReport> {
+    let index_path = Path::new(index_path_str);
+    let edits = edits.into_iter().map(Edit::from).collect::<Vec<_>>();
+
+    py.allow_threads(|| {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            CodeNav::index_only(index_path).apply_edits(&edits).await
+        })
+    })
+    .map(PyIndexReport::from)
+    .map_err(|e| operation_error("Failed to apply the edits", e))
+}
+
+/// Indexes the files of the zip or tar archive at `archive_path_str` into the index at
+/// `index_path_str`, without extracting it, and returns what changed. They are stored
+/// under the path of the archive, e.g. `/work/vendor.zip/src/lib.rs`, and searched and
+/// navigated like the other files of the index.
+#[pyfunction]
+#[pyo3(signature = (index_path_str, archive_path_str, dry_run=false))]
+fn index_archive(py: Python<'_>, index_path_str: &str, archive_path_str: &str, dry_run: bool) -> PyResult<PyIndexReport> {
+    let (index_path, archive_path) = (Path::new(index_path_str), Path::new(archive_path_str));
+
+    py.allow_threads(|| {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            let on_incompatible = if dry_run { OnIncompatible::Fail } else { OnIncompatible::Rebuild };
+            let nav = CodeNav::index_only(index_path).with_source(File::new().dry_run(dry_run)).on_incompatible(on_incompatible);
+            nav.index_archive(archive_path).await
+        })
+    })
+    .map(PyIndexReport::from)
+    .map_err(|e| operation_error("Failed to index archive", e))
+}
+
+/// Merges the index at `index_path_str` into a single segment, dropping its deleted
+/// documents. Indexing already does so once they pass the thresholds of the default
+/// `CompactionPolicy`.
+#[pyfunction]
+fn compact_index(py: Python<'_>, index_path_str: &str) -> PyResult<()> {
     let index_path = Path::new(index_path_str);
 
     if !index_path.exists() {
         return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Index path does not exist"));
     }
-    
-    let buffer_size_per_thread = 15_000_000;
-    let num_threads = 4;
 
-    let rt = tokio::runtime::Runtime::new().map_err(|e| {
-        pyo3::exceptions::PyRuntimeError::new_err(format!("Internal error: Failed to create Tokio runtime: {}", e))
-    })?;
-    
-    rt.block_on(async {
-        let indexes = Indexes::new(&index_path, buffer_size_per_thread, num_threads).await.map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create indexes: {}", e))
-        })?;
-        
-        indexes.index(root_path).await.map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to index repository: {}", e))
-        })?;
+    py.allow_threads(|| {
+        let rt = tokio::runtime::Runtime::new()?;
+        rt.block_on(async {
+            CodeNav::index_only(index_path).compact().await
+        })
+    })
+    .map_err(|e| operation_error("Failed to compact index", e))
+}
 
-        let searcher = Searcher::new(&index_path).map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create searcher: {}", e))
-        })?;
-        
-        let result = searcher.fuzzy_search(query, max_distance).map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Error performing fuzzy search: {}", e))
-        })?;
-        
-        Ok(search::Searcher::format_fuzzy_search_results(result))
+/// Checks that the extension works in this environment: indexes a tiny built-in
+/// fixture into a temporary directory, searches it and goes to a definition. Failures
+/// are reported in the result rather than raised.
+#[pyfunction]
+fn self_test(py: Python<'_>) -> PyResult<PySelfTest> {
+    py.allow_threads(|| {
+        let rt = tokio::runtime::Runtime::new()?;
+        anyhow::Ok(rt.block_on(self_test::run()))
     })
+    .map(PySelfTest::from)
+    .map_err(|e| operation_error("Failed to run the self-test", e))
 }
 
+/// Keyword arguments accepted by `search`
+const QUERY_OPTIONS: &[&str] = &[
+    "kind",
+    "case_sensitive",
+    "context",
+    "include_flagged",
+    "scope",
+    "limit",
+    "max_distance",
+    "transpositions",
+    "allow_leading_wildcard",
+    "format",
+    "extension_hints",
+    "explain",
+    "group_by",
+];
 
-#[pyfunction]
-fn get_hoverable_ranges(root_path_str: &str, index_path_str: &str, relative_path: &str) -> PyResult<String> {
-    let root_path = Path::new(root_path_str);
+/// Builds a `Query` from the arguments of `search`.
+fn build_query(query: &Bound<'_, PyAny>, options: Option<&Bound<'_, PyDict>>) -> PyResult<Query> {
+    let value_error = |e: anyhow::Error| pyo3::exceptions::PyValueError::new_err(e.to_string());
 
-    if !root_path.exists() {
-        return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Root path does not exist"));
+    if let Some(options) = options {
+        for key in options.keys() {
+            let key = key.extract::<String>()?;
+            if !QUERY_OPTIONS.contains(&key.as_str()) {
+                return Err(value_error(anyhow::anyhow!("unknown search option `{key}`")));
+            }
+        }
     }
 
-    let index_path = Path::new(index_path_str);
+    let kind = query_option::<String>(options, "kind")?.unwrap_or_else(|| "text".to_owned());
+    let mut built = match kind.as_str() {
+        "text" => Query::text(&query.extract::<String>()?),
+        "fuzzy" => Query::fuzzy(&query.extract::<String>()?, query_option(options, "max_distance")?.unwrap_or(2))
+            .transpositions(query_option(options, "transpositions")?.unwrap_or(true)),
+        "wildcard" => Query::wildcard(&query.extract::<String>()?)
+            .allow_leading_wildcard(query_option(options, "allow_leading_wildcard")?.unwrap_or(false)),
+        "multi_term" => Query::multi_term(&query.extract::<Vec<String>>()?),
+        "proximity" => Query::proximity(&query.extract::<String>()?).map_err(value_error)?,
+        "regex" => Query::regex(&query.extract::<String>()?),
+        kind => {
+            return Err(value_error(anyhow::anyhow!(
+                "invalid kind `{kind}`, expected `text`, `fuzzy`, `wildcard`, `multi_term`, `proximity` or `regex`"
+            )))
+        }
+    };
+
+    built = built
+        .case_sensitive(query_option(options, "case_sensitive")?.unwrap_or(false))
+        .context(parse_context(query_option::<String>(options, "context")?.as_deref())?)
+        .scope(parse_scope(query_option::<String>(options, "scope")?.as_deref())?);
+
+    if let Some(include_flagged) = query_option(options, "include_flagged")? {
+        built = built.include_flagged(include_flagged);
+    }
+    if let Some(limit) = query_option(options, "limit")? {
+        built = built.limit(limit);
+    }
+    if let Some(format) = query_option::<String>(options, "format")? {
+        built = built.format(format.parse().map_err(value_error)?);
+    }
+    if let Some(extension_hints) = query_option(options, "extension_hints")? {
+        built = built.extension_hints(extension_hints);
+    }
+    if let Some(explain) = query_option(options, "explain")? {
+        built = built.explain(explain);
+    }
+    if let Some(group_by) = query_option::<String>(options, "group_by")? {
+        built = built.group_by(Some(group_by.parse().map_err(value_error)?));
+    }
 
+    Ok(built)
+}
+
+fn query_option<'py, T: FromPyObject<'py>>(options: Option<&Bound<'py, PyDict>>, key: &str) -> PyResult<Option<T>> {
+    match options {
+        Some(options) => options.get_item(key)?.map(|value| value.extract()).transpose(),
+        None => Ok(None),
+    }
+}
+
+/// Parses the `context` argument accepted by the search functions, e.g. `lines:5`.
+fn parse_context(context: Option<&str>) -> PyResult<Option<ContextMode>> {
+    context
+        .map(str::parse::<ContextMode>)
+        .transpose()
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
+
+/// Parses the `kind` (`definition` or `reference`), `path_glob` and `lang` arguments
+/// of `go_to` and `occurrences`.
+fn parse_occurrence_filter(kind: Option<&str>, path_glob: Option<&str>, lang: Option<&str>) -> PyResult<OccurrenceFilter> {
+    let value_error = |e: anyhow::Error| pyo3::exceptions::PyValueError::new_err(e.to_string());
+    let mut filter = OccurrenceFilter::default();
+    if let Some(kind) = kind {
+        filter = filter.kind(kind.parse().map_err(value_error)?);
+    }
+    if let Some(glob) = path_glob {
+        filter = filter.path_glob(glob).map_err(value_error)?;
+    }
+    if let Some(lang) = lang {
+        filter = filter.lang(lang).map_err(value_error)?;
+    }
+    Ok(filter)
+}
+
+/// Parses the `lang` argument of the hoverable range functions, any name of a
+/// supported language, overriding the language of the file's extension.
+fn parse_lang(lang: Option<&str>) -> PyResult<Option<&'static str>> {
+    lang.map(intelligence::TSLanguage::supported_name)
+        .transpose()
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
+
+/// Parses the `format` argument of the search and navigation functions: `text`
+/// (the default), `json` or `markdown`.
+fn parse_format(format: Option<&str>) -> PyResult<OutputFormat> {
+    format
+        .map(str::parse::<OutputFormat>)
+        .transpose()
+        .map(Option::unwrap_or_default)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
+
+/// Parses the `scope` argument accepted by the search functions, e.g. `workspace_member:core`
+/// or `owner:@org/team`.
+fn parse_scope(scope: Option<&str>) -> PyResult<Option<Scope>> {
+    scope
+        .map(str::parse::<Scope>)
+        .transpose()
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
+
+/// Brings the index at `index_path_str` up to date with `root_path_str`, then runs
+/// `f` against a searcher over it. Errors returned by `f` are prefixed with `context`.
+fn with_searcher<T>(
+    root_path_str: &str,
+    index_path_str: &str,
+    context: &str,
+    f: impl FnOnce(Searcher) -> anyhow::Result<T>,
+) -> PyResult<T> {
+    let nav = code_nav(root_path_str, index_path_str)?;
+    let rt = runtime()?;
+
+    rt.block_on(async {
+        let searcher = refreshed_session(&nav).await?.into_searcher();
+
+        f(searcher).map_err(|e| operation_error(context, e))
+    })
+}
+
+/// The repository at `root_path_str` and its index at `index_path_str`, both of which
+/// must exist.
+fn code_nav(root_path_str: &str, index_path_str: &str) -> PyResult<CodeNav> {
+    let (root_path, index_path) = (Path::new(root_path_str), Path::new(index_path_str));
+
+    if !root_path.exists() {
+        return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Root path does not exist"));
+    }
     if !index_path.exists() {
         return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Index path does not exist"));
     }
-    
-    let buffer_size_per_thread = 15_000_000;
-    let num_threads = 4;
+    Ok(CodeNav::new(root_path, index_path))
+}
 
-    let rt = tokio::runtime::Runtime::new().map_err(|e| {
+fn runtime() -> PyResult<tokio::runtime::Runtime> {
+    tokio::runtime::Runtime::new().map_err(|e| {
         pyo3::exceptions::PyRuntimeError::new_err(format!("Internal error: Failed to create Tokio runtime: {}", e))
+    })
+}
+
+/// A session over the index of `nav`, brought up to date first, as every query does.
+async fn refreshed_session(nav: &CodeNav) -> PyResult<SearchSession<'_>> {
+    nav.indexes().await.map_err(|e| {
+        pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create indexes: {}", e))
     })?;
-    
-    rt.block_on(async {
-        let indexes = Indexes::new(&index_path, buffer_size_per_thread, num_threads).await.map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create indexes: {}", e))
-        })?;
-        
-        indexes.index(root_path).await.map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to index repository: {}", e))
-        })?;
 
-        let searcher = Searcher::new(&index_path).map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create searcher: {}", e))
-        })?;
-        
-        let ranges = searcher.get_hoverable_ranges(relative_path).map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Error retrieving hoverable ranges: {}", e))
-        })?;
-        
-        let formatted_ranges = search::Searcher::format_hoverable_ranges(ranges);
-        
-        Ok(json!(formatted_ranges).to_string())
+    nav.refresh().await.map_err(|e| {
+        pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to index repository: {}", e))
+    })?;
+
+    SearchSession::open(nav).map_err(|e| {
+        pyo3::exceptions::PyRuntimeError::new_err(format!("Failed to create searcher: {}", e))
     })
 }
 
 #[pymodule]
 fn code_nav_devon(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(go_to, m)?)?;
+    m.add_function(wrap_pyfunction!(go_to_continue, m)?)?;
+    m.add_function(wrap_pyfunction!(go_to_definition, m)?)?;
+    m.add_function(wrap_pyfunction!(find_references, m)?)?;
     m.add_function(wrap_pyfunction!(text_search, m)?)?;
     m.add_function(wrap_pyfunction!(fuzzy_search, m)?)?;
+    m.add_function(wrap_pyfunction!(regex_search, m)?)?;
     m.add_function(wrap_pyfunction!(get_hoverable_ranges, m)?)?;
+    m.add_function(wrap_pyfunction!(get_hoverable_ranges_bulk, m)?)?;
+    m.add_function(wrap_pyfunction!(doc_search, m)?)?;
+    m.add_function(wrap_pyfunction!(tests_for, m)?)?;
+    m.add_function(wrap_pyfunction!(hybrid_search, m)?)?;
+    m.add_function(wrap_pyfunction!(public_api, m)?)?;
+    m.add_function(wrap_pyfunction!(file_summary, m)?)?;
+    m.add_function(wrap_pyfunction!(document_symbols, m)?)?;
+    m.add_function(wrap_pyfunction!(workspace_symbols, m)?)?;
+    m.add_function(wrap_pyfunction!(multi_term_search, m)?)?;
+    m.add_function(wrap_pyfunction!(complete_symbol, m)?)?;
+    m.add_function(wrap_pyfunction!(fuzzy_symbol_search, m)?)?;
+    m.add_function(wrap_pyfunction!(wildcard_search, m)?)?;
+    m.add_function(wrap_pyfunction!(package_info, m)?)?;
+    m.add_function(wrap_pyfunction!(owners_of, m)?)?;
+    m.add_function(wrap_pyfunction!(language_breakdown, m)?)?;
+    m.add_function(wrap_pyfunction!(search_query, m)?)?;
+    m.add_function(wrap_pyfunction!(sharded_search, m)?)?;
+    m.add_function(wrap_pyfunction!(reindex_shard, m)?)?;
+    m.add_function(wrap_pyfunction!(occurrences, m)?)?;
+    m.add_function(wrap_pyfunction!(hoverable_ranges, m)?)?;
+    m.add_function(wrap_pyfunction!(token_at, m)?)?;
+    m.add_function(wrap_pyfunction!(capabilities, m)?)?;
+    m.add_function(wrap_pyfunction!(plan_replace, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_rename, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_edits, m)?)?;
+    m.add_function(wrap_pyfunction!(search_results, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_context, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_go_to, m)?)?;
+    m.add_function(wrap_pyfunction!(index_revision, m)?)?;
+    m.add_function(wrap_pyfunction!(indexed_revisions, m)?)?;
+    m.add_function(wrap_pyfunction!(revision_search, m)?)?;
+    m.add_function(wrap_pyfunction!(set_response_limits, m)?)?;
+    m.add_function(wrap_pyfunction!(get_response_limits, m)?)?;
+    m.add_function(wrap_pyfunction!(set_query_log, m)?)?;
+    m.add_function(wrap_pyfunction!(query_log_enabled, m)?)?;
+    m.add_function(wrap_pyfunction!(replay_query_log, m)?)?;
+    m.add_function(wrap_pyfunction!(set_line_base, m)?)?;
+    m.add_function(wrap_pyfunction!(get_line_base, m)?)?;
+    m.add_function(wrap_pyfunction!(repo_fingerprint, m)?)?;
+    m.add_function(wrap_pyfunction!(index_fingerprint, m)?)?;
+    m.add_function(wrap_pyfunction!(index_format_version, m)?)?;
+    m.add_function(wrap_pyfunction!(index_health, m)?)?;
+    m.add_function(wrap_pyfunction!(index_repository, m)?)?;
+    m.add_function(wrap_pyfunction!(index_archive, m)?)?;
+    m.add_function(wrap_pyfunction!(index_documents, m)?)?;
+    m.add_function(wrap_pyfunction!(compact_index, m)?)?;
+    m.add_function(wrap_pyfunction!(self_test, m)?)?;
+    m.add("FORMAT_VERSION", format::FORMAT_VERSION)?;
+    m.add("CodeNavError", m.py().get_type_bound::<CodeNavError>())?;
+    m.add_class::<PyRange>()?;
+    m.add_class::<PySearchResult>()?;
+    m.add_class::<PyDefinedSymbol>()?;
+    m.add_class::<PyOccurrence>()?;
+    m.add_class::<PyCursorToken>()?;
+    m.add_class::<PyEdit>()?;
+    m.add_class::<PyRenameViolation>()?;
+    m.add_class::<PyIndexEvent>()?;
+    m.add_class::<PyIndexEvents>()?;
+    m.add_class::<PyIndexReport>()?;
+    m.add_class::<PyIndexHandle>()?;
+    m.add_class::<PySyncHandle>()?;
+    // the same class, under the name of a session kept open across queries
+    m.add("CodeNavSession", m.getattr("IndexHandle")?)?;
+    m.add_class::<PySelfTest>()?;
+    m.add_class::<PySelfTestCheck>()?;
     Ok(())
 }
\ No newline at end of file
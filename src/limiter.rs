@@ -0,0 +1,149 @@
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use serde::Serialize;
+
+/// Heavy queries allowed to run at once by default
+pub const DEFAULT_HEAVY_QUERIES: usize = 2;
+
+/// Bounds how many heavy queries run at once, such as wildcard scans of the term
+/// dictionary or `token_info` over every document of a language, so that they can't
+/// starve the cheap interactive ones, which never wait.
+///
+/// Heavy queries over the limit queue up and are let in first come, first served.
+pub struct QueryLimiter {
+    max_running: usize,
+    state: Mutex<State>,
+    admitted: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    running: usize,
+    /// Ticket handed to the next query to arrive
+    next_ticket: u64,
+    /// Ticket of the query at the head of the queue
+    next_admitted: u64,
+}
+
+/// How busy a [`QueryLimiter`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LimiterStats {
+    pub max_running: usize,
+    pub running: usize,
+    /// Heavy queries waiting for one of the running ones to finish
+    pub queued: usize,
+}
+
+impl QueryLimiter {
+    /// A `max_running` of 0 is treated as 1.
+    pub fn new(max_running: usize) -> Self {
+        Self {
+            max_running: max_running.max(1),
+            state: Mutex::new(State::default()),
+            admitted: Condvar::new(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait for a turn to run a heavy query, which lasts as long as the permit.
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut state = self.state();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+
+        while ticket != state.next_admitted || state.running >= self.max_running {
+            state = self.admitted.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        state.next_admitted += 1;
+        state.running += 1;
+        // the next query in line may fit too
+        self.admitted.notify_all();
+
+        Permit { limiter: self }
+    }
+
+    /// Run `f` as a heavy query if `heavy`, or straight away otherwise.
+    pub fn run<T>(&self, heavy: bool, f: impl FnOnce() -> T) -> T {
+        let _permit = heavy.then(|| self.acquire());
+        f()
+    }
+
+    pub fn stats(&self) -> LimiterStats {
+        let state = self.state();
+        LimiterStats {
+            max_running: self.max_running,
+            running: state.running,
+            queued: (state.next_ticket - state.next_admitted) as usize,
+        }
+    }
+}
+
+impl Default for QueryLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_HEAVY_QUERIES)
+    }
+}
+
+/// A turn to run a heavy query, given back on drop
+pub struct Permit<'a> {
+    limiter: &'a QueryLimiter,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.state().running -= 1;
+        self.limiter.admitted.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn bounds_heavy_queries() {
+        let limiter = QueryLimiter::new(2);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    limiter.run(true, || {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(10));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                });
+            }
+        });
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            limiter.stats(),
+            LimiterStats {
+                max_running: 2,
+                running: 0,
+                queued: 0
+            }
+        );
+    }
+
+    #[test]
+    fn light_queries_never_wait() {
+        let limiter = QueryLimiter::new(1);
+        let _permit = limiter.acquire();
+
+        // would deadlock if light queries needed a permit
+        assert_eq!(limiter.run(false, || 42), 42);
+        assert_eq!(limiter.stats().running, 1);
+    }
+}
@@ -0,0 +1,196 @@
+//! Code ownership, from the `CODEOWNERS` file of every root, read while indexing and
+//! kept next to the index, so that questions about a file can be routed to the people
+//! owning it and searches restricted to the files of a team.
+//!
+//! Patterns follow the GitHub syntax, much like `.gitignore` patterns: anchored at the
+//! root when they contain a `/` before their end, matching everything under the
+//! directories they match, and the last pattern matching a file wins. A pattern
+//! without owners leaves the files it matches without one.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use tantivy::directory::error::OpenReadError;
+use tantivy::Index;
+
+/// Name of the file, inside the index directory, that holds the `CODEOWNERS` rules of
+/// the roots
+pub const OWNERS_FILE: &str = "owners.json";
+
+/// Where the `CODEOWNERS` file of a root may be, the first one found wins
+pub const CODEOWNERS_PATHS: &[&str] = &[".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// A line of a `CODEOWNERS` file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnerRule {
+    pub pattern: String,
+    /// Users, `@org/team`s or emails, as written
+    pub owners: Vec<String>,
+}
+
+/// The rules of the `CODEOWNERS` file of a root
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeOwners {
+    /// Absolute path of the root the patterns are relative to
+    pub root: String,
+    pub rules: Vec<OwnerRule>,
+}
+
+impl CodeOwners {
+    /// The rules of the `CODEOWNERS` file `content`, without its comments and the
+    /// section headers of GitLab.
+    pub fn parse(root: &str, content: &str) -> Self {
+        let rules = content
+            .lines()
+            .map(|line| line.split_once(" #").map_or(line, |(rule, _)| rule).trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('[') && !line.starts_with("^["))
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                Some(OwnerRule {
+                    pattern: parts.next()?.to_owned(),
+                    owners: parts.map(str::to_owned).collect(),
+                })
+            })
+            .collect();
+        Self { root: root.trim_end_matches('/').to_owned(), rules }
+    }
+
+    /// The rules of the `CODEOWNERS` file of `root`, `None` if there is none.
+    pub fn read(root: &Path) -> Result<Option<Self>> {
+        for location in CODEOWNERS_PATHS {
+            match std::fs::read_to_string(root.join(location)) {
+                Ok(content) => return Ok(Some(Self::parse(&root.to_string_lossy().replace('\\', "/"), &content))),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(anyhow::Error::new(e).context(format!("failed to read {location} of {}", root.display()))),
+            }
+        }
+        Ok(None)
+    }
+
+    /// The rules compiled, to find the owners of many files. Patterns that aren't
+    /// valid globs match nothing.
+    pub fn matcher(&self) -> OwnersMatcher<'_> {
+        let rules = self
+            .rules
+            .iter()
+            .filter_map(|rule| Some((pattern_globs(&rule.pattern)?, rule.owners.as_slice())))
+            .collect();
+        OwnersMatcher { root: &self.root, rules }
+    }
+}
+
+/// The compiled rules of a [`CodeOwners`]
+pub struct OwnersMatcher<'a> {
+    root: &'a str,
+    rules: Vec<(GlobSet, &'a [String])>,
+}
+
+impl OwnersMatcher<'_> {
+    /// The owners of the file at `relative_path`, `/`-separated, from the last rule
+    /// matching it.
+    pub fn owners_of(&self, relative_path: &str) -> &[String] {
+        let relative_path = relative_path.trim_start_matches('/');
+        self.rules
+            .iter()
+            .rev()
+            .find(|(globs, _)| globs.is_match(relative_path))
+            .map_or(&[][..], |(_, owners)| *owners)
+    }
+
+    /// The owners of the file stored at the absolute `path`, `None` if it isn't under
+    /// the root.
+    pub fn owners_of_stored(&self, path: &str) -> Option<&[String]> {
+        let relative_path = path.strip_prefix(self.root)?.strip_prefix('/')?;
+        Some(self.owners_of(relative_path))
+    }
+}
+
+/// The globs matching the files of the `CODEOWNERS` `pattern`.
+fn pattern_globs(pattern: &str) -> Option<GlobSet> {
+    let is_dir = pattern.ends_with('/');
+    let trimmed = pattern.trim_matches('/');
+    if trimmed.is_empty() {
+        return None;
+    }
+    let anchored = pattern.starts_with('/') || trimmed.contains('/');
+    let base = if anchored { trimmed.to_owned() } else { format!("**/{trimmed}") };
+
+    let mut globs = GlobSetBuilder::new();
+    let variants = if is_dir { vec![format!("{base}/**")] } else { vec![base.clone(), format!("{base}/**")] };
+    for glob in variants {
+        globs.add(GlobBuilder::new(&glob).literal_separator(true).build().ok()?);
+    }
+    globs.build().ok()
+}
+
+/// Whether `owner`, as written in a `CODEOWNERS` file, is the one `name`s, regardless
+/// of case and of the leading `@`.
+pub fn is_owner(owner: &str, name: &str) -> bool {
+    owner.trim_start_matches('@').eq_ignore_ascii_case(name.trim().trim_start_matches('@'))
+}
+
+/// The owners of the file at `path` from the `CODEOWNERS` of the innermost root
+/// holding it. A relative `path` is relative to the first root with a `CODEOWNERS`.
+pub fn owners_of(codeowners: &[CodeOwners], path: &str) -> Vec<String> {
+    let path = path.replace('\\', "/");
+    if !path.starts_with('/') {
+        return codeowners.first().map(|first| first.matcher().owners_of(&path).to_vec()).unwrap_or_default();
+    }
+    codeowners
+        .iter()
+        .filter(|codeowners| path.starts_with(&format!("{}/", codeowners.root)))
+        .max_by_key(|codeowners| codeowners.root.len())
+        .map(|innermost| innermost.matcher().owners_of_stored(&path).unwrap_or_default().to_vec())
+        .unwrap_or_default()
+}
+
+/// Record the `CODEOWNERS` rules of the indexed `roots` in `index`, in place of those
+/// recorded for them before, `codeowners` holding those of the roots that have one.
+pub fn record(index: &Index, roots: &[String], codeowners: Vec<CodeOwners>) -> Result<()> {
+    let mut recorded = stored(index)?;
+    recorded.retain(|recorded| !roots.contains(&recorded.root));
+    recorded.extend(codeowners);
+    recorded.sort_by(|a, b| a.root.cmp(&b.root));
+    Ok(index
+        .directory()
+        .atomic_write(Path::new(OWNERS_FILE), &serde_json::to_vec(&recorded)?)?)
+}
+
+/// The `CODEOWNERS` rules recorded in `index`, outer roots first. None for indexes
+/// written before they were recorded.
+pub fn stored(index: &Index) -> Result<Vec<CodeOwners>> {
+    match index.directory().atomic_read(Path::new(OWNERS_FILE)) {
+        Ok(stored) => serde_json::from_slice(&stored).context("invalid owners file"),
+        Err(OpenReadError::FileDoesNotExist(_)) => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let codeowners = CodeOwners::parse(
+            "/repo",
+            "# default owners\n* @org/core\n\n[Docs]\n*.md @org/docs # prose\n/src/search/ @alice\nbuild/ @org/infra\nsrc/search/vendored.rs\n",
+        );
+        assert_eq!(codeowners.rules.len(), 5);
+
+        let matcher = codeowners.matcher();
+        assert_eq!(matcher.owners_of("src/lib.rs"), ["@org/core"]);
+        assert_eq!(matcher.owners_of("guide/intro.md"), ["@org/docs"]);
+        assert_eq!(matcher.owners_of("src/search/query.rs"), ["@alice"]);
+        assert_eq!(matcher.owners_of("tools/build/run.sh"), ["@org/infra"]);
+        assert!(matcher.owners_of("src/search/vendored.rs").is_empty());
+        // anchored at the root
+        assert_eq!(matcher.owners_of("lib/src/search/query.rs"), ["@org/core"]);
+
+        assert_eq!(owners_of(&[codeowners.clone()], "/repo/src/search/query.rs"), ["@alice"]);
+        assert!(owners_of(&[codeowners], "/elsewhere/src/lib.rs").is_empty());
+        assert!(is_owner("@org/Docs", "org/docs"));
+    }
+}
@@ -0,0 +1,347 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Name of the file, inside the index directory, that holds the packages found while indexing
+pub const PACKAGES_FILE: &str = "packages.json";
+
+/// Manifest file names recognised as package definitions
+pub const MANIFEST_FILES: &[&str] = &["Cargo.toml", "package.json", "pyproject.toml"];
+
+/// Metadata of a directory containing a package manifest and/or a README
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageInfo {
+    /// Absolute path of the directory
+    pub dir: String,
+    /// File name of the manifest, if there is one
+    pub manifest: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Names of the declared (non-dev) dependencies
+    pub dependencies: Vec<String>,
+    /// First paragraph of the README, if there is one
+    pub readme_summary: Option<String>,
+}
+
+impl PackageInfo {
+    /// Record what `file_name` in this directory tells about the package.
+    ///
+    /// Returns whether the file was a manifest or README.
+    pub fn update(&mut self, file_name: &str, content: &str) -> bool {
+        if is_readme(file_name) {
+            self.readme_summary = readme_summary(content);
+            return true;
+        }
+
+        let parsed = match file_name {
+            "Cargo.toml" => parse_cargo_toml(content),
+            "package.json" => parse_package_json(content),
+            "pyproject.toml" => parse_pyproject_toml(content),
+            _ => return false,
+        };
+
+        // a directory with several manifests keeps the first one that parses
+        if self.manifest.is_none() {
+            if let Some((name, description, dependencies)) = parsed {
+                self.manifest = Some(file_name.to_owned());
+                self.name = name;
+                self.description = description;
+                self.dependencies = dependencies;
+            }
+        }
+        true
+    }
+
+    /// Whether `file_name` is a manifest or README worth recording.
+    pub fn is_package_file(file_name: &str) -> bool {
+        MANIFEST_FILES.contains(&file_name) || is_readme(file_name)
+    }
+
+    /// Find the package of `dir`: the recorded directory that is equal to, or the
+    /// closest ancestor of, `dir`. Relative `dir`s match the end of recorded paths.
+    pub fn find<'a>(packages: &'a [PackageInfo], dir: &str) -> Option<&'a PackageInfo> {
+        let is_dir = |candidate: &str, target: &Path| {
+            let candidate = Path::new(candidate);
+            candidate == target || (target.is_relative() && candidate.ends_with(target))
+        };
+
+        Path::new(dir.trim_end_matches('/'))
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .enumerate()
+            .find_map(|(depth, ancestor)| {
+                packages
+                    .iter()
+                    // a README alone doesn't make a package of the subdirectories
+                    .filter(|p| depth == 0 || p.manifest.is_some())
+                    .find(|p| is_dir(&p.dir, ancestor))
+            })
+    }
+}
+
+/// A part of the repository that searches can be restricted to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// The package with this name inside a monorepo: a Cargo workspace member, an npm
+    /// workspace or a Python project
+    WorkspaceMember(String),
+    /// The files owned by this user or team in the `CODEOWNERS` of the repository, see
+    /// [`crate::owners`]
+    Owner(String),
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let value = |prefix: &str| trimmed.strip_prefix(prefix).map(str::trim).filter(|value| !value.is_empty()).map(str::to_owned);
+        value("workspace_member:")
+            .map(Self::WorkspaceMember)
+            .or_else(|| value("owner:").map(Self::Owner))
+            .ok_or_else(|| anyhow::anyhow!("invalid scope `{s}`, expected `workspace_member:<name>` or `owner:<owner>`"))
+    }
+}
+
+impl Scope {
+    /// The directory this scope covers, `None` for an owner, whose files are told by
+    /// the `CODEOWNERS` rules rather than by a directory.
+    pub fn resolve(&self, packages: &[PackageInfo]) -> Result<Option<String>> {
+        match self {
+            Self::WorkspaceMember(name) => packages
+                .iter()
+                .find(|p| p.manifest.is_some() && p.name.as_deref() == Some(name.as_str()))
+                .map(|p| Some(p.dir.clone()))
+                .ok_or_else(|| {
+                    let mut members = packages
+                        .iter()
+                        .filter(|p| p.manifest.is_some())
+                        .filter_map(|p| p.name.as_deref())
+                        .collect::<Vec<_>>();
+                    members.sort_unstable();
+                    anyhow::anyhow!(
+                        "no workspace member named `{name}`, known members: {}",
+                        members.join(", ")
+                    )
+                }),
+            Self::Owner(_) => Ok(None),
+        }
+    }
+
+    /// The owner whose files this scope covers.
+    pub fn owner(&self) -> Option<&str> {
+        match self {
+            Self::Owner(owner) => Some(owner),
+            Self::WorkspaceMember(_) => None,
+        }
+    }
+}
+
+fn is_readme(file_name: &str) -> bool {
+    let lower = file_name.to_lowercase();
+    lower == "readme" || lower.starts_with("readme.")
+}
+
+type Manifest = (Option<String>, Option<String>, Vec<String>);
+
+fn parse_cargo_toml(content: &str) -> Option<Manifest> {
+    let table = content.parse::<toml::Table>().ok()?;
+    let package = table.get("package");
+    let field = |key: &str| {
+        package
+            .and_then(|p| p.get(key))
+            .and_then(|v| v.as_str())
+            .map(ToOwned::to_owned)
+    };
+    let dependencies = table
+        .get("dependencies")
+        .and_then(|d| d.as_table())
+        .map(|d| d.keys().cloned().collect())
+        .unwrap_or_default();
+
+    Some((field("name"), field("description"), dependencies))
+}
+
+fn parse_package_json(content: &str) -> Option<Manifest> {
+    let json = serde_json::from_str::<serde_json::Value>(content).ok()?;
+    let field = |key: &str| json.get(key).and_then(|v| v.as_str()).map(ToOwned::to_owned);
+    let dependencies = json
+        .get("dependencies")
+        .and_then(|d| d.as_object())
+        .map(|d| d.keys().cloned().collect())
+        .unwrap_or_default();
+
+    Some((field("name"), field("description"), dependencies))
+}
+
+fn parse_pyproject_toml(content: &str) -> Option<Manifest> {
+    let table = content.parse::<toml::Table>().ok()?;
+
+    // PEP 621 metadata
+    if let Some(project) = table.get("project") {
+        let field = |key: &str| project.get(key).and_then(|v| v.as_str()).map(ToOwned::to_owned);
+        let dependencies = project
+            .get("dependencies")
+            .and_then(|d| d.as_array())
+            .map(|d| {
+                d.iter()
+                    .filter_map(|v| v.as_str())
+                    .map(requirement_name)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        return Some((field("name"), field("description"), dependencies));
+    }
+
+    // poetry metadata
+    let poetry = table.get("tool").and_then(|t| t.get("poetry"))?;
+    let field = |key: &str| poetry.get(key).and_then(|v| v.as_str()).map(ToOwned::to_owned);
+    let dependencies = poetry
+        .get("dependencies")
+        .and_then(|d| d.as_table())
+        .map(|d| d.keys().filter(|k| *k != "python").cloned().collect())
+        .unwrap_or_default();
+
+    Some((field("name"), field("description"), dependencies))
+}
+
+/// The distribution name of a PEP 508 requirement, e.g. `requests` for `requests[socks]>=2.0`
+fn requirement_name(requirement: &str) -> String {
+    requirement
+        .split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_' || c == '.'))
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// The first paragraph of prose in a README, skipping headings, badges and code fences.
+fn readme_summary(content: &str) -> Option<String> {
+    let mut paragraph = Vec::new();
+    let mut in_code_block = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+
+        let is_prose = !line.is_empty()
+            && !line.starts_with('#')
+            && !line.starts_with("[![")
+            && !line.starts_with("![")
+            && !line.starts_with('<')
+            && !line.starts_with("===")
+            && !line.starts_with("---");
+
+        if is_prose {
+            paragraph.push(line);
+        } else if !paragraph.is_empty() {
+            break;
+        }
+    }
+
+    (!paragraph.is_empty()).then(|| paragraph.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cargo_manifest() {
+        let mut info = PackageInfo::default();
+        info.update(
+            "Cargo.toml",
+            "[package]\nname = \"foo\"\ndescription = \"A foo\"\n\n[dependencies]\nserde = \"1\"\nregex = { version = \"1\" }\n",
+        );
+
+        assert_eq!(info.manifest.as_deref(), Some("Cargo.toml"));
+        assert_eq!(info.name.as_deref(), Some("foo"));
+        assert_eq!(info.description.as_deref(), Some("A foo"));
+        assert_eq!(info.dependencies, vec!["regex", "serde"]);
+    }
+
+    #[test]
+    fn pyproject_manifest() {
+        let mut info = PackageInfo::default();
+        info.update(
+            "pyproject.toml",
+            "[project]\nname = \"bar\"\ndependencies = [\"requests[socks]>=2.0\", \"click\"]\n",
+        );
+
+        assert_eq!(info.name.as_deref(), Some("bar"));
+        assert_eq!(info.dependencies, vec!["requests", "click"]);
+    }
+
+    #[test]
+    fn readme() {
+        let mut info = PackageInfo::default();
+        info.update("README.md", "# Title\n\n[![ci](badge.svg)](ci)\n\nDoes a thing\nquickly.\n\nMore.\n");
+
+        assert_eq!(info.readme_summary.as_deref(), Some("Does a thing quickly."));
+    }
+
+    #[test]
+    fn find_closest_package() {
+        let packages = vec![
+            PackageInfo {
+                dir: "/repo".to_owned(),
+                manifest: Some("Cargo.toml".to_owned()),
+                ..Default::default()
+            },
+            PackageInfo {
+                dir: "/repo/crates/a".to_owned(),
+                manifest: Some("Cargo.toml".to_owned()),
+                ..Default::default()
+            },
+            PackageInfo {
+                dir: "/repo/crates/a/docs".to_owned(),
+                readme_summary: Some("Docs".to_owned()),
+                ..Default::default()
+            },
+        ];
+
+        let dir = |d: &str| PackageInfo::find(&packages, d).map(|p| p.dir.as_str());
+        assert_eq!(dir("/repo/crates/a/src"), Some("/repo/crates/a"));
+        assert_eq!(dir("crates/a"), Some("/repo/crates/a"));
+        assert_eq!(dir("/repo/crates/a/docs"), Some("/repo/crates/a/docs"));
+        assert_eq!(dir("/repo/crates/a/docs/img"), Some("/repo/crates/a"));
+        assert_eq!(dir("/elsewhere"), None);
+    }
+
+    #[test]
+    fn workspace_member_scope() {
+        let packages = vec![
+            PackageInfo {
+                dir: "/repo".to_owned(),
+                manifest: Some("Cargo.toml".to_owned()),
+                name: Some("root".to_owned()),
+                ..Default::default()
+            },
+            PackageInfo {
+                dir: "/repo/crates/a".to_owned(),
+                manifest: Some("Cargo.toml".to_owned()),
+                name: Some("a".to_owned()),
+                ..Default::default()
+            },
+        ];
+
+        let scope = "workspace_member:a".parse::<Scope>().unwrap();
+        assert_eq!(scope, Scope::WorkspaceMember("a".to_owned()));
+        assert_eq!(scope.resolve(&packages).unwrap().as_deref(), Some("/repo/crates/a"));
+
+        let err = Scope::WorkspaceMember("b".to_owned()).resolve(&packages).unwrap_err();
+        assert!(err.to_string().contains("known members: a, root"));
+
+        assert!("a".parse::<Scope>().is_err());
+        assert!("workspace_member:".parse::<Scope>().is_err());
+        assert_eq!("owner: @org/core".parse::<Scope>().unwrap(), Scope::Owner("@org/core".to_owned()));
+    }
+}
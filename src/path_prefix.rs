@@ -0,0 +1,126 @@
+//! The directories a file is in, indexed in the `path_prefixes` field, so that searches
+//! restricted to a directory, by a scope or a `path:src/service/**` term, look a single
+//! term up rather than matching every stored path against a pattern.
+//!
+//! A `path:` glob is looked up by the directory it starts with, the components before
+//! the first one with a wildcard, and only the paths under that directory are matched
+//! against the rest of it. A glob that is a directory followed by `/**` is not matched
+//! at all.
+
+/// The stored paths of the directories `path` is in, outer directories first: `/a` and
+/// `/a/b` for `/a/b/c.rs`.
+pub fn prefixes(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(|(end, _)| &path[..end]).filter(|prefix| !prefix.is_empty())
+}
+
+/// The directory `glob` starts with, the components before the first one with a
+/// wildcard and never the last one: `src/service` for `src/service/**/*.rs`, and
+/// nothing for `**/*.rs`.
+pub fn literal_dir(glob: &str) -> &str {
+    let mut end = 0;
+    for (slash, _) in glob.match_indices('/') {
+        if glob[..slash].contains(is_wildcard) {
+            break;
+        }
+        end = slash;
+    }
+    &glob[..end]
+}
+
+/// Whether `glob` matches every file under the directory it starts with, as
+/// `src/service/**` does.
+pub fn is_whole_dir(glob: &str) -> bool {
+    let dir = literal_dir(glob);
+    glob.strip_prefix(dir).is_some_and(|rest| rest == "/**") || (dir.is_empty() && glob == "**")
+}
+
+/// The glob matching every file under `dir`, its wildcard chars escaped: `src/service/**`
+/// for `src/service/`.
+pub fn dir_glob(dir: &str) -> String {
+    format!("{}/**", globset::escape(dir.trim_end_matches('/')))
+}
+
+fn is_wildcard(c: char) -> bool {
+    matches!(c, '*' | '?' | '[' | '{')
+}
+
+/// A regex matching the paths `glob` matches, for a `RegexQuery`. As for the other path
+/// globs, see [`crate::search::path_matcher`], `*` and `?` match `/` too, and `**/`
+/// matches no directory as well.
+pub fn glob_regex(glob: &str) -> String {
+    let mut regex = String::with_capacity(glob.len() * 2);
+    let mut chars = glob.chars().peekable();
+    let mut in_alternatives = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                regex.push('[');
+                if chars.next_if_eq(&'!').is_some() {
+                    regex.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            '{' if !in_alternatives => {
+                in_alternatives = true;
+                regex.push('(');
+            }
+            '}' if in_alternatives => {
+                in_alternatives = false;
+                regex.push(')');
+            }
+            ',' if in_alternatives => regex.push('|'),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_and_globs() {
+        assert_eq!(prefixes("/repo/src/main.rs").collect::<Vec<_>>(), ["/repo", "/repo/src"]);
+
+        assert_eq!(literal_dir("src/service/**/*.rs"), "src/service");
+        assert_eq!(literal_dir("/repo/src/*.{rs,py}"), "/repo/src");
+        assert_eq!(literal_dir("src/handler_*/mod.rs"), "src");
+        assert_eq!(literal_dir("**/*.rs"), "");
+        assert_eq!(literal_dir("main.rs"), "");
+
+        assert!(is_whole_dir("src/service/**"));
+        assert!(!is_whole_dir("src/service/**/*.rs"));
+        assert!(!is_whole_dir("src/*/**"));
+        assert_eq!(dir_glob("src/service/"), "src/service/**");
+        assert!(is_whole_dir(&dir_glob("src/[legacy]")));
+
+        let matches = |glob: &str, path: &str| regex::Regex::new(&format!("^{}$", glob_regex(glob))).unwrap().is_match(path);
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/a/b/lib.rs"));
+        assert!(matches("src/*.{rs,py}", "src/app.py"));
+        assert!(matches("src/[!_]*.rs", "src/main.rs"));
+        assert!(!matches("src/[!_]*.rs", "src/_private.rs"));
+        assert!(!matches("src/main.rs", "src/mainxrs"));
+    }
+}
@@ -0,0 +1,896 @@
+//! Python classes of the module: the structured results, which mirror read-only the
+//! Rust results they're built from, and the persistent index handle.
+//!
+//! Their signatures are declared in `code_nav_devon.pyi`, keep it in sync.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde::{Serialize, Serializer};
+
+use crate::api::{DEFAULT_BUFFER_SIZE, DEFAULT_THREADS};
+use crate::compaction::IndexHealth;
+use crate::edit::{Edit, RenameViolation};
+use crate::events::{Closed, Events, IndexEvent};
+use crate::handle::IndexHandle;
+use crate::indexes::{IndexReport, Priority};
+use crate::limiter::DEFAULT_HEAVY_QUERIES;
+use crate::sync_handle::SyncHandle;
+use crate::watcher::{Watcher, DEFAULT_DEBOUNCE};
+use crate::path_prefix;
+use crate::intelligence::code_navigation::{FileSymbols, OccurrenceKind};
+use crate::search::{CursorToken, DefinedSymbol, TermMatch};
+use crate::self_test::SelfTest;
+use crate::text_range::{Point, TextRange};
+use crate::response_limits::ResponseLimits;
+use crate::{build_query, format_go_to, format_query_response, line_base_from, operation_error, parse_context, parse_format, parse_lang, parse_occurrence_filter, parse_scope};
+use crate::query::Query;
+use crate::search::Searcher;
+
+fn to_json(value: &impl Serialize) -> PyResult<String> {
+    serde_json::to_string(value).map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
+
+/// Serialize a JSON string as the value it holds.
+fn nested_json<S: Serializer>(json: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    let value = json.as_deref().map(serde_json::from_str::<serde_json::Value>).transpose().map_err(serde::ser::Error::custom)?;
+    value.serialize(serializer)
+}
+
+/// A span of a file, with the lines and columns of both ends and their byte offsets
+#[pyclass(name = "Range", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone, Serialize)]
+pub struct PyRange {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub start_byte: usize,
+    pub end_byte: usize,
+}
+
+#[pymethods]
+impl PyRange {
+    fn __repr__(&self) -> String {
+        format!(
+            "Range({}:{}-{}:{})",
+            self.start_line, self.start_column, self.end_line, self.end_column
+        )
+    }
+}
+
+impl From<TextRange> for PyRange {
+    fn from(range: TextRange) -> Self {
+        Self {
+            start_line: range.start.line,
+            start_column: range.start.column,
+            end_line: range.end.line,
+            end_column: range.end.column,
+            start_byte: range.start.byte,
+            end_byte: range.end.byte,
+        }
+    }
+}
+
+/// A line matching a search
+#[pyclass(name = "SearchResult", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone, Serialize)]
+pub struct PySearchResult {
+    pub path: String,
+    /// `path` relative to the root it was indexed from
+    pub relative_path: String,
+    pub line_number: usize,
+    pub column: usize,
+    pub context: String,
+    /// Line number of the first line of `context`, counted from the `LineBase`
+    pub context_start_line: usize,
+    /// The query term that matched, the query text itself unless it had several terms
+    pub term: String,
+    pub is_minified: bool,
+    pub is_generated: bool,
+    /// The symbols defined on the matching line
+    pub symbols: Vec<PyDefinedSymbol>,
+    /// The score of the document, with `explain`
+    pub score: Option<f32>,
+    /// How tantivy computed `score`, as JSON
+    #[serde(serialize_with = "nested_json")]
+    pub explanation: Option<String>,
+}
+
+#[pymethods]
+impl PySearchResult {
+    fn __repr__(&self) -> String {
+        format!("SearchResult({}:{}:{})", self.path, self.line_number, self.column)
+    }
+
+    /// The result as a JSON object of its attributes, `explanation` nested rather
+    /// than a string.
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+}
+
+impl From<TermMatch> for PySearchResult {
+    fn from(hit: TermMatch) -> Self {
+        Self {
+            path: hit.result.path,
+            relative_path: hit.result.relative_path,
+            line_number: hit.result.line_number,
+            column: hit.result.column,
+            context: hit.result.snippet.to_text(),
+            context_start_line: hit.result.snippet.start_line(),
+            term: hit.term,
+            is_minified: hit.result.flags.is_minified,
+            is_generated: hit.result.flags.is_generated,
+            symbols: hit.result.symbols.into_iter().map(PyDefinedSymbol::from).collect(),
+            score: hit.result.score.as_ref().map(|score| score.value),
+            explanation: hit.result.score.map(|score| score.explanation.to_string()),
+        }
+    }
+}
+
+/// A symbol defined on the line of a search result
+#[pyclass(name = "DefinedSymbol", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone, Serialize)]
+pub struct PyDefinedSymbol {
+    pub name: String,
+    pub kind: String,
+    pub range: PyRange,
+}
+
+#[pymethods]
+impl PyDefinedSymbol {
+    fn __repr__(&self) -> String {
+        format!("DefinedSymbol({} {}, {}:{})", self.kind, self.name, self.range.start_line, self.range.start_column)
+    }
+}
+
+impl From<DefinedSymbol> for PyDefinedSymbol {
+    fn from(symbol: DefinedSymbol) -> Self {
+        Self {
+            name: symbol.name,
+            kind: symbol.kind,
+            range: symbol.range.into(),
+        }
+    }
+}
+
+/// A definition or reference of the symbol under a position
+#[pyclass(name = "Occurrence", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone, Serialize)]
+pub struct PyOccurrence {
+    pub path: String,
+    /// `definition` or `reference`
+    pub kind: &'static str,
+    pub range: PyRange,
+    pub snippet: String,
+    /// Whether the occurrence comes from a dependency index rather than the repository
+    pub external: bool,
+    /// For definitions, the number of references found along with them
+    pub reference_count: Option<usize>,
+}
+
+#[pymethods]
+impl PyOccurrence {
+    fn __repr__(&self) -> String {
+        format!(
+            "Occurrence({}, {}:{}:{})",
+            self.kind, self.path, self.range.start_line, self.range.start_column
+        )
+    }
+
+    /// The occurrence as a JSON object of its attributes.
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+}
+
+impl PyOccurrence {
+    /// Flattens the occurrences of every file, in order.
+    pub fn from_file_symbols(results: Vec<FileSymbols>) -> Vec<Self> {
+        results
+            .into_iter()
+            .flat_map(|file_symbols| {
+                let FileSymbols { file, data, external } = file_symbols;
+                data.into_iter().map(move |occurrence| Self {
+                    path: file.clone(),
+                    kind: match occurrence.kind {
+                        OccurrenceKind::Definition => "definition",
+                        OccurrenceKind::Reference => "reference",
+                    },
+                    range: occurrence.range.into(),
+                    snippet: occurrence.snippet.to_text(),
+                    external,
+                    reference_count: occurrence.reference_count,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The token under a position, see `token_at`
+#[pyclass(name = "CursorToken", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone, Serialize)]
+pub struct PyCursorToken {
+    pub text: String,
+    /// The kind of its syntax node, e.g. `field_identifier`
+    pub kind: String,
+    /// `identifier`, `keyword`, `literal`, `comment`, `punctuation` or `other`
+    pub classification: &'static str,
+    /// The line and char columns to pass to `go_to`
+    pub line: usize,
+    pub start_index: usize,
+    pub end_index: usize,
+    pub range: PyRange,
+}
+
+#[pymethods]
+impl PyCursorToken {
+    fn __repr__(&self) -> String {
+        format!("CursorToken({} {:?}, {}:{})", self.classification, self.text, self.line, self.start_index)
+    }
+
+    /// The token as a JSON object of its attributes.
+    fn to_json(&self) -> PyResult<String> {
+        to_json(self)
+    }
+}
+
+impl From<CursorToken> for PyCursorToken {
+    fn from(token: CursorToken) -> Self {
+        Self {
+            text: token.text,
+            kind: token.kind,
+            classification: token.classification.as_str(),
+            line: token.line,
+            start_index: token.start_index,
+            end_index: token.end_index,
+            range: token.range.into(),
+        }
+    }
+}
+
+/// A replacement of the text of a range of a file, see `plan_replace`
+#[pyclass(name = "Edit", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct PyEdit {
+    pub path: String,
+    pub range: PyRange,
+    /// The text of the range, before the edit
+    pub old: String,
+    pub new: String,
+}
+
+#[pymethods]
+impl PyEdit {
+    fn __repr__(&self) -> String {
+        format!("Edit({}:{}:{}, {:?} -> {:?})", self.path, self.range.start_line, self.range.start_column, self.old, self.new)
+    }
+}
+
+impl From<Edit> for PyEdit {
+    fn from(edit: Edit) -> Self {
+        Self {
+            path: edit.path,
+            range: edit.range.into(),
+            old: edit.old,
+            new: edit.new,
+        }
+    }
+}
+
+impl From<PyEdit> for Edit {
+    fn from(edit: PyEdit) -> Self {
+        let range = &edit.range;
+        Self {
+            path: edit.path,
+            range: TextRange::new(
+                Point::new(range.start_byte, range.start_line, range.start_column),
+                Point::new(range.end_byte, range.end_line, range.end_column),
+            ),
+            old: edit.old,
+            new: edit.new,
+        }
+    }
+}
+
+/// A problem with the edits of a rename, see `verify_rename`
+#[pyclass(name = "RenameViolation", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct PyRenameViolation {
+    pub path: String,
+    /// `stray_reference`, `unintended_rename` or `collision`
+    pub kind: &'static str,
+    pub range: PyRange,
+    pub text: String,
+}
+
+#[pymethods]
+impl PyRenameViolation {
+    fn __repr__(&self) -> String {
+        format!("RenameViolation({}, {}:{}:{}, {:?})", self.kind, self.path, self.range.start_line, self.range.start_column, self.text)
+    }
+}
+
+impl From<RenameViolation> for PyRenameViolation {
+    fn from(violation: RenameViolation) -> Self {
+        Self {
+            path: violation.path,
+            kind: violation.kind.as_str(),
+            range: violation.range.into(),
+            text: violation.text,
+        }
+    }
+}
+
+/// One step of `self_test`
+#[pyclass(name = "SelfTestCheck", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct PySelfTestCheck {
+    /// `index`, `search` or `go_to`
+    pub name: &'static str,
+    pub passed: bool,
+    /// What was found, or why the step failed or was skipped
+    pub detail: String,
+}
+
+#[pymethods]
+impl PySelfTestCheck {
+    fn __repr__(&self) -> String {
+        let outcome = if self.passed { "passed" } else { "failed" };
+        format!("SelfTestCheck({}, {outcome}: {})", self.name, self.detail)
+    }
+}
+
+/// The outcome of `self_test`, true if every check passed
+#[pyclass(name = "SelfTest", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct PySelfTest {
+    pub passed: bool,
+    pub version: &'static str,
+    pub format_version: u32,
+    pub checks: Vec<PySelfTestCheck>,
+}
+
+#[pymethods]
+impl PySelfTest {
+    fn __bool__(&self) -> bool {
+        self.passed
+    }
+
+    fn __repr__(&self) -> String {
+        let outcome = if self.passed { "passed" } else { "failed" };
+        format!("SelfTest({outcome}, version {})", self.version)
+    }
+}
+
+impl From<SelfTest> for PySelfTest {
+    fn from(report: SelfTest) -> Self {
+        Self {
+            passed: report.passed(),
+            version: report.version,
+            format_version: report.format_version,
+            checks: report
+                .checks
+                .into_iter()
+                .map(|check| PySelfTestCheck {
+                    name: check.name,
+                    passed: check.passed,
+                    detail: check.detail,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What an indexing run did, see [`IndexReport`]
+#[pyclass(name = "IndexReport", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct PyIndexReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+    /// `(from, to)` paths
+    pub renamed: Vec<(String, String)>,
+    /// `(path, size)` of the files over the size cap
+    pub skipped: Vec<(String, u64)>,
+    /// `(path, error)` of the entries that couldn't be read
+    pub errors: Vec<(String, String)>,
+    /// `(path, category, size)` of the files evicted to fit in the size budget
+    pub evicted: Vec<(String, &'static str, u64)>,
+    pub duration_ms: f64,
+    pub changed: bool,
+}
+
+#[pymethods]
+impl PyIndexReport {
+    fn __bool__(&self) -> bool {
+        self.changed
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "IndexReport({} added, {} updated, {} deleted, {} renamed, {} skipped, {} errors, {} evicted in {:.0} ms)",
+            self.added.len(),
+            self.updated.len(),
+            self.deleted.len(),
+            self.renamed.len(),
+            self.skipped.len(),
+            self.errors.len(),
+            self.evicted.len(),
+            self.duration_ms
+        )
+    }
+}
+
+impl From<IndexReport> for PyIndexReport {
+    fn from(report: IndexReport) -> Self {
+        Self {
+            changed: report.changed(),
+            duration_ms: report.duration.as_secs_f64() * 1000.0,
+            added: report.added,
+            updated: report.updated,
+            deleted: report.deleted,
+            renamed: report.renamed.into_iter().map(|rename| (rename.from, rename.to)).collect(),
+            skipped: report.skipped.into_iter().map(|skipped| (skipped.path, skipped.size)).collect(),
+            errors: report.errors.into_iter().map(|error| (error.path, error.error)).collect(),
+            evicted: report.evicted.into_iter().map(|evicted| (evicted.path, evicted.category.as_str(), evicted.size)).collect(),
+        }
+    }
+}
+
+/// A repository index kept open across calls, see [`IndexHandle`].
+///
+/// One handle can be shared by any number of python threads: every method releases
+/// the GIL and runs on its own snapshot of the searcher.
+#[pyclass(name = "IndexHandle", module = "code_nav_devon", frozen)]
+pub struct PyIndexHandle {
+    handle: IndexHandle,
+    watcher: Mutex<Option<Watcher>>,
+}
+
+#[pymethods]
+impl PyIndexHandle {
+    /// Indexes the repository, then keeps the index open. At most `max_heavy_queries`
+    /// fuzzy, wildcard or `go_to` queries run at once, the others queue up. With
+    /// `index_missing_files`, a query of a file created since the last refresh indexes
+    /// it on the fly instead of failing. With `line_base`, 0 or 1, the lines of its
+    /// queries are numbered from it whatever `set_line_base` was called with.
+    #[new]
+    #[pyo3(signature = (root_path_str, index_path_str, max_heavy_queries=DEFAULT_HEAVY_QUERIES, index_missing_files=false, line_base=None))]
+    fn new(py: Python<'_>, root_path_str: &str, index_path_str: &str, max_heavy_queries: usize, index_missing_files: bool, line_base: Option<usize>) -> PyResult<Self> {
+        let line_base = line_base.map(line_base_from).transpose()?;
+        let root_path = Path::new(root_path_str);
+
+        if !root_path.exists() {
+            return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Root path does not exist"));
+        }
+
+        let index_path = Path::new(index_path_str);
+
+        if !index_path.exists() {
+            return Err(pyo3::exceptions::PyRuntimeError::new_err("Internal error: Index path does not exist"));
+        }
+
+        py.allow_threads(|| IndexHandle::open(root_path, index_path, DEFAULT_BUFFER_SIZE, DEFAULT_THREADS))
+            .map(|handle| {
+                let handle = handle.heavy_query_limit(max_heavy_queries).index_missing_files(index_missing_files);
+                Self {
+                    handle: match line_base {
+                        Some(base) => handle.line_base(base),
+                        None => handle,
+                    },
+                    watcher: Mutex::new(None),
+                }
+            })
+            .map_err(|e| operation_error("Failed to open index", e))
+    }
+
+    /// Brings the index up to date with the repository, returning what changed. With
+    /// `background`, on a single thread, pausing after every file read.
+    #[pyo3(signature = (background=false))]
+    fn refresh(&self, py: Python<'_>, background: bool) -> PyResult<PyIndexReport> {
+        let priority = if background { Priority::Background } else { Priority::Normal };
+        py.allow_threads(|| self.handle.refresh_at(priority))
+            .map(PyIndexReport::from)
+            .map_err(|e| operation_error("Failed to index repository", e))
+    }
+
+    /// Starts a refresh like `refresh()` without waiting for it, and returns its
+    /// `SyncHandle` right away, to follow it while it runs.
+    #[pyo3(signature = (background=false))]
+    fn start_refresh(&self, background: bool) -> PySyncHandle {
+        let priority = if background { Priority::Background } else { Priority::Normal };
+        PySyncHandle { progress: self.handle.start_refresh(priority) }
+    }
+
+    /// Keeps the index up to date from now on, reindexing the files that change once
+    /// they settle for `debounce_ms`, until `stop_watching()`. What changes is published
+    /// to the `events`. Watching again leaves the running watch as it is.
+    #[pyo3(signature = (debounce_ms=None))]
+    fn start_watching(&self, py: Python<'_>, debounce_ms: Option<u64>) -> PyResult<()> {
+        let mut watcher = self.watcher.lock().unwrap_or_else(|e| e.into_inner());
+        if watcher.is_none() {
+            let debounce = debounce_ms.map_or(DEFAULT_DEBOUNCE, Duration::from_millis);
+            *watcher = Some(py.allow_threads(|| self.handle.watch(debounce)).map_err(|e| operation_error("Failed to watch repository", e))?);
+        }
+        Ok(())
+    }
+
+    /// Stops the watch of `start_watching()`, if any. The index stays as it is until the
+    /// next `refresh()`.
+    fn stop_watching(&self) {
+        self.watcher.lock().unwrap_or_else(|e| e.into_inner()).take();
+    }
+
+    /// Whether `start_watching()` keeps the index up to date.
+    #[getter]
+    fn watching(&self) -> bool {
+        self.watcher.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+    }
+
+    /// Like the module's `search`.
+    #[pyo3(signature = (query, **options))]
+    fn search(&self, py: Python<'_>, query: &Bound<'_, PyAny>, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+        let query = build_query(query, options)?;
+        self.run_search(py, query, "Error performing search")
+    }
+
+    /// Like the module's `search_results`.
+    #[pyo3(signature = (query, **options))]
+    fn search_results(&self, py: Python<'_>, query: &Bound<'_, PyAny>, options: Option<&Bound<'_, PyDict>>) -> PyResult<Vec<PySearchResult>> {
+        let query = build_query(query, options)?;
+
+        self.allow_threads(py, || {
+            let response = self.handle.query(query.is_heavy(), |searcher| searcher.search(&query))?;
+            let hits = ResponseLimits::global().truncate(response.hits);
+            Ok(hits.into_iter().map(PySearchResult::from).collect())
+        })
+            .map_err(|e| operation_error("Error performing search", e))
+    }
+
+    /// Like the module's `text_search`, without refreshing the index first.
+    #[pyo3(signature = (query, case_sensitive, context=None, include_flagged=false, scope=None, format=None))]
+    fn text_search(&self, py: Python<'_>, query: &str, case_sensitive: bool, context: Option<&str>, include_flagged: bool, scope: Option<&str>, format: Option<&str>) -> PyResult<String> {
+        let query = Query::text(query)
+            .case_sensitive(case_sensitive)
+            .context(parse_context(context)?)
+            .include_flagged(include_flagged)
+            .scope(parse_scope(scope)?)
+            .format(parse_format(format)?);
+        self.run_search(py, query, "Error performing text search")
+    }
+
+    /// Like the module's `fuzzy_search`, without refreshing the index first.
+    #[pyo3(signature = (query, max_distance, include_flagged=false, scope=None, format=None, transpositions=true))]
+    fn fuzzy_search(&self, py: Python<'_>, query: &str, max_distance: u8, include_flagged: bool, scope: Option<&str>, format: Option<&str>, transpositions: bool) -> PyResult<String> {
+        let query = Query::fuzzy(query, max_distance)
+            .transpositions(transpositions)
+            .include_flagged(include_flagged)
+            .scope(parse_scope(scope)?)
+            .format(parse_format(format)?);
+        self.run_search(py, query, "Error performing fuzzy search")
+    }
+
+    /// Like the module's `regex_search`, without refreshing the index first.
+    #[pyo3(signature = (pattern, case_sensitive, context=None, include_flagged=false, scope=None, format=None))]
+    fn regex_search(&self, py: Python<'_>, pattern: &str, case_sensitive: bool, context: Option<&str>, include_flagged: bool, scope: Option<&str>, format: Option<&str>) -> PyResult<String> {
+        let query = Query::regex(pattern)
+            .case_sensitive(case_sensitive)
+            .context(parse_context(context)?)
+            .include_flagged(include_flagged)
+            .scope(parse_scope(scope)?)
+            .format(parse_format(format)?);
+        self.run_search(py, query, "Error performing regex search")
+    }
+
+    /// Like the module's `go_to`, without dependencies.
+    #[pyo3(signature = (relative_path, line, start_index, end_index, context=None, format=None, kind=None, path_glob=None, lang=None))]
+    fn go_to(&self, py: Python<'_>, relative_path: &str, line: usize, start_index: usize, end_index: usize, context: Option<&str>, format: Option<&str>, kind: Option<&str>, path_glob: Option<&str>, lang: Option<&str>) -> PyResult<String> {
+        let context_mode = parse_context(context)?;
+        let format = parse_format(format)?;
+        let filter = parse_occurrence_filter(kind, path_glob, lang)?;
+
+        self.allow_threads(py, || {
+            self.handle.query_file(true, relative_path, |searcher| {
+                let result = searcher.token_info_filtered(relative_path, line, start_index, end_index, context_mode, &filter)?;
+                format_go_to(searcher, result, relative_path, line, start_index, end_index, filter.lang, format)
+            })
+        })
+        .map_err(|e| operation_error("Error retrieving token info", e))
+    }
+
+    /// Like the module's `go_to_definition`, without dependencies.
+    #[pyo3(signature = (relative_path, line, start_index, end_index, context=None, format=None, path_glob=None, lang=None))]
+    fn go_to_definition(&self, py: Python<'_>, relative_path: &str, line: usize, start_index: usize, end_index: usize, context: Option<&str>, format: Option<&str>, path_glob: Option<&str>, lang: Option<&str>) -> PyResult<String> {
+        self.go_to(py, relative_path, line, start_index, end_index, context, format, Some("definition"), path_glob, lang)
+    }
+
+    /// Like the module's `find_references`, without dependencies.
+    #[pyo3(signature = (relative_path, line, start_index, end_index, include_declaration=false, directory=None, context=None, format=None, lang=None))]
+    fn find_references(&self, py: Python<'_>, relative_path: &str, line: usize, start_index: usize, end_index: usize, include_declaration: bool, directory: Option<&str>, context: Option<&str>, format: Option<&str>, lang: Option<&str>) -> PyResult<String> {
+        let kind = (!include_declaration).then_some("reference");
+        let path_glob = directory.map(path_prefix::dir_glob);
+        self.go_to(py, relative_path, line, start_index, end_index, context, format, kind, path_glob.as_deref(), lang)
+    }
+
+    /// Like the module's `occurrences`, without dependencies.
+    #[pyo3(signature = (relative_path, line, start_index, end_index, context=None, kind=None, path_glob=None, lang=None))]
+    fn occurrences(&self, py: Python<'_>, relative_path: &str, line: usize, start_index: usize, end_index: usize, context: Option<&str>, kind: Option<&str>, path_glob: Option<&str>, lang: Option<&str>) -> PyResult<Vec<PyOccurrence>> {
+        let context_mode = parse_context(context)?;
+        let filter = parse_occurrence_filter(kind, path_glob, lang)?;
+
+        // the snippets are numbered as they are converted
+        self.allow_threads(py, || {
+            self.handle
+                .query_file(true, relative_path, |searcher| searcher.token_info_filtered(relative_path, line, start_index, end_index, context_mode, &filter))
+                .map(|result| ResponseLimits::global().truncate(PyOccurrence::from_file_symbols(result)))
+        })
+            .map_err(|e| operation_error("Error retrieving token info", e))
+    }
+
+    /// Like the module's `hoverable_ranges`.
+    #[pyo3(signature = (relative_path, start_line=None, end_line=None, lang=None))]
+    fn hoverable_ranges(&self, py: Python<'_>, relative_path: &str, start_line: Option<usize>, end_line: Option<usize>, lang: Option<&str>) -> PyResult<Vec<PyRange>> {
+        let lang = parse_lang(lang)?;
+        self.allow_threads(py, || self.handle.query_file(false, relative_path, |searcher| searcher.hoverable_tokens_in(relative_path, start_line, end_line, lang)))
+            .map(|tokens| tokens.into_iter().map(|token| PyRange::from(token.range)).collect())
+            .map_err(|e| operation_error("Error retrieving hoverable ranges", e))
+    }
+
+    /// Like the module's `get_hoverable_ranges_bulk`, returning `Range` objects by path.
+    #[pyo3(signature = (relative_paths, lang=None))]
+    fn hoverable_ranges_bulk(&self, py: Python<'_>, relative_paths: Vec<String>, lang: Option<&str>) -> PyResult<HashMap<String, Vec<PyRange>>> {
+        let lang = parse_lang(lang)?;
+        self.allow_threads(py, || self.handle.query(false, |searcher| searcher.hoverable_tokens_bulk(&relative_paths, lang)))
+            .map(|ranges| {
+                ranges
+                    .into_iter()
+                    .map(|(path, tokens)| (path, tokens.into_iter().map(|token| PyRange::from(token.range)).collect()))
+                    .collect()
+            })
+            .map_err(|e| operation_error("Error retrieving hoverable ranges", e))
+    }
+
+    /// Like the module's `get_hoverable_ranges`, as JSON.
+    #[pyo3(signature = (relative_path, start_line=None, end_line=None, lang=None))]
+    fn get_hoverable_ranges(&self, py: Python<'_>, relative_path: &str, start_line: Option<usize>, end_line: Option<usize>, lang: Option<&str>) -> PyResult<String> {
+        let lang = parse_lang(lang)?;
+        self.allow_threads(py, || self.handle.query_file(false, relative_path, |searcher| searcher.hoverable_tokens_in(relative_path, start_line, end_line, lang)))
+            .map(|tokens| serde_json::json!(Searcher::format_hoverable_ranges(tokens)).to_string())
+            .map_err(|e| operation_error("Error retrieving hoverable ranges", e))
+    }
+
+    /// Like the module's `get_hoverable_ranges_bulk`, as JSON.
+    #[pyo3(signature = (relative_paths, lang=None))]
+    fn get_hoverable_ranges_bulk(&self, py: Python<'_>, relative_paths: Vec<String>, lang: Option<&str>) -> PyResult<String> {
+        let lang = parse_lang(lang)?;
+        self.allow_threads(py, || self.handle.query(false, |searcher| searcher.hoverable_tokens_bulk(&relative_paths, lang)))
+            .map(|ranges| {
+                let ranges = ranges
+                    .into_iter()
+                    .map(|(path, tokens)| (path, serde_json::json!(Searcher::format_hoverable_ranges(tokens))))
+                    .collect::<serde_json::Map<_, _>>();
+                serde_json::Value::Object(ranges).to_string()
+            })
+            .map_err(|e| operation_error("Error retrieving hoverable ranges", e))
+    }
+
+    /// Like the module's `token_at`.
+    fn token_at(&self, py: Python<'_>, relative_path: &str, line: usize, column: usize) -> PyResult<Option<PyCursorToken>> {
+        self.allow_threads(py, || self.handle.query_file(false, relative_path, |searcher| searcher.token_at(relative_path, line, column)))
+            .map(|token| token.map(PyCursorToken::from))
+            .map_err(|e| operation_error("Error resolving token", e))
+    }
+
+    /// The heavy queries running (`running`), waiting for a turn (`queued`), and the
+    /// limit (`max_running`).
+    fn stats(&self) -> HashMap<&'static str, usize> {
+        let stats = self.handle.stats();
+        HashMap::from([
+            ("max_running", stats.max_running),
+            ("running", stats.running),
+            ("queued", stats.queued),
+        ])
+    }
+
+    /// The documents of the index: searchable (`num_docs`), deleted but still stored
+    /// (`num_deleted_docs`), and the number of segments holding them.
+    fn health(&self) -> HashMap<&'static str, u64> {
+        health_dict(self.handle.health())
+    }
+
+    /// Merge away the deleted documents of the index.
+    fn compact(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| self.handle.compact())
+            .map_err(|e| operation_error("Failed to compact index", e))
+    }
+
+    /// The events of the refreshes made from now on: an iterator blocking until the
+    /// next one, see `IndexEvents`.
+    fn events(&self) -> PyIndexEvents {
+        PyIndexEvents {
+            events: Mutex::new(self.handle.events()),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("IndexHandle({})", self.handle.root_path().display())
+    }
+}
+
+impl PyIndexHandle {
+    /// Run `f` without the GIL, with the lines numbered like the queries of the handle.
+    fn allow_threads<T: Send>(&self, py: Python<'_>, f: impl FnOnce() -> T + Send) -> T {
+        py.allow_threads(|| self.handle.numbered(f))
+    }
+
+    /// The response to `query` in its format, as `search` returns it.
+    fn run_search(&self, py: Python<'_>, query: Query, context: &str) -> PyResult<String> {
+        self.allow_threads(py, || {
+            self.handle.query(query.is_heavy(), |searcher| {
+                let response = searcher.search(&query)?;
+                format_query_response(searcher, &query, response)
+            })
+        })
+        .map_err(|e| operation_error(context, e))
+    }
+}
+
+/// The progress of a refresh started with `IndexHandle.start_refresh()`, see
+/// [`SyncHandle`].
+#[pyclass(name = "SyncHandle", module = "code_nav_devon", frozen)]
+pub struct PySyncHandle {
+    progress: SyncHandle,
+}
+
+#[pymethods]
+impl PySyncHandle {
+    /// `queued`, `walking`, `parsing`, `committing`, `done` or `failed`
+    #[getter]
+    fn state(&self) -> &'static str {
+        self.progress.state().as_str()
+    }
+
+    /// The files the walk found so far.
+    #[getter]
+    fn walked(&self) -> usize {
+        self.progress.status().walked
+    }
+
+    /// The files parsed so far, those new or changed.
+    #[getter]
+    fn parsed(&self) -> usize {
+        self.progress.status().parsed
+    }
+
+    /// Why the refresh failed, `None` unless it did.
+    #[getter]
+    fn error(&self) -> Option<String> {
+        self.progress.status().error
+    }
+
+    /// Whether the refresh is over, done or failed.
+    #[getter]
+    fn done(&self) -> bool {
+        self.progress.state().is_finished()
+    }
+
+    /// Waits for the refresh to finish, at most `timeout` seconds if given, and returns
+    /// what it changed, `None` if it is still running. Raises if it failed.
+    #[pyo3(signature = (timeout=None))]
+    fn wait(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<PyIndexReport>> {
+        let timeout = timeout
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(format!("invalid timeout: {e}")))?;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let left = deadline.map_or(SIGNAL_CHECK_INTERVAL, |deadline| deadline.saturating_duration_since(Instant::now()));
+            match py.allow_threads(|| self.progress.wait_timeout(left.min(SIGNAL_CHECK_INTERVAL))) {
+                Some(indexed) => {
+                    return indexed
+                        .map(|report| Some(PyIndexReport::from(report)))
+                        .map_err(|e| operation_error("Failed to index repository", e))
+                }
+                None if deadline.map_or(true, |deadline| Instant::now() < deadline) => py.check_signals()?,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        let status = self.progress.status();
+        format!("SyncHandle({}, {} walked, {} parsed)", status.state.as_str(), status.walked, status.parsed)
+    }
+}
+
+/// How long an `IndexEvents` waits for an event before checking for a `KeyboardInterrupt`
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Something that happened to the index of an `IndexHandle`, see [`IndexEvent`]
+#[pyclass(name = "IndexEvent", module = "code_nav_devon", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct PyIndexEvent {
+    /// `indexed`, `removed` or `error`
+    pub kind: &'static str,
+    /// `None` for the error of a whole refresh
+    pub path: Option<String>,
+    /// The error, for errors
+    pub message: Option<String>,
+}
+
+#[pymethods]
+impl PyIndexEvent {
+    fn __repr__(&self) -> String {
+        match (&self.path, &self.message) {
+            (Some(path), None) => format!("IndexEvent({}, {path})", self.kind),
+            (path, message) => format!("IndexEvent({}, {path:?}, {message:?})", self.kind),
+        }
+    }
+}
+
+impl From<IndexEvent> for PyIndexEvent {
+    fn from(event: IndexEvent) -> Self {
+        let kind = event.kind();
+        match event {
+            IndexEvent::Indexed { path } | IndexEvent::Removed { path } => Self { kind, path: Some(path), message: None },
+            IndexEvent::Error { path, message } => Self { kind, path, message: Some(message) },
+        }
+    }
+}
+
+/// The events of an `IndexHandle`. Iterating blocks until the next event, without
+/// holding the GIL, and ends once the handle is closed.
+#[pyclass(name = "IndexEvents", module = "code_nav_devon", frozen)]
+pub struct PyIndexEvents {
+    events: Mutex<Events>,
+}
+
+impl PyIndexEvents {
+    /// The next event, waiting at most `timeout`, or until it comes if `None`.
+    fn wait(&self, py: Python<'_>, timeout: Option<Duration>) -> PyResult<Result<Option<IndexEvent>, Closed>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let left = deadline.map_or(SIGNAL_CHECK_INTERVAL, |deadline| deadline.saturating_duration_since(Instant::now()));
+            let next = py.allow_threads(|| {
+                let events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+                events.next_timeout(left.min(SIGNAL_CHECK_INTERVAL))
+            });
+            match next {
+                Ok(None) if deadline.map_or(true, |deadline| Instant::now() < deadline) => py.check_signals()?,
+                next => return Ok(next),
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl PyIndexEvents {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyIndexEvent>> {
+        Ok(self.wait(py, None)?.ok().flatten().map(PyIndexEvent::from))
+    }
+
+    /// The next event, waiting at most `timeout` seconds for it, `None` if none came.
+    #[pyo3(signature = (timeout=0.0))]
+    fn poll(&self, py: Python<'_>, timeout: f64) -> PyResult<Option<PyIndexEvent>> {
+        let timeout = Duration::try_from_secs_f64(timeout).map_err(|e| pyo3::exceptions::PyValueError::new_err(format!("invalid timeout: {e}")))?;
+        Ok(self.wait(py, Some(timeout))?.ok().flatten().map(PyIndexEvent::from))
+    }
+}
+
+pub(crate) fn health_dict(health: IndexHealth) -> HashMap<&'static str, u64> {
+    HashMap::from([
+        ("num_docs", health.num_docs),
+        ("num_deleted_docs", health.num_deleted_docs),
+        ("num_segments", health.num_segments as u64),
+    ])
+}
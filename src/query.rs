@@ -0,0 +1,446 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+
+use crate::grouping::GroupBy;
+use crate::intelligence::{TSLanguage, PLAINTEXT};
+use crate::package::Scope;
+use crate::snippet::ContextMode;
+
+/// How a [`Query`] matches the repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryKind {
+    /// Literal text
+    Text,
+    /// Terms within `max_distance` edits of the query, at most [`MAX_FUZZY_DISTANCE`],
+    /// swapping two adjacent chars counting as one edit with `transpositions`
+    Fuzzy { max_distance: u8, transpositions: bool },
+    /// `*` and `?` wildcards, see [`crate::Searcher::wildcard_search`]
+    Wildcard { allow_leading_wildcard: bool },
+    /// Any of several literal terms
+    MultiTerm(Vec<String>),
+    /// Every one of several literal terms, at most `max_lines` lines apart, see
+    /// [`Query::proximity`]
+    Near { terms: Vec<String>, max_lines: usize },
+    /// Lines matching a regex, see [`crate::Searcher::regex_search`]
+    Regex,
+}
+
+/// The largest edit distance of fuzzy queries, past which tantivy has no automaton
+pub const MAX_FUZZY_DISTANCE: u8 = 2;
+
+/// The prefix of the terms of a query restricting its results to the files a glob
+/// matches, see [`Query::path_globs`]
+pub const PATH_TERM: &str = "path:";
+
+/// A file named in a query, which its results are restricted to rather than searched
+/// for, see [`Query::extension_hints`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileHint {
+    /// `*.rs`: the files with the extension
+    Extension(String),
+    /// `config.py`: the files with the name, in any directory
+    Name(String),
+}
+
+impl FileHint {
+    /// The hint `token` gives, if it names an extension or a file of a known language,
+    /// so that `self.value` or `v1.0` are searched for.
+    pub fn parse(token: &str) -> Option<Self> {
+        let (name, extension) = token.rsplit_once('.')?;
+        let is_name = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !is_name(extension) || TSLanguage::of_path(Path::new(token)) == PLAINTEXT {
+            return None;
+        }
+        match name {
+            "*" => Some(Self::Extension(extension.to_owned())),
+            name if is_name(name) => Some(Self::Name(token.to_owned())),
+            _ => None,
+        }
+    }
+
+    /// A regex matching the stored paths of the files of the hint.
+    pub(crate) fn path_pattern(&self) -> String {
+        match self {
+            Self::Extension(extension) => format!(".*\\.{}", regex::escape(extension)),
+            Self::Name(name) => format!(".*/{}", regex::escape(name)),
+        }
+    }
+}
+
+/// How search results are rendered
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The plain text listing returned by the python functions
+    #[default]
+    Text,
+    Json,
+    /// Fenced code blocks under `path:line` headers, to paste into a prompt
+    Markdown,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "markdown" => Ok(Self::Markdown),
+            s => Err(anyhow::anyhow!("invalid format `{s}`, expected `text`, `json` or `markdown`")),
+        }
+    }
+}
+
+/// A search request for [`crate::Searcher::search`].
+///
+/// Built from one of the kind constructors, then refined with the builder methods,
+/// so that new options don't change the signature of every caller:
+///
+/// ```ignore
+/// let query = Query::text("initialise_db").case_sensitive(true).limit(5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub(crate) kind: QueryKind,
+    pub(crate) text: String,
+    pub(crate) case_sensitive: bool,
+    pub(crate) context: Option<ContextMode>,
+    /// Overrides the searcher's own setting when set
+    pub(crate) include_flagged: Option<bool>,
+    /// Overrides the searcher's own scope when set
+    pub(crate) scope: Option<Scope>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) format: OutputFormat,
+    pub(crate) extension_hints: bool,
+    pub(crate) explain: bool,
+    pub(crate) group_by: Option<GroupBy>,
+}
+
+impl Query {
+    fn new(kind: QueryKind, text: String) -> Self {
+        Self {
+            kind,
+            text,
+            case_sensitive: false,
+            context: None,
+            include_flagged: None,
+            scope: None,
+            limit: None,
+            format: OutputFormat::default(),
+            extension_hints: true,
+            explain: false,
+            group_by: None,
+        }
+    }
+
+    pub fn text(text: &str) -> Self {
+        Self::new(QueryKind::Text, text.to_owned())
+    }
+
+    pub fn fuzzy(text: &str, max_distance: u8) -> Self {
+        Self::new(
+            QueryKind::Fuzzy {
+                max_distance,
+                transpositions: true,
+            },
+            text.to_owned(),
+        )
+    }
+
+    pub fn wildcard(pattern: &str) -> Self {
+        Self::new(
+            QueryKind::Wildcard {
+                allow_leading_wildcard: false,
+            },
+            pattern.to_owned(),
+        )
+    }
+
+    pub fn regex(pattern: &str) -> Self {
+        Self::new(QueryKind::Regex, pattern.to_owned())
+    }
+
+    pub fn multi_term(terms: &[String]) -> Self {
+        Self::new(QueryKind::MultiTerm(terms.to_vec()), terms.join(" "))
+    }
+
+    /// Lines with every one of `terms` at most `max_lines` lines away, 0 for the same
+    /// line.
+    pub fn near(terms: &[String], max_lines: usize) -> Self {
+        Self::new(
+            QueryKind::Near {
+                terms: terms.to_vec(),
+                max_lines,
+            },
+            terms.join(" "),
+        )
+    }
+
+    /// Parse a proximity operator: `near(foo, bar, 3)` for lines with `foo` and `bar`
+    /// at most 3 lines apart, or `sameline(foo, bar)`. Both take two terms or more,
+    /// matched literally, in double quotes if they hold a comma.
+    pub fn proximity(expression: &str) -> Result<Self> {
+        let invalid = || format!("invalid proximity query `{expression}`, expected `near(foo, bar, 3)` or `sameline(foo, bar)`");
+        let (operator, arguments) = expression
+            .trim()
+            .strip_suffix(')')
+            .and_then(|call| call.split_once('('))
+            .with_context(invalid)?;
+
+        let mut terms = split_arguments(arguments).with_context(invalid)?;
+        let max_lines = match operator.trim() {
+            "sameline" => 0,
+            "near" => {
+                let max_lines = terms.pop().unwrap_or_default();
+                max_lines
+                    .parse()
+                    .with_context(|| format!("the last argument of `near` is a number of lines, not `{max_lines}`"))?
+            }
+            operator => bail!("unknown operator `{operator}`, expected `near` or `sameline`"),
+        };
+        if terms.len() < 2 || terms.iter().any(String::is_empty) {
+            bail!("`{}` takes two terms or more, in `{expression}`", operator.trim());
+        }
+        Ok(Self::near(&terms, max_lines))
+    }
+
+    /// Defaults to `false`. Fuzzy queries always match case-sensitively.
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    pub fn context(mut self, context: Option<ContextMode>) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn include_flagged(mut self, include_flagged: bool) -> Self {
+        self.include_flagged = Some(include_flagged);
+        self
+    }
+
+    pub fn scope(mut self, scope: Option<Scope>) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Allow wildcard words starting with a wildcard. Only affects wildcard queries.
+    pub fn allow_leading_wildcard(mut self, allow: bool) -> Self {
+        if let QueryKind::Wildcard {
+            allow_leading_wildcard,
+        } = &mut self.kind
+        {
+            *allow_leading_wildcard = allow;
+        }
+        self
+    }
+
+    /// Whether swapping two adjacent chars, as in `form` for `from`, is one edit rather
+    /// than two. Defaults to `true`. Only affects fuzzy queries.
+    pub fn transpositions(mut self, enabled: bool) -> Self {
+        if let QueryKind::Fuzzy { transpositions, .. } = &mut self.kind {
+            *transpositions = enabled;
+        }
+        self
+    }
+
+    /// Maximum number of results returned.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Whether the terms naming an extension or a source file, such as `*.rs` or
+    /// `config.py`, restrict the results to those files rather than being searched
+    /// for, as in `parse_config *.py`. Defaults to `true`. A query made only of such
+    /// terms, which would be left empty, searches for them.
+    pub fn extension_hints(mut self, enabled: bool) -> Self {
+        self.extension_hints = enabled;
+        self
+    }
+
+    /// The query without its terms naming files, and the files they name, see
+    /// [`Self::extension_hints`].
+    pub fn file_hints(&self) -> (Self, Vec<FileHint>) {
+        if !self.extension_hints {
+            return (self.clone(), Vec::new());
+        }
+        self.split_terms(FileHint::parse)
+    }
+
+    /// The query without its `path:<glob>` terms, and their globs: results come from
+    /// the files one of them matches, as in `parse_config path:src/service/**`. The
+    /// globs are looked up in the index, see [`crate::path_prefix`]. A query made only
+    /// of such terms searches for them.
+    pub fn path_globs(&self) -> (Self, Vec<String>) {
+        self.split_terms(|term| term.strip_prefix(PATH_TERM).filter(|glob| !glob.is_empty()).map(str::to_owned))
+    }
+
+    /// The query without the terms `parse` returns something for, and what it returned,
+    /// unless that would leave it without terms to search for. A regex has no terms:
+    /// `config.py` or `path:src` in it are part of the pattern.
+    fn split_terms<T>(&self, parse: impl Fn(&str) -> Option<T>) -> (Self, Vec<T>) {
+        let (terms, min_terms) = match &self.kind {
+            QueryKind::Regex => return (self.clone(), Vec::new()),
+            QueryKind::MultiTerm(terms) => (terms.clone(), 1),
+            QueryKind::Near { terms, .. } => (terms.clone(), 2),
+            _ => (self.text.split_whitespace().map(str::to_owned).collect(), 1),
+        };
+
+        let (mut rest, mut parsed) = (Vec::new(), Vec::new());
+        for term in terms {
+            match parse(&term) {
+                Some(value) => parsed.push(value),
+                None => rest.push(term),
+            }
+        }
+        if parsed.is_empty() || rest.len() < min_terms {
+            return (self.clone(), Vec::new());
+        }
+
+        let mut query = self.clone();
+        if let QueryKind::MultiTerm(terms) | QueryKind::Near { terms, .. } = &mut query.kind {
+            terms.clone_from(&rest);
+        }
+        query.text = rest.join(" ");
+        (query, parsed)
+    }
+
+    /// Give every hit the score of its document and tantivy's explanation of it, to
+    /// find out why a file outranks another. Defaults to `false`.
+    pub fn explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// Search up to [`crate::grouping::GROUPED_DOC_LIMIT`] documents rather than the
+    /// top 10, for the hits to be grouped by `group_by` with [`crate::Searcher::group_hits`].
+    pub fn group_by(mut self, group_by: Option<GroupBy>) -> Self {
+        self.group_by = group_by;
+        self
+    }
+
+    pub fn kind(&self) -> &QueryKind {
+        &self.kind
+    }
+
+    /// Whether the query walks the term dictionary rather than looking terms up, as
+    /// regexes do on top of reading the files, which is what
+    /// [`crate::limiter::QueryLimiter`] bounds.
+    pub fn is_heavy(&self) -> bool {
+        matches!(self.kind, QueryKind::Fuzzy { .. } | QueryKind::Wildcard { .. } | QueryKind::Regex)
+    }
+}
+
+/// The comma separated arguments of an operator, trimmed, with double quotes around
+/// the ones holding commas.
+fn split_arguments(arguments: &str) -> Result<Vec<String>> {
+    let mut split = vec![String::new()];
+    let mut quoted = false;
+    for c in arguments.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => split.push(String::new()),
+            c => split.last_mut().unwrap().push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quote");
+    }
+    Ok(split.into_iter().map(|argument| argument.trim().to_owned()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder() {
+        let query = Query::wildcard("*_config")
+            .case_sensitive(true)
+            .allow_leading_wildcard(true)
+            .limit(3);
+
+        assert_eq!(
+            query.kind(),
+            &QueryKind::Wildcard {
+                allow_leading_wildcard: true
+            }
+        );
+        assert!(query.case_sensitive);
+        assert_eq!(query.limit, Some(3));
+        assert_eq!(query.include_flagged, None);
+
+        // only meaningful for wildcard queries
+        let query = Query::text("config").allow_leading_wildcard(true);
+        assert_eq!(query.kind(), &QueryKind::Text);
+        assert!(!query.is_heavy());
+        assert!(Query::fuzzy("config", 1).is_heavy());
+        assert!(Query::regex(r"fn\s+\w+_handler").is_heavy());
+        assert_eq!(
+            Query::fuzzy("config", 1).transpositions(false).kind(),
+            &QueryKind::Fuzzy {
+                max_distance: 1,
+                transpositions: false
+            }
+        );
+    }
+
+    #[test]
+    fn proximity_operators() {
+        let terms = |terms: &[&str]| terms.iter().map(|term| term.to_string()).collect::<Vec<_>>();
+        let near = |terms, max_lines| QueryKind::Near { terms, max_lines };
+
+        assert_eq!(Query::proximity("near(open, close, 3)").unwrap().kind(), &near(terms(&["open", "close"]), 3));
+        assert_eq!(Query::proximity(" sameline(a, \"b, c\", d) ").unwrap().kind(), &near(terms(&["a", "b, c", "d"]), 0));
+        for invalid in ["near(open, 3)", "near(open, close)", "sameline(open)", "after(a, b)", "near(a, b, 3", "sameline(a, \"b)"] {
+            assert!(Query::proximity(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn extension_hints() {
+        let (query, hints) = Query::text("parse_config *.py settings.rs").file_hints();
+        assert_eq!(query.text, "parse_config");
+        assert_eq!(hints, [FileHint::Extension("py".to_owned()), FileHint::Name("settings.rs".to_owned())]);
+
+        let (query, hints) = Query::near(&["open".to_owned(), "close".to_owned(), "*.rs".to_owned()], 2).file_hints();
+        assert_eq!(query.kind(), &QueryKind::Near { terms: vec!["open".to_owned(), "close".to_owned()], max_lines: 2 });
+        assert_eq!(hints.len(), 1);
+
+        // searched for: not a source file, nothing else to search, or opted out
+        for query in [Query::text("self.value v1.0"), Query::text("*.rs"), Query::text("parse *.rs").extension_hints(false)] {
+            let (unchanged, hints) = query.file_hints();
+            assert_eq!(unchanged.text, query.text);
+            assert!(hints.is_empty(), "{}", query.text);
+        }
+    }
+
+    #[test]
+    fn path_globs() {
+        let (query, globs) = Query::text("parse_config path:src/service/** *.py").extension_hints(false).path_globs();
+        assert_eq!(query.text, "parse_config *.py");
+        assert_eq!(globs, ["src/service/**"]);
+
+        for query in [Query::text("path:src/**"), Query::text("path: config")] {
+            let (unchanged, globs) = query.path_globs();
+            assert_eq!(unchanged.text, query.text);
+            assert!(globs.is_empty(), "{}", query.text);
+        }
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!("markdown".parse::<OutputFormat>().unwrap(), OutputFormat::Markdown);
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+}
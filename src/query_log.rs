@@ -0,0 +1,347 @@
+//! An optional log of the queries run against an index, and replaying it, to benchmark
+//! real workloads and to look into what an agent did after the fact.
+//!
+//! Once enabled, every searcher opened from an index directory appends one JSON line
+//! per query to [`QUERY_LOG_FILE`] in that directory: the type and parameters of the
+//! query, when it ran, how long it took, and how many results it returned or the error
+//! it failed with. The log outlives rebuilds of the index.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::intelligence::code_navigation::{OccurrenceFilter, OccurrenceKind};
+use crate::intelligence::TSLanguage;
+use crate::query::{Query, QueryKind};
+use crate::search::{path_matcher, Searcher};
+use crate::text_range::LineBase;
+
+/// The query log of an index, in the index directory
+pub const QUERY_LOG_FILE: &str = "queries.jsonl";
+
+static ENABLED: RwLock<bool> = RwLock::new(false);
+
+/// Whether searchers opened from now on log their queries, `false` unless set.
+pub fn is_enabled() -> bool {
+    *ENABLED.read().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn set_enabled(enabled: bool) {
+    *ENABLED.write().unwrap_or_else(|e| e.into_inner()) = enabled;
+}
+
+/// A search, with the options it ran with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedSearch {
+    /// `text`, `fuzzy`, `wildcard`, `multi_term`, `near` or `regex`
+    pub kind: String,
+    pub text: String,
+    /// The terms of a `multi_term` or `near` search
+    #[serde(default, skip_serializing_if = Vec::is_empty)]
+    pub terms: Vec<String>,
+    pub case_sensitive: bool,
+    #[serde(default, skip_serializing_if = Option::is_none)]
+    pub max_distance: Option<u8>,
+    /// `Some(false)` when a fuzzy search opted out of them, see [`Query::transpositions`]
+    #[serde(default, skip_serializing_if = Option::is_none)]
+    pub transpositions: Option<bool>,
+    #[serde(default, skip_serializing_if = Option::is_none)]
+    pub allow_leading_wildcard: Option<bool>,
+    #[serde(default, skip_serializing_if = Option::is_none)]
+    pub max_lines: Option<usize>,
+    /// `Some(false)` when the search opted out of them, see [`Query::extension_hints`]
+    #[serde(default, skip_serializing_if = Option::is_none)]
+    pub extension_hints: Option<bool>,
+    /// Written as for the `context` option, e.g. `lines:3`
+    pub context: Option<String>,
+    pub include_flagged: bool,
+    /// The directory results were restricted to
+    pub scope_dir: Option<String>,
+    /// The owner whose files results were restricted to, see [`crate::owners`]
+    #[serde(default, skip_serializing_if = Option::is_none)]
+    pub owner: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl LoggedSearch {
+    pub fn new(query: &Query, include_flagged: bool, scope_dir: Option<String>) -> Self {
+        let (mut max_distance, mut transpositions, mut allow_leading_wildcard, mut max_lines) = (None, None, None, None);
+        let (kind, terms) = match &query.kind {
+            QueryKind::Text => ("text", Vec::new()),
+            QueryKind::Fuzzy {
+                max_distance: distance,
+                transpositions: swapped,
+            } => {
+                max_distance = Some(*distance);
+                transpositions = (!swapped).then_some(false);
+                ("fuzzy", Vec::new())
+            }
+            QueryKind::Wildcard { allow_leading_wildcard: allow } => {
+                allow_leading_wildcard = Some(*allow);
+                ("wildcard", Vec::new())
+            }
+            QueryKind::MultiTerm(terms) => ("multi_term", terms.clone()),
+            QueryKind::Near { terms, max_lines: lines } => {
+                max_lines = Some(*lines);
+                ("near", terms.clone())
+            }
+            QueryKind::Regex => ("regex", Vec::new()),
+        };
+        Self {
+            kind: kind.to_owned(),
+            text: query.text.clone(),
+            terms,
+            case_sensitive: query.case_sensitive,
+            max_distance,
+            transpositions,
+            allow_leading_wildcard,
+            max_lines,
+            extension_hints: (!query.extension_hints).then_some(false),
+            context: query.context.map(|context| context.to_string()),
+            include_flagged,
+            scope_dir,
+            owner: None,
+            limit: query.limit,
+        }
+    }
+
+    /// The query to run again, without its scope, which is passed resolved.
+    pub fn query(&self) -> Result<Query> {
+        let query = match self.kind.as_str() {
+            "text" => Query::text(&self.text),
+            "fuzzy" => Query::fuzzy(&self.text, self.max_distance.unwrap_or(1)).transpositions(self.transpositions.unwrap_or(true)),
+            "wildcard" => Query::wildcard(&self.text).allow_leading_wildcard(self.allow_leading_wildcard.unwrap_or(false)),
+            "multi_term" => Query::multi_term(&self.terms),
+            "near" => Query::near(&self.terms, self.max_lines.unwrap_or(0)),
+            "regex" => Query::regex(&self.text),
+            kind => anyhow::bail!("unknown search kind `{kind}`"),
+        };
+        let mut query = query
+            .case_sensitive(self.case_sensitive)
+            .context(self.context.as_deref().map(str::parse).transpose()?)
+            .include_flagged(self.include_flagged)
+            .extension_hints(self.extension_hints.unwrap_or(true));
+        query.limit = self.limit;
+        Ok(query)
+    }
+}
+
+/// A query of the log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LoggedQuery {
+    Search(LoggedSearch),
+    GoTo {
+        path: String,
+        line: usize,
+        start_index: usize,
+        end_index: usize,
+        context: Option<String>,
+        /// The number of the first line when the query ran, see [`LineBase`]
+        line_base: usize,
+        /// The filter of the occurrences, see [`OccurrenceFilter`]
+        #[serde(default, skip_serializing_if = Option::is_none)]
+        kind: Option<OccurrenceKind>,
+        #[serde(default, skip_serializing_if = Option::is_none)]
+        path_glob: Option<String>,
+        /// The language the file was read in, if not the one of its extension
+        #[serde(default, skip_serializing_if = Option::is_none)]
+        lang: Option<String>,
+    },
+}
+
+/// A line of the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub query: LoggedQuery,
+    pub latency_ms: f64,
+    /// `None` when the query failed
+    pub results: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// Where a searcher logs its queries
+#[derive(Debug, Clone)]
+pub struct QueryLog {
+    path: PathBuf,
+}
+
+impl QueryLog {
+    /// The log of the index at `index_path`.
+    pub fn new(index_path: &Path) -> Self {
+        Self {
+            path: index_path.join(QUERY_LOG_FILE),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run `run`, then log `query` with its latency and the number of results counted
+    /// by `results`. A log that can't be written only costs a warning.
+    pub fn record<T>(&self, query: impl FnOnce() -> LoggedQuery, results: impl FnOnce(&T) -> usize, run: impl FnOnce() -> Result<T>) -> Result<T> {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let started = Instant::now();
+        let outcome = run();
+
+        let entry = LogEntry {
+            timestamp_ms,
+            query: query(),
+            latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+            results: outcome.as_ref().ok().map(results),
+            error: outcome.as_ref().err().map(|e| format!("{e:#}")),
+        };
+        if let Err(e) = self.append(&entry) {
+            eprintln!("Failed to log a query to {}: {e:#}", self.path.display());
+        }
+        outcome
+    }
+
+    fn append(&self, entry: &LogEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        // one write per entry, so that concurrent searchers don't interleave lines
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+}
+
+/// The entries of the log at `path`.
+pub fn read(path: &Path) -> Result<Vec<LogEntry>> {
+    fs::read_to_string(path)
+        .with_context(|| format!("failed to read the query log {}", path.display()))?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).with_context(|| format!("invalid entry on line {} of the query log", i + 1)))
+        .collect()
+}
+
+/// A logged query run again
+#[derive(Debug, Clone, Serialize)]
+pub struct Replayed {
+    #[serde(flatten)]
+    pub query: LoggedQuery,
+    pub logged_latency_ms: f64,
+    pub latency_ms: f64,
+    pub logged_results: Option<usize>,
+    pub results: Option<usize>,
+    pub error: Option<String>,
+}
+
+impl Replayed {
+    /// Whether the query returned as many results as when it was logged, or failed
+    /// both times.
+    pub fn matches(&self) -> bool {
+        self.results == self.logged_results
+    }
+}
+
+/// The outcome of replaying a log
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayReport {
+    pub queries: Vec<Replayed>,
+    pub logged_latency_ms: f64,
+    pub latency_ms: f64,
+    /// Queries whose number of results changed
+    pub mismatches: usize,
+}
+
+/// Run every query of `entries` again with `searcher`, in order. Open the searcher
+/// without a query log, or the replayed queries are logged again.
+pub fn replay(searcher: &Searcher, entries: &[LogEntry]) -> ReplayReport {
+    let queries = entries
+        .iter()
+        .map(|entry| {
+            let started = Instant::now();
+            let outcome = run(searcher, &entry.query);
+            Replayed {
+                query: entry.query.clone(),
+                logged_latency_ms: entry.latency_ms,
+                latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+                logged_results: entry.results,
+                results: outcome.as_ref().ok().copied(),
+                error: outcome.err().map(|e| format!("{e:#}")),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    ReplayReport {
+        logged_latency_ms: queries.iter().map(|query| query.logged_latency_ms).sum(),
+        latency_ms: queries.iter().map(|query| query.latency_ms).sum(),
+        mismatches: queries.iter().filter(|query| !query.matches()).count(),
+        queries,
+    }
+}
+
+/// Run `query`, returning its number of results.
+fn run(searcher: &Searcher, query: &LoggedQuery) -> Result<usize> {
+    match query {
+        LoggedQuery::Search(search) => {
+            let response = searcher.search_in(&search.query()?, search.scope_dir.clone(), search.owner.as_deref())?;
+            Ok(response.hits.len())
+        }
+        LoggedQuery::GoTo { path, line, start_index, end_index, context, line_base, kind, path_glob, lang } => {
+            // the line as numbered now
+            let line = line
+                .checked_sub(*line_base)
+                .map_or(*line, |index| LineBase::current().number(index));
+            let context = context.as_deref().map(str::parse).transpose()?;
+            let filter = OccurrenceFilter {
+                kind: *kind,
+                paths: path_glob.as_deref().map(path_matcher).transpose()?,
+                lang: lang.as_deref().map(TSLanguage::supported_name).transpose()?,
+            };
+            let results = searcher.token_info_filtered(path, line, *start_index, *end_index, context, &filter)?;
+            Ok(results.iter().map(|file_symbols| file_symbols.data.len()).sum())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snippet::ContextMode;
+
+    #[test]
+    fn searches_round_trip() -> Result<()> {
+        let queries = [
+            Query::text("config").case_sensitive(true).context(Some(ContextMode::Lines(3))),
+            Query::fuzzy("confg", 2),
+            Query::wildcard("*_config").allow_leading_wildcard(true).limit(5),
+            Query::multi_term(&["open".to_owned(), "close".to_owned()]),
+            Query::near(&["open".to_owned(), "close".to_owned()], 3),
+            Query::text("config *.rs").extension_hints(false),
+        ];
+        for query in queries {
+            let logged = LoggedSearch::new(&query, false, Some("/repo/crates/core".to_owned()));
+            let line = serde_json::to_string(&LoggedQuery::Search(logged.clone()))?;
+            let LoggedQuery::Search(parsed) = serde_json::from_str(&line)? else {
+                panic!("{line}");
+            };
+            assert_eq!(parsed, logged);
+
+            let replayed = parsed.query()?;
+            assert_eq!(replayed.kind(), query.kind());
+            assert_eq!((replayed.text.as_str(), replayed.case_sensitive), (query.text.as_str(), query.case_sensitive));
+            assert_eq!((replayed.context, replayed.limit), (query.context, query.limit));
+            assert_eq!(replayed.extension_hints, query.extension_hints);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! Regions of a file written in another language than the file itself, such as the
+//! `<script>` elements of HTML, Vue and Svelte templates.
+//!
+//! Templates have no grammar of their own, so their documents are stored as plaintext
+//! along with their [`Region`]s. Navigating from a position inside a region reads the
+//! template as the language of that region: the parser is given the regions of that
+//! language only, everything else blanked out, so that the ranges it finds are those
+//! of the template. Only languages with a grammar get regions, so the SQL of strings
+//! and the CSS of `<style>` elements have none.
+
+use std::ops::Range;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// The extensions of the templates whose regions are stored
+pub const TEMPLATE_EXTENSIONS: &[&str] = &["html", "htm", "vue", "svelte"];
+
+/// A range of bytes of a file written in `lang`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    /// The canonical name of the language of the region
+    pub lang: String,
+    /// The bytes of the code, the tags around it left out
+    pub range: Range<usize>,
+}
+
+pub fn is_template(path: &Path) -> bool {
+    path.extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .is_some_and(|extension| TEMPLATE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// The regions of the file at `path`, in order. None unless it is a template.
+pub fn regions(path: &Path, content: &str) -> Vec<Region> {
+    if !is_template(path) {
+        return Vec::new();
+    }
+    // offsets are the same in the lowercase copy, for tags in any case
+    let lowercase = content.to_ascii_lowercase();
+
+    let mut regions = Vec::new();
+    let mut position = 0;
+    while let Some(found) = lowercase[position..].find("<script") {
+        let tag = position + found;
+        let after_name = tag + "<script".len();
+        position = after_name;
+        // `<scripts>` or the like
+        if !lowercase[after_name..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+            continue;
+        }
+        let Some(tag_end) = lowercase[after_name..].find('>').map(|end| after_name + end) else {
+            break;
+        };
+        let attributes = &lowercase[after_name..tag_end];
+        let start = tag_end + 1;
+        let end = lowercase[start..].find("</script").map_or(content.len(), |end| start + end);
+        position = end;
+
+        // `<script src="..." />` has no code
+        if attributes.trim_end().ends_with('/') || content[start..end].trim().is_empty() {
+            continue;
+        }
+        if let Some(lang) = script_language(attributes) {
+            regions.push(Region {
+                lang: lang.to_owned(),
+                range: start..end,
+            });
+        }
+    }
+    regions
+}
+
+/// The language of a `<script>` element with the lowercase `attributes`: its `lang`,
+/// else JavaScript unless its `type` says it holds data or a template.
+fn script_language(attributes: &str) -> Option<&'static str> {
+    match attribute(attributes, "lang") {
+        Some("ts" | "tsx" | "typescript") => return Some("typescript"),
+        Some("js" | "jsx" | "javascript") | None => {}
+        Some(_) => return None,
+    }
+    match attribute(attributes, "type") {
+        None | Some("module" | "text/javascript" | "application/javascript" | "text/babel") => Some("javascript"),
+        Some("text/typescript" | "application/typescript") => Some("typescript"),
+        Some(_) => None,
+    }
+}
+
+/// The value of the attribute `name` among `attributes`, unquoted.
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attributes;
+    while let Some(found) = rest.find(name) {
+        let before = &rest[..found];
+        let after = rest[found + name.len()..].trim_start();
+        rest = &rest[found + name.len()..];
+        // `data-lang=` or `language=` are other attributes
+        if !before.is_empty() && !before.ends_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        return Some(match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value.split(|c: char| c.is_ascii_whitespace() || c == '>').next().unwrap_or_default(),
+        });
+    }
+    None
+}
+
+/// `content` as the parser of `lang` sees it: only the `regions` of `lang` are kept,
+/// every other char is blanked out to as many spaces as it has bytes, so that lines and
+/// offsets stay where they were.
+pub fn mask(content: &str, regions: &[Region], lang: &str) -> String {
+    let kept = regions.iter().filter(|region| region.lang == lang).map(|region| &region.range).collect::<Vec<_>>();
+    let mut masked = String::with_capacity(content.len());
+    for (offset, c) in content.char_indices() {
+        if c == '\n' || kept.iter().any(|range| range.contains(&offset)) {
+            masked.push(c);
+        } else {
+            masked.extend(std::iter::repeat(' ').take(c.len_utf8()));
+        }
+    }
+    masked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_regions_of_a_template() {
+        let content = "<template>\n  <p>{{ n }}</p>\n</template>\n<SCRIPT lang=\"ts\">\nconst n = 1;\n</SCRIPT>\n<script src=\"a.js\"></script>\n<script type=\"application/json\">{}</script>\n<script>\nlet é = 2;\n</script>\n";
+        let regions = regions(Path::new("App.vue"), content);
+        let found = regions
+            .iter()
+            .map(|region| (region.lang.as_str(), content[region.range.clone()].trim()))
+            .collect::<Vec<_>>();
+        assert_eq!(found, [("typescript", "const n = 1;"), ("javascript", "let é = 2;")]);
+
+        let masked = mask(content, &regions, "javascript");
+        assert_eq!(masked.len(), content.len());
+        assert_eq!(masked.lines().count(), content.lines().count());
+        assert_eq!(masked.trim(), "let é = 2;");
+
+        assert!(super::regions(Path::new("main.rs"), content).is_empty());
+    }
+
+    #[test]
+    fn script_attributes() {
+        assert_eq!(script_language(" type=\"module\""), Some("javascript"));
+        assert_eq!(script_language(" setup lang='tsx'"), Some("typescript"));
+        assert_eq!(script_language(" data-lang=\"ts\""), Some("javascript"));
+        assert_eq!(script_language(" type=text/x-template"), None);
+        assert_eq!(script_language(" lang=\"coffee\""), None);
+    }
+}
@@ -0,0 +1,345 @@
+//! Read-only indexes kept in object storage, such as an S3 or GCS bucket the index was
+//! uploaded to, and queried in place by workers that don't have a copy.
+//!
+//! [`RemoteDirectory`] is a tantivy [`Directory`] reading its files from an
+//! [`ObjectStore`] in chunks, when a query needs them, so opening a searcher only
+//! downloads the metadata. Chunks can be kept in a local cache directory: index files
+//! never change once written, so cached chunks of a file stay valid for as long as the
+//! file exists.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tantivy::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use tantivy::directory::{
+    Directory, DirectoryLock, FileHandle, Lock, OwnedBytes, WatchCallback, WatchHandle, WritePtr,
+};
+use tantivy::HasLen;
+
+/// Chunks are fetched and cached 1 MiB at a time by default
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// The objects of a bucket, or any other store of immutable blobs addressed by key.
+///
+/// Implement it over the client of the storage service, e.g. with ranged `GetObject`
+/// requests for S3.
+pub trait ObjectStore: fmt::Debug + Send + Sync + 'static {
+    /// The size in bytes of the object at `key`, or `None` if there is none.
+    fn size(&self, key: &str) -> io::Result<Option<u64>>;
+
+    /// The bytes `range` of the object at `key`.
+    fn read_range(&self, key: &str, range: Range<u64>) -> io::Result<Vec<u8>>;
+}
+
+/// Objects stored as the files of a local directory, e.g. a bucket mounted with
+/// `s3fs` or `gcsfuse`.
+#[derive(Debug, Clone)]
+pub struct FsObjectStore {
+    root: PathBuf,
+}
+
+impl FsObjectStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ObjectStore for FsObjectStore {
+    fn size(&self, key: &str) -> io::Result<Option<u64>> {
+        match fs::metadata(self.root.join(key)) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_range(&self, key: &str, range: Range<u64>) -> io::Result<Vec<u8>> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = fs::File::open(self.root.join(key))?;
+        file.seek(SeekFrom::Start(range.start))?;
+        let mut bytes = vec![0; (range.end - range.start) as usize];
+        file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// A read-only index directory whose files are objects under `prefix` in an
+/// [`ObjectStore`].
+///
+/// ```ignore
+/// let directory = RemoteDirectory::new(store, "indexes/my-repo").cache_dir("/tmp/index-cache");
+/// let searcher = Searcher::from_directory(directory)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RemoteDirectory {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+    cache_dir: Option<PathBuf>,
+    chunk_size: usize,
+}
+
+impl RemoteDirectory {
+    pub fn new(store: impl ObjectStore, prefix: &str) -> Self {
+        Self {
+            store: Arc::new(store),
+            prefix: prefix.trim_matches('/').to_owned(),
+            cache_dir: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Keep the chunks read in `cache_dir`, for later reads and later processes.
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Fetch files `chunk_size` bytes at a time. A size of 0 is treated as 1.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    fn key(&self, path: &Path) -> String {
+        let path = path.to_string_lossy().replace('\\', "/");
+        if self.prefix.is_empty() {
+            path
+        } else {
+            format!("{}/{}", self.prefix, path)
+        }
+    }
+
+    fn size(&self, path: &Path) -> Result<u64, OpenReadError> {
+        self.store
+            .size(&self.key(path))
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_path_buf()))?
+            .ok_or_else(|| OpenReadError::FileDoesNotExist(path.to_path_buf()))
+    }
+}
+
+fn read_only(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{} can't be written: remote indexes are read-only", path.display()),
+    )
+}
+
+impl Directory for RemoteDirectory {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let len = self.size(path)? as usize;
+        let key = self.key(path);
+        // the size tells apart the versions of files that are rewritten, like the symbol cache
+        let cache_dir = self
+            .cache_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.{}", key.replace('/', "%2F"), len)));
+
+        Ok(Arc::new(RemoteFile {
+            store: Arc::clone(&self.store),
+            key,
+            len,
+            chunk_size: self.chunk_size,
+            cache_dir,
+        }))
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        match self.size(path) {
+            Ok(_) => Ok(true),
+            Err(OpenReadError::FileDoesNotExist(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Never cached: this reads the metadata files, which are rewritten on every commit.
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        let size = self.size(path)?;
+        self.store
+            .read_range(&self.key(path), 0..size)
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_path_buf()))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        Err(DeleteError::IoError {
+            io_error: Arc::new(read_only(path)),
+            filepath: path.to_path_buf(),
+        })
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        Err(OpenWriteError::wrap_io_error(read_only(path), path.to_path_buf()))
+    }
+
+    fn atomic_write(&self, path: &Path, _data: &[u8]) -> io::Result<()> {
+        Err(read_only(path))
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Nobody writes to the index through this directory, there is nothing to lock
+    /// against. Readers take a lock while they load the segments.
+    fn acquire_lock(&self, _lock: &Lock) -> Result<DirectoryLock, LockError> {
+        Ok(DirectoryLock::from(Box::new(()) as Box<dyn Send + Sync + 'static>))
+    }
+
+    /// Commits made elsewhere are picked up by opening a new searcher.
+    fn watch(&self, _watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        Ok(WatchHandle::empty())
+    }
+}
+
+/// A file of a [`RemoteDirectory`], read one chunk at a time
+struct RemoteFile {
+    store: Arc<dyn ObjectStore>,
+    key: String,
+    len: usize,
+    chunk_size: usize,
+    /// Where the chunks of this version of the file are cached
+    cache_dir: Option<PathBuf>,
+}
+
+impl fmt::Debug for RemoteFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteFile")
+            .field("key", &self.key)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl RemoteFile {
+    fn chunk(&self, index: usize) -> io::Result<Vec<u8>> {
+        let cached = self.cache_dir.as_ref().map(|dir| dir.join(index.to_string()));
+        if let Some(cached) = &cached {
+            match fs::read(cached) {
+                Ok(bytes) => return Ok(bytes),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let start = index * self.chunk_size;
+        let end = usize::min(start + self.chunk_size, self.len);
+        let bytes = self.store.read_range(&self.key, start as u64..end as u64)?;
+
+        if let Some(cached) = &cached {
+            // written aside then renamed, so that concurrent readers never see half a chunk
+            let dir = cached.parent().expect("chunks are cached in a directory");
+            fs::create_dir_all(dir)?;
+            let partial = dir.join(format!("{}.{}.partial", index, std::process::id()));
+            fs::write(&partial, &bytes)?;
+            fs::rename(&partial, cached)?;
+        }
+        Ok(bytes)
+    }
+}
+
+impl HasLen for RemoteFile {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl FileHandle for RemoteFile {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        if range.end > self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{:?} is out of the {} bytes of {}", range, self.len, self.key),
+            ));
+        }
+        if range.is_empty() {
+            return Ok(OwnedBytes::empty());
+        }
+
+        let first_chunk = range.start / self.chunk_size;
+        let last_chunk = (range.end - 1) / self.chunk_size;
+        let mut bytes = Vec::with_capacity(range.len());
+        for index in first_chunk..=last_chunk {
+            let chunk = self.chunk(index)?;
+            let chunk_start = index * self.chunk_size;
+            let from = range.start.saturating_sub(chunk_start);
+            let to = usize::min(range.end - chunk_start, chunk.len());
+            bytes.extend_from_slice(&chunk[from..to]);
+        }
+        Ok(OwnedBytes::new(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tantivy::collector::Count;
+    use tantivy::query::AllQuery;
+    use tantivy::schema::{Schema, STORED, TEXT};
+    use tantivy::{doc, Index};
+    use tempdir::TempDir;
+
+    use super::*;
+
+    /// Counts the ranges read from the store
+    #[derive(Debug)]
+    struct CountingStore {
+        inner: FsObjectStore,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl ObjectStore for CountingStore {
+        fn size(&self, key: &str) -> io::Result<Option<u64>> {
+            self.inner.size(key)
+        }
+
+        fn read_range(&self, key: &str, range: Range<u64>) -> io::Result<Vec<u8>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.read_range(key, range)
+        }
+    }
+
+    #[test]
+    fn searches_a_remote_index() -> anyhow::Result<()> {
+        let bucket = TempDir::new("bucket")?;
+        let cache = TempDir::new("cache")?;
+
+        let mut schema = Schema::builder();
+        let body = schema.add_text_field("body", TEXT | STORED);
+        let index = Index::create_in_dir(bucket.path().join("repo"), schema.build())?;
+        let mut writer = index.writer_with_num_threads(1, 15_000_000)?;
+        for i in 0..50 {
+            writer.add_document(doc!(body => format!("document number {i}")))?;
+        }
+        writer.commit()?;
+
+        let reads = Arc::new(AtomicUsize::new(0));
+        let open = || {
+            let store = CountingStore {
+                inner: FsObjectStore::new(bucket.path()),
+                reads: Arc::clone(&reads),
+            };
+            let directory = RemoteDirectory::new(store, "/repo/").cache_dir(cache.path()).chunk_size(64);
+            Index::open(directory)
+        };
+
+        let index = open()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.search(&AllQuery, &Count)?, 50);
+        let first_reads = reads.swap(0, Ordering::SeqCst);
+        assert!(first_reads > 0);
+
+        // the second reader only fetches the metadata, the chunks are cached
+        let index = open()?;
+        let searcher = index.reader()?.searcher();
+        assert_eq!(searcher.search(&AllQuery, &Count)?, 50);
+        assert!(reads.load(Ordering::SeqCst) < first_reads);
+
+        assert!(index.directory().atomic_write(Path::new("meta.json"), b"{}").is_err());
+        Ok(())
+    }
+}
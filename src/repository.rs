@@ -1,14 +1,229 @@
-use anyhow::{Context, Result};
+//! A repository indexed from disk: where its root is, how the paths of its files are
+//! stored and told relative to it, which of them its `.gitignore` files leave out, and
+//! what is checked out.
+
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug)]
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+
+use crate::file::{is_excluded, is_index_dir};
+use crate::fingerprint::git;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Repository {
+    /// The canonical root, unless the repository was recorded in an index
     pub disk_path: PathBuf,
+    /// The root with `/` separators, which the stored paths of its files start with
+    root: String,
 }
 
 impl Repository {
+    /// The repository rooted at `path`.
     pub fn from_path(path: &Path) -> Result<Self> {
-        let disk_path = path.canonicalize().context("failed to canonicalize path")?;
-        Ok(Self { disk_path })
+        let disk_path = path.canonicalize().with_context(|| format!("invalid root {}", path.display()))?;
+        Ok(Self::at(disk_path))
+    }
+
+    /// The repository holding `path`: the closest directory from it up that holds a
+    /// `.git`, `path` itself if none does.
+    pub fn discover(path: &Path) -> Result<Self> {
+        let path = Self::from_path(path)?.disk_path;
+        let root = path
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .map_or_else(|| path.clone(), Path::to_path_buf);
+        Ok(Self::at(root))
+    }
+
+    /// A repository recorded in an index as `root`, which may not exist on this machine.
+    pub(crate) fn stored(root: String) -> Self {
+        Self {
+            disk_path: PathBuf::from(&root),
+            root,
+        }
+    }
+
+    fn at(disk_path: PathBuf) -> Self {
+        let root = disk_path.to_string_lossy().replace('\\', "/");
+        Self { disk_path, root }
+    }
+
+    /// The root as the stored paths of its files start with.
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// Whether the stored `path` is the root or under it.
+    pub fn contains(&self, path: &str) -> bool {
+        is_under(path, &self.root)
+    }
+
+    /// The stored `path` relative to the root, `None` if it isn't under it.
+    pub fn relative_path<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let relative = path.strip_prefix(self.root.trim_end_matches('/'))?.strip_prefix('/')?;
+        (!relative.is_empty()).then_some(relative)
+    }
+
+    /// The commit checked out, `None` if it isn't a git repository with a commit, or git
+    /// isn't installed.
+    pub fn head(&self) -> Result<Option<String>> {
+        Ok(git(&self.disk_path, &["rev-parse", "--verify", "HEAD"])?.map(|head| head.trim().to_owned()))
+    }
+
+    /// The branch checked out, `None` if the `HEAD` is detached or it isn't a git
+    /// repository.
+    pub fn branch(&self) -> Result<Option<String>> {
+        Ok(git(&self.disk_path, &["symbolic-ref", "--quiet", "--short", "HEAD"])?.map(|branch| branch.trim().to_owned()))
     }
-}
\ No newline at end of file
+
+    /// Every `.gitignore` of the repository, but those in the directories named one of
+    /// `excludes` and in the directory of an index.
+    pub(crate) fn gitignores(&self, excludes: &[String]) -> Gitignores {
+        let mut gitignores = Gitignores::new();
+        for path in gitignores_under(&self.disk_path, excludes) {
+            gitignores.add(&path);
+        }
+        gitignores.sort();
+        gitignores
+    }
+
+    /// The `.gitignore` files that apply to `paths`, those of the directories from the
+    /// root down to each of them, and those under the ones that are directories but in
+    /// the directories named one of `excludes`, without walking the root.
+    pub(crate) fn gitignores_of(&self, paths: &[PathBuf], excludes: &[String]) -> Gitignores {
+        let mut files = paths
+            .iter()
+            .flat_map(|path| path.ancestors().skip(1).take_while(|dir| dir.starts_with(&self.disk_path)))
+            .map(|dir| dir.join(".gitignore"))
+            .filter(|path| path.is_file())
+            .collect::<HashSet<_>>();
+        for dir in paths.iter().filter(|path| path.starts_with(&self.disk_path) && path.is_dir()) {
+            files.extend(gitignores_under(dir, excludes));
+        }
+        let mut gitignores = Gitignores::new();
+        for path in files {
+            gitignores.add(&path);
+        }
+        gitignores.sort();
+        gitignores
+    }
+}
+
+/// The `.gitignore` files under `dir`, but those in the directories named one of
+/// `excludes` and in the directory of an index.
+fn gitignores_under(dir: &Path, excludes: &[String]) -> Vec<PathBuf> {
+    let excludes = excludes.to_vec();
+    let walk = WalkBuilder::new(dir)
+        .hidden(false)
+        .git_ignore(false)
+        .filter_entry(move |entry| {
+            entry.depth() == 0 || !(is_excluded(entry.path(), &excludes) || is_index_dir(entry.path()))
+        })
+        .build();
+
+    walk
+        // unreadable directories are reported by the traversal
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.file_name() == Some(".gitignore".as_ref()))
+        .collect()
+}
+
+/// `path` relative to the outermost of `repositories` holding it, e.g. the root of a
+/// workspace rather than one of its members.
+pub fn relative_path<'a>(repositories: &[Repository], path: &'a str) -> Option<&'a str> {
+    repositories.iter().find_map(|repository| repository.relative_path(path))
+}
+
+/// Whether the stored `path` is `dir` or under it.
+pub(crate) fn is_under(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir.trim_end_matches('/'))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The `.gitignore` files of a repository
+pub(crate) struct Gitignores {
+    gitignores: Vec<(PathBuf, Gitignore)>,
+}
+
+impl Gitignores {
+    fn new() -> Self {
+        Self { gitignores: Vec::new() }
+    }
+
+    fn add(&mut self, path: &Path) {
+        let gitignore_dir = path.parent().unwrap().to_path_buf();
+        let mut builder = GitignoreBuilder::new(&gitignore_dir);
+        builder.add(path);
+        match builder.build() {
+            Ok(gitignore) => {
+                self.gitignores.push((gitignore_dir, gitignore));
+            },
+            Err(err) => {
+                eprintln!("Error building gitignore for {:?}: {}", path, err);
+            }
+        }
+    }
+
+    /// Sort gitignores from most specific (deepest) to least specific (root)
+    fn sort(&mut self) {
+        self.gitignores.sort_by(|a, b| b.0.components().count().cmp(&a.0.components().count()));
+    }
+
+    pub(crate) fn is_ignored(&self, path: &Path) -> bool {
+        self.matched(path) == Some(true)
+    }
+
+    /// Whether a pattern such as `!target` re-includes `path`.
+    pub(crate) fn is_whitelisted(&self, path: &Path) -> bool {
+        self.matched(path) == Some(false)
+    }
+
+    /// Whether the most specific `.gitignore` with a say about `path` ignores it.
+    fn matched(&self, path: &Path) -> Option<bool> {
+        for (dir, gitignore) in &self.gitignores {
+            if path.starts_with(dir) {
+                let relative_path = path.strip_prefix(dir).unwrap();
+                match gitignore.matched(relative_path, false) {
+                    ignore::Match::Ignore(_) => return Some(true),
+                    ignore::Match::Whitelist(_) => return Some(false),
+                    ignore::Match::None => continue,
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_relative_to_the_root() {
+        let workspace = [Repository::stored("/work/repo".to_owned()), Repository::stored("/work/repo/crates/core".to_owned())];
+        assert_eq!(relative_path(&workspace, "/work/repo/crates/core/lib.rs"), Some("crates/core/lib.rs"));
+        assert_eq!(relative_path(&workspace, "/work/repo/main.rs"), Some("main.rs"));
+        assert_eq!(relative_path(&workspace, "/work/repository/main.rs"), None);
+        assert_eq!(relative_path(&[Repository::stored("/".to_owned())], "/main.rs"), Some("main.rs"));
+        assert_eq!(relative_path(&[], "/work/repo/main.rs"), None);
+
+        let repository = &workspace[0];
+        assert!(repository.contains("/work/repo") && repository.contains("/work/repo/main.rs"));
+        assert!(!repository.contains("/work/repository/main.rs"));
+    }
+
+    #[test]
+    fn discovers_the_root() -> Result<()> {
+        let dir = tempdir::TempDir::new("repository")?;
+        let root = dir.path().canonicalize()?;
+        std::fs::create_dir_all(root.join(".git"))?;
+        std::fs::create_dir_all(root.join("src/nested"))?;
+        assert_eq!(Repository::discover(&root.join("src/nested"))?.disk_path, root);
+        assert_eq!(Repository::discover(&root)?.root(), root.to_string_lossy().replace('\\', "/"));
+        Ok(())
+    }
+}
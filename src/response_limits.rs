@@ -0,0 +1,237 @@
+//! Bounds on the size of the responses of the python functions, so that a careless
+//! query can't flood the context window of the agent reading them.
+//!
+//! Responses over a limit keep as many whole results as fit and say so: text and
+//! markdown end with a note, JSON objects have a `truncation` field.
+
+use std::sync::RwLock;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::query::OutputFormat;
+
+pub const DEFAULT_MAX_RESULTS: usize = 100;
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 512 * 1024;
+
+static LIMITS: RwLock<ResponseLimits> = RwLock::new(ResponseLimits::DEFAULT);
+
+/// The most results a response lists, and the most bytes it takes. `None` is unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ResponseLimits {
+    pub max_results: Option<usize>,
+    pub max_response_bytes: Option<usize>,
+}
+
+/// How much of the results made it into a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Truncation {
+    pub truncated: bool,
+    pub total_results: usize,
+    pub returned_results: usize,
+    pub max_results: Option<usize>,
+    pub max_response_bytes: Option<usize>,
+}
+
+impl ResponseLimits {
+    pub const DEFAULT: Self = Self {
+        max_results: Some(DEFAULT_MAX_RESULTS),
+        max_response_bytes: Some(DEFAULT_MAX_RESPONSE_BYTES),
+    };
+
+    pub const UNLIMITED: Self = Self {
+        max_results: None,
+        max_response_bytes: None,
+    };
+
+    /// The limits applied by the python functions.
+    pub fn global() -> Self {
+        *LIMITS.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_global(limits: Self) {
+        *LIMITS.write().unwrap_or_else(|e| e.into_inner()) = limits;
+    }
+
+    /// Truncate a list of `results` to `max_results`.
+    pub fn truncate<T>(&self, mut results: Vec<T>) -> Vec<T> {
+        if let Some(max_results) = self.max_results {
+            results.truncate(max_results);
+        }
+        results
+    }
+
+    /// Render the first results of `total` that fit the limits, with `render(n)`
+    /// rendering the first `n` in `format`.
+    ///
+    /// When not even one result fits in `max_response_bytes`, text and markdown keep
+    /// the start of the first one.
+    pub fn fit(&self, total: usize, format: OutputFormat, render: impl Fn(usize) -> Result<String>) -> Result<String> {
+        Ok(self.fit_counted(total, format, render)?.0)
+    }
+
+    /// Like [`Self::fit`], also returning the number of results rendered.
+    pub fn fit_counted(&self, total: usize, format: OutputFormat, render: impl Fn(usize) -> Result<String>) -> Result<(String, usize)> {
+        let max_results = self.max_results.map_or(total, |max| max.min(total));
+        let produce = |n: usize| -> Result<(String, usize)> { Ok((self.annotate(render(n)?, format, total, n)?, n)) };
+
+        let Some(max_bytes) = self.max_response_bytes else {
+            return produce(max_results);
+        };
+        let response = produce(max_results)?;
+        if response.0.len() <= max_bytes {
+            return Ok(response);
+        }
+
+        // the largest number of results that fits, knowing that `max_results` don't
+        let (mut fits, mut overflows) = (0, max_results);
+        while overflows - fits > 1 {
+            let n = fits + (overflows - fits) / 2;
+            if produce(n)?.0.len() <= max_bytes {
+                fits = n;
+            } else {
+                overflows = n;
+            }
+        }
+
+        if fits > 0 || format == OutputFormat::Json {
+            return produce(fits);
+        }
+
+        let note = self.note(total, 1);
+        let mut partial = render(1)?;
+        partial.truncate(floor_char_boundary(&partial, max_bytes.saturating_sub(note.len())));
+        partial.push_str(&note);
+        Ok((partial, 1))
+    }
+
+    /// Cut a response that isn't made of results to `max_response_bytes`.
+    pub fn fit_text(&self, mut text: String) -> String {
+        let Some(max_bytes) = self.max_response_bytes else {
+            return text;
+        };
+        if text.len() <= max_bytes {
+            return text;
+        }
+
+        let note = format!(
+            "\n[Truncated: the response is limited to {} bytes, narrow the request to see the rest]\n",
+            max_bytes
+        );
+        text.truncate(floor_char_boundary(&text, max_bytes.saturating_sub(note.len())));
+        text.push_str(&note);
+        text
+    }
+
+    fn annotate(&self, mut rendered: String, format: OutputFormat, total: usize, returned: usize) -> Result<String> {
+        if format == OutputFormat::Json {
+            let mut value: serde_json::Value = serde_json::from_str(&rendered)?;
+            if let Some(object) = value.as_object_mut() {
+                object.insert("truncation".to_owned(), serde_json::to_value(self.truncation(total, returned))?);
+            }
+            return Ok(serde_json::to_string(&value)?);
+        }
+
+        if returned < total {
+            rendered.push_str(&self.note(total, returned));
+        }
+        Ok(rendered)
+    }
+
+    fn truncation(&self, total: usize, returned: usize) -> Truncation {
+        Truncation {
+            truncated: returned < total,
+            total_results: total,
+            returned_results: returned,
+            max_results: self.max_results,
+            max_response_bytes: self.max_response_bytes,
+        }
+    }
+
+    fn note(&self, total: usize, returned: usize) -> String {
+        format!(
+            "\n[Truncated: showing {} of {} results, narrow the query to see the rest]\n",
+            returned, total
+        )
+    }
+}
+
+impl Default for ResponseLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The largest char boundary of `s` at or before `index`
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    if index >= s.len() {
+        return s.len();
+    }
+    (0..=index).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_lines(n: usize) -> Result<String> {
+        Ok((0..n).map(|i| format!("result {i}\n")).collect())
+    }
+
+    #[test]
+    fn keeps_what_fits() -> Result<()> {
+        let limits = ResponseLimits {
+            max_results: Some(5),
+            max_response_bytes: None,
+        };
+        let response = limits.fit(8, OutputFormat::Text, render_lines)?;
+        assert!(response.starts_with(&render_lines(5)?), "{response}");
+        assert!(response.ends_with("[Truncated: showing 5 of 8 results, narrow the query to see the rest]\n"));
+
+        let limits = ResponseLimits {
+            max_results: None,
+            max_response_bytes: Some(120),
+        };
+        let response = limits.fit(8, OutputFormat::Text, |n| Ok(render_lines(n)?.replace("result", "result number")))?;
+        assert!(response.len() <= 120, "{response}");
+        assert!(response.contains("showing 3 of 8"), "{response}");
+        let (_, returned) = limits.fit_counted(8, OutputFormat::Text, |n| Ok(render_lines(n)?.replace("result", "result number")))?;
+        assert_eq!(returned, 3);
+
+        // everything fits: no note
+        assert_eq!(ResponseLimits::DEFAULT.fit(8, OutputFormat::Text, render_lines)?, render_lines(8)?);
+        Ok(())
+    }
+
+    #[test]
+    fn json_carries_truncation() -> Result<()> {
+        let limits = ResponseLimits {
+            max_results: Some(2),
+            max_response_bytes: None,
+        };
+        let response = limits.fit(3, OutputFormat::Json, |n| {
+            Ok(serde_json::json!({ "hits": (0..n).collect::<Vec<_>>() }).to_string())
+        })?;
+        let value: serde_json::Value = serde_json::from_str(&response)?;
+        assert_eq!(value["hits"], serde_json::json!([0, 1]));
+        assert_eq!(value["truncation"]["truncated"], true);
+        assert_eq!(value["truncation"]["total_results"], 3);
+        Ok(())
+    }
+
+    #[test]
+    fn cuts_oversized_results() {
+        let limits = ResponseLimits {
+            max_results: None,
+            max_response_bytes: Some(100),
+        };
+        let response = limits
+            .fit(2, OutputFormat::Text, |n| Ok("ようこそ".repeat(50 * n)))
+            .unwrap();
+        assert!(response.len() <= 100);
+        assert!(response.ends_with("showing 1 of 2 results, narrow the query to see the rest]\n"));
+
+        let text = limits.fit_text("x".repeat(1000));
+        assert!(text.len() <= 100 && text.contains("limited to 100 bytes"));
+    }
+}
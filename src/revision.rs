@@ -0,0 +1,268 @@
+//! Indexes of a repository as it was at past commits, read from the git objects
+//! without checking them out, so that its history can be searched: which revisions
+//! define a function, since when a string is there.
+//!
+//! Every commit gets its own index, in the [`REVISIONS_DIR`] of the index of the
+//! working tree, named after the commit. Its documents are stored under the paths the
+//! files would have in the working tree. Commits don't change, so a revision is only
+//! indexed once.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::fingerprint::git;
+use crate::indexes::Indexer;
+use crate::query::{OutputFormat, Query};
+use crate::source::{FileSource, Sourced};
+use crate::search::{SearchResponse, Searcher};
+use crate::{roots, symbol_cache};
+
+/// The directory of the revision indexes, in the index directory
+pub const REVISIONS_DIR: &str = "revisions";
+
+/// The full hash of the commit `revision` names in the repository at `root`, such as
+/// a branch, a tag or an abbreviated hash.
+pub fn resolve(root: &Path, revision: &str) -> Result<String> {
+    let spec = format!("{revision}^{{commit}}");
+    match git(root, &["rev-parse", "--verify", "--quiet", &spec])? {
+        Some(commit) => Ok(commit.trim().to_owned()),
+        None => bail!("`{revision}` is not a commit of the repository at {}", root.display()),
+    }
+}
+
+/// The index of `commit`, next to the index at `index_path`.
+pub fn index_dir(index_path: &Path, commit: &str) -> PathBuf {
+    index_path.join(REVISIONS_DIR).join(commit)
+}
+
+/// The commits indexed next to the index at `index_path`, sorted.
+pub fn indexed(index_path: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(index_path.join(REVISIONS_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut commits = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.path().join("meta.json").is_file() {
+            commits.extend(entry.file_name().to_str().map(str::to_owned));
+        }
+    }
+    commits.sort();
+    Ok(commits)
+}
+
+/// Index the repository at `root` as it was at `revision`, next to the index at
+/// `index_path`, returning the commit it names.
+pub async fn index(root: &Path, index_path: &Path, revision: &str, buffer_size: usize, threads: usize) -> Result<String> {
+    let commit = resolve(root, revision)?;
+    let indexer = Indexer::create(Sourced::new(Revision::new(&commit)), &index_dir(index_path, &commit), buffer_size, threads)?;
+    if indexer.reader.searcher().num_docs() > 0 {
+        return Ok(commit);
+    }
+
+    let mut writer = indexer.write_handle()?;
+    writer.index(root).await?;
+    writer.commit()?;
+    let file = indexer.source.file();
+    symbol_cache::refresh(&indexer.index, file.symbols_field, file.path_field)?;
+    roots::record(&indexer.index, &[root])?;
+    Ok(commit)
+}
+
+/// The files of a commit, as a source of documents for [`Sourced`]
+pub struct Revision {
+    commit: String,
+}
+
+impl Revision {
+    pub fn new(commit: &str) -> Self {
+        Self { commit: commit.to_owned() }
+    }
+}
+
+impl FileSource for Revision {
+    fn read_files(
+        &self,
+        root: &Path,
+        select: &mut dyn FnMut(&str, u64) -> bool,
+        read: &mut dyn FnMut(&str, Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        let blobs = tree(root, &self.commit)?
+            .into_iter()
+            .filter(|blob| select(&blob.path, blob.size))
+            .collect::<Vec<_>>();
+        let objects = blobs.iter().map(|blob| blob.object.as_str()).collect::<Vec<_>>();
+        read_blobs(root, &objects, |i, content| read(&blobs[i].path, content))
+    }
+}
+
+/// A file of a commit
+struct Blob {
+    object: String,
+    /// Relative to the root, with `/` separators
+    path: String,
+    size: u64,
+}
+
+/// The files under `root` at `commit`, without links and submodules.
+fn tree(root: &Path, commit: &str) -> Result<Vec<Blob>> {
+    let listing = git(root, &["ls-tree", "-r", "-l", "-z", commit])?
+        .with_context(|| format!("failed to list the files of {commit}"))?;
+    let mut blobs = Vec::new();
+    for entry in listing.split('\0').filter(|entry| !entry.is_empty()) {
+        // `<mode> <type> <object> <size>\t<path>`
+        let parsed = entry.split_once('\t').and_then(|(meta, path)| {
+            let [mode, kind, object, size] = meta.split_whitespace().collect::<Vec<_>>()[..] else {
+                return None;
+            };
+            Some((mode, kind, object, size, path))
+        });
+        let Some((mode, kind, object, size, path)) = parsed else {
+            bail!("unexpected entry `{entry}` in the files of {commit}");
+        };
+        if kind != "blob" || mode == "120000" {
+            continue;
+        }
+        blobs.push(Blob {
+            object: object.to_owned(),
+            path: path.to_owned(),
+            size: size.parse().with_context(|| format!("invalid size of {path} in {commit}"))?,
+        });
+    }
+    Ok(blobs)
+}
+
+/// Read the contents of the blobs `objects` with a single `git cat-file` process,
+/// passing each one to `f` along with its index, in order.
+fn read_blobs(root: &Path, objects: &[&str], mut f: impl FnMut(usize, Vec<u8>) -> Result<()>) -> Result<()> {
+    if objects.is_empty() {
+        return Ok(());
+    }
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("failed to run git")?;
+
+    // written from another thread, so that a full stdout pipe doesn't block the input
+    let input = objects.iter().map(|object| format!("{object}\n")).collect::<String>();
+    let mut stdin = child.stdin.take().context("no stdin for git")?;
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let mut stdout = BufReader::new(child.stdout.take().context("no stdout for git")?);
+    let read = objects.iter().enumerate().try_for_each(|(i, object)| {
+        let mut header = String::new();
+        stdout.read_line(&mut header)?;
+        // `<object> blob <size>`, or `<object> missing`
+        let size = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [_, "blob", size] => size.parse::<usize>()?,
+            _ => bail!("failed to read the blob {object}: `{}`", header.trim_end()),
+        };
+        // followed by a newline
+        let mut content = vec![0; size + 1];
+        stdout.read_exact(&mut content)?;
+        content.pop();
+        f(i, content)
+    });
+
+    if read.is_err() {
+        let _ = child.kill();
+    }
+    let _ = writer.join();
+    child.wait()?;
+    read
+}
+
+/// The hits of a query in the index of a revision
+#[derive(Debug, Clone, Serialize)]
+pub struct RevisionHits {
+    pub commit: String,
+    /// Seconds since the Unix epoch, `None` if the commit is no longer in the repository
+    pub committed_at: Option<u64>,
+    #[serde(flatten)]
+    pub response: SearchResponse,
+}
+
+/// Run `query` against the index of every one of `commits` indexed next to the index
+/// at `index_path`, newest commits first.
+pub fn search(root: &Path, index_path: &Path, commits: &[String], query: &Query) -> Result<Vec<RevisionHits>> {
+    let mut dated = commits.iter().map(|commit| (commit.clone(), committed_at(root, commit))).collect::<Vec<_>>();
+    dated.sort_by(|a, b| b.1.cmp(&a.1));
+
+    dated
+        .into_iter()
+        .map(|(commit, committed_at)| {
+            let path = index_dir(index_path, &commit);
+            if !path.join("meta.json").is_file() {
+                bail!("{commit} isn't indexed, index it with `index_revision` first");
+            }
+            let response = Searcher::new(&path)?.search(query)?;
+            Ok(RevisionHits { commit, committed_at, response })
+        })
+        .collect()
+}
+
+fn committed_at(root: &Path, commit: &str) -> Option<u64> {
+    git(root, &["show", "-s", "--format=%ct", commit]).ok()??.trim().parse().ok()
+}
+
+/// The first `n` hits of `revisions`, in order, as many revisions as they come from.
+pub fn first_hits(revisions: &[RevisionHits], mut n: usize) -> Vec<RevisionHits> {
+    let mut first = Vec::new();
+    for revision in revisions {
+        if n == 0 {
+            break;
+        }
+        let mut revision = revision.clone();
+        revision.response.hits.truncate(n);
+        n -= revision.response.hits.len();
+        first.push(revision);
+    }
+    first
+}
+
+/// Render the hits of `query` in every revision, those without hits left out of the
+/// text and markdown formats.
+pub fn format_search(query: &Query, revisions: Vec<RevisionHits>) -> Result<String> {
+    if query.format == OutputFormat::Json {
+        return Ok(serde_json::to_string(&revisions)?);
+    }
+    let mut formatted = String::new();
+    for revision in revisions.into_iter().filter(|revision| !revision.response.hits.is_empty()) {
+        formatted.push_str(&format!("Revision {}:\n", revision.commit));
+        formatted.push_str(&Searcher::format_search_response(query, revision.response)?);
+        formatted.push('\n');
+    }
+    if formatted.is_empty() {
+        formatted.push_str("No results found");
+    }
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_revisions_without_a_directory() -> Result<()> {
+        let dir = tempdir::TempDir::new("revisions")?;
+        assert!(indexed(dir.path())?.is_empty());
+
+        fs::create_dir_all(index_dir(dir.path(), "0123abcd"))?;
+        // not an index yet
+        assert!(indexed(dir.path())?.is_empty());
+        fs::write(index_dir(dir.path(), "0123abcd").join("meta.json"), "{}")?;
+        assert_eq!(indexed(dir.path())?, ["0123abcd"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+//! The roots an index was built from, stored once in the index rather than with every
+//! document, so that the absolute paths of the documents can be told relative to the
+//! repository holding them.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use tantivy::directory::error::OpenReadError;
+use tantivy::Index;
+
+use crate::repository::Repository;
+
+/// The canonical roots indexed into an index, as a JSON list, stored in the index
+pub const ROOTS_FILE: &str = "roots";
+
+/// The roots recorded in `index`, outer roots first. Empty for indexes built before
+/// roots were recorded.
+pub fn stored(index: &Index) -> Result<Vec<String>> {
+    match index.directory().atomic_read(Path::new(ROOTS_FILE)) {
+        Ok(stored) => serde_json::from_slice(&stored).context("invalid roots file"),
+        Err(OpenReadError::FileDoesNotExist(_)) => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Add `roots` to the roots recorded in `index`.
+pub fn record(index: &Index, roots: &[&Path]) -> Result<()> {
+    let mut recorded = stored(index)?;
+    for root in roots {
+        recorded.push(Repository::from_path(root)?.root().to_owned());
+    }
+    recorded.sort();
+    recorded.dedup();
+    Ok(index
+        .directory()
+        .atomic_write(Path::new(ROOTS_FILE), &serde_json::to_vec(&recorded)?)?)
+}
+
+/// The repositories recorded in `index`, outer roots first, see [`stored`].
+pub fn repositories(index: &Index) -> Result<Vec<Repository>> {
+    Ok(stored(index)?.into_iter().map(Repository::stored).collect())
+}
@@ -1,14 +1,25 @@
 use tantivy::schema::{Schema, TEXT, STRING, STORED, FAST, BytesOptions, SchemaBuilder};
 
+/// Changing the schema makes existing indexes unreadable, which `Indexer::open`
+/// rebuilds or refuses, see [`crate::format`]. Bump [`crate::format::FORMAT_VERSION`]
+/// along with it.
 pub fn build_schema() -> Schema {
     let mut schema_builder = SchemaBuilder::default();
     schema_builder.add_text_field("path", STRING | FAST | STORED);
     schema_builder.add_text_field("content", TEXT | STORED);
-    schema_builder.add_text_field("content_insensitive", TEXT | STORED);
     schema_builder.add_bytes_field("symbol_locations", STORED);
-    schema_builder.add_bytes_field("line_end_indices", BytesOptions::default().set_stored());
+    // delta-encoded, see [`crate::text_range::encode_line_ends`]
+    schema_builder.add_bytes_field("line_ends", BytesOptions::default().set_stored());
     schema_builder.add_text_field("symbols", TEXT | STORED);
     schema_builder.add_text_field("lang", STRING | FAST | STORED);
     schema_builder.add_text_field("hash", STRING | FAST | STORED);
+    // stored so that renamed files keep it when their documents are moved
+    schema_builder.add_text_field("symbol_docs", TEXT | STORED);
+    // bincode encoded, see [`crate::regions`]
+    schema_builder.add_bytes_field("regions", STORED);
+    // the directories of the path, see [`crate::path_prefix`]
+    schema_builder.add_text_field("path_prefixes", STRING);
+    // `minified` and `generated`, see [`crate::file_heuristics::FileFlags::terms`]
+    schema_builder.add_text_field("flags", STRING);
     schema_builder.build()
 }
@@ -1,24 +1,261 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 use std::path::Path;
-use tantivy::query::{FuzzyTermQuery, TermQuery, QueryParser};
-use tantivy::schema::Field;
-use tantivy::{Index, IndexReader, collector::TopDocs, Term};
+use std::sync::Arc;
+use globset::{Glob, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+use tantivy::query::{AllQuery, BooleanQuery, FuzzyTermQuery, Occur, Query, RegexQuery, TermQuery, TermSetQuery, QueryParser};
+use tantivy::schema::{Field, IndexRecordOption};
+use tantivy::directory::error::OpenReadError;
+use tantivy::directory::Directory;
+use tantivy::{DocAddress, Index, IndexReader, collector::{Count, DocSetCollector, TopDocs}, Term};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+use crate::compaction::IndexHealth;
 use crate::content_document::ContentDocument;
-use crate::intelligence::code_navigation::{CodeNavigationContext, FileSymbols, OccurrenceKind, Token};
-use crate::intelligence::TSLanguage;
+use crate::edit::{self, Edit, RenameViolation, Replace};
+use crate::error::{ErrorCode, NavError};
+use crate::file_heuristics::FileFlags;
+use crate::format;
+use crate::grouping::{self, GroupBy, HitGroup, GROUPED_DOC_LIMIT};
+use crate::hybrid::{self, HybridHit, HybridSource};
+use crate::intelligence::code_navigation::{count_references, CodeNavigationContext, FileSymbols, Occurrence, OccurrenceFilter, OccurrenceKind, Token, TokenOccurrences};
+use crate::intelligence::file_summary::{summarize, FileSummary};
+use crate::intelligence::public_api::{public_symbols, PublicApi};
+use crate::intelligence::symbol_docs::symbol_docs;
+use crate::intelligence::builtins::{self, ExternalSymbol};
+use crate::intelligence::{Language, NodeKind, TSLanguage, TokenClass, TreeSitterFile};
+use crate::owners::{self, CodeOwners};
+use crate::package::{PackageInfo, Scope, PACKAGES_FILE};
+use crate::path_prefix;
+use crate::query::{FileHint, OutputFormat, Query as SearchQuery, QueryKind, MAX_FUZZY_DISTANCE};
 use crate::schema::build_schema;
-use crate::symbol::SymbolLocations;
-use crate::text_range::TextRange;
+use crate::snippet::{ContextMode, Snipper, Snippet};
+use crate::symbol::{LazySymbolLocations, Symbol};
+use crate::query_log::{self, LoggedQuery, LoggedSearch, QueryLog};
+use crate::repository::{self, Repository};
+use crate::roots;
+use crate::symbol_cache::SymbolCache;
+use crate::test_discovery::{self, TestMatch, TestTarget};
+use crate::text_range::{self, LineBase, TextRange};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SearchResult {
+    /// The absolute path of the file
     pub path: String,
+    /// The path of the file relative to the root it was indexed from, `path` itself
+    /// if the index doesn't know its roots
+    pub relative_path: String,
     pub line_number: usize,
     pub column: usize,
-    pub context: String,
+    /// The matching line and the lines around it
+    pub snippet: Snippet,
+    #[serde(flatten)]
+    pub flags: FileFlags,
+    /// The symbols defined on the matching line, to go to without another query
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<DefinedSymbol>,
+    /// The relevance of the document, when the query asked for it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<Score>,
+}
+
+/// How relevant the document of a search hit is to the query, see
+/// [`SearchQuery::explain`]
+#[derive(Debug, Clone, Serialize)]
+pub struct Score {
+    /// The BM25 score documents are ranked by
+    pub value: f32,
+    /// How tantivy computed it, a tree of `value`, `description` and `details`
+    pub explanation: serde_json::Value,
+}
+
+impl Score {
+    /// The score `value` of the document at `address`, explained for `query`.
+    fn explained(query: &dyn Query, searcher: &tantivy::Searcher, address: DocAddress, value: f32) -> Result<Self> {
+        let explanation = query.explain(searcher, address)?;
+        Ok(Self {
+            value,
+            explanation: serde_json::to_value(&explanation)?,
+        })
+    }
+
+    /// The score and its explanation under the content of a hit in the text formats,
+    /// nothing without a score.
+    fn format(score: &Option<Self>) -> String {
+        match score {
+            Some(score) => format!(
+                "Score: {}\nExplanation:\n{}\n",
+                score.value,
+                serde_json::to_string_pretty(&score.explanation).unwrap_or_default()
+            ),
+            None => String::new(),
+        }
+    }
+}
+
+/// A symbol defined on the line of a search hit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DefinedSymbol {
+    pub name: String,
+    /// e.g. `function` or `class`, as named by the language
+    pub kind: String,
+    /// The range of the name, numbered from the [`LineBase::current`]
+    pub range: TextRange,
+}
+
+/// A symbol defined somewhere in the repository, see [`Searcher::workspace_symbols`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WorkspaceSymbol {
+    pub name: String,
+    /// e.g. `function` or `class`, as named by the language
+    pub kind: String,
+    /// The file defining it
+    pub path: String,
+    pub relative_path: String,
+    /// The range of the name, numbered from the [`LineBase::current`]
+    pub range: TextRange,
+}
+
+/// The token under a position of a file, see [`Searcher::token_at`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CursorToken {
+    pub text: String,
+    /// The kind of its syntax node, e.g. `field_identifier`
+    pub kind: String,
+    pub classification: TokenClass,
+    /// The line of its start, numbered from the [`LineBase::current`], and its char
+    /// columns on that line, as `go_to` takes them
+    pub line: usize,
+    pub start_index: usize,
+    pub end_index: usize,
+    /// Numbered from the [`LineBase::current`], with byte columns like hoverable ranges
+    pub range: TextRange,
+}
+
+/// A hoverable range of a file and the token it spans, see
+/// [`Searcher::hoverable_tokens_in`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HoverableToken {
+    /// Numbered from the [`LineBase::current`]
+    pub range: TextRange,
+    pub text: String,
+    pub kind: HoverKind,
+}
+
+/// What the scope graph of its file says of a hoverable token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoverKind {
+    /// It defines a symbol
+    Definition,
+    /// It refers to a definition or an import of its file, or is an import itself
+    Reference,
+    /// It resolves to nothing in its file: a symbol defined in another one, a builtin,
+    /// or a file without a scope graph
+    Unresolved,
+}
+
+impl HoverKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Definition => "definition",
+            Self::Reference => "reference",
+            Self::Unresolved => "unresolved",
+        }
+    }
+}
+
+/// The indexed files of a language, see [`Searcher::language_breakdown`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LanguageStats {
+    pub lang: String,
+    pub files: usize,
+    pub lines: usize,
+    pub bytes: u64,
+    /// The share of the indexed bytes, in percent
+    pub percent: f64,
+}
+
+/// A symbol whose documentation matches a [`Searcher::doc_search`] query
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocMatch {
+    pub path: String,
+    /// `path` relative to the root it was indexed from
+    pub relative_path: String,
+    pub name: String,
+    pub kind: Option<String>,
+    /// The range of the name, numbered from the [`LineBase::current`]
+    pub range: TextRange,
+    pub doc: String,
+    /// The summed weights of the query words its name and documentation contain,
+    /// rarer words weighing more
+    pub score: f32,
+}
+
+/// A completion for a symbol prefix
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SymbolCompletion {
+    pub name: String,
+    /// Files defining a symbol with this name
+    pub paths: Vec<String>,
+}
+
+/// A search hit labelled with the query term that produced it
+#[derive(Debug, Clone, Serialize)]
+pub struct TermMatch {
+    pub term: String,
+    pub result: SearchResult,
+}
+
+/// Everything a search response is made of: hits, and suggestions when there are none
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResponse {
+    /// For single term queries, every hit is labelled with the query text
+    pub hits: Vec<TermMatch>,
+    pub suggestions: Vec<String>,
+}
+
+impl SearchResponse {
+    /// The response to a single term query for `term`.
+    pub fn labelled(term: &str, results: Vec<SearchResult>, suggestions: Vec<String>) -> Self {
+        let hits = results
+            .into_iter()
+            .map(|result| TermMatch {
+                term: term.to_owned(),
+                result,
+            })
+            .collect();
+        Self { hits, suggestions }
+    }
+}
+
+/// Restrictions on which files search results may come from
+#[derive(Debug, Clone, Default)]
+struct Filters {
+    /// Whether results from minified or generated files are returned
+    include_flagged: bool,
+    /// Directory that search results are restricted to
+    scope_dir: Option<String>,
+    /// Files of an owner that search results are restricted to
+    owned: Option<Arc<OwnedFiles>>,
+    /// Files named by the query, results come from one of them if there are some
+    file_hints: Vec<FileHint>,
+    /// The `path:` globs of the query, results come from a file matching one of them
+    /// if there are some
+    path_globs: Vec<String>,
+    /// Whether hits carry the score of their document, see [`SearchQuery::explain`]
+    explain: bool,
+    /// The number of top documents hits come from, 10 if unset
+    doc_limit: Option<usize>,
+}
+
+/// The stored files of an owner, see [`Scope::Owner`]
+#[derive(Debug)]
+struct OwnedFiles {
+    owner: String,
+    paths: HashSet<String>,
 }
 
 pub struct Searcher {
@@ -26,52 +263,755 @@ pub struct Searcher {
     reader: IndexReader,
     path_field: Field,
     content_field: Field,
-    content_insensitive_field: Field, // Added field
-    line_end_indices_field: Field,
+    line_ends_field: Field,
     lang_field: Field, // Added lang field
     symbol_locations_field: Field,
+    symbols_field: Field,
+    regions_field: Field,
+    path_prefixes_field: Field,
+    flags_field: Field,
+    /// Default restrictions on which files results come from
+    filters: Filters,
+    /// Read-only indexes of dependency sources, used to resolve definitions that
+    /// aren't in the repository
+    external: Vec<Searcher>,
+    /// Symbol names persisted at index time, when there are some
+    symbol_cache: Option<SymbolCache>,
+    /// The repositories the index was built from, outer roots first
+    repositories: Vec<Repository>,
+    /// Where queries are logged, if they are
+    query_log: Option<QueryLog>,
 }
 
 impl Searcher {
+    /// Open a searcher over the index at `index_path`, logging its queries next to the
+    /// index if the query log is enabled, see [`crate::query_log`].
     pub fn new(index_path: &Path) -> Result<Self> {
-        let index = Index::open_in_dir(index_path)?;
+        let query_log = query_log::is_enabled().then(|| QueryLog::new(index_path));
+        Ok(Self::from_index(Index::open_in_dir(index_path)?)?.query_log(query_log))
+    }
+
+    /// Open a searcher over the index stored in `directory`, such as a read-only
+    /// [`crate::remote::RemoteDirectory`].
+    pub fn from_directory(directory: impl Directory) -> Result<Self> {
+        Self::from_index(Index::open(directory)?)
+    }
+
+    fn from_index(index: Index) -> Result<Self> {
+        format::check_readable(&index)?;
         let reader = index.reader()?;
         let schema = build_schema();
         let path_field = schema.get_field("path").unwrap();
         let content_field = schema.get_field("content").unwrap();
-        let content_insensitive_field = schema.get_field("content_insensitive").unwrap(); // Added field
-        let line_end_indices_field = schema.get_field("line_end_indices").unwrap();
+        let line_ends_field = schema.get_field("line_ends").unwrap();
         let lang_field = schema.get_field("lang").unwrap();
         let symbol_locations_field = schema.get_field("symbol_locations").unwrap();
+        let symbols_field = schema.get_field("symbols").unwrap();
+        let regions_field = schema.get_field("regions").unwrap();
+        let path_prefixes_field = schema.get_field("path_prefixes").unwrap();
+        let flags_field = schema.get_field("flags").unwrap();
+        // a cache that can't be read only costs the speed up
+        let symbol_cache = SymbolCache::open(index.directory()).unwrap_or(None);
+        let repositories = roots::repositories(&index)?;
 
         Ok(Self {
             index,
             reader,
             path_field,
             content_field,
-            content_insensitive_field,
-            line_end_indices_field,
+            line_ends_field,
             lang_field,
             symbol_locations_field,
+            symbols_field,
+            regions_field,
+            path_prefixes_field,
+            flags_field,
+            filters: Filters::default(),
+            external: Vec::new(),
+            symbol_cache,
+            repositories,
+            query_log: None,
         })
     }
+
+    /// Log the queries of the searcher to `query_log`, or stop logging them if `None`.
+    pub fn query_log(mut self, query_log: Option<QueryLog>) -> Self {
+        self.query_log = query_log;
+        self
+    }
+
+    /// Run `run`, logging `query` along with its results counted by `results` if
+    /// queries are logged.
+    fn logged<T>(&self, query: impl FnOnce() -> LoggedQuery, results: impl FnOnce(&T) -> usize, run: impl FnOnce() -> Result<T>) -> Result<T> {
+        match &self.query_log {
+            Some(log) => log.record(query, results, run),
+            None => run(),
+        }
+    }
+
+    fn logged_search(&self, query: &SearchQuery, filters: &Filters) -> LoggedQuery {
+        let mut search = LoggedSearch::new(query, filters.include_flagged, filters.scope_dir.clone());
+        search.owner = filters.owned.as_ref().map(|owned| owned.owner.clone());
+        LoggedQuery::Search(search)
+    }
+
+    /// The repositories the index was built from, outer roots first.
+    pub fn repositories(&self) -> &[Repository] {
+        &self.repositories
+    }
+
+    /// `path` relative to the root it was indexed from, itself if there is none.
+    pub fn relative_path<'a>(&self, path: &'a str) -> &'a str {
+        repository::relative_path(&self.repositories, path).unwrap_or(path)
+    }
+
+    /// Include results from minified and generated files, which are skipped by default.
+    pub fn include_flagged(mut self, include_flagged: bool) -> Self {
+        self.filters.include_flagged = include_flagged;
+        self
+    }
+
+    /// Restrict search results to the files of `scope`, resolved against the packages
+    /// and the `CODEOWNERS` found at index time.
+    pub fn scope(mut self, scope: Option<Scope>) -> Result<Self> {
+        let mut filters = self.filters.clone();
+        self.apply_scope(&mut filters, scope.as_ref())?;
+        self.filters = filters;
+        Ok(self)
+    }
+
+    /// Restrict `filters` to the files of `scope`: the directory of a workspace member,
+    /// or the files of an owner. Not at all if `None`.
+    fn apply_scope(&self, filters: &mut Filters, scope: Option<&Scope>) -> Result<()> {
+        filters.scope_dir = scope.map(|scope| scope.resolve(&self.packages()?)).transpose()?.flatten();
+        filters.owned = scope.and_then(Scope::owner).map(|owner| self.owned_files(owner)).transpose()?.map(Arc::new);
+        Ok(())
+    }
+
+    /// Number of documents in the index.
+    pub fn num_docs(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
+    /// Live and deleted documents of the index, see [`crate::compaction`].
+    pub fn health(&self) -> IndexHealth {
+        IndexHealth::of(&self.reader.searcher())
+    }
+
+    /// Register the index of a dependency's source, e.g. a `~/.cargo/registry` checkout
+    /// or `site-packages`, so that `token_info` can resolve definitions into it.
+    pub fn with_external(mut self, searcher: Searcher) -> Self {
+        self.external.push(searcher);
+        self
+    }
+
+    /// Narrow `query` down to the files in scope, if a scope is set, to the files the
+    /// query names or the globs of its `path:` terms match, if it has some, and to the
+    /// files that are neither minified nor generated unless the filters include them.
+    fn scoped(&self, query: Box<dyn Query>, filters: &Filters) -> Result<Box<dyn Query>> {
+        let mut clauses = Vec::new();
+        if let Some(dir) = &filters.scope_dir {
+            clauses.push((Occur::Must, self.in_dir(dir)));
+        }
+        if let Some(owned) = &filters.owned {
+            let owned = TermSetQuery::new(owned.paths.iter().map(|path| Term::from_field_text(self.path_field, path)));
+            clauses.push((Occur::Must, Box::new(owned) as Box<dyn Query>));
+        }
+        if !filters.file_hints.is_empty() {
+            let named = filters
+                .file_hints
+                .iter()
+                .map(|hint| Ok((Occur::Should, Box::new(RegexQuery::from_pattern(&hint.path_pattern(), self.path_field)?) as Box<dyn Query>)))
+                .collect::<Result<Vec<_>>>()?;
+            clauses.push((Occur::Must, Box::new(BooleanQuery::new(named))));
+        }
+        if !filters.path_globs.is_empty() {
+            let matched = filters
+                .path_globs
+                .iter()
+                .map(|glob| Ok((Occur::Should, self.matching_glob(glob)?)))
+                .collect::<Result<Vec<_>>>()?;
+            clauses.push((Occur::Must, Box::new(BooleanQuery::new(matched))));
+        }
+        // left out in the query, so that they don't take the place of the top documents
+        if !filters.include_flagged {
+            for flag in FileFlags::TERMS {
+                let flagged = TermQuery::new(Term::from_field_text(self.flags_field, flag), IndexRecordOption::Basic);
+                clauses.push((Occur::MustNot, Box::new(flagged)));
+            }
+        }
+        if clauses.is_empty() {
+            return Ok(query);
+        }
+
+        clauses.insert(0, (Occur::Must, query));
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
+
+    /// The files under the stored directory `dir`, one term of the `path_prefixes`
+    /// field.
+    fn in_dir(&self, dir: &str) -> Box<dyn Query> {
+        Box::new(TermQuery::new(Term::from_field_text(self.path_prefixes_field, dir), IndexRecordOption::Basic))
+    }
+
+    /// The files matching the path `glob`, relative to the roots of the index unless it
+    /// is absolute, see [`crate::path_prefix`]. Relative globs match anywhere in the
+    /// path of an index without roots.
+    fn matching_glob(&self, glob: &str) -> Result<Box<dyn Query>> {
+        let bases = if glob.starts_with('/') {
+            vec![String::new()]
+        } else if self.repositories.is_empty() {
+            let anywhere = RegexQuery::from_pattern(&format!("(.*/)?{}", path_prefix::glob_regex(glob)), self.path_field)?;
+            return Ok(Box::new(anywhere));
+        } else {
+            self.repositories.iter().map(|repository| format!("{}/", repository.root().trim_end_matches('/'))).collect()
+        };
+
+        let dir = path_prefix::literal_dir(glob);
+        let mut per_root = Vec::new();
+        for base in bases {
+            let mut clauses = Vec::new();
+            let base_dir = format!("{base}{dir}");
+            let base_dir = base_dir.trim_end_matches('/');
+            if !base_dir.is_empty() {
+                clauses.push((Occur::Must, self.in_dir(base_dir)));
+            }
+            if !path_prefix::is_whole_dir(glob) {
+                let pattern = format!("{}{}", regex::escape(&base), path_prefix::glob_regex(glob));
+                clauses.push((Occur::Must, Box::new(RegexQuery::from_pattern(&pattern, self.path_field)?) as Box<dyn Query>));
+            }
+            if clauses.is_empty() {
+                clauses.push((Occur::Must, Box::new(AllQuery)));
+            }
+            per_root.push((Occur::Should, Box::new(BooleanQuery::new(clauses)) as Box<dyn Query>));
+        }
+        Ok(Box::new(BooleanQuery::new(per_root)))
+    }
     
-    pub fn text_search(&self, query_str: &str, case_sensitive: bool) -> Result<Vec<SearchResult>> {
-        let searcher = self.reader.searcher();
-        
-        // Choose the appropriate field and query parser based on case sensitivity
-        let (field, query_str) = if case_sensitive {
-            (self.content_field, query_str.to_string())
+    /// Run any kind of search. Options set on `query` take precedence over the ones
+    /// set on the searcher.
+    pub fn search(&self, query: &SearchQuery) -> Result<SearchResponse> {
+        let mut filters = Filters {
+            include_flagged: query.include_flagged.unwrap_or(self.filters.include_flagged),
+            scope_dir: self.filters.scope_dir.clone(),
+            owned: self.filters.owned.clone(),
+            file_hints: Vec::new(),
+            path_globs: Vec::new(),
+            explain: false,
+            doc_limit: None,
+        };
+        if let Some(scope) = &query.scope {
+            self.apply_scope(&mut filters, Some(scope))?;
+        }
+
+        self.logged(
+            || self.logged_search(query, &filters),
+            |response: &SearchResponse| response.hits.len(),
+            || self.search_with(query, &filters),
+        )
+    }
+
+    /// Run `query` restricted to `scope_dir` and the files of `owner` rather than to its
+    /// scope, without logging it, e.g. to replay it.
+    pub(crate) fn search_in(&self, query: &SearchQuery, scope_dir: Option<String>, owner: Option<&str>) -> Result<SearchResponse> {
+        let filters = Filters {
+            include_flagged: query.include_flagged.unwrap_or(self.filters.include_flagged),
+            scope_dir,
+            owned: owner.map(|owner| self.owned_files(owner)).transpose()?.map(Arc::new),
+            file_hints: Vec::new(),
+            path_globs: Vec::new(),
+            explain: false,
+            doc_limit: None,
+        };
+        self.search_with(query, &filters)
+    }
+
+    fn search_with(&self, query: &SearchQuery, filters: &Filters) -> Result<SearchResponse> {
+        let (query, path_globs) = query.path_globs();
+        let (query, file_hints) = query.file_hints();
+        let (query, filters) = (&query, &Filters {
+            file_hints,
+            path_globs,
+            explain: query.explain,
+            // documents found nearly always have a hit, those of `limit` of them are
+            // enough to fill it
+            doc_limit: query.group_by.map(|_| GROUPED_DOC_LIMIT).into_iter().chain(query.limit.map(|limit| limit.max(10))).max(),
+            ..filters.clone()
+        });
+        let label = |results: Vec<SearchResult>| {
+            results
+                .into_iter()
+                .map(|result| TermMatch {
+                    term: query.text.clone(),
+                    result,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut hits = match &query.kind {
+            QueryKind::Text => label(self.text_matches(&query.text, query.case_sensitive, query.context, filters)?),
+            QueryKind::Fuzzy { max_distance, transpositions } => label(self.fuzzy_matches(&query.text, *max_distance, *transpositions, filters)?),
+            QueryKind::Wildcard { allow_leading_wildcard } => label(self.wildcard_matches(
+                &query.text,
+                query.case_sensitive,
+                *allow_leading_wildcard,
+                query.context,
+                filters,
+            )?),
+            QueryKind::MultiTerm(terms) => self.term_matches(terms, query.case_sensitive, query.context, filters)?,
+            QueryKind::Near { terms, max_lines } => label(self.near_matches(terms, *max_lines, query.case_sensitive, query.context, filters)?),
+            QueryKind::Regex => label(self.regex_matches(&query.text, query.case_sensitive, query.context, filters)?),
+        };
+
+        if let Some(limit) = query.limit {
+            hits.truncate(limit);
+        }
+
+        // a regex is no misspelt symbol
+        let suggestions = if hits.is_empty() && query.kind != QueryKind::Regex {
+            self.did_you_mean(&query.text, 3)?
         } else {
-            (self.content_insensitive_field, query_str.to_lowercase())
+            Vec::new()
+        };
+
+        Ok(SearchResponse { hits, suggestions })
+    }
+
+    /// Aggregate `hits` by `group_by`, see [`crate::grouping`].
+    pub fn group_hits(&self, hits: Vec<TermMatch>, group_by: GroupBy) -> Result<Vec<HitGroup>> {
+        let packages = match group_by {
+            GroupBy::Package => self.packages()?,
+            _ => Vec::new(),
         };
+        Ok(grouping::group(hits, group_by, &packages))
+    }
+
+    pub fn format_search_response(query: &SearchQuery, response: SearchResponse) -> Result<String> {
+        Self::format_response(&query.kind, query.format, response)
+    }
+
+    /// Render the response to a query of `kind` in `format`.
+    pub fn format_response(kind: &QueryKind, format: OutputFormat, response: SearchResponse) -> Result<String> {
+        if format == OutputFormat::Json {
+            return Ok(serde_json::to_string(&response)?);
+        }
+
+        if response.hits.is_empty() {
+            return Ok(Self::format_did_you_mean(response.suggestions));
+        }
+
+        if format == OutputFormat::Markdown {
+            let show_terms = matches!(kind, QueryKind::MultiTerm(_));
+            return Ok(Self::format_markdown_search_results(response.hits, show_terms));
+        }
+
+        if let QueryKind::MultiTerm(_) = kind {
+            return Ok(Self::format_multi_term_search_results(response.hits));
+        }
+
+        let results = response.hits.into_iter().map(|hit| hit.result).collect();
+        Ok(match kind {
+            QueryKind::Fuzzy { .. } => Self::format_fuzzy_search_results(results),
+            _ => Self::format_search_results(results),
+        })
+    }
+
+    pub fn text_search(&self, query_str: &str, case_sensitive: bool, context_mode: Option<ContextMode>) -> Result<Vec<SearchResult>> {
+        self.logged(
+            || self.logged_search(&SearchQuery::text(query_str).case_sensitive(case_sensitive).context(context_mode), &self.filters),
+            Vec::len,
+            || self.text_matches(query_str, case_sensitive, context_mode, &self.filters),
+        )
+    }
+
+    fn text_matches(&self, query_str: &str, case_sensitive: bool, context_mode: Option<ContextMode>, filters: &Filters) -> Result<Vec<SearchResult>> {
+        // The index is case-insensitive, case only matters when matching lines
+        let query_parser = QueryParser::for_index(&self.index, vec![self.content_field]);
+        let query = query_parser
+            .parse_query(query_str)
+            .map_err(|e| invalid_query(query_str, e))?;
+
+        let needle = literal_regex(query_str, case_sensitive)?;
+        let results = self
+            .line_matches(&*query, &[needle], None, context_mode, filters)?
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect();
+
+        Ok(results)
+    }
+
+    /// Search for any of several literal `terms` with a single boolean-OR query.
+    ///
+    /// Every hit is labelled with the term that matched it.
+    pub fn multi_term_search(&self, terms: &[String], case_sensitive: bool, context_mode: Option<ContextMode>) -> Result<Vec<TermMatch>> {
+        self.logged(
+            || self.logged_search(&SearchQuery::multi_term(terms).case_sensitive(case_sensitive).context(context_mode), &self.filters),
+            Vec::len,
+            || self.term_matches(terms, case_sensitive, context_mode, &self.filters),
+        )
+    }
+
+    fn term_matches(&self, terms: &[String], case_sensitive: bool, context_mode: Option<ContextMode>, filters: &Filters) -> Result<Vec<TermMatch>> {
+        // an empty term would match every line of the documents of the others
+        let terms = terms.iter().filter(|term| !term.is_empty()).cloned().collect::<Vec<_>>();
+        let subqueries = self.phrase_queries(&terms, Occur::Should)?;
+        if subqueries.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = BooleanQuery::new(subqueries);
+        let needles = terms
+            .iter()
+            .map(|term| literal_regex(term, case_sensitive))
+            .collect::<Result<Vec<_>>>()?;
+        let results = self
+            .line_matches(&query, &needles, None, context_mode, filters)?
+            .into_iter()
+            .map(|(idx, result)| TermMatch {
+                term: terms[idx].clone(),
+                result,
+            })
+            .collect();
+
+        Ok(results)
+    }
+
+    /// One query per non-empty term, quoted so that it is matched literally.
+    fn phrase_queries(&self, terms: &[String], occur: Occur) -> Result<Vec<(Occur, Box<dyn Query>)>> {
+        let query_parser = QueryParser::for_index(&self.index, vec![self.content_field]);
+        terms
+            .iter()
+            .filter(|term| !term.is_empty())
+            .map(|term| {
+                let phrase = format!("\"{}\"", term.replace('"', " "));
+                Ok((occur, query_parser.parse_query(&phrase)?))
+            })
+            .collect()
+    }
+
+    /// Search for lines with every one of `terms` at most `max_lines` lines away, 0 for
+    /// the same line, see [`SearchQuery::proximity`].
+    ///
+    /// Documents with every term are found in the index, then proximity is checked
+    /// over their lines. A hit is on the first term, and spans to the nearest
+    /// occurrence of each other term.
+    pub fn near_search(&self, terms: &[String], max_lines: usize, case_sensitive: bool, context_mode: Option<ContextMode>) -> Result<Vec<SearchResult>> {
+        self.logged(
+            || self.logged_search(&SearchQuery::near(terms, max_lines).case_sensitive(case_sensitive).context(context_mode), &self.filters),
+            Vec::len,
+            || self.near_matches(terms, max_lines, case_sensitive, context_mode, &self.filters),
+        )
+    }
+
+    fn near_matches(&self, terms: &[String], max_lines: usize, case_sensitive: bool, context_mode: Option<ContextMode>, filters: &Filters) -> Result<Vec<SearchResult>> {
+        let subqueries = self.phrase_queries(terms, Occur::Must)?;
+        if subqueries.len() < 2 {
+            anyhow::bail!("proximity searches take two terms or more");
+        }
+
+        let query = BooleanQuery::new(subqueries);
+        let needles = terms
+            .iter()
+            .filter(|term| !term.is_empty())
+            .map(|term| literal_regex(term, case_sensitive))
+            .collect::<Result<Vec<_>>>()?;
+        let results = self
+            .line_matches(&query, &needles, Some(max_lines), context_mode, filters)?
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect();
+
+        Ok(results)
+    }
+
+    /// Search for a pattern containing `*` (any run of identifier characters) and `?`
+    /// (a single identifier character) wildcards, such as `get_*_config`.
+    ///
+    /// Each word of the pattern is looked up in the index, wildcard words through a
+    /// regex over the term dictionary. Words starting with a wildcard force a scan of
+    /// the whole dictionary, so they are refused unless `allow_leading_wildcard` is set.
+    pub fn wildcard_search(&self, pattern: &str, case_sensitive: bool, allow_leading_wildcard: bool, context_mode: Option<ContextMode>) -> Result<Vec<SearchResult>> {
+        let query = || {
+            SearchQuery::wildcard(pattern)
+                .case_sensitive(case_sensitive)
+                .allow_leading_wildcard(allow_leading_wildcard)
+                .context(context_mode)
+        };
+        self.logged(
+            || self.logged_search(&query(), &self.filters),
+            Vec::len,
+            || self.wildcard_matches(pattern, case_sensitive, allow_leading_wildcard, context_mode, &self.filters),
+        )
+    }
+
+    fn wildcard_matches(&self, pattern: &str, case_sensitive: bool, allow_leading_wildcard: bool, context_mode: Option<ContextMode>, filters: &Filters) -> Result<Vec<SearchResult>> {
+        let field = self.content_field;
+
+        let is_wildcard = |c: char| c == '*' || c == '?';
+        let words = pattern
+            .split(|c: char| !(c.is_alphanumeric() || is_wildcard(c)))
+            // words made only of wildcards don't narrow the search down
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .collect::<Vec<_>>();
+
+        if words.is_empty() {
+            return Err(anyhow::anyhow!("Wildcard pattern `{pattern}` contains no literal characters"));
+        }
+
+        if !allow_leading_wildcard && words.iter().any(|word| word.starts_with(is_wildcard)) {
+            return Err(anyhow::anyhow!(
+                "Wildcard pattern `{pattern}` has a word starting with a wildcard, which scans the whole index"
+            ));
+        }
+
+        let subqueries = words
+            .iter()
+            .map(|word| {
+                // the index only stores lowercased tokens
+                let word = word.to_lowercase();
+                let query: Box<dyn Query> = if word.contains(is_wildcard) {
+                    Box::new(RegexQuery::from_pattern(&wildcard_to_regex(&word, "."), field)?)
+                } else {
+                    Box::new(TermQuery::new(
+                        Term::from_field_text(field, &word),
+                        IndexRecordOption::Basic,
+                    ))
+                };
+                Ok((Occur::Must, query))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let query = BooleanQuery::new(subqueries);
+        let needle = RegexBuilder::new(&wildcard_to_regex(pattern, r"\w"))
+            .case_insensitive(!case_sensitive)
+            .build()?;
+        let results = self
+            .line_matches(&query, &[needle], None, context_mode, filters)?
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect();
+
+        Ok(results)
+    }
+
+    /// Search for the lines matching the regex `pattern`, such as `fn\s+\w+_handler`.
+    ///
+    /// Lines are matched one by one, so patterns don't match across lines. The words
+    /// the pattern can't match without narrow the files down through the term
+    /// dictionary, the other files in scope are read until 10 of them have a match.
+    pub fn regex_search(&self, pattern: &str, case_sensitive: bool, context_mode: Option<ContextMode>) -> Result<Vec<SearchResult>> {
+        self.logged(
+            || self.logged_search(&SearchQuery::regex(pattern).case_sensitive(case_sensitive).context(context_mode), &self.filters),
+            Vec::len,
+            || self.regex_matches(pattern, case_sensitive, context_mode, &self.filters),
+        )
+    }
+
+    fn regex_matches(&self, pattern: &str, case_sensitive: bool, context_mode: Option<ContextMode>, filters: &Filters) -> Result<Vec<SearchResult>> {
+        if pattern.is_empty() {
+            return Err(NavError::new(ErrorCode::InvalidQuery, "The regex is empty, it would match every line").into());
+        }
+        let needle = RegexBuilder::new(pattern)
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map_err(|e| NavError::new(ErrorCode::InvalidQuery, format!("Invalid regex `{pattern}`: {e}")))?;
+        let needles = [needle];
+
+        let searcher = self.reader.searcher();
+        let query = self.scoped(regex_candidates(pattern, self.content_field)?, filters)?;
+        let mut candidates = searcher.search(&*query, &DocSetCollector)?.into_iter().collect::<Vec<_>>();
+        candidates.sort();
+
+        let (mut results, mut matched_docs) = (Vec::new(), 0);
+        for doc_address in candidates {
+            if matched_docs == filters.doc_limit.unwrap_or(10) {
+                break;
+            }
+            let hits = self.document_hits(&searcher, &*query, doc_address, 1.0, &needles, None, context_mode, filters)?;
+            matched_docs += usize::from(!hits.is_empty());
+            results.extend(hits.into_iter().map(|(_, result)| result));
+        }
+        Ok(results)
+    }
+
+    /// Run `query` and report every line of the top documents that matches one of
+    /// `needles`, along with the index of the needle found on that line.
+    ///
+    /// Lines are matched against the stored content, so case-insensitive needles
+    /// report columns in the original text. With `near`, only the lines matching the
+    /// first needle with a line matching each other one at most `near` lines away are
+    /// reported, see [`near_hits`].
+    fn line_matches(&self, query: &dyn Query, needles: &[Regex], near: Option<usize>, context_mode: Option<ContextMode>, filters: &Filters) -> Result<Vec<(usize, SearchResult)>> {
+        let searcher = self.reader.searcher();
+        let query = self.scoped(query.box_clone(), filters)?;
+        let top_docs = searcher.search(&*query, &TopDocs::with_limit(filters.doc_limit.unwrap_or(10)))?;
     
-        let query_parser = QueryParser::for_index(&self.index, vec![field]);
-        let query = query_parser.parse_query(&query_str)?;
-        let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+        let mut results = Vec::new();
+        for (score, doc_address) in top_docs {
+            results.extend(self.document_hits(&searcher, &*query, doc_address, score, needles, near, context_mode, filters)?);
+        }
     
+        Ok(results)
+    }
+
+    /// The lines of the document at `doc_address`, found by `query` with `score`, that
+    /// match one of `needles`, see [`Self::line_matches`].
+    #[allow(clippy::too_many_arguments)]
+    fn document_hits(
+        &self,
+        searcher: &tantivy::Searcher,
+        query: &dyn Query,
+        doc_address: DocAddress,
+        score: f32,
+        needles: &[Regex],
+        near: Option<usize>,
+        context_mode: Option<ContextMode>,
+        filters: &Filters,
+    ) -> Result<Vec<(usize, SearchResult)>> {
+        let retrieved_doc = searcher.doc(doc_address)?;
+
+        let path = match retrieved_doc.get_first(self.path_field) {
+            Some(path_field) => path_field.as_text().unwrap().to_string(),
+            None => {
+                println!("Debug: Path field is missing");
+                return Ok(Vec::new());
+            }
+        };
+
+        let content = match retrieved_doc.get_first(self.content_field) {
+            Some(content_field) => content_field.as_text().unwrap().to_string(),
+            None => {
+                println!("Debug: Content field is missing");
+                return Ok(Vec::new());
+            }
+        };
+
+        let flags = FileFlags::classify(&path, &content);
+
+        let line_end_indices = match retrieved_doc.get_first(self.line_ends_field).and_then(|field| field.as_bytes()) {
+            Some(bytes) => text_range::decode_line_ends(bytes),
+            None => {
+                println!("Debug: Line ends field is missing");
+                return Ok(Vec::new());
+            }
+        };
+
+        let lang = retrieved_doc.get_first(self.lang_field)
+            .and_then(|f| f.as_text())
+            .unwrap_or("")
+            .to_string();
+
+        let score = filters
+            .explain
+            .then(|| Score::explained(query, searcher, doc_address, score))
+            .transpose()?;
+
+        // only parsed when a match asks for its enclosing function
+        let mut function_ranges = None;
+        // only decoded for documents with a hit
+        let mut symbols = None;
+
+        let hits = line_hits(&content, &line_end_indices, needles);
+        let hits = match near {
+            Some(max_lines) => near_hits(hits.collect(), needles.len(), max_lines),
+            None => hits.collect(),
+        };
         let mut results = Vec::new();
-        for (_score, doc_address) in top_docs {
+        for hit in hits {
+            let location = match context_mode {
+                Some(mode) => {
+                    let function_ranges: &[_] = function_ranges.get_or_insert_with(|| {
+                        Self::function_ranges_of(&content, &lang, mode)
+                    });
+                    mode.expand(hit.highlight.clone(), &content, &line_end_indices, function_ranges)
+                }
+                // the line before the match and the two after it
+                None => Snipper::default().context(1, 2).expand(hit.highlight.clone(), &content, &line_end_indices),
+            };
+            let snippet = location.reify(&content, &[]).with_origin(&path, Some(&lang));
+            let symbols = symbols.get_or_insert_with(|| self.stored_symbols(&retrieved_doc));
+
+            results.push((hit.needle, SearchResult {
+                path: path.clone(),
+                relative_path: self.relative_path(&path).to_owned(),
+                line_number: hit.line_number,
+                column: hit.column,
+                snippet,
+                flags,
+                symbols: defined_on(symbols, &content, hit.line_idx),
+                score: score.clone(),
+            }));
+        }
+
+        Ok(results)
+    }
+
+    /// The symbols defined in a stored document, none if it has no scope graph.
+    fn stored_symbols(&self, doc: &tantivy::Document) -> Vec<Symbol> {
+        let bytes = doc.get_first(self.symbol_locations_field).and_then(|field| field.as_bytes()).unwrap_or_default();
+        LazySymbolLocations::from_bytes(bytes.to_vec()).list()
+    }
+
+    fn format_flags(flags: FileFlags) -> &'static str {
+        match (flags.is_minified, flags.is_generated) {
+            (true, true) => " [minified, generated]",
+            (true, false) => " [minified]",
+            (false, true) => " [generated]",
+            (false, false) => "",
+        }
+    }
+
+    pub fn format_multi_term_search_results(results: Vec<TermMatch>) -> String {
+        if results.is_empty() {
+            return "No results found".to_string();
+        }
+
+        let mut formatted_results = String::new();
+        for TermMatch { term, result } in results {
+            formatted_results.push_str(&format!(
+                "Term: {}, File: {}{}, Line: {}, Column: {}, \nContent:\n{}\n{}\n",
+                term, result.path, Self::format_flags(result.flags), result.line_number, result.column, result.snippet.to_text(), Score::format(&result.score)
+            ));
+        }
+        formatted_results
+    }
+    
+
+    /// The lines with a term at most `max_distance` edits away from `query_str`, up to
+    /// [`MAX_FUZZY_DISTANCE`], a swap of two adjacent chars counting as one edit with
+    /// `transpositions`.
+    pub fn fuzzy_search(&self, query_str: &str, max_distance: u8, transpositions: bool) -> Result<Vec<SearchResult>> {
+        self.logged(
+            || self.logged_search(&SearchQuery::fuzzy(query_str, max_distance).transpositions(transpositions), &self.filters),
+            Vec::len,
+            || self.fuzzy_matches(query_str, max_distance, transpositions, &self.filters),
+        )
+    }
+
+    fn fuzzy_matches(&self, query_str: &str, max_distance: u8, transpositions: bool, filters: &Filters) -> Result<Vec<SearchResult>> {
+        if max_distance > MAX_FUZZY_DISTANCE {
+            return Err(NavError::new(
+                ErrorCode::InvalidQuery,
+                format!("Fuzzy searches match terms at most {MAX_FUZZY_DISTANCE} edits away, not {max_distance}"),
+            )
+            .suggest(format!("Pass a max_distance of 0 to {MAX_FUZZY_DISTANCE}, or a wildcard search for looser matches"))
+            .into());
+        }
+        let searcher = self.reader.searcher();
+        
+        let query = FuzzyTermQuery::new(
+            Term::from_field_text(self.content_field, query_str),
+            max_distance,
+            transpositions,
+        );
+        let query = self.scoped(Box::new(query), filters)?;
+        let needle = literal_regex(query_str, true)?;
+    
+        let top_docs = searcher.search(&*query, &TopDocs::with_limit(filters.doc_limit.unwrap_or(10)))?;
+    
+        let mut results = Vec::new();
+        for (score, doc_address) in top_docs {
             let retrieved_doc = searcher.doc(doc_address)?;
     
             let path = match retrieved_doc.get_first(self.path_field) {
@@ -82,310 +1022,1146 @@ impl Searcher {
                 }
             };
     
-            let content = match retrieved_doc.get_first(field) {
-                Some(field) => field.as_text().unwrap().to_string(),
+            let content = match retrieved_doc.get_first(self.content_field) {
+                Some(content_field) => content_field.as_text().unwrap().to_string(),
                 None => {
                     println!("Debug: Content field is missing");
                     continue;
                 }
             };
 
-            let new_content = match retrieved_doc.get_first(self.content_field) {
-                Some(content_field) => content_field.as_text().unwrap().to_string(),
+            let flags = FileFlags::classify(&path, &content);
+    
+            let line_end_indices = match retrieved_doc.get_first(self.line_ends_field).and_then(|field| field.as_bytes()) {
+                Some(bytes) => text_range::decode_line_ends(bytes),
                 None => {
-                    println!("Debug: Content field is missing");
+                    println!("Debug: Line ends field is missing");
                     continue;
                 }
             };
+
+            let lang = retrieved_doc.get_first(self.lang_field)
+                .and_then(|f| f.as_text())
+                .unwrap_or("")
+                .to_string();
+
+            let score = filters
+                .explain
+                .then(|| Score::explained(&*query, &searcher, doc_address, score))
+                .transpose()?;
     
-            let line_end_indices_field = retrieved_doc.get_first(self.line_end_indices_field);
+            let mut symbols = None;
+            for hit in line_hits(&content, &line_end_indices, std::slice::from_ref(&needle)) {
+                let snippet = Snipper::default()
+                    .expand(hit.highlight, &content, &line_end_indices)
+                    .reify(&content, &[])
+                    .with_origin(&path, Some(&lang));
+                let symbols = symbols.get_or_insert_with(|| self.stored_symbols(&retrieved_doc));
+
+                results.push(SearchResult {
+                    path: path.clone(),
+                    relative_path: self.relative_path(&path).to_owned(),
+                    line_number: hit.line_number,
+                    column: hit.column,
+                    snippet,
+                    flags,
+                    symbols: defined_on(symbols, &content, hit.line_idx),
+                    score: score.clone(),
+                });
+            }
+        }
     
-            let line_end_indices: Vec<u32> = match line_end_indices_field {
-                Some(field) => {
-                    match field.as_bytes() {
-                        Some(bytes) => {
-                            bytes.chunks_exact(4).map(|c| {
-                                u32::from_le_bytes([c[0], c[1], c[2], c[3]])
-                            }).collect()
-                        }
-                        None => {
-                            println!("Debug: Failed to get bytes");
-                            continue;
-                        }
-                    }
-                }
-                None => {
-                    println!("Debug: Line end indices field is missing");
-                    continue;
+        Ok(results)
+    }
+
+    /// Complete `prefix` to the names of defined symbols, along with the files defining them.
+    ///
+    /// Names come from the symbol cache when it is up to date with the index. Otherwise
+    /// candidates are found with a prefix query over the `symbols` field, then matched
+    /// case-insensitively against the stored symbol names. Names matching the exact case
+    /// of `prefix` rank first, followed by shorter names.
+    pub fn complete_symbol(&self, prefix: &str, limit: usize) -> Result<Vec<SymbolCompletion>> {
+        if prefix.is_empty() {
+            return Err(anyhow::anyhow!("Prefix must not be empty"));
+        }
+
+        let searcher = self.reader.searcher();
+
+        let completions = match &self.symbol_cache {
+            Some(cache) if cache.is_current(&searcher) => cache.complete(prefix)?.into_iter().collect(),
+            _ => self.stored_symbol_completions(&searcher, prefix)?,
+        };
+
+        let mut completions = completions
+            .into_iter()
+            .map(|(name, mut paths)| {
+                paths.sort();
+                paths.dedup();
+                SymbolCompletion { name, paths }
+            })
+            .collect::<Vec<_>>();
+
+        completions.sort_by(|a, b| {
+            let exact_case = |c: &SymbolCompletion| !c.name.starts_with(prefix);
+            exact_case(a)
+                .cmp(&exact_case(b))
+                .then(a.name.len().cmp(&b.name.len()))
+                .then(a.name.cmp(&b.name))
+        });
+        completions.truncate(limit);
+
+        Ok(completions)
+    }
+
+    /// The symbols starting with `prefix`, ignoring case, read from the stored documents.
+    fn stored_symbol_completions(&self, searcher: &tantivy::Searcher, prefix: &str) -> Result<HashMap<String, Vec<String>>> {
+        // the symbols field is tokenized, so only the leading word of the prefix
+        // can be looked up in the term dictionary
+        let leading_word = prefix
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        let query: Box<dyn Query> = if leading_word.is_empty() {
+            Box::new(AllQuery)
+        } else {
+            Box::new(FuzzyTermQuery::new_prefix(
+                Term::from_field_text(self.symbols_field, &leading_word),
+                0,
+                false,
+            ))
+        };
+
+        let lowercase_prefix = prefix.to_lowercase();
+        let mut completions: HashMap<String, Vec<String>> = HashMap::new();
+        for doc_address in searcher.search(&*query, &DocSetCollector)? {
+            let doc = searcher.doc(doc_address)?;
+            let Some(path) = doc.get_first(self.path_field).and_then(|f| f.as_text()) else {
+                continue;
+            };
+            let symbols = doc.get_first(self.symbols_field).and_then(|f| f.as_text()).unwrap_or("");
+
+            for name in symbols.lines() {
+                if name.to_lowercase().starts_with(&lowercase_prefix) {
+                    completions.entry(name.to_owned()).or_default().push(path.to_owned());
                 }
+            }
+        }
+
+        Ok(completions)
+    }
+
+    /// Symbol names close to `query`, best first, for answering empty searches with a
+    /// "did you mean" hint.
+    pub fn did_you_mean(&self, query: &str, limit: usize) -> Result<Vec<String>> {
+        let query = query.trim().to_lowercase();
+        let max_distance = (query.chars().count() / 3).max(2);
+
+        // every word of the query may have been misspelled
+        let mut suggestions = self.close_symbols(&query, |word| if word.chars().count() > 4 { 2 } else { 1 }, max_distance, 20)?;
+        suggestions.retain(|symbol| symbol.distance > 0);
+        rank_by_prefix(&query, &mut suggestions);
+        suggestions.truncate(limit);
+
+        Ok(suggestions.into_iter().map(|symbol| symbol.name).collect())
+    }
+
+    /// Symbol names at most `max_distance` edits away from `query`, ignoring case, along
+    /// with the files defining them. Names starting with more of the chars `query` starts
+    /// with rank first, since an identifier is nearly always remembered by how it starts,
+    /// then names fewer edits away.
+    pub fn fuzzy_symbols(&self, query: &str, max_distance: u8, limit: usize) -> Result<Vec<SymbolCompletion>> {
+        if max_distance > MAX_FUZZY_DISTANCE {
+            return Err(NavError::new(
+                ErrorCode::InvalidQuery,
+                format!("Fuzzy symbol lookups match names at most {MAX_FUZZY_DISTANCE} edits away, not {max_distance}"),
+            )
+            .suggest(format!("Pass a max_distance of 0 to {MAX_FUZZY_DISTANCE}, or complete_symbol for the names starting with a prefix"))
+            .into());
+        }
+
+        let query = query.trim().to_lowercase();
+        let mut symbols = self.close_symbols(&query, |_| max_distance, usize::from(max_distance), 50)?;
+        rank_by_prefix(&query, &mut symbols);
+        symbols.truncate(limit);
+
+        Ok(symbols
+            .into_iter()
+            .map(|symbol| SymbolCompletion { name: symbol.name, paths: symbol.paths })
+            .collect())
+    }
+
+    /// The symbols at most `max_distance` edits away from the lowercased `query`, read from
+    /// the best `doc_limit` documents with a term within `word_distance` edits of one of
+    /// its words.
+    fn close_symbols(&self, query: &str, word_distance: impl Fn(&str) -> u8, max_distance: usize, doc_limit: usize) -> Result<Vec<CloseSymbol>> {
+        let searcher = self.reader.searcher();
+
+        let subqueries = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                let query: Box<dyn Query> = Box::new(FuzzyTermQuery::new(
+                    Term::from_field_text(self.symbols_field, word),
+                    word_distance(word),
+                    true,
+                ));
+                (Occur::Should, query)
+            })
+            .collect::<Vec<_>>();
+
+        if subqueries.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut symbols: HashMap<String, CloseSymbol> = HashMap::new();
+        for (_score, doc_address) in searcher.search(&BooleanQuery::new(subqueries), &TopDocs::with_limit(doc_limit))? {
+            let doc = searcher.doc(doc_address)?;
+            let Some(path) = doc.get_first(self.path_field).and_then(|f| f.as_text()) else {
+                continue;
             };
-    
-            for (mut line_number, window) in line_end_indices.windows(2).enumerate() {
-                if let [start, end] = *window {
-                    let line = &content[start as usize..end as usize];
-    
-                    if line.contains(&query_str) {
-                        line_number += 2;
-                        let column = line.find(&query_str).unwrap();
-                        let context_start = if line_number >= 3 { line_number - 3 } else { 0 };
-                        let context_end = usize::min(line_number + 3, line_end_indices.len() - 1);
-                        let context: String = line_end_indices[context_start..=context_end]
-                            .windows(2)
-                            .map(|w| {
-                                let start = w[0] as usize;
-                                let end = w[1] as usize;
-                                &new_content[start..end]
-                            })
-                            .collect::<Vec<_>>()
-                            .join("\n");
-    
-                        results.push(SearchResult {
-                            path: path.clone(),
-                            line_number,
-                            column,
-                            context,
-                        });
+            let names = doc.get_first(self.symbols_field).and_then(|f| f.as_text()).unwrap_or("");
+
+            for name in names.lines() {
+                let distance = edit_distance(&name.to_lowercase(), query);
+                if distance <= max_distance {
+                    let symbol = symbols.entry(name.to_owned()).or_insert_with(|| CloseSymbol { name: name.to_owned(), distance, paths: Vec::new() });
+                    if !symbol.paths.iter().any(|p| p == path) {
+                        symbol.paths.push(path.to_owned());
                     }
                 }
             }
         }
+
+        let mut symbols = symbols.into_values().collect::<Vec<_>>();
+        for symbol in &mut symbols {
+            symbol.paths.sort();
+        }
+        Ok(symbols)
+    }
+
+    pub fn format_did_you_mean(suggestions: Vec<String>) -> String {
+        if suggestions.is_empty() {
+            return "No results found".to_string();
+        }
+
+        let suggestions = suggestions
+            .iter()
+            .map(|name| format!("`{}`", name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("No results found. Did you mean {}?\n", suggestions)
+    }
+
+    pub fn format_symbol_completions(completions: Vec<SymbolCompletion>) -> String {
+        if completions.is_empty() {
+            return "No results found".to_string();
+        }
+
+        let mut formatted_results = String::new();
+        for completion in completions {
+            formatted_results.push_str(&format!(
+                "Symbol: {}, Defined in: {}\n",
+                completion.name,
+                completion.paths.join(", ")
+            ));
+        }
+        formatted_results
+    }
+
+    pub fn format_fuzzy_search_results(results: Vec<SearchResult>) -> String {
+        if results.is_empty() {
+            return "No results found".to_string();
+        }
     
-        Ok(results)
+        let mut formatted_results = String::new();
+        for result in results {
+            formatted_results.push_str(&format!(
+                "File: {}{}, Line: {}, Column: {}, \nContent:\n{}\n{}\n",
+                result.path, Self::format_flags(result.flags), result.line_number, result.column, result.snippet.to_text(), Score::format(&result.score)
+            ));
+        }
+        formatted_results
     }
     
-
-    pub fn fuzzy_search(&self, query_str: &str, max_distance: u8) -> Result<Vec<SearchResult>> {
-        let searcher = self.reader.searcher();
-        
-        let query = FuzzyTermQuery::new(
-            Term::from_field_text(self.content_field, query_str),
-            max_distance,  // max edit distance for fuzzy search
-            true,
-        );
     
-        let top_docs = searcher.search(&query, &TopDocs::with_limit(10))?;
+    /// Every hit as a fenced code block under a `path:line:column` header, labelled
+    /// with its term if `show_terms`.
+    pub fn format_markdown_search_results(results: Vec<TermMatch>, show_terms: bool) -> String {
+        if results.is_empty() {
+            return "No results found".to_string();
+        }
+
+        let mut formatted_results = String::new();
+        for TermMatch { term, result } in results {
+            let term = if show_terms { format!(" (term `{}`)", term) } else { String::new() };
+            formatted_results.push_str(&format!(
+                "`{}:{}:{}`{}{}\n{}\n{}\n",
+                result.path, result.line_number, result.column, Self::format_flags(result.flags), term, result.snippet.to_code_block(), Score::format(&result.score)
+            ));
+        }
+        formatted_results
+    }
+
+    pub fn format_search_results(results: Vec<SearchResult>) -> String {
+        if results.is_empty() {
+            return "No results found".to_string();
+        }
     
-        let mut results = Vec::new();
-        for (_score, doc_address) in top_docs {
-            let retrieved_doc = searcher.doc(doc_address)?;
+        let mut formatted_results = String::new();
+        for result in results {
+            formatted_results.push_str(&format!(
+                "File: {}{}, Line: {}, Column: {}, \nContent:\n{}\n{}\n",
+                result.path, Self::format_flags(result.flags), result.line_number, result.column, result.snippet.to_text(), Score::format(&result.score)
+            ));
+        }
+        formatted_results
+    }
     
-            let path = match retrieved_doc.get_first(self.path_field) {
-                Some(path_field) => path_field.as_text().unwrap().to_string(),
-                None => {
-                    println!("Debug: Path field is missing");
-                    continue;
+    pub fn load_all_documents(&self, lang: &str) -> Result<Vec<ContentDocument>> {
+        self.load_documents(Some(lang))
+    }
+
+    /// Load every stored document, optionally restricted to a single language.
+    ///
+    /// A language is looked up in the `lang` index, so only its own documents are read
+    /// from the store.
+    pub fn load_documents(&self, lang: Option<&str>) -> Result<Vec<ContentDocument>> {
+        if let Some(lang) = lang {
+            let query = BooleanQuery::new(
+                stored_langs(lang)
+                    .into_iter()
+                    .map(|stored| {
+                        let term = Term::from_field_text(self.lang_field, &stored);
+                        (Occur::Should, Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as Box<dyn Query>)
+                    })
+                    .collect(),
+            );
+            return self.documents_matching(&query);
+        }
+
+        let searcher = self.reader.searcher();
+        let mut documents = Vec::new();
+        for segment_reader in searcher.segment_readers() {
+            let store_reader = segment_reader.get_store_reader(0)?;
+            for doc in store_reader.iter(segment_reader.alive_bitset()) {
+                documents.push(self.content_document(&doc?));
+            }
+        }
+        Ok(documents)
+    }
+
+    /// Load the document stored for `relative_path`, if there is one.
+    pub fn load_document(&self, relative_path: &str) -> Result<Option<ContentDocument>> {
+        let term = Term::from_field_text(self.path_field, relative_path);
+        Ok(self
+            .documents_matching(&TermQuery::new(term, IndexRecordOption::Basic))?
+            .into_iter()
+            .next())
+    }
+
+    /// The document stored for `relative_path`, or an error suggesting the indexed
+    /// path the caller probably meant.
+    fn require_document(&self, relative_path: &str) -> Result<ContentDocument> {
+        match self.load_document(relative_path)? {
+            Some(doc) => Ok(doc),
+            None => Err(self.missing_document(relative_path)?.into()),
+        }
+    }
+
+    /// The error for a document missing from the index, suggesting a stored path that
+    /// ends like `relative_path` or has its file name. The paths are read from the term
+    /// dictionary of the `path` field, without reading any document.
+    fn missing_document(&self, relative_path: &str) -> Result<NavError> {
+        let searcher = self.reader.searcher();
+        let mut paths = Vec::new();
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(self.path_field)?;
+            let mut terms = inverted_index.terms().stream()?;
+            while terms.advance() {
+                paths.push(String::from_utf8_lossy(terms.key()).into_owned());
+            }
+        }
+        paths.sort_unstable();
+        paths.dedup();
+
+        // the dictionary still lists the paths of documents deleted since the last merge
+        let is_stored = |path: &str| {
+            let query = TermQuery::new(Term::from_field_text(self.path_field, path), IndexRecordOption::Basic);
+            searcher.search(&query, &Count).is_ok_and(|count| count > 0)
+        };
+        Ok(document_not_found(relative_path, &paths, is_stored))
+    }
+
+    /// The stored documents matching `query`, in index order.
+    fn documents_matching(&self, query: &dyn Query) -> Result<Vec<ContentDocument>> {
+        let searcher = self.reader.searcher();
+        let mut addresses = searcher.search(query, &DocSetCollector)?.into_iter().collect::<Vec<_>>();
+        addresses.sort_unstable();
+
+        addresses
+            .into_iter()
+            .map(|address| Ok(self.content_document(&searcher.doc(address)?)))
+            .collect()
+    }
+
+    /// Whether `doc` is in the scope of the searcher, and not a minified or generated
+    /// file unless it includes them.
+    fn passes_filters(&self, doc: &ContentDocument) -> bool {
+        let in_scope = match &self.filters.scope_dir {
+            Some(dir) => doc.relative_path.starts_with(&format!("{dir}/")),
+            None => true,
+        };
+        let owned = self.filters.owned.as_ref().map_or(true, |owned| owned.paths.contains(&doc.relative_path));
+        in_scope && owned && (self.filters.include_flagged || !FileFlags::classify(&doc.relative_path, &doc.content).any())
+    }
+
+    fn content_document(&self, doc: &tantivy::Document) -> ContentDocument {
+        let text = |field: Field| doc.get_first(field).and_then(|f| f.as_text()).unwrap_or("");
+        let bytes = |field: Field| doc.get_first(field).and_then(|f| f.as_bytes()).unwrap_or(&[]);
+
+        ContentDocument {
+            content: text(self.content_field).to_string(),
+            lang: Some(TSLanguage::normalize(text(self.lang_field))),
+            relative_path: text(self.path_field).to_string(),
+            line_end_indices: text_range::decode_line_ends(bytes(self.line_ends_field)),
+            // decoded only if a lookup needs this document's scope graph
+            symbol_locations: LazySymbolLocations::from_bytes(bytes(self.symbol_locations_field).to_vec()),
+            regions: bincode::deserialize(bytes(self.regions_field)).unwrap_or_default(),
+        }
+    }
+
+    /// List the exported/public symbols of every indexed file, optionally restricted
+    /// to a language and to paths matching `path_glob`.
+    pub fn public_api(&self, lang: Option<&str>, path_glob: Option<&str>) -> Result<Vec<PublicApi>> {
+        let matcher = path_glob.map(path_matcher).transpose()?;
+        let base = LineBase::current();
+
+        let mut results = self
+            .load_documents(lang)?
+            .iter()
+            .filter(|doc| matcher.as_ref().map_or(true, |m| m.is_match(&doc.relative_path)))
+            .filter_map(|doc| {
+                let mut symbols = public_symbols(doc);
+                for symbol in &mut symbols {
+                    symbol.range = base.numbered(symbol.range);
                 }
+
+                (!symbols.is_empty()).then(|| PublicApi {
+                    file: doc.relative_path.clone(),
+                    symbols,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        results.sort_by(|a, b| a.file.cmp(&b.file));
+        Ok(results)
+    }
+
+    /// Every edit `replace` makes to the indexed files in scope, by file then position,
+    /// planned from the stored contents without touching the disk. Minified and
+    /// generated files are left alone unless the searcher includes them.
+    pub fn plan_replace(&self, replace: &Replace) -> Result<Vec<Edit>> {
+        let regex = replace.compiled()?;
+        let matcher = replace.path_glob.as_deref().map(path_matcher).transpose()?;
+
+        let mut docs = self
+            .load_documents(replace.lang.as_deref())?
+            .into_iter()
+            .filter(|doc| self.passes_filters(doc))
+            .filter(|doc| matcher.as_ref().map_or(true, |m| m.is_match(&doc.relative_path)))
+            .collect::<Vec<_>>();
+        docs.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+        let mut edits = Vec::new();
+        for doc in docs {
+            edits.extend(replace.edits_in(&regex, &doc.relative_path, &doc.content, &doc.line_end_indices));
+        }
+        Ok(edits)
+    }
+
+    /// The problems with renaming `old_name` to `new_name` with `edits`, checked before
+    /// they are applied by parsing every file they edit, as stored in the index, with
+    /// its edits: references to `old_name` left, edits of anything else, and references
+    /// to `new_name` that were already there. Empty when the rename is clean.
+    pub fn verify_rename(&self, edits: &[Edit], old_name: &str, new_name: &str) -> Result<Vec<RenameViolation>> {
+        let mut violations = Vec::new();
+        for (path, edits) in edit::by_path(edits) {
+            let doc = self.require_document(path)?;
+            let lang = doc.lang.as_deref().unwrap_or_default();
+            violations.extend(edit::rename_violations(path, lang, &doc.content, &edits, old_name, new_name)?);
+        }
+        Ok(violations)
+    }
+
+    /// The symbols whose documentation, the comment or docstring indexed along with
+    /// them, best matches the natural-language `query`, at most `limit`. Symbols are
+    /// scored by the query words their name and documentation contain, rarer words
+    /// weighing more, so that it finds what identifier search misses.
+    pub fn doc_search(&self, query: &str, limit: usize) -> Result<Vec<DocMatch>> {
+        let words = doc_words(query);
+        if words.is_empty() {
+            return Err(NavError::new(ErrorCode::InvalidQuery, "The query has no words to look up").into());
+        }
+        // indexes written before the field was added are still readable
+        let Ok(field) = self.index.schema().get_field("symbol_docs") else {
+            let error = NavError::new(ErrorCode::IncompatibleIndex, "The index has no symbol documentation");
+            return Err(error.suggest("Reindex it with this version").into());
+        };
+
+        let searcher = self.reader.searcher();
+        let num_docs = searcher.num_docs() as f32;
+        let mut weights = Vec::new();
+        let mut clauses = Vec::<(Occur, Box<dyn Query>)>::new();
+        for word in &words {
+            let term = Term::from_field_text(field, word);
+            let doc_freq = searcher.doc_freq(&term)? as f32;
+            weights.push((word.as_str(), (1.0 + (num_docs - doc_freq + 0.5) / (doc_freq + 0.5)).ln()));
+            clauses.push((Occur::Should, Box::new(TermQuery::new(term, IndexRecordOption::Basic))));
+        }
+        let doc_limit = self.filters.doc_limit.unwrap_or(DOC_SEARCH_DOC_LIMIT);
+        let top_docs = searcher.search(&BooleanQuery::new(clauses), &TopDocs::with_limit(doc_limit))?;
+
+        let base = LineBase::current();
+        let mut matches = Vec::new();
+        for (_, address) in top_docs {
+            let doc = self.content_document(&searcher.doc(address)?);
+            if !self.passes_filters(&doc) {
+                continue;
+            }
+            let Some(scope_graph) = doc.symbol_locations.scope_graph() else {
+                continue;
             };
-    
-            let content = match retrieved_doc.get_first(self.content_field) {
-                Some(content_field) => content_field.as_text().unwrap().to_string(),
-                None => {
-                    println!("Debug: Content field is missing");
-                    continue;
+            for symbol in symbol_docs(doc.lang.as_deref().unwrap_or_default(), &doc.content, scope_graph) {
+                let mut contained = doc_words(&symbol.doc);
+                contained.extend(doc_words(&symbol.name));
+                let score = weights.iter().filter(|(word, _)| contained.contains(*word)).map(|(_, weight)| weight).sum::<f32>();
+                if score > 0.0 {
+                    matches.push(DocMatch {
+                        path: doc.relative_path.clone(),
+                        relative_path: self.relative_path(&doc.relative_path).to_owned(),
+                        name: symbol.name,
+                        kind: symbol.kind,
+                        range: base.numbered(symbol.range),
+                        doc: symbol.doc,
+                        score,
+                    });
                 }
+            }
+        }
+
+        matches.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.path.cmp(&b.path))
+                .then(a.range.start.byte.cmp(&b.range.start.byte))
+        });
+        matches.truncate(limit);
+        Ok(matches)
+    }
+
+    /// Render the result of `doc_search` in `format`: every symbol with the first line
+    /// of its documentation, or all of it in markdown.
+    pub fn format_doc_search(matches: &[DocMatch], format: OutputFormat) -> Result<String> {
+        if format == OutputFormat::Json {
+            return Ok(serde_json::json!({ "results": matches }).to_string());
+        }
+        if matches.is_empty() {
+            return Ok("No results found".to_string());
+        }
+
+        let mut formatted = String::new();
+        for found in matches {
+            let kind = found.kind.as_deref().map(|kind| format!("{kind} ")).unwrap_or_default();
+            formatted.push_str(&match format {
+                OutputFormat::Markdown => format!(
+                    "### {kind}`{}` in `{}:{}`\n\n{}\n\n",
+                    found.name, found.relative_path, found.range.start.line, found.doc
+                ),
+                _ => format!(
+                    "{}:{}: {kind}{}\n    {}\n",
+                    found.relative_path,
+                    found.range.start.line,
+                    found.name,
+                    found.doc.lines().next().unwrap_or_default()
+                ),
+            });
+        }
+        Ok(formatted)
+    }
+
+    /// The files likely to test `target`, a file or a symbol, and their test functions
+    /// concerned, so that the tests to run after editing it are known. See
+    /// [`crate::test_discovery`] for the evidence they are found by.
+    pub fn tests_for(&self, target: &TestTarget) -> Result<Vec<TestMatch>> {
+        let mut docs = self.load_documents(None)?.into_iter().filter(|doc| self.passes_filters(doc)).collect::<Vec<_>>();
+        let target = match target {
+            TestTarget::Path(path) => {
+                let source = self.require_document(path)?;
+                let path = source.relative_path.clone();
+                // the file itself may be out of scope of the tests
+                if !docs.iter().any(|doc| doc.relative_path == path) {
+                    docs.push(source);
+                }
+                TestTarget::Path(path)
+            }
+            TestTarget::Symbol(name) => TestTarget::Symbol(name.clone()),
+        };
+        Ok(test_discovery::find(&docs, &target, &self.repositories))
+    }
+
+    /// Render the result of `tests_for` in `format`: every file with its evidence, then
+    /// its test functions.
+    pub fn format_tests_for(matches: &[TestMatch], format: OutputFormat) -> Result<String> {
+        if format == OutputFormat::Json {
+            return Ok(serde_json::json!({ "results": matches }).to_string());
+        }
+        if matches.is_empty() {
+            return Ok("No tests found".to_string());
+        }
+
+        let mut formatted = String::new();
+        for found in matches {
+            let evidence = found.evidence.iter().map(|evidence| evidence.as_str()).collect::<Vec<_>>().join(", ");
+            formatted.push_str(&match format {
+                OutputFormat::Markdown => format!("- `{}` ({evidence})\n", found.relative_path),
+                _ => format!("{} ({evidence})\n", found.relative_path),
+            });
+            for test in &found.functions {
+                formatted.push_str(&match format {
+                    OutputFormat::Markdown => format!("  - `{}` line {}\n", test.name, test.range.start.line),
+                    _ => format!("    {}: {}\n", test.range.start.line, test.name),
+                });
+            }
+        }
+        Ok(formatted)
+    }
+
+    /// Symbols, paths and lines matching `query`, found by running the symbol, path and
+    /// content searches at once, then fused into one list ranked by source, at most
+    /// `limit`: the single place to look for something. See [`crate::hybrid`].
+    pub fn hybrid_search(&self, query: &str, limit: usize) -> Result<Vec<HybridHit>> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(NavError::new(ErrorCode::InvalidQuery, "The query is empty").into());
+        }
+
+        // the sources number their lines like the calling thread
+        let base = LineBase::current();
+        let ranked = std::thread::scope(|scope| {
+            let symbols = scope.spawn(|| base.scope(|| self.symbol_hits(query)));
+            let paths = scope.spawn(|| base.scope(|| self.path_hits(query)));
+            let contents = scope.spawn(|| base.scope(|| self.content_hits(query)));
+            [symbols, paths, contents]
+                .into_iter()
+                .map(|handle| handle.join().expect("hybrid search source panicked"))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        Ok(hybrid::fuse(ranked, limit))
+    }
+
+    /// The definitions of the symbols named like `query`, best matching first.
+    fn symbol_hits(&self, query: &str) -> Result<Vec<HybridHit>> {
+        // the symbols field is tokenized, candidates are found by the leading word
+        let leading_word = query.split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default().to_lowercase();
+        if leading_word.is_empty() {
+            return Ok(Vec::new());
+        }
+        let candidates = FuzzyTermQuery::new_prefix(Term::from_field_text(self.symbols_field, &leading_word), 0, false);
+
+        let base = LineBase::current();
+        let mut ranked = Vec::new();
+        for doc in self.documents_matching(&candidates)? {
+            if !self.passes_filters(&doc) {
+                continue;
+            }
+            let Some(scope_graph) = doc.symbol_locations.scope_graph() else {
+                continue;
             };
-    
-            let line_end_indices_field = retrieved_doc.get_first(self.line_end_indices_field);
-    
-            let line_end_indices: Vec<u32> = match line_end_indices_field {
-                Some(field) => {
-                    match field.as_bytes() {
-                        Some(bytes) => {
-                            bytes.chunks_exact(4).map(|c| {
-                                u32::from_le_bytes([c[0], c[1], c[2], c[3]])
-                            }).collect()
-                        }
-                        None => {
-                            println!("Debug: Failed to get bytes");
-                            continue;
-                        }
-                    }
+            for idx in scope_graph.graph.node_indices().filter(|&idx| scope_graph.is_definition(idx)) {
+                let range = scope_graph.graph[idx].range();
+                let Some(name) = doc.content.get(range.start.byte..range.end.byte) else {
+                    continue;
+                };
+                let Some(rank) = hybrid::symbol_rank(name, query) else {
+                    continue;
+                };
+                let line = base.number(range.start.line);
+                let mut hit = HybridHit::new(HybridSource::Symbol, doc.relative_path.clone(), self.relative_path(&doc.relative_path).to_owned(), Some(line));
+                hit.symbol = Some(name.to_owned());
+                hit.kind = scope_graph.symbol_name_of(idx).map(str::to_owned);
+                hit.text = text_range::line_text(&doc.content, &doc.line_end_indices, range.start.line).map(|text| text.trim().to_owned());
+                ranked.push((rank, name.len(), range.start.byte, hit));
+            }
+        }
+
+        ranked.sort_by(|a, b| (a.0, a.1, &a.3.path, a.2).cmp(&(b.0, b.1, &b.3.path, b.2)));
+        Ok(ranked.into_iter().take(hybrid::SOURCE_LIMIT).map(|(_, _, _, hit)| hit).collect())
+    }
+
+    /// The files whose path relative to their root contains `query`, those named after
+    /// it first.
+    fn path_hits(&self, query: &str) -> Result<Vec<HybridHit>> {
+        let candidates = self.documents_matching(&RegexQuery::from_pattern(&hybrid::path_pattern(query), self.path_field)?)?;
+        let mut ranked = candidates
+            .into_iter()
+            .filter(|doc| self.passes_filters(doc))
+            .filter_map(|doc| {
+                let relative_path = self.relative_path(&doc.relative_path).to_owned();
+                let rank = hybrid::path_rank(&relative_path, query)?;
+                Some((rank, HybridHit::new(HybridSource::Path, doc.relative_path, relative_path, None)))
+            })
+            .collect::<Vec<_>>();
+
+        ranked.sort_by(|a, b| {
+            (a.0, a.1.relative_path.len(), &a.1.path).cmp(&(b.0, b.1.relative_path.len(), &b.1.path))
+        });
+        Ok(ranked.into_iter().take(hybrid::SOURCE_LIMIT).map(|(_, hit)| hit).collect())
+    }
+
+    /// The lines containing `query` literally, ignoring case, from the most relevant
+    /// documents first.
+    fn content_hits(&self, query: &str) -> Result<Vec<HybridHit>> {
+        let hits = self.term_matches(&[query.to_owned()], false, None, &self.filters)?;
+        Ok(hits
+            .into_iter()
+            .take(hybrid::SOURCE_LIMIT)
+            .map(|TermMatch { result, .. }| {
+                let line = result.line_number;
+                let text = result.snippet.lines().find(|(number, _)| *number == line).map(|(_, text)| text.trim().to_owned());
+                let mut hit = HybridHit::new(HybridSource::Content, result.path, result.relative_path, Some(line));
+                hit.text = text;
+                hit
+            })
+            .collect())
+    }
+
+    /// Render the result of `hybrid_search` in `format`: every hit with its location,
+    /// the sources that found it, and the symbol or line there.
+    pub fn format_hybrid_search(hits: &[HybridHit], format: OutputFormat) -> Result<String> {
+        if format == OutputFormat::Json {
+            return Ok(serde_json::json!({ "results": hits }).to_string());
+        }
+        if hits.is_empty() {
+            return Ok("No results found".to_string());
+        }
+
+        let mut formatted = String::new();
+        for hit in hits {
+            let location = match hit.line {
+                Some(line) => format!("{}:{line}", hit.relative_path),
+                None => hit.relative_path.clone(),
+            };
+            let sources = hit.sources.iter().map(|source| source.as_str()).collect::<Vec<_>>().join(", ");
+            let found = match (&hit.symbol, &hit.text) {
+                (Some(symbol), _) => {
+                    let kind = hit.kind.as_deref().map(|kind| format!("{kind} ")).unwrap_or_default();
+                    format!("{kind}{symbol}")
                 }
-                None => {
-                    println!("Debug: Line end indices field is missing");
+                (None, Some(text)) => text.clone(),
+                (None, None) => String::new(),
+            };
+            formatted.push_str(&match format {
+                OutputFormat::Markdown if found.is_empty() => format!("- `{location}` ({sources})\n"),
+                OutputFormat::Markdown => format!("- `{location}` ({sources}): `{found}`\n"),
+                _ if found.is_empty() => format!("{location} ({sources})\n"),
+                _ => format!("{location} ({sources}): {found}\n"),
+            });
+        }
+        Ok(formatted)
+    }
+
+    pub fn format_public_api(results: Vec<PublicApi>) -> String {
+        if results.is_empty() {
+            return "No results found".to_string();
+        }
+
+        let mut formatted_results = String::new();
+        for file in results {
+            formatted_results.push_str(&format!("File: {}\n", file.file));
+            for symbol in file.symbols {
+                formatted_results.push_str(&format!(
+                    "  Kind: {}, Name: {}, Line: {}, Column: {}\n",
+                    symbol.kind.as_deref().unwrap_or("unknown"),
+                    symbol.name,
+                    symbol.range.start.line,
+                    symbol.range.start.column,
+                ));
+            }
+            formatted_results.push('\n');
+        }
+        formatted_results
+    }
+
+    /// The header comment of the file at `relative_path`, and how many classes and
+    /// functions it defines.
+    pub fn file_summary(&self, relative_path: &str) -> Result<FileSummary> {
+        Ok(summarize(&self.require_document(relative_path)?))
+    }
+
+    /// The symbols the file at `relative_path` defines, in the order of the file, as an
+    /// outline of it.
+    pub fn document_symbols(&self, relative_path: &str) -> Result<Vec<DefinedSymbol>> {
+        let doc = self.require_document(relative_path)?;
+        let base = LineBase::current();
+        let mut symbols = doc.symbol_locations.list();
+        symbols.sort_by_key(|symbol| symbol.range.start.byte);
+        Ok(symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                Some(DefinedSymbol {
+                    name: doc.content.get(symbol.range.start.byte..symbol.range.end.byte)?.to_owned(),
+                    kind: symbol.kind,
+                    range: base.numbered(symbol.range),
+                })
+            })
+            .collect())
+    }
+
+    /// The symbols defined in the indexed files whose name holds `query`, ignoring case,
+    /// at most `limit` of them: the names equal to it first, then those starting with
+    /// it, then shorter names.
+    pub fn workspace_symbols(&self, query: &str, limit: usize) -> Result<Vec<WorkspaceSymbol>> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Err(NavError::new(ErrorCode::InvalidQuery, "Workspace symbol lookups need a part of the name to look for")
+                .suggest("Pass a part of a symbol name, or use document_symbols for every symbol of a file")
+                .into());
+        }
+
+        // the symbols field is tokenized into lowercase words, one of which holds the
+        // longest word of the query
+        let longest_word = query.split(|c: char| !c.is_alphanumeric()).max_by_key(|word| word.len()).unwrap_or_default();
+        let candidates: Box<dyn Query> = if longest_word.is_empty() {
+            Box::new(AllQuery)
+        } else {
+            Box::new(RegexQuery::from_pattern(&format!(".*{longest_word}.*"), self.symbols_field)?)
+        };
+
+        let base = LineBase::current();
+        let searcher = self.reader.searcher();
+        let mut symbols = Vec::new();
+        for address in searcher.search(&*candidates, &DocSetCollector)? {
+            let doc = self.content_document(&searcher.doc(address)?);
+            for symbol in doc.symbol_locations.list() {
+                let Some(name) = doc.content.get(symbol.range.start.byte..symbol.range.end.byte) else {
                     continue;
-                }
-            };
-    
-            for (mut line_number, window) in line_end_indices.windows(2).enumerate() {
-                if let [start, end] = *window {
-                    let line = &content[start as usize..end as usize];
-    
-                    if line.contains(query_str) {
-                        line_number += 2;
-                        let column = line.find(query_str).unwrap();
-                        let context_start = line_number - 2;
-                        let context_end = usize::min(line_number - 1, line_end_indices.len() - 1);
-                        let context: String = line_end_indices[context_start..=context_end]
-                            .windows(2)
-                            .map(|w| {
-                                let start = w[0] as usize;
-                                let end = w[1] as usize;
-                                &content[start..end]
-                            })
-                            .collect::<Vec<_>>()
-                            .join("\n");
-    
-                        results.push(SearchResult {
-                            path: path.clone(),
-                            line_number,
-                            column,
-                            context,
-                        });
-                    }
+                };
+                if name.to_lowercase().contains(&query) {
+                    symbols.push(WorkspaceSymbol {
+                        name: name.to_owned(),
+                        kind: symbol.kind,
+                        path: doc.relative_path.clone(),
+                        relative_path: self.relative_path(&doc.relative_path).to_owned(),
+                        range: base.numbered(symbol.range),
+                    });
                 }
             }
         }
-    
-        Ok(results)
+
+        symbols.sort_by_cached_key(|symbol| {
+            let name = symbol.name.to_lowercase();
+            (name != query, !name.starts_with(&query), name.len(), symbol.name.clone(), symbol.path.clone(), symbol.range.start.byte)
+        });
+        symbols.truncate(limit);
+        Ok(symbols)
     }
 
-    pub fn format_fuzzy_search_results(results: Vec<SearchResult>) -> String {
-        if results.is_empty() {
+    pub fn format_document_symbols(relative_path: &str, symbols: &[DefinedSymbol]) -> String {
+        if symbols.is_empty() {
             return "No results found".to_string();
         }
-    
-        let mut formatted_results = String::new();
-        for result in results {
+
+        let mut formatted_results = format!("File: {relative_path}\n");
+        for symbol in symbols {
             formatted_results.push_str(&format!(
-                "File: {}, Line: {}, Column: {}, \nContent:\n{}\n\n",
-                result.path, result.line_number, result.column, result.context
+                "  Kind: {}, Name: {}, Line: {}, Column: {}\n",
+                symbol.kind, symbol.name, symbol.range.start.line, symbol.range.start.column,
             ));
         }
         formatted_results
     }
-    
-    
-    pub fn format_search_results(results: Vec<SearchResult>) -> String {
-        if results.is_empty() {
+
+    pub fn format_workspace_symbols(symbols: &[WorkspaceSymbol]) -> String {
+        if symbols.is_empty() {
             return "No results found".to_string();
         }
-    
+
         let mut formatted_results = String::new();
-        for result in results {
+        for symbol in symbols {
             formatted_results.push_str(&format!(
-                "File: {}, Line: {}, Column: {}, \nContent:\n{}\n\n",
-                result.path, result.line_number, result.column, result.context
+                "Symbol: {}, Kind: {}, Defined in: {}:{}:{}\n",
+                symbol.name, symbol.kind, symbol.relative_path, symbol.range.start.line, symbol.range.start.column,
             ));
         }
         formatted_results
     }
-    
-    pub fn load_all_documents(&self, lang: &str) -> Result<Vec<ContentDocument>> {
+
+    pub fn format_file_summary(summary: &FileSummary) -> String {
+        let mut formatted = format!(
+            "File: {} ({}, {} lines)\nClasses: {}, Functions: {}\n",
+            summary.file,
+            summary.lang.as_deref().unwrap_or("unknown"),
+            summary.lines,
+            summary.classes,
+            summary.functions,
+        );
+        if let Some(header) = &summary.header {
+            formatted.push('\n');
+            formatted.push_str(header);
+            formatted.push('\n');
+        }
+        formatted
+    }
+
+    /// Look up the package that `dir` belongs to, from the manifests and READMEs
+    /// recorded at index time.
+    pub fn package_info(&self, dir: &str) -> Result<Option<PackageInfo>> {
+        Ok(PackageInfo::find(&self.packages()?, dir).cloned())
+    }
+
+    /// The packages recorded at index time.
+    fn packages(&self) -> Result<Vec<PackageInfo>> {
+        match self.index.directory().atomic_read(Path::new(PACKAGES_FILE)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(OpenReadError::FileDoesNotExist(_)) => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The owners of the file at `path` in the `CODEOWNERS` of its root, recorded at
+    /// index time. The file needn't be indexed, and a relative `path` is relative to
+    /// the first root with a `CODEOWNERS`.
+    pub fn owners_of(&self, path: &str) -> Result<Vec<String>> {
+        Ok(owners::owners_of(&owners::stored(&self.index)?, path))
+    }
+
+    /// The stored files `owner` owns, from the `CODEOWNERS` of the innermost root
+    /// holding each one.
+    fn owned_files(&self, owner: &str) -> Result<OwnedFiles> {
+        let codeowners = owners::stored(&self.index)?;
+        let matchers = codeowners.iter().map(CodeOwners::matcher).collect::<Vec<_>>();
+        let paths = self
+            .stored_paths()?
+            .into_iter()
+            .filter(|path| {
+                // outer roots come first, the innermost one holding the file decides
+                let owners = matchers.iter().rev().find_map(|matcher| matcher.owners_of_stored(path));
+                owners.is_some_and(|owners| owners.iter().any(|candidate| owners::is_owner(candidate, owner)))
+            })
+            .collect::<HashSet<_>>();
+        if paths.is_empty() {
+            let mut known = codeowners
+                .iter()
+                .flat_map(|codeowners| &codeowners.rules)
+                .flat_map(|rule| rule.owners.iter().map(String::as_str))
+                .collect::<Vec<_>>();
+            known.sort_unstable();
+            known.dedup();
+            anyhow::bail!("no indexed files owned by `{owner}`, known owners: {}", known.join(", "));
+        }
+        Ok(OwnedFiles { owner: owner.to_owned(), paths })
+    }
+
+    /// The paths of every stored document.
+    fn stored_paths(&self) -> Result<Vec<String>> {
         let searcher = self.reader.searcher();
+        searcher
+            .search(&AllQuery, &DocSetCollector)?
+            .into_iter()
+            .map(|address| {
+                let doc = searcher.doc(address)?;
+                Ok(doc.get_first(self.path_field).and_then(|path| path.as_text()).unwrap_or_default().to_owned())
+            })
+            .collect()
+    }
 
-        let mut documents = Vec::new();
+    pub fn format_package_info(info: Option<PackageInfo>) -> String {
+        let Some(info) = info else {
+            return "No package found".to_string();
+        };
+
+        let mut formatted = format!("Directory: {}\n", info.dir);
+        if let Some(manifest) = info.manifest {
+            formatted.push_str(&format!("Manifest: {}\n", manifest));
+        }
+        if let Some(name) = info.name {
+            formatted.push_str(&format!("Name: {}\n", name));
+        }
+        if let Some(description) = info.description {
+            formatted.push_str(&format!("Description: {}\n", description));
+        }
+        if !info.dependencies.is_empty() {
+            formatted.push_str(&format!("Dependencies: {}\n", info.dependencies.join(", ")));
+        }
+        if let Some(summary) = info.readme_summary {
+            formatted.push_str(&format!("README: {}\n", summary));
+        }
+        formatted
+    }
+
+    /// The files, lines and bytes of every indexed language, largest first by bytes,
+    /// as in the language bar of a forge.
+    pub fn language_breakdown(&self) -> Result<Vec<LanguageStats>> {
+        let searcher = self.reader.searcher();
+        let mut by_lang = HashMap::<String, LanguageStats>::new();
         for segment_reader in searcher.segment_readers() {
             let store_reader = segment_reader.get_store_reader(0)?;
-            let alive_bitset = segment_reader.alive_bitset();
-
-            for doc in store_reader.iter(alive_bitset) {
+            for doc in store_reader.iter(segment_reader.alive_bitset()) {
                 let doc = doc?;
-                let lang_field_value = doc.get_first(self.lang_field)
-                    .and_then(|f| f.as_text())
-                    .unwrap_or("").to_lowercase();
-
-                // println!("{:?} {:?}", lang_field_value, lang);
-
-                if lang_field_value == lang {
-                    let content = doc.get_first(self.content_field)
-                        .and_then(|f| f.as_text())
-                        .unwrap_or("")
-                        .to_string();
-
-                    let relative_path = doc.get_first(self.path_field)
-                        .and_then(|f| f.as_text())
-                        .unwrap_or("")
-                        .to_string();
-
-                    let line_end_indices: Vec<u32> = doc.get_first(self.line_end_indices_field)
-                        .and_then(|f| f.as_bytes())
-                        .unwrap_or(&[])
-                        .chunks_exact(4)
-                        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
-                        .collect();
-
-                    let symbol_locations: SymbolLocations = doc.get_first(self.symbol_locations_field)
-                        .and_then(|f| f.as_bytes())
-                        .and_then(|b| bincode::deserialize(b).ok())
-                        .unwrap_or_default();
-
-                    // println!("{:?}", symbol_locations);
-
-                    documents.push(ContentDocument {
-                        content,
-                        lang: Some(lang.to_string()),
-                        relative_path,
-                        line_end_indices,
-                        symbol_locations,
-                    });
-                }
+                let text = |field| doc.get_first(field).and_then(|value| value.as_text()).unwrap_or_default();
+                let lang = TSLanguage::normalize(text(self.lang_field));
+                let content = text(self.content_field);
+
+                let stats = by_lang.entry(lang.clone()).or_insert_with(|| LanguageStats {
+                    lang,
+                    files: 0,
+                    lines: 0,
+                    bytes: 0,
+                    percent: 0.0,
+                });
+                stats.files += 1;
+                stats.lines += text_range::line_count(content);
+                stats.bytes += content.len() as u64;
             }
         }
 
-        Ok(documents)
+        let total = by_lang.values().map(|stats| stats.bytes).sum::<u64>();
+        let mut breakdown = by_lang.into_values().collect::<Vec<_>>();
+        for stats in &mut breakdown {
+            stats.percent = if total == 0 { 0.0 } else { stats.bytes as f64 * 100.0 / total as f64 };
+        }
+        breakdown.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.lang.cmp(&b.lang)));
+        Ok(breakdown)
     }
 
+    /// Render a language breakdown in `format`: a line per language, a table in markdown.
+    pub fn format_language_breakdown(breakdown: &[LanguageStats], format: OutputFormat) -> Result<String> {
+        if format == OutputFormat::Json {
+            return Ok(serde_json::to_string(breakdown)?);
+        }
+        if breakdown.is_empty() {
+            return Ok("No files indexed".to_string());
+        }
+
+        let mut formatted = String::new();
+        if format == OutputFormat::Markdown {
+            formatted.push_str("| Language | Files | Lines | Share |\n|---|---:|---:|---:|\n");
+        }
+        for stats in breakdown {
+            formatted.push_str(&match format {
+                OutputFormat::Markdown => format!("| {} | {} | {} | {:.1}% |\n", stats.lang, stats.files, stats.lines, stats.percent),
+                _ => format!("{}: {:.1}%, {} files, {} lines\n", stats.lang, stats.percent, stats.files, stats.lines),
+            });
+        }
+        Ok(formatted)
+    }
 
     pub fn line_word_to_byte_range(&self, content: &str, line_end_indices: &[u32], line_number: usize, word_start_index: usize, word_end_index: usize) -> Result<(usize, usize)> {
-        if line_number == 0 || line_number > line_end_indices.len() {
-            return Err(anyhow::anyhow!("Invalid line number"));
+        text_range::line_word_to_byte_range(content, line_end_indices, line_number, word_start_index, word_end_index)
+    }
+
+    /// Function ranges of `content`, computed only when `mode` needs them.
+    fn function_ranges_of(content: &str, lang: &str, mode: ContextMode) -> Vec<Range<usize>> {
+        if mode != ContextMode::EnclosingFunction {
+            return Vec::new();
         }
-    
-        // Calculate the start and end byte indices for the line
-        let start_of_line = if line_number == 1 {
-            0
-        } else {
-            line_end_indices[line_number - 2] as usize + 1
-        };
-    
-        let end_of_line = line_end_indices[line_number - 1] as usize;
-    
-        // Extract the line as a &str
-        let line = &content[start_of_line..end_of_line];
-    
-        // println!("{}", line);
-    
-        // Validate word start and end indices
-        if word_start_index >= word_end_index || word_end_index > line.chars().count() {
-            return Err(anyhow::anyhow!("Invalid word indices"));
+
+        TreeSitterFile::try_build(content.as_bytes(), lang)
+            .and_then(TreeSitterFile::function_ranges)
+            .unwrap_or_default()
+    }
+
+    pub fn token_info(&self, relative_path: &str, line: usize, start_index: usize, end_index: usize, context_mode: Option<ContextMode>) -> Result<Vec<FileSymbols>> {
+        self.token_info_filtered(relative_path, line, start_index, end_index, context_mode, &OccurrenceFilter::default())
+    }
+
+    /// The definitions of the token at a position, without its references, for tools
+    /// jumping straight to them.
+    pub fn find_definitions(&self, relative_path: &str, line: usize, start_index: usize, end_index: usize, context_mode: Option<ContextMode>) -> Result<Vec<FileSymbols>> {
+        let filter = OccurrenceFilter::default().kind(OccurrenceKind::Definition);
+        self.token_info_filtered(relative_path, line, start_index, end_index, context_mode, &filter)
+    }
+
+    /// The references to the token at the given position, along with its definitions if
+    /// `include_declaration`: who calls a function, without sifting through `go_to`.
+    /// Only those in the files under `directory` if given, see [`OccurrenceFilter::directory`].
+    pub fn find_references(&self, relative_path: &str, line: usize, start_index: usize, end_index: usize, include_declaration: bool, directory: Option<&str>, context_mode: Option<ContextMode>) -> Result<Vec<FileSymbols>> {
+        let mut filter = OccurrenceFilter::default();
+        if !include_declaration {
+            filter = filter.kind(OccurrenceKind::Reference);
         }
-    
-        // Find the byte index for the start of the word
-        let word_start_byte_index = line.chars().take(word_start_index).map(|c| c.len_utf8()).sum::<usize>();
-    
-        // Find the byte index for the end of the word
-        let word_end_byte_index = line.chars().take(word_end_index).map(|c| c.len_utf8()).sum::<usize>();
-    
-        let start_byte = start_of_line + word_start_byte_index;
-        let end_byte = start_of_line + word_end_byte_index;
-    
-        println!("{:?}", &content[start_byte..end_byte]);
-    
-        Ok((start_byte, end_byte))
+        if let Some(directory) = directory {
+            filter = filter.directory(directory)?;
+        }
+        self.token_info_filtered(relative_path, line, start_index, end_index, context_mode, &filter)
     }
 
-    fn detect_language(path: &Path) -> &'static str {
-        let extension = path.extension().and_then(std::ffi::OsStr::to_str).unwrap_or("");
-        TSLanguage::from_extension(extension).unwrap_or("plaintext")
+    /// Like [`Self::token_info`], returning only the occurrences passing `filter`.
+    /// Files outside of its paths aren't searched for the token at all. The reference
+    /// counts of definitions count the references it keeps.
+    pub fn token_info_filtered(&self, relative_path: &str, line: usize, start_index: usize, end_index: usize, context_mode: Option<ContextMode>, filter: &OccurrenceFilter) -> Result<Vec<FileSymbols>> {
+        Ok(self.token_occurrences(relative_path, line, start_index, end_index, context_mode, filter)?.files)
     }
 
-    pub fn token_info(&self, relative_path: &str, line: usize, start_index: usize, end_index: usize) -> Result<Vec<FileSymbols>> {
-        let lang = Self::detect_language(Path::new(relative_path)).to_lowercase();
+    /// Like [`Self::token_info_filtered`], telling whether the repository-wide lookups
+    /// kept only the first [`crate::intelligence::code_navigation::MAX_REPO_WIDE_FILES`]
+    /// files by path.
+    pub fn token_occurrences(&self, relative_path: &str, line: usize, start_index: usize, end_index: usize, context_mode: Option<ContextMode>, filter: &OccurrenceFilter) -> Result<TokenOccurrences> {
+        let query = || LoggedQuery::GoTo {
+            path: relative_path.to_owned(),
+            line,
+            start_index,
+            end_index,
+            context: context_mode.map(|context| context.to_string()),
+            line_base: LineBase::current().first(),
+            kind: filter.kind,
+            path_glob: filter.paths.as_ref().map(|paths| paths.glob().glob().to_owned()),
+            lang: filter.lang.map(str::to_owned),
+        };
+        self.logged(
+            query,
+            |occurrences: &TokenOccurrences| occurrences.files.iter().map(|file_symbols| file_symbols.data.len()).sum(),
+            || self.occurrences_of_token(relative_path, line, start_index, end_index, context_mode, filter),
+        )
+    }
+
+    fn occurrences_of_token(&self, relative_path: &str, line: usize, start_index: usize, end_index: usize, context_mode: Option<ContextMode>, filter: &OccurrenceFilter) -> Result<TokenOccurrences> {
+        let source = self.require_document(relative_path)?;
+        // Convert line number and indices to byte range
+        let (start_byte, end_byte) = Self::line_word_to_byte_range(self, &source.content, &source.line_end_indices, line, start_index, end_index)?;
+
+        // the language asked for, or that of the region of the token in a template
+        let region_lang = source.region_at(start_byte).map(|region| region.lang.clone());
+        let lang = filter
+            .lang
+            .or(region_lang.as_deref())
+            .unwrap_or_else(|| TSLanguage::of_path(Path::new(relative_path)));
+        let source = source.read_as(lang);
 
         // println!("{}", lang);
 
-        let all_docs = self.load_all_documents(&lang)?;
-        
-        // Find the source document based on the provided relative path
-        let source_document_idx = all_docs.iter().position(|doc| doc.relative_path == relative_path)
-            .ok_or(anyhow::anyhow!("Source document not found"))?;
-        
-        let doc = all_docs.get(source_document_idx).unwrap();
-    
-        // Convert line number and indices to byte range
-        let (start_byte, end_byte) = Self::line_word_to_byte_range(self, &doc.content, &doc.line_end_indices, line, start_index, end_index)?;
+        let mut all_docs = self.load_all_documents(lang)?;
+
+        // the source document read as `lang`, among the other documents of `lang`
+        let source_document_idx = match all_docs.iter().position(|doc| doc.relative_path == relative_path) {
+            Some(idx) => {
+                all_docs[idx] = source;
+                idx
+            }
+            None => {
+                all_docs.push(source);
+                all_docs.len() - 1
+            }
+        };
 
+        // Dependency documents go after the repository's own
+        let local_paths = all_docs.iter().map(|doc| doc.relative_path.clone()).collect::<HashSet<_>>();
+        for external in &self.external {
+            all_docs.extend(external.load_all_documents(lang)?);
+        }
+        
         let token = Token {
             relative_path,
             start_byte,
@@ -397,19 +2173,72 @@ impl Searcher {
             all_docs: &all_docs,
             source_document_idx,
             snipper: None,
+            filter: filter.clone(),
         };
     
-        let mut data = context.token_info();
+        let TokenOccurrences { files: mut data, truncated } = context.token_occurrences();
 
-        // Adjust line numbers by 1
+        // Only definitions are of interest in dependencies, not their own uses of a name
+        for file_symbols in &mut data {
+            if !local_paths.contains(&file_symbols.file) {
+                file_symbols.external = true;
+                file_symbols.data.retain(|occurrence| occurrence.is_definition());
+            }
+        }
+        data.retain(|file_symbols| !file_symbols.data.is_empty());
+        // without the references, there is nothing to count
+        if filter.wants(OccurrenceKind::Reference) {
+            count_references(&mut data);
+        }
+
+        if let Some(mode) = context_mode {
+            for file_symbols in &mut data {
+                let Some(doc) = all_docs.iter().find(|doc| doc.relative_path == file_symbols.file) else {
+                    continue;
+                };
+                let function_ranges = match mode {
+                    ContextMode::EnclosingFunction => doc.function_ranges().unwrap_or_default(),
+                    ContextMode::Lines(_) => Vec::new(),
+                };
+                for occurrence in &mut file_symbols.data {
+                    occurrence.snippet = mode
+                        .expand(occurrence.range.into(), &doc.content, &doc.line_end_indices, &function_ranges)
+                        .reify(&doc.content, &[])
+                        .with_origin(&doc.relative_path, doc.lang.as_deref());
+                }
+            }
+        }
+
+        let base = LineBase::current();
         for file_symbols in &mut data {
             for occurrence in &mut file_symbols.data {
-                occurrence.range.start.line += 1;
-                occurrence.range.end.line += 1;
+                occurrence.range = base.numbered(occurrence.range);
             }
         }
         
-        Ok(data)
+        Ok(TokenOccurrences { files: data, truncated })
+    }
+
+    /// Recognise the token at the given position as a builtin or standard library
+    /// symbol of the file's language, of its region in a template or of `lang` if
+    /// given, for tokens that `token_info` can't resolve.
+    pub fn builtin_symbol(&self, relative_path: &str, line: usize, start_index: usize, end_index: usize, lang: Option<&str>) -> Result<Option<ExternalSymbol>> {
+        let doc = self.require_document(relative_path)?;
+
+        let (start_byte, end_byte) = Self::line_word_to_byte_range(self, &doc.content, &doc.line_end_indices, line, start_index, end_index)?;
+        let lang = lang
+            .or_else(|| doc.region_at(start_byte).map(|region| region.lang.as_str()))
+            .unwrap_or_else(|| TSLanguage::of_path(Path::new(relative_path)));
+        let name = doc.content.get(start_byte..end_byte).unwrap_or_default().trim();
+
+        Ok(builtins::lookup(lang, name))
+    }
+
+    pub fn format_external_symbol(symbol: ExternalSymbol) -> String {
+        format!(
+            "External symbol: {}, Language: {}, Module: {}\nDefined outside the repository, by the language or its standard library.\n",
+            symbol.name, symbol.language, symbol.module,
+        )
     }
 
     // New function to format token info results
@@ -422,41 +2251,472 @@ impl Searcher {
         for file_symbols in token_info_results {
             for occurrence in file_symbols.data {
                 formatted_results.push_str(&format!(
-                    "Kind: {}, File: {}, Line: {}, Column: {}\nContent:\n{}\n\n",
+                    "Kind: {}{}{}, File: {}, Line: {}, Column: {}\nContent:\n{}\n\n",
+                    if let OccurrenceKind::Reference = occurrence.kind {"Reference"} else {"Definition"},
+                    Self::format_usage(&occurrence),
+                    if file_symbols.external {" (external)"} else {""},
+                    file_symbols.file,
+                    occurrence.range.start.line,
+                    occurrence.range.start.column,
+                    occurrence.snippet.to_text(),
+                ));
+            }
+        }
+        formatted_results
+    }
+
+    /// ` (used N times)` after a definition.
+    fn format_usage(occurrence: &Occurrence) -> String {
+        match occurrence.reference_count {
+            Some(1) => " (used 1 time)".to_owned(),
+            Some(count) => format!(" (used {count} times)"),
+            None => String::new(),
+        }
+    }
+
+    /// Every occurrence as a fenced code block under its kind and `path:line:column`.
+    pub fn format_token_info_markdown(token_info_results: Vec<FileSymbols>) -> String {
+        if token_info_results.is_empty() {
+            return "No results found".to_string();
+        }
+
+        let mut formatted_results = String::new();
+        for file_symbols in token_info_results {
+            for occurrence in file_symbols.data {
+                formatted_results.push_str(&format!(
+                    "**{}**{}{} `{}:{}:{}`\n{}\n\n",
                     if let OccurrenceKind::Reference = occurrence.kind {"Reference"} else {"Definition"},
+                    Self::format_usage(&occurrence),
+                    if file_symbols.external {" (external)"} else {""},
                     file_symbols.file,
                     occurrence.range.start.line,
                     occurrence.range.start.column,
-                    occurrence.snippet.data,
+                    occurrence.snippet.to_code_block(),
                 ));
             }
         }
         formatted_results
     }
 
+    /// Render the result of `token_info` in `format`.
+    pub fn format_token_info_as(token_info_results: Vec<FileSymbols>, format: OutputFormat) -> Result<String> {
+        Ok(match format {
+            OutputFormat::Text => Self::format_token_info(token_info_results),
+            OutputFormat::Json => serde_json::json!({ "results": token_info_results }).to_string(),
+            OutputFormat::Markdown => Self::format_token_info_markdown(token_info_results),
+        })
+    }
+
     pub fn get_hoverable_ranges(&self, relative_path: &str) -> Result<Vec<TextRange>> {
-        let lang = Self::detect_language(Path::new(relative_path)).to_lowercase();
-        let all_docs = self.load_all_documents(&lang)?;
-        
-        // Find the document based on the provided relative path
-        let doc = all_docs.iter().find(|doc| doc.relative_path == relative_path)
-            .ok_or(anyhow::anyhow!("Document not found"))?;
-        
-        doc.hoverable_ranges().ok_or(anyhow::anyhow!("Hoverable ranges not found"))
+        self.hoverable_ranges_in(relative_path, None, None)
+    }
+
+    /// The token at the char `column` of the line `line`, numbered from the
+    /// [`LineBase::current`], or the one ending there. `None` between tokens. In a
+    /// template, the token is that of the language of the region there.
+    pub fn token_at(&self, relative_path: &str, line: usize, column: usize) -> Result<Option<CursorToken>> {
+        let mut doc = self.require_document(relative_path)?;
+        let byte = text_range::line_column_to_byte(&doc.content, &doc.line_end_indices, line, column)?;
+        if let Some(region_lang) = doc.region_at(byte).map(|region| region.lang.clone()) {
+            doc = doc.read_as(&region_lang);
+        }
+        let lang = doc.lang.as_deref().unwrap_or_default();
+        let source = doc.source();
+        let file = TreeSitterFile::try_build(source.as_bytes(), lang)
+            .map_err(|e| anyhow::anyhow!("failed to parse {relative_path}: {e:?}"))?;
+        let Some(token) = file.token_at(byte) else {
+            return Ok(None);
+        };
+
+        let (start, end) = (token.range.start, token.range.end);
+        let start_of_line = text_range::line_start(start.line, &doc.line_end_indices);
+        let line_text = text_range::line_text(&doc.content, &doc.line_end_indices, start.line).unwrap_or_default();
+        let end_of_line = start_of_line + line_text.len();
+        let start_index = text_range::column_of_byte(line_text, start.byte - start_of_line);
+        // a token spanning several lines ends with its first line
+        let end_index = text_range::column_of_byte(line_text, end.byte.min(end_of_line) - start_of_line);
+        Ok(Some(CursorToken {
+            text: doc.content[start.byte..end.byte].to_owned(),
+            kind: token.kind.to_owned(),
+            classification: token.class,
+            line: LineBase::current().number(start.line),
+            start_index,
+            end_index,
+            range: LineBase::current().numbered(token.range),
+        }))
+    }
+
+    /// The hoverable ranges of the file touching the lines from `start_line` to
+    /// `end_line`, both included and numbered from the [`LineBase::current`], such as
+    /// the viewport of an editor. A missing bound leaves that side open.
+    pub fn hoverable_ranges_in(&self, relative_path: &str, start_line: Option<usize>, end_line: Option<usize>) -> Result<Vec<TextRange>> {
+        Ok(self
+            .hoverable_tokens_in(relative_path, start_line, end_line, None)?
+            .into_iter()
+            .map(|token| token.range)
+            .collect())
+    }
+
+    /// Like [`Self::hoverable_ranges_in`], with the text of every range. The file is
+    /// read as `lang` if given, instead of the language it was indexed as.
+    pub fn hoverable_tokens_in(&self, relative_path: &str, start_line: Option<usize>, end_line: Option<usize>, lang: Option<&str>) -> Result<Vec<HoverableToken>> {
+        if let (Some(start_line), Some(end_line)) = (start_line, end_line) {
+            if start_line > end_line {
+                anyhow::bail!("start_line {start_line} is after end_line {end_line}");
+            }
+        }
+        let mut doc = self.require_document(relative_path)?;
+        if let Some(lang) = lang {
+            doc = doc.read_as(lang);
+        }
+        let ranges = doc.hoverable_ranges().ok_or(anyhow::anyhow!("Hoverable ranges not found"))?;
+        Ok(hoverable_tokens(&doc, ranges)
+            .into_iter()
+            .filter(|token| start_line.map_or(true, |start_line| token.range.end.line >= start_line))
+            .filter(|token| end_line.map_or(true, |end_line| token.range.start.line <= end_line))
+            .collect())
+    }
+
+    /// The hoverable ranges of every file of `relative_paths`, in order, their
+    /// documents loaded with a single query. Fails if one of them isn't indexed.
+    pub fn hoverable_ranges_bulk(&self, relative_paths: &[String]) -> Result<Vec<(String, Vec<TextRange>)>> {
+        Ok(self
+            .hoverable_tokens_bulk(relative_paths, None)?
+            .into_iter()
+            .map(|(path, tokens)| (path, tokens.into_iter().map(|token| token.range).collect()))
+            .collect())
+    }
+
+    /// Like [`Self::hoverable_ranges_bulk`], with the text of every range, every file
+    /// read as `lang` if given.
+    pub fn hoverable_tokens_bulk(&self, relative_paths: &[String], lang: Option<&str>) -> Result<Vec<(String, Vec<HoverableToken>)>> {
+        let query = BooleanQuery::new(
+            relative_paths
+                .iter()
+                .map(|path| {
+                    let term = Term::from_field_text(self.path_field, path);
+                    (Occur::Should, Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as Box<dyn Query>)
+                })
+                .collect(),
+        );
+        let docs = self
+            .documents_matching(&query)?
+            .into_iter()
+            .map(|doc| match lang {
+                Some(lang) => doc.read_as(lang),
+                None => doc,
+            })
+            .map(|doc| (doc.relative_path.clone(), doc))
+            .collect::<HashMap<_, _>>();
+
+        let mut tokens = Vec::new();
+        for path in relative_paths {
+            let doc = match docs.get(path) {
+                Some(doc) => doc,
+                None => return Err(self.missing_document(path)?.into()),
+            };
+            let file_ranges = doc
+                .hoverable_ranges()
+                .ok_or_else(|| anyhow::anyhow!("Hoverable ranges not found for {path}"))?;
+            tokens.push((path.clone(), hoverable_tokens(doc, file_ranges)));
+        }
+        Ok(tokens)
+    }
+
+    /// The JSON objects of `tokens`, with the lines, columns and bytes of both ends of
+    /// their ranges, their text and their kind.
+    pub fn format_hoverable_ranges(tokens: Vec<HoverableToken>) -> Vec<serde_json::Value> {
+        tokens
+            .into_iter()
+            .map(|HoverableToken { range, text, kind }| {
+                serde_json::json!({
+                    "start_line": range.start.line,
+                    "start_column": range.start.column,
+                    "start_byte": range.start.byte,
+                    "end_line": range.end.line,
+                    "end_column": range.end.column,
+                    "end_byte": range.end.byte,
+                    "text": text,
+                    "kind": kind,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The text and kind of the hoverable `ranges` of `doc`, numbered from the
+/// [`LineBase::current`]. The kinds of a template come from the scope graphs of its
+/// regions.
+fn hoverable_tokens(doc: &ContentDocument, ranges: Vec<TextRange>) -> Vec<HoverableToken> {
+    // the kinds of the nodes of the scope graphs, by the bytes they span
+    let mut kinds = HashMap::new();
+    for view in doc.by_language() {
+        let Some(scope_graph) = view.symbol_locations.scope_graph() else {
+            continue;
+        };
+        kinds.extend(scope_graph.graph.node_indices().filter_map(|idx| {
+            let kind = match scope_graph.get_node(idx)? {
+                NodeKind::Def(_) => HoverKind::Definition,
+                NodeKind::Import(_) => HoverKind::Reference,
+                NodeKind::Ref(_) if scope_graph.definitions(idx).chain(scope_graph.imports(idx)).next().is_some() => HoverKind::Reference,
+                NodeKind::Ref(_) | NodeKind::Scope(_) => return None,
+            };
+            let range = scope_graph.graph[idx].range();
+            Some(((range.start.byte, range.end.byte), kind))
+        }));
+    }
+
+    let base = LineBase::current();
+    ranges
+        .into_iter()
+        .map(|range| HoverableToken {
+            text: doc.content.get(range.start.byte..range.end.byte).unwrap_or_default().to_owned(),
+            kind: kinds.get(&(range.start.byte, range.end.byte)).copied().unwrap_or(HoverKind::Unresolved),
+            range: base.numbered(range),
+        })
+        .collect()
+}
+
+/// The values of the `lang` field of the documents of `lang`: its canonical name, and
+/// the language identifier indexes stored before names were normalized, e.g. `Rust`.
+fn stored_langs(lang: &str) -> Vec<String> {
+    let lang = TSLanguage::normalize(lang);
+    let legacy = match TSLanguage::from_id(&lang) {
+        Language::Supported(config) => Some(config.language_ids[0].to_owned()),
+        Language::Unsupported => None,
+    };
+    std::iter::once(lang).chain(legacy).collect()
+}
+
+/// Characters with a meaning in the query syntax of `text_search`
+const QUERY_SYNTAX_CHARS: &str = "+-&|!(){}[]^\"~*?:\\/";
+
+/// Describes why `query` couldn't be parsed, pointing at its first syntax character.
+fn invalid_query(query: &str, error: tantivy::query::QueryParserError) -> NavError {
+    let mut nav_error = NavError::new(ErrorCode::InvalidQuery, format!("Invalid query `{query}`: {error}"))
+        .suggest("Use multi_term_search with the text as its single term to match it literally");
+
+    if let Some(position) = query.chars().position(|c| QUERY_SYNTAX_CHARS.contains(c)) {
+        nav_error = nav_error.at(position);
+    }
+    nav_error
+}
+
+/// Describes a missing document, suggesting an indexed path that ends with, or has
+/// the file name of, `relative_path`.
+fn document_not_found(relative_path: &str, paths: &[String], is_stored: impl Fn(&str) -> bool) -> NavError {
+    let nav_error = NavError::new(ErrorCode::DocumentNotFound, format!("Source document `{relative_path}` not found"));
+
+    let wanted = relative_path.trim_start_matches("./").replace('\\', "/");
+    let file_name = wanted.rsplit('/').next().unwrap_or_default();
+    let candidate = paths
+        .iter()
+        .filter(|path| path.ends_with(&format!("/{wanted}")))
+        .find(|path| is_stored(path))
+        .or_else(|| paths.iter().filter(|path| path.rsplit('/').next() == Some(file_name)).find(|path| is_stored(path)));
+
+    match candidate {
+        Some(path) => nav_error.suggest(format!("Did you mean `{path}`?")),
+        None => nav_error.suggest("Paths are absolute, as returned by the search functions"),
+    }
+}
+
+/// A match of one of the needles of a search on a line of a stored document
+struct LineHit {
+    /// The index of the needle that matched
+    needle: usize,
+    /// 0-indexed
+    line_idx: usize,
+    line_number: usize,
+    column: usize,
+    /// The bytes of the stored content that matched
+    highlight: Range<usize>,
+}
+
+/// Every line of `content` matching one of `needles`, once per needle, numbered from
+/// the [`LineBase::current`].
+///
+/// The index only tells which documents match, lowercased: case-folding can change
+/// the length of the text, e.g. `İ` is 2 bytes and its lowercase `i̇` 3. Matches are
+/// found again in the stored content, and the column and highlight of a hit both
+/// come from that one match.
+fn line_hits<'a>(content: &'a str, line_end_indices: &'a [u32], needles: &'a [Regex]) -> impl Iterator<Item = LineHit> + 'a {
+    let base = LineBase::current();
+    (0..line_end_indices.len()).flat_map(move |line_idx| {
+        let start = text_range::line_start(line_idx, line_end_indices);
+        let line = text_range::line_text(content, line_end_indices, line_idx).unwrap_or_default();
+        needles.iter().enumerate().filter_map(move |(needle, regex)| {
+            let found = regex.find(line)?;
+            Some(LineHit {
+                needle,
+                line_idx,
+                line_number: base.number(line_idx),
+                column: found.start(),
+                highlight: start + found.start()..start + found.end(),
+            })
+        })
+    })
+}
+
+/// The hits of the first of `needles` needles with a hit of every other needle at most
+/// `max_lines` lines away, each highlighting up to the nearest of them.
+fn near_hits(hits: Vec<LineHit>, needles: usize, max_lines: usize) -> Vec<LineHit> {
+    let (anchors, others): (Vec<_>, Vec<_>) = hits.into_iter().partition(|hit| hit.needle == 0);
+    anchors
+        .into_iter()
+        .filter_map(|mut anchor| {
+            for needle in 1..needles {
+                let nearest = others
+                    .iter()
+                    .filter(|hit| hit.needle == needle)
+                    .map(|hit| (hit.line_idx.abs_diff(anchor.line_idx), hit))
+                    .filter(|(distance, _)| *distance <= max_lines)
+                    .min_by_key(|(distance, _)| *distance)?
+                    .1;
+                anchor.highlight = anchor.highlight.start.min(nearest.highlight.start)..anchor.highlight.end.max(nearest.highlight.end);
+            }
+            Some(anchor)
+        })
+        .collect()
+}
+
+/// The symbols of `symbols` defined on the 0-indexed line `line_idx` of `content`.
+fn defined_on(symbols: &[Symbol], content: &str, line_idx: usize) -> Vec<DefinedSymbol> {
+    let base = LineBase::current();
+    symbols
+        .iter()
+        .filter(|symbol| symbol.range.start.line == line_idx)
+        .filter_map(|symbol| {
+            Some(DefinedSymbol {
+                name: content.get(symbol.range.start.byte..symbol.range.end.byte)?.to_owned(),
+                kind: symbol.kind.clone(),
+                range: base.numbered(symbol.range),
+            })
+        })
+        .collect()
+}
+
+/// A regex matching `text` literally, ignoring case unless `case_sensitive`.
+fn literal_regex(text: &str, case_sensitive: bool) -> Result<Regex> {
+    Ok(RegexBuilder::new(&regex::escape(text))
+        .case_insensitive(!case_sensitive)
+        .build()?)
+}
+
+/// A symbol name close to a fuzzy lookup, see [`Searcher::fuzzy_symbols`].
+struct CloseSymbol {
+    name: String,
+    distance: usize,
+    paths: Vec<String>,
+}
+
+/// Sorts `symbols` for the lowercased `query`: the names starting with more of its chars
+/// first, ignoring case, then those fewer edits away, then shorter names.
+fn rank_by_prefix(query: &str, symbols: &mut [CloseSymbol]) {
+    let shared_prefix = |name: &str| name.chars().flat_map(char::to_lowercase).zip(query.chars()).take_while(|(a, b)| a == b).count();
+    symbols.sort_by_cached_key(|symbol| (std::cmp::Reverse(shared_prefix(&symbol.name)), symbol.distance, symbol.name.len(), symbol.name.clone()));
+}
+
+/// Levenshtein distance between two strings, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// The documents that may have a line matching the regex `pattern`: those with a term
+/// holding each of its [`required_words`], or every document if it has none.
+fn regex_candidates(pattern: &str, field: Field) -> Result<Box<dyn Query>> {
+    let words = required_words(pattern)
+        .iter()
+        // the index only stores lowercased tokens, which may go on around the word
+        .map(|word| Ok((Occur::Must, Box::new(RegexQuery::from_pattern(&format!(".*{}.*", regex::escape(&word.to_lowercase())), field)?) as Box<dyn Query>)))
+        .collect::<Result<Vec<_>>>()?;
+
+    if words.is_empty() {
+        return Ok(Box::new(AllQuery));
     }
+    Ok(Box::new(BooleanQuery::new(words)))
+}
+
+/// The words of the literal runs of the sequence the regex `pattern` is, which every
+/// match holds: `fn` and `handler` for `fn\s+\w+_handler`, none for an alternation.
+fn required_words(pattern: &str) -> Vec<String> {
+    use regex_syntax::hir::{HirKind, Literal};
+
+    let Ok(mut hir) = regex_syntax::Parser::new().parse(pattern) else {
+        return Vec::new();
+    };
+    while let HirKind::Group(group) = hir.kind() {
+        hir = (*group.hir).clone();
+    }
+    let parts = match hir.kind() {
+        HirKind::Concat(parts) => parts.clone(),
+        _ => vec![hir],
+    };
 
-    pub fn format_hoverable_ranges(ranges: Vec<TextRange>) -> Vec<HashMap<String, u32>> {
-        let mut formatted_ranges = Vec::new();
-        for range in ranges {
-            let mut range_map = HashMap::new();
-            range_map.insert("start_line".to_string(), range.start.line as u32);
-            range_map.insert("start_column".to_string(), range.start.column as u32);
-            range_map.insert("end_line".to_string(), range.end.line as u32);
-            range_map.insert("end_column".to_string(), range.end.column as u32);
-            formatted_ranges.push(range_map);
+    // the runs of literal chars, each split around anything else of the sequence
+    let mut runs = vec![String::new()];
+    for part in &parts {
+        match part.kind() {
+            HirKind::Literal(Literal::Unicode(c)) => runs.last_mut().unwrap().push(*c),
+            _ => runs.push(String::new()),
         }
-        formatted_ranges
     }
+    runs.iter()
+        .flat_map(|run| run.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Translate a wildcard pattern into a regex, where `*` and `?` match any number of,
+/// or exactly one, `wildcard_class`.
+fn wildcard_to_regex(pattern: &str, wildcard_class: &str) -> String {
+    pattern
+        .split_inclusive(|c: char| c == '*' || c == '?')
+        .map(|part| match part.strip_suffix('*') {
+            Some(literal) => format!("{}{wildcard_class}*", regex::escape(literal)),
+            None => match part.strip_suffix('?') {
+                Some(literal) => format!("{}{wildcard_class}", regex::escape(literal)),
+                None => regex::escape(part),
+            },
+        })
+        .collect()
+}
+
+/// The documents searched by [`Searcher::doc_search`], unless the query sets another limit
+pub const DOC_SEARCH_DOC_LIMIT: usize = 50;
+
+/// The lowercased words of `text`, split as the `symbol_docs` field is tokenized.
+fn doc_words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Build a matcher for a user supplied path glob.
+///
+/// Stored paths are absolute, so relative globs such as `src/**/*.rs` are anchored
+/// anywhere in the path.
+pub fn path_matcher(glob: &str) -> Result<GlobMatcher> {
+    let glob = if glob.starts_with('/') || glob.starts_with("**") {
+        glob.to_owned()
+    } else {
+        format!("**/{glob}")
+    };
+    Ok(Glob::new(&glob)?.compile_matcher())
 }
 
 #[cfg(test)]
@@ -465,6 +2725,82 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("initialise_db", "initialize_db"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn prefix_ranking() {
+        let symbol = |name: &str, distance| CloseSymbol { name: name.to_owned(), distance, paths: Vec::new() };
+        let mut symbols = vec![symbol("load_config", 1), symbol("lead_config", 1), symbol("LoadConfig", 0), symbol("read_config", 1)];
+        rank_by_prefix("loadconfig", &mut symbols);
+        let names = symbols.iter().map(|symbol| symbol.name.as_str()).collect::<Vec<_>>();
+        // `LoadConfig` shares the whole query, `load_config` its first four chars
+        assert_eq!(names, ["LoadConfig", "load_config", "lead_config", "read_config"]);
+    }
+
+    #[test]
+    fn wildcard_patterns() {
+        assert_eq!(wildcard_to_regex("get_*_config", r"\w"), r"get_\w*_config");
+        assert_eq!(wildcard_to_regex("conf?g", "."), "conf.g");
+        assert_eq!(wildcard_to_regex("a.b*", "."), r"a\.b.*");
+
+        let re = Regex::new(&wildcard_to_regex("get_*_config", r"\w")).unwrap();
+        assert!(re.is_match("let c = get_db_config();"));
+        assert!(!re.is_match("get_db = load_config();"));
+    }
+
+    #[test]
+    fn hits_keep_offsets_of_the_stored_content() -> Result<()> {
+        // `İ` lowercases to the longer `i̇`: offsets into a lowercased copy would be off
+        let content = "// header\nlet İİ = \"İSTANBUL\";\nİ(istanbul);\n";
+        let line_ends = text_range::line_end_indices(content);
+        let needles = [literal_regex("stanbul", false)?, literal_regex("İ(", true)?];
+
+        let hits = line_hits(content, &line_ends, &needles)
+            .map(|hit| (hit.needle, hit.line_number, &content[hit.highlight]))
+            .collect::<Vec<_>>();
+        assert_eq!(hits, [(0, 2, "STANBUL"), (0, 3, "stanbul"), (1, 3, "İ(")]);
+        Ok(())
+    }
+
+    #[test]
+    fn words_required_by_regexes() {
+        assert_eq!(required_words(r"fn\s+\w+_handler"), ["fn", "handler"]);
+        assert_eq!(required_words(r"(parse_config\(\))"), ["parse", "config"]);
+        assert_eq!(required_words(r"colou?r"), ["colo", "r"]);
+        assert!(required_words(r"foo|bar").is_empty());
+        assert!(required_words(r"\d+").is_empty());
+    }
+
+    #[tokio::test]
+    async fn empty_terms_match_nothing() -> Result<()> {
+        let dir = tempdir::TempDir::new("empty_terms")?;
+        let (root, index_path) = (dir.path().join("repo"), dir.path().join("index"));
+        std::fs::create_dir_all(&root)?;
+        std::fs::create_dir_all(&index_path)?;
+        std::fs::write(root.join("lib.rs"), "fn foo() {}\nfn bar() {}\n")?;
+        Indexes::new(&index_path, 15_000_000, 1).await?.index(&root).await?;
+
+        let searcher = Searcher::new(&index_path)?;
+        let matches = searcher.multi_term_search(&["foo".to_owned(), String::new()], true, None)?;
+        let matches = matches.iter().map(|hit| (hit.term.as_str(), hit.result.line_number)).collect::<Vec<_>>();
+        assert_eq!(matches, [("foo", 1)]);
+        assert!(searcher.multi_term_search(&[String::new()], true, None)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn stored_languages() {
+        assert_eq!(stored_langs("Rust"), ["rust", "Rust"]);
+        assert_eq!(stored_langs("cpp"), ["c++", "C++"]);
+        assert_eq!(stored_langs("plaintext"), ["plaintext"]);
+    }
+
     #[tokio::test]
     async fn test_searcher_with_test_files() -> Result<()> {
         let root_path = Path::new("./test_files");
@@ -484,13 +2820,13 @@ mod tests {
 
         // Create a searcher and perform a search
         let searcher = Searcher::new(index_path)?;
-        let result = searcher.text_search("indexes", true)?;
+        let result = searcher.text_search("indexes", true, None)?;
 
         // Print out the results (or you can write assertions here)
         for res in result {
             println!(
                 "File: {}, Line: {}, Column: {}, Context: {}",
-                res.path, res.line_number, res.column, res.context
+                res.path, res.line_number, res.column, res.snippet.to_text()
             );
         }
 
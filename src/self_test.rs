@@ -0,0 +1,176 @@
+//! A check that the extension works where it's installed, for embedders to run before
+//! relying on it: a tiny built-in fixture is indexed into a temporary directory, then
+//! searched and navigated.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::format::FORMAT_VERSION;
+use crate::indexes::Indexes;
+use crate::search::Searcher;
+use crate::text_range::LineBase;
+
+/// The file indexed by the self-test
+const FIXTURE_PATH: &str = "self_test.py";
+
+const FIXTURE: &str = "def self_test_target():\n    return \"self_test_marker\"\n\n\nprint(self_test_target())\n";
+
+/// The call of `self_test_target` on the last line of the fixture: its 0-indexed line
+/// and its columns
+const CALL: (usize, usize, usize) = (4, 6, 22);
+
+/// The outcome of one step of the self-test
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    /// What was found, or why the step failed or was skipped
+    pub detail: String,
+}
+
+/// The outcome of every step of the self-test, in order
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTest {
+    /// The version of the crate
+    pub version: &'static str,
+    pub format_version: u32,
+    pub checks: Vec<Check>,
+}
+
+impl SelfTest {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+}
+
+/// Index the fixture, search it and go to a definition, in a temporary directory
+/// removed afterwards. A step that fails skips the steps that depend on it.
+pub async fn run() -> SelfTest {
+    let dir = std::env::temp_dir().join(unique_name());
+    let checks = run_in(&dir).await;
+    let _ = fs::remove_dir_all(&dir);
+
+    SelfTest {
+        version: env!("CARGO_PKG_VERSION"),
+        format_version: FORMAT_VERSION,
+        checks,
+    }
+}
+
+async fn run_in(dir: &Path) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let indexed = index(dir).await;
+    let searcher = record(&mut checks, "index", indexed);
+
+    let Some((searcher, fixture)) = searcher else {
+        skip(&mut checks, &["search", "go_to"], "index");
+        return checks;
+    };
+    record(&mut checks, "search", search(&searcher, &fixture));
+    record(&mut checks, "go_to", go_to(&searcher, &fixture));
+    checks
+}
+
+/// Push the check `name` for `result`, returning its value if it passed.
+fn record<T>(checks: &mut Vec<Check>, name: &'static str, result: Result<(T, String)>) -> Option<T> {
+    let (value, check) = match result {
+        Ok((value, detail)) => (Some(value), Check { name, passed: true, detail }),
+        Err(e) => (None, Check { name, passed: false, detail: format!("{e:#}") }),
+    };
+    checks.push(check);
+    value
+}
+
+fn skip(checks: &mut Vec<Check>, names: &[&'static str], failed: &str) {
+    checks.extend(names.iter().map(|&name| Check {
+        name,
+        passed: false,
+        detail: format!("skipped, `{failed}` failed"),
+    }));
+}
+
+/// Write the fixture under `dir` and index it, returning a searcher of the index and
+/// the path the fixture is stored under.
+async fn index(dir: &Path) -> Result<((Searcher, String), String)> {
+    let root = dir.join("repo");
+    let index_path = dir.join("index");
+    fs::create_dir_all(&root).context("failed to create the fixture")?;
+    fs::create_dir_all(&index_path).context("failed to create the index directory")?;
+    fs::write(root.join(FIXTURE_PATH), FIXTURE).context("failed to write the fixture")?;
+    // stored paths are canonical, e.g. with the `/tmp` symlink of macOS resolved
+    let root = root.canonicalize()?;
+
+    let indexes = Indexes::new(&index_path, 15_000_000, 1).await?;
+    indexes.index(&root).await?;
+    let searcher = Searcher::new(&index_path)?;
+
+    let num_docs = searcher.num_docs();
+    if num_docs != 1 {
+        bail!("indexed {num_docs} files instead of 1");
+    }
+    let fixture = stored_path(&root.join(FIXTURE_PATH));
+    Ok(((searcher, fixture), format!("indexed {FIXTURE_PATH}")))
+}
+
+fn search(searcher: &Searcher, fixture: &str) -> Result<((), String)> {
+    let results = searcher.text_search("self_test_marker", true, None)?;
+    let [result] = results.as_slice() else {
+        bail!("found {} matches of `self_test_marker` instead of 1", results.len());
+    };
+    let expected = LineBase::current().number(1);
+    if result.path != fixture || result.line_number != expected {
+        bail!("found `self_test_marker` at {}:{}, expected line {expected}", result.path, result.line_number);
+    }
+    Ok(((), format!("found `self_test_marker` on line {expected}")))
+}
+
+fn go_to(searcher: &Searcher, fixture: &str) -> Result<((), String)> {
+    let base = LineBase::current();
+    let (line, start, end) = CALL;
+    let results = searcher.token_info(fixture, base.number(line), start, end, None)?;
+
+    let definition = results
+        .iter()
+        .filter(|file_symbols| file_symbols.file == fixture)
+        .flat_map(|file_symbols| &file_symbols.data)
+        .find(|occurrence| occurrence.is_definition())
+        .context("no definition of `self_test_target` from its call")?;
+    let expected = base.number(0);
+    if definition.range.start.line != expected {
+        bail!(
+            "found the definition of `self_test_target` on line {}, expected line {expected}",
+            definition.range.start.line
+        );
+    }
+    Ok(((), format!("resolved `self_test_target` to its definition on line {expected}")))
+}
+
+/// `path` as the index stores it.
+fn stored_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn unique_name() -> PathBuf {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.subsec_nanos());
+    PathBuf::from(format!("code_nav_devon_self_test.{}.{nanos}", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn passes() {
+        let report = run().await;
+        assert!(report.passed(), "{:#?}", report.checks);
+        assert_eq!(
+            report.checks.iter().map(|check| check.name).collect::<Vec<_>>(),
+            ["index", "search", "go_to"]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::try_join_all;
+use ignore::WalkBuilder;
+
+use crate::file::File;
+use crate::indexes::Indexes;
+use crate::intelligence::PLAINTEXT;
+use crate::query::Query;
+use crate::search::{SearchResponse, Searcher};
+use crate::text_range::LineBase;
+
+/// Shard holding the files directly inside the root, when sharding by directory
+pub const ROOT_SHARD: &str = "_root";
+
+/// Directory, inside the index directory, holding one sub-index per shard
+const SHARDS_DIR: &str = "shards";
+
+/// How a repository is split into separately indexed shards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardBy {
+    /// One shard per language, named after it, e.g. `rust`
+    Language,
+    /// One shard per top-level directory, plus [`ROOT_SHARD`]
+    TopLevelDir,
+}
+
+impl FromStr for ShardBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "language" => Ok(Self::Language),
+            "directory" => Ok(Self::TopLevelDir),
+            s => Err(anyhow::anyhow!("invalid shard_by `{s}`, expected `language` or `directory`")),
+        }
+    }
+}
+
+impl ShardBy {
+    /// The shard that the file at `path`, inside `root`, belongs to.
+    pub fn shard_of(self, root: &Path, path: &Path) -> String {
+        match self {
+            Self::Language => File::detect_language(path).to_owned(),
+            Self::TopLevelDir => {
+                let mut components = path.strip_prefix(root).unwrap_or(path).components();
+                match (components.next(), components.next()) {
+                    (Some(dir), Some(_)) => dir.as_os_str().to_string_lossy().into_owned(),
+                    _ => ROOT_SHARD.to_owned(),
+                }
+            }
+        }
+    }
+
+    /// The shards of the repository at `root`. Only paths are looked at, no file is read.
+    pub fn discover(self, root: &Path) -> Result<Vec<String>> {
+        let walk = WalkBuilder::new(root)
+            .standard_filters(false)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+
+        let mut shards = Vec::new();
+        for entry in walk {
+            let entry = entry?;
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+
+            let shard = self.shard_of(root, entry.path());
+            // plaintext files are never indexed
+            if shard != PLAINTEXT && !shards.contains(&shard) {
+                shards.push(shard);
+            }
+        }
+
+        shards.sort();
+        Ok(shards)
+    }
+}
+
+/// An index split into per-language or per-directory sub-indexes.
+///
+/// Every shard has its own writer, so shards are indexed concurrently and one shard
+/// can be rebuilt without touching the others.
+pub struct ShardedIndexes {
+    index_path: PathBuf,
+    shard_by: ShardBy,
+    buffer_size: usize,
+    threads: usize,
+}
+
+impl ShardedIndexes {
+    pub fn new(index_path: &Path, shard_by: ShardBy, buffer_size: usize, threads: usize) -> Self {
+        Self {
+            index_path: index_path.to_path_buf(),
+            shard_by,
+            buffer_size,
+            threads,
+        }
+    }
+
+    fn shard_path(&self, shard: &str) -> PathBuf {
+        self.index_path.join(SHARDS_DIR).join(shard)
+    }
+
+    /// Bring every shard of the repository at `root_path` up to date.
+    pub async fn index(&self, root_path: &Path) -> Result<()> {
+        let shards = self.shard_by.discover(root_path)?;
+        try_join_all(shards.iter().map(|shard| self.index_shard(root_path, shard))).await?;
+        Ok(())
+    }
+
+    /// Bring the single shard named `shard` up to date.
+    pub async fn index_shard(&self, root_path: &Path, shard: &str) -> Result<()> {
+        let root = root_path.to_path_buf();
+        let shard_by = self.shard_by;
+        let name = shard.to_owned();
+        let file = File::new().with_path_filter(Arc::new(move |path: &Path| shard_by.shard_of(&root, path) == name));
+
+        let indexes = Indexes::with_source(file, &self.shard_path(shard), self.buffer_size, self.threads).await?;
+        indexes.index(root_path).await?;
+        Ok(())
+    }
+
+    /// Open a searcher over every shard indexed so far.
+    pub fn searcher(&self) -> Result<ShardedSearcher> {
+        let mut shards = Vec::new();
+
+        let shards_dir = self.index_path.join(SHARDS_DIR);
+        if shards_dir.is_dir() {
+            for entry in std::fs::read_dir(shards_dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    shards.push((name, Searcher::new(&entry.path())?));
+                }
+            }
+        }
+
+        shards.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(ShardedSearcher { shards })
+    }
+}
+
+/// Searches all shards of a [`ShardedIndexes`] in parallel and merges the results.
+pub struct ShardedSearcher {
+    shards: Vec<(String, Searcher)>,
+}
+
+impl ShardedSearcher {
+    /// The searcher over a single shard.
+    pub fn shard(&self, name: &str) -> Option<&Searcher> {
+        self.shards
+            .iter()
+            .find(|(shard, _)| shard == name)
+            .map(|(_, searcher)| searcher)
+    }
+
+    pub fn search(&self, query: &Query) -> Result<SearchResponse> {
+        // the shards number their lines like the calling thread
+        let base = LineBase::current();
+        let responses = std::thread::scope(|scope| {
+            let handles = self
+                .shards
+                .iter()
+                .map(|(_, searcher)| scope.spawn(move || base.scope(|| searcher.search(query))))
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("shard search panicked"))
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        let mut merged = SearchResponse {
+            hits: Vec::new(),
+            suggestions: Vec::new(),
+        };
+        for response in responses {
+            merged.hits.extend(response.hits);
+            for suggestion in response.suggestions {
+                if !merged.suggestions.contains(&suggestion) {
+                    merged.suggestions.push(suggestion);
+                }
+            }
+        }
+
+        if let Some(limit) = query.limit {
+            merged.hits.truncate(limit);
+        }
+        // suggestions only make sense when no shard has a hit
+        if !merged.hits.is_empty() {
+            merged.suggestions.clear();
+        }
+        merged.suggestions.truncate(3);
+
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shard_of_path() {
+        let root = Path::new("/repo");
+
+        assert_eq!(ShardBy::TopLevelDir.shard_of(root, Path::new("/repo/src/main.rs")), "src");
+        assert_eq!(ShardBy::TopLevelDir.shard_of(root, Path::new("/repo/build.rs")), ROOT_SHARD);
+        assert_eq!(ShardBy::Language.shard_of(root, Path::new("/repo/src/main.rs")), "rust");
+        assert_eq!(ShardBy::Language.shard_of(root, Path::new("/repo/notes.txt")), "plaintext");
+    }
+
+    #[test]
+    fn parse_shard_by() {
+        assert_eq!("directory".parse::<ShardBy>().unwrap(), ShardBy::TopLevelDir);
+        assert!("size".parse::<ShardBy>().is_err());
+    }
+}
@@ -1,10 +1,11 @@
 use anyhow::Result;
 use regex::{Regex, RegexBuilder};
+use serde::ser::{SerializeStruct, Serializer};
 use serde::Serialize;
 use smallvec::{smallvec, SmallVec};
 
-use crate::{content_document::ContentDocument, symbol::Symbol};
-use std::ops::Range;
+use crate::{content_document::ContentDocument, intelligence::TSLanguage, symbol::Symbol, text_range::{self, LineBase}};
+use std::{fmt, ops::Range, str::FromStr};
 
 #[derive(Serialize, Debug, PartialEq, Eq)]
 pub struct SnippedFile {
@@ -13,12 +14,114 @@ pub struct SnippedFile {
     pub snippets: Vec<Snippet>,
 }
 
-#[derive(Serialize, Debug, PartialEq, Eq)]
+/// A run of whole lines cut from a file, with the ranges to highlight in it.
+///
+/// Every formatter renders snippets through [`Snippet::to_text`], [`Snippet::to_markdown`]
+/// or their JSON serialization, which lists the numbered lines.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Snippet {
+    /// The file the snippet comes from, once known, see [`Snippet::with_origin`]
+    pub file: Option<String>,
+    pub lang: Option<String>,
     pub data: String,
+    /// Byte ranges, in `data`
     pub highlights: Vec<Range<usize>>,
     pub symbols: Vec<Symbol>,
+    /// 0-indexed lines of the file, both ends included
     pub line_range: Range<usize>,
+    /// Whether the file has lines before the snippet
+    pub truncated_before: bool,
+    /// Whether the file has lines after the snippet
+    pub truncated_after: bool,
+}
+
+impl Snippet {
+    /// Record the file the snippet was cut from.
+    pub fn with_origin(mut self, file: &str, lang: Option<&str>) -> Self {
+        self.file = Some(file.to_owned());
+        self.lang = lang.filter(|lang| !lang.is_empty()).map(TSLanguage::normalize);
+        self
+    }
+
+    /// The number of the first line, counted from the [`LineBase::current`].
+    pub fn start_line(&self) -> usize {
+        LineBase::current().number(self.line_range.start)
+    }
+
+    /// The lines of the snippet, with their numbers.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, &str)> {
+        (self.start_line()..).zip(self.data.split('\n'))
+    }
+
+    /// The snippet as it appears in the file.
+    pub fn to_text(&self) -> String {
+        self.data.clone()
+    }
+
+    /// The snippet with every line prefixed by its number.
+    pub fn to_numbered_text(&self) -> String {
+        let last_line = self.start_line() + self.data.matches('\n').count();
+        let width = last_line.to_string().len();
+
+        self.lines()
+            .map(|(number, line)| format!("{number:>width$} | {line}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// A fenced code block tagged with the language, under a `path:line` header.
+    pub fn to_markdown(&self) -> String {
+        match &self.file {
+            Some(file) => format!("`{}:{}`\n{}", file, self.start_line(), self.to_code_block()),
+            None => self.to_code_block(),
+        }
+    }
+
+    /// A fenced code block tagged with the language.
+    pub fn to_code_block(&self) -> String {
+        // a fence longer than any run of backticks in the snippet
+        let longest_run = self
+            .data
+            .split(|c| c != '`')
+            .map(str::len)
+            .max()
+            .unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+
+        format!(
+            "{fence}{}\n{}\n{fence}",
+            self.lang.as_deref().unwrap_or_default(),
+            self.data
+        )
+    }
+}
+
+impl Serialize for Snippet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Line<'a> {
+            number: usize,
+            text: &'a str,
+        }
+
+        let lines = self
+            .lines()
+            .map(|(number, text)| Line { number, text })
+            .collect::<Vec<_>>();
+
+        let mut state = serializer.serialize_struct("Snippet", 9)?;
+        state.serialize_field("file", &self.file)?;
+        state.serialize_field("lang", &self.lang)?;
+        state.serialize_field("start_line", &self.start_line())?;
+        state.serialize_field("lines", &lines)?;
+        state.serialize_field("highlights", &self.highlights)?;
+        state.serialize_field("symbols", &self.symbols)?;
+        let base = LineBase::current();
+        state.serialize_field("line_range", &(base.number(self.line_range.start)..base.number(self.line_range.end)))?;
+        state.serialize_field("truncated_before", &self.truncated_before)?;
+        state.serialize_field("truncated_after", &self.truncated_after)?;
+        state.end()
+    }
 }
 
 /// A marker indicating a subset of some source text, with a list of highlighted ranges.
@@ -47,8 +150,8 @@ impl Location {
             return Ok(());
         }
 
-        // Fail if the locations don't overlap.
-        if self.line_range.end < rhs.line_range.start {
+        // Fail if the locations don't overlap, or come out of order.
+        if self.line_range.end < rhs.line_range.start || rhs.byte_range.start < self.byte_range.start {
             return Err(rhs);
         }
 
@@ -68,8 +171,13 @@ impl Location {
     /// Reify this `Location` into a `Snippet`, given the source string and symbols list.
     pub fn reify(self, s: &str, symbols: &[Symbol]) -> Snippet {
         Snippet {
+            file: None,
+            lang: None,
             data: s[self.byte_range.clone()].to_owned(),
             line_range: self.line_range.clone(),
+            truncated_before: self.byte_range.start > 0,
+            // the snippet stops short of the newline ending its last line
+            truncated_after: s.len() > self.byte_range.end + 1,
             highlights: self.highlights.into_vec(),
             symbols: symbols
                 .iter()
@@ -102,6 +210,68 @@ impl SnippedFile {
     }
 }
 
+/// How much of the surrounding source to render around a highlight.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ContextMode {
+    /// A fixed number of lines before and after the highlight, written `lines:N`
+    Lines(usize),
+
+    /// The whole function that encloses the highlight, written `enclosing_function`.
+    ///
+    /// Falls back to the highlighted lines when there is no enclosing function.
+    EnclosingFunction,
+}
+
+impl FromStr for ContextMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "enclosing_function" => Ok(Self::EnclosingFunction),
+            s => s
+                .strip_prefix("lines:")
+                .and_then(|n| n.trim().parse().ok())
+                .map(Self::Lines)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("invalid context `{s}`, expected `lines:N` or `enclosing_function`")
+                }),
+        }
+    }
+}
+
+impl fmt::Display for ContextMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lines(n) => write!(f, "lines:{n}"),
+            Self::EnclosingFunction => write!(f, "enclosing_function"),
+        }
+    }
+}
+
+impl ContextMode {
+    /// Render the context around `highlight` in `text`.
+    ///
+    /// `function_ranges` are the byte ranges of all functions in `text`, and are only
+    /// consulted for [`ContextMode::EnclosingFunction`].
+    pub fn expand(
+        self,
+        highlight: Range<usize>,
+        text: &str,
+        line_ends: &[u32],
+        function_ranges: &[Range<usize>],
+    ) -> Location {
+        match self {
+            Self::Lines(n) => Snipper::default().context(n, n).expand(highlight, text, line_ends),
+            Self::EnclosingFunction => function_ranges
+                .iter()
+                .filter(|r| r.start <= highlight.start && highlight.end <= r.end)
+                .min_by_key(|r| r.len())
+                .map(|scope| Snipper::expand_to_scope(highlight.clone(), scope.clone(), text, line_ends))
+                .unwrap_or_else(|| Snipper::default().expand(highlight, text, line_ends)),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Snipper {
     pub context_before: usize,
@@ -251,12 +421,17 @@ impl Snipper {
         })
     }
 
+    /// The lines of `text` around `highlight`, with up to [`Self::context_before`] lines
+    /// before them and [`Self::context_after`] lines after, fewer at the start or the end
+    /// of the text. A highlight past the end of the text is cut to it. Like every line,
+    /// the last one is cut without the newline ending it, see [`text_range::line_text`].
     pub fn expand<'a>(
         &'a self,
         highlight: Range<usize>,
         text: &'a str,
         line_ends: &'a [u32],
     ) -> Location {
+        let highlight = clamp(highlight, text);
         let start = text[..highlight.start]
             .rmatch_indices('\n')
             .nth(self.context_before)
@@ -267,7 +442,7 @@ impl Snipper {
             .match_indices('\n')
             .nth(self.context_after)
             .map(|(i, _)| i + highlight.end)
-            .unwrap_or(text.len());
+            .unwrap_or_else(|| text_range::last_line_end(text).max(highlight.end));
 
         let line_end = line_ends
             .iter()
@@ -289,6 +464,32 @@ impl Snipper {
     }
 }
 
+impl Snipper {
+    /// Expand `highlight` to the whole lines spanned by `scope`, which should contain it.
+    /// A scope that doesn't, read from a stale parse of the text, is grown to it.
+    pub fn expand_to_scope(
+        highlight: Range<usize>,
+        scope: Range<usize>,
+        text: &str,
+        line_ends: &[u32],
+    ) -> Location {
+        let highlight = clamp(highlight, text);
+        let scope = clamp(scope, text);
+        let scope = scope.start.min(highlight.start)..scope.end.max(highlight.end);
+        let mut location = Snipper::default().expand(scope, text, line_ends);
+        let start = location.byte_range.start;
+        location.highlights = smallvec![(highlight.start - start)..(highlight.end - start)];
+        location
+    }
+}
+
+/// `range` cut to the bytes of `text`, both ends moved back to char boundaries.
+fn clamp(range: Range<usize>, text: &str) -> Range<usize> {
+    let floor = |byte: usize| (0..=byte.min(text.len())).rev().find(|&byte| text.is_char_boundary(byte)).unwrap_or(0);
+    let start = floor(range.start);
+    start..floor(range.end).max(start)
+}
+
 #[derive(Serialize)]
 pub struct HighlightedString {
     pub text: String,
@@ -325,15 +526,11 @@ mod tests {
         }
     }
 
-    /// Test helper to ensure a string is newline-terminated, and also return an array of newline
-    /// indices.
+    /// Test helper to ensure a string is newline-terminated, and also return its line ends as
+    /// they are indexed.
     fn with_line_ends(s: &str) -> (&str, Vec<u32>) {
         assert!(s.ends_with('\n'));
-        let line_ends = s
-            .match_indices('\n')
-            .map(|(i, _)| i as u32)
-            .collect::<Vec<_>>();
-        (s, line_ends)
+        (s, text_range::line_end_indices(s))
     }
 
     #[test]
@@ -350,6 +547,9 @@ mod tests {
                 line_range: 0..0,
                 highlights: vec![0..3],
                 symbols: vec![],
+                truncated_before: false,
+                truncated_after: false,
+                ..Default::default()
             }
         );
     }
@@ -367,6 +567,9 @@ mod tests {
                 line_range: 2..4,
                 highlights: vec![4..7],
                 symbols: vec![],
+                truncated_before: true,
+                truncated_after: true,
+                ..Default::default()
             }
         );
     }
@@ -384,6 +587,9 @@ mod tests {
                 line_range: 0..2,
                 highlights: vec![5..8],
                 symbols: vec![],
+                truncated_before: false,
+                truncated_after: false,
+                ..Default::default()
             }
         );
     }
@@ -401,6 +607,9 @@ mod tests {
                 line_range: 0..2,
                 highlights: vec![4..7],
                 symbols: vec![],
+                truncated_before: false,
+                truncated_after: false,
+                ..Default::default()
             }
         );
     }
@@ -418,6 +627,9 @@ mod tests {
                 line_range: 1..2,
                 highlights: vec![4..7],
                 symbols: vec![],
+                truncated_before: true,
+                truncated_after: true,
+                ..Default::default()
             }
         );
     }
@@ -436,6 +648,9 @@ mod tests {
                 line_range: 0..0,
                 highlights: vec![0..3],
                 symbols: vec![],
+                truncated_before: false,
+                truncated_after: true,
+                ..Default::default()
             }
         );
     }
@@ -453,6 +668,9 @@ mod tests {
                 line_range: 2..3,
                 highlights: vec![0..3],
                 symbols: vec![],
+                truncated_before: true,
+                truncated_after: true,
+                ..Default::default()
             }
         );
     }
@@ -467,14 +685,72 @@ mod tests {
         assert_eq!(
             snipper.expand(highlight, text, &line_ends).reify(text, &[]),
             Snippet {
-                data: "bar\n".to_owned(),
-                line_range: 2..3,
+                data: "bar".to_owned(),
+                line_range: 2..2,
                 highlights: vec![0..3],
                 symbols: vec![],
+                truncated_before: true,
+                truncated_after: false,
+                ..Default::default()
             }
         );
     }
 
+    /// Check that the last line is cut the same with and without a trailing newline, and
+    /// that a text of one line, or none, is its own snippet.
+    #[test]
+    fn last_lines() {
+        let snipper = Snipper::default().context(1, 1);
+        for text in ["a\nbar", "a\nbar\n"] {
+            let line_ends = text_range::line_end_indices(text);
+            let snippet = snipper.expand(2..5, text, &line_ends).reify(text, &[]);
+            assert_eq!((snippet.data.as_str(), snippet.line_range), ("a\nbar", 0..1), "{text:?}");
+            assert!(!snippet.truncated_after, "{text:?}");
+        }
+
+        let snippet = snipper.expand(0..3, "bar", &text_range::line_end_indices("bar")).reify("bar", &[]);
+        assert_eq!((snippet.data.as_str(), snippet.line_range), ("bar", 0..0));
+
+        let snippet = snipper.expand(0..0, "", &text_range::line_end_indices("")).reify("", &[]);
+        assert_eq!((snippet.data.as_str(), snippet.line_range), ("", 0..0));
+    }
+
+    /// Check that highlights past the end of the text, or inside a char, are cut to it.
+    #[test]
+    fn highlight_clamped_to_text() {
+        let (text, line_ends) = with_line_ends("a\nfoé\n");
+        let snipper = Snipper::default().context(2, 2);
+
+        assert_eq!(
+            snipper.expand(2..50, text, &line_ends).reify(text, &[]),
+            Snippet {
+                data: "a\nfoé\n".to_owned(),
+                line_range: 0..2,
+                highlights: vec![2..7],
+                symbols: vec![],
+                truncated_before: false,
+                truncated_after: false,
+                ..Default::default()
+            }
+        );
+
+        // from the second byte of `é`
+        let location = snipper.expand(5..6, text, &line_ends);
+        assert_eq!(location.highlights.as_slice(), &[4..6]);
+
+        let location = Snipper::default().expand(0..0, "", &[]);
+        assert_eq!((location.byte_range, location.line_range), (0..0, 0..0));
+    }
+
+    /// Check that a scope that doesn't contain the highlight is grown to it.
+    #[test]
+    fn scope_grown_to_highlight() {
+        let (text, line_ends) = with_line_ends("a\nfn foo() {}\nz\n");
+        let location = Snipper::expand_to_scope(0..1, 2..13, text, &line_ends);
+        assert_eq!(location.byte_range, 0..13);
+        assert_eq!(location.highlights.as_slice(), &[0..1]);
+    }
+
     #[test]
     fn merge_into_one() {
         let text = &[
@@ -498,7 +774,7 @@ mod tests {
             .collect::<Vec<_>>();
 
         assert_eq!(observed.len(), 1);
-        assert_eq!(observed[0].line_count(), 6);
+        assert_eq!(observed[0].line_count(), 5);
     }
 
     #[test]
@@ -537,11 +813,10 @@ mod tests {
             [
                 r#"pub const INTO: [&str; 4] = ["core", "slice", "<impl [T]>", "iter"];"#,
                 r#"pub const SLICE_ITER: [&str; 4] = ["core", "slice", "iter", "Iter"];"#,
-                ""
             ]
             .join("\n")
         );
-        assert_eq!(observed[1].line_count(), 2);
+        assert_eq!(observed[1].line_count(), 1);
     }
 
     #[test]
@@ -592,6 +867,81 @@ mod tests {
 
 
 
+    #[test]
+    fn parse_context_mode() {
+        assert_eq!("lines:3".parse::<ContextMode>().unwrap(), ContextMode::Lines(3));
+        assert_eq!(
+            "enclosing_function".parse::<ContextMode>().unwrap(),
+            ContextMode::EnclosingFunction
+        );
+        assert!("lines:".parse::<ContextMode>().is_err());
+        assert!("function".parse::<ContextMode>().is_err());
+
+        for mode in [ContextMode::Lines(3), ContextMode::EnclosingFunction] {
+            assert_eq!(mode.to_string().parse::<ContextMode>().unwrap(), mode);
+        }
+    }
+
+    #[test]
+    fn enclosing_function_context() {
+        let (text, line_ends) = with_line_ends("a\nfn foo() {\n    bar();\n}\nz\n");
+        let highlight = 17..20;
+        let functions = [2..25];
+
+        assert_eq!(
+            ContextMode::EnclosingFunction
+                .expand(highlight.clone(), text, &line_ends, &functions)
+                .reify(text, &[]),
+            Snippet {
+                data: "fn foo() {\n    bar();\n}".to_owned(),
+                line_range: 1..3,
+                highlights: vec![15..18],
+                symbols: vec![],
+                truncated_before: true,
+                truncated_after: true,
+                ..Default::default()
+            }
+        );
+
+        // no enclosing function: only the highlighted line is rendered
+        assert_eq!(
+            ContextMode::EnclosingFunction
+                .expand(highlight, text, &line_ends, &[])
+                .reify(text, &[])
+                .data,
+            "    bar();"
+        );
+    }
+
+    #[test]
+    fn snippet_formats() {
+        let (text, line_ends) = with_line_ends("a\nfoo\nbar\nquux\nz\n");
+        let snippet = Snipper::default()
+            .context(1, 0)
+            .expand(6..9, text, &line_ends)
+            .reify(text, &[])
+            .with_origin("src/lib.rs", Some("Rust"));
+
+        assert_eq!(snippet.start_line(), 2);
+        assert_eq!(snippet.lines().collect::<Vec<_>>(), vec![(2, "foo"), (3, "bar")]);
+        assert_eq!(snippet.to_text(), "foo\nbar");
+        assert_eq!(snippet.to_numbered_text(), "2 | foo\n3 | bar");
+        assert_eq!(snippet.to_markdown(), "`src/lib.rs:2`\n```rust\nfoo\nbar\n```");
+
+        let json = serde_json::to_value(&snippet).unwrap();
+        assert_eq!(json["file"], "src/lib.rs");
+        assert_eq!(json["start_line"], 2);
+        assert_eq!(json["lines"][1], serde_json::json!({ "number": 3, "text": "bar" }));
+        assert_eq!(json["truncated_before"], true);
+
+        // the fence outgrows the backticks of the snippet
+        let snippet = Snippet {
+            data: "let s = \"```\";".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(snippet.to_markdown(), "````\nlet s = \"```\";\n````");
+    }
+
     #[test]
     fn test_highlighted_string() {
         let mut s = HighlightedString::new("foo bar quux");
@@ -0,0 +1,269 @@
+//! Indexing files that aren't in a directory of the filesystem: the files of a commit,
+//! of a zip or tar archive, or any other [`FileSource`].
+//!
+//! A source lists its files and reads those the index wants. [`Sourced`] indexes them
+//! as [`File`] would index a directory, with its path filter, excluded directories,
+//! redactor, hash and size cap, under the paths they would have if they were
+//! extracted to the root, e.g. `/work/vendor.zip/src/lib.rs`. The source is the truth
+//! for its root: documents stored under it that it no longer holds are deleted.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path};
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use tantivy::schema::Schema;
+use tantivy::{IndexWriter, Term};
+
+use crate::file::{ExistingDocs, File};
+use crate::indexes::{IndexReport, Indexable, SkippedFile};
+use crate::intelligence::PLAINTEXT;
+use crate::regions;
+
+/// Files to index that don't come from walking a directory
+pub trait FileSource: Send + Sync {
+    /// Pass every file of the source at `root` to `select`, with its path relative to
+    /// `root`, `/`-separated, and its size in bytes, then pass the content of those it
+    /// selects to `read`, in any order.
+    fn read_files(
+        &self,
+        root: &Path,
+        select: &mut dyn FnMut(&str, u64) -> bool,
+        read: &mut dyn FnMut(&str, Vec<u8>) -> Result<()>,
+    ) -> Result<()>;
+}
+
+/// The files of a [`FileSource`], as a source of documents
+pub struct Sourced<S> {
+    source: S,
+    file: File,
+}
+
+impl<S: FileSource> Sourced<S> {
+    pub fn new(source: S) -> Self {
+        Self { source, file: File::new() }
+    }
+
+    /// Index the files of the source as `file` would index them.
+    pub fn with_file(mut self, file: File) -> Self {
+        self.file = file;
+        self
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+#[async_trait]
+impl<S: FileSource> Indexable for Sourced<S> {
+    async fn index_repository(&self, root_path: &Path, writer: &IndexWriter) -> Result<IndexReport> {
+        let file = &self.file;
+        let root = root_path.canonicalize().with_context(|| format!("invalid root {}", root_path.display()))?;
+        let root_str = root.to_string_lossy().replace('\\', "/");
+        let existing_docs = ExistingDocs::load(writer, file.hash_field, file.path_field, file.lang_field)?;
+        let mut skipped = Vec::new();
+        let mut listed = HashSet::new();
+        let mut documents = Vec::new();
+
+        let mut select = |relative_path: &str, size: u64| {
+            listed.insert(format!("{root_str}/{relative_path}"));
+            let path = root.join(relative_path);
+            let in_index = file.path_filter.as_deref().map_or(true, |filter| filter(&path));
+            if (File::detect_language(&path) == PLAINTEXT && !regions::is_template(&path)) || !in_index || is_excluded(relative_path, &file.excludes) {
+                return false;
+            }
+            if file.max_file_size.is_some_and(|max_size| size > max_size) {
+                let stored_path = format!("{root_str}/{relative_path}");
+                eprintln!("Warning: not indexing {stored_path}, its {size} bytes are over the size cap");
+                skipped.push(SkippedFile { path: stored_path, size });
+                return false;
+            }
+            true
+        };
+        let mut read = |relative_path: &str, content: Vec<u8>| {
+            // files that aren't UTF-8 are skipped, as by a traversal
+            if let Ok(content) = String::from_utf8(content) {
+                documents.push((format!("{root_str}/{relative_path}"), content));
+            }
+            Ok(())
+        };
+        self.source.read_files(&root, &mut select, &mut read)?;
+
+        // indexed as the documents of a traversal, renamed from the files of the source
+        // it no longer holds
+        let prefix = format!("{root_str}/");
+        let report = Mutex::new(IndexReport::default());
+        let gone = |from: &str| from.starts_with(&prefix) && !listed.contains(from);
+        file.index_contents(documents, writer, &existing_docs, Some(&gone), &report)?;
+        let mut report = report.into_inner().unwrap();
+        report.skipped.extend(skipped);
+
+        // what the source no longer holds, too large, excluded or redacted files included
+        for path in existing_docs.unseen_under(&prefix) {
+            if !file.dry_run {
+                writer.delete_term(Term::from_field_text(file.path_field, &path));
+            }
+            report.deleted.push(path);
+        }
+        report.deleted.sort();
+
+        Ok(report)
+    }
+
+    fn schema(&self) -> Schema {
+        self.file.schema()
+    }
+}
+
+/// Whether one of the directories of `path` is named one of `excludes`, see
+/// [`File::with_excludes`]. Unlike in the working tree, no `.gitignore` re-includes it.
+pub(crate) fn is_excluded(path: &str, excludes: &[String]) -> bool {
+    let Some((dirs, _)) = path.rsplit_once('/') else {
+        return false;
+    };
+    dirs.split('/').any(|dir| excludes.iter().any(|exclude| exclude == dir))
+}
+
+/// How an [`Archive`] is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    /// A gzipped tar, `.tar.gz` or `.tgz`
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// The format of the archive at `path`, from its extension.
+    pub fn of_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// The files of a zip or tar archive, the root being the archive itself, read without
+/// extracting it. Links and entries with a path leaving the archive are left out.
+#[derive(Debug, Clone, Default)]
+pub struct Archive {
+    format: Option<ArchiveFormat>,
+}
+
+impl Archive {
+    /// An archive whose format is told by its extension.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An archive of `format`, whatever its extension.
+    pub fn of_format(format: ArchiveFormat) -> Self {
+        Self { format: Some(format) }
+    }
+}
+
+impl FileSource for Archive {
+    fn read_files(
+        &self,
+        root: &Path,
+        select: &mut dyn FnMut(&str, u64) -> bool,
+        read: &mut dyn FnMut(&str, Vec<u8>) -> Result<()>,
+    ) -> Result<()> {
+        let Some(format) = self.format.or_else(|| ArchiveFormat::of_path(root)) else {
+            bail!("unknown archive format of {}, expected a .zip, .tar, .tar.gz or .tgz file", root.display());
+        };
+        let archive = fs::File::open(root).with_context(|| format!("failed to open the archive {}", root.display()))?;
+        match format {
+            ArchiveFormat::Zip => read_zip(archive, select, read),
+            ArchiveFormat::Tar => read_tar(archive, select, read),
+            ArchiveFormat::TarGz => read_tar(flate2::read::GzDecoder::new(archive), select, read),
+        }
+        .with_context(|| format!("failed to read the archive {}", root.display()))
+    }
+}
+
+fn read_zip(
+    archive: fs::File,
+    select: &mut dyn FnMut(&str, u64) -> bool,
+    read: &mut dyn FnMut(&str, Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(archive)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let is_link = entry.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000);
+        if !entry.is_file() || is_link {
+            continue;
+        }
+        let Some(path) = entry_path(Path::new(entry.name())) else {
+            continue;
+        };
+        if !select(&path, entry.size()) {
+            continue;
+        }
+        let mut content = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut content)?;
+        read(&path, content)?;
+    }
+    Ok(())
+}
+
+fn read_tar(
+    archive: impl Read,
+    select: &mut dyn FnMut(&str, u64) -> bool,
+    read: &mut dyn FnMut(&str, Vec<u8>) -> Result<()>,
+) -> Result<()> {
+    let mut archive = tar::Archive::new(archive);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(path) = entry_path(&entry.path()?) else {
+            continue;
+        };
+        if !select(&path, entry.size()) {
+            continue;
+        }
+        let mut content = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut content)?;
+        read(&path, content)?;
+    }
+    Ok(())
+}
+
+/// The path of an archive entry, `/`-separated, `None` if it leaves the archive.
+fn entry_path(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_entries_stay_inside() {
+        assert_eq!(entry_path(Path::new("./src/lib.rs")).as_deref(), Some("src/lib.rs"));
+        assert_eq!(entry_path(Path::new("src/../../etc/passwd")), None);
+        assert_eq!(entry_path(Path::new("/etc/passwd")), None);
+        assert_eq!(ArchiveFormat::of_path(Path::new("/tmp/deps.TGZ")), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::of_path(Path::new("/tmp/deps.rar")), None);
+    }
+}
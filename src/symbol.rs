@@ -1,5 +1,8 @@
+use std::ops::Deref;
+
 use crate::{intelligence::ScopeGraph, text_range::TextRange};
 
+use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -34,4 +37,93 @@ impl SymbolLocations {
             Self::Empty => None,
         }
     }
+
+    /// The distinct names of the symbols defined in `content`, sorted. Names are slices
+    /// of `content`, so a symbol defined many times is never copied.
+    pub fn defined_names<'a>(&self, content: &'a str) -> Vec<&'a str> {
+        let Some(graph) = self.scope_graph() else {
+            return Vec::new();
+        };
+        let mut names = graph
+            .symbol_ranges()
+            .filter_map(|range| content.get(range.start.byte..range.end.byte))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+}
+
+/// [`SymbolLocations`] as stored in the index, deserialized on first access.
+///
+/// Lookups load every document of a language, but only open the scope graphs of the
+/// few that mention the token, so the others are never decoded.
+#[derive(Debug, Clone, Default)]
+pub struct LazySymbolLocations {
+    bytes: Vec<u8>,
+    decoded: OnceCell<SymbolLocations>,
+}
+
+impl LazySymbolLocations {
+    /// Wrap a bincode encoded [`SymbolLocations`]. Undecodable bytes read as
+    /// [`SymbolLocations::Empty`].
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            decoded: OnceCell::new(),
+        }
+    }
+
+    pub fn is_decoded(&self) -> bool {
+        self.decoded.get().is_some()
+    }
+}
+
+impl From<SymbolLocations> for LazySymbolLocations {
+    fn from(symbol_locations: SymbolLocations) -> Self {
+        Self {
+            bytes: Vec::new(),
+            decoded: OnceCell::with_value(symbol_locations),
+        }
+    }
+}
+
+impl Deref for LazySymbolLocations {
+    type Target = SymbolLocations;
+
+    fn deref(&self) -> &SymbolLocations {
+        self.decoded
+            .get_or_init(|| bincode::deserialize(&self.bytes).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lazy_symbol_locations() {
+        let bytes = bincode::serialize(&SymbolLocations::Empty).unwrap();
+        let lazy = LazySymbolLocations::from_bytes(bytes);
+        assert!(!lazy.is_decoded());
+
+        assert!(lazy.scope_graph().is_none());
+        assert!(lazy.is_decoded());
+
+        // garbage reads as no symbols rather than failing the lookup
+        let lazy = LazySymbolLocations::from_bytes(vec![0xff; 3]);
+        assert!(lazy.list().is_empty());
+    }
+
+    #[test]
+    fn defined_names_are_distinct() {
+        let content = "fn a() {}\nfn b() { let x = 1; let x = 2; }\nfn a() {}\n";
+        let graph = crate::intelligence::TreeSitterFile::try_build(content.as_bytes(), "rust")
+            .and_then(crate::intelligence::TreeSitterFile::scope_graph)
+            .unwrap();
+        let locations = SymbolLocations::TreeSitter(graph);
+
+        assert_eq!(locations.defined_names(content), ["a", "b", "x"]);
+        assert!(SymbolLocations::Empty.defined_names(content).is_empty());
+    }
 }
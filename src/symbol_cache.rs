@@ -0,0 +1,257 @@
+//! The names of all defined symbols, with the files defining them, persisted next to
+//! the index so that completing a symbol doesn't read every stored document.
+//!
+//! The cache is a single file, memory-mapped when the searcher opens:
+//!
+//! ```text
+//! magic | generation | fst | postings | paths
+//! ```
+//!
+//! - the generation identifies the index commit the cache was built from
+//! - the fst maps `lowercase(name) \0 name` to the offset of the name's postings, so that
+//!   case-insensitive prefixes are a range scan
+//! - postings are a `u32` count followed by that many path ids
+//! - paths are a `u32` count, `count + 1` offsets, then the concatenated paths
+//!
+//! Every integer is little-endian, lengths of sections are `u64`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use tantivy::directory::error::OpenReadError;
+use tantivy::directory::{Directory, OwnedBytes};
+use tantivy::schema::Field;
+use tantivy::Index;
+
+pub const SYMBOL_CACHE_FILE: &str = "symbols.cache";
+
+const MAGIC: &[u8; 4] = b"CNSC";
+
+pub struct SymbolCache {
+    generation: String,
+    map: Map<OwnedBytes>,
+    postings: OwnedBytes,
+    paths: OwnedBytes,
+}
+
+/// Identifies the state of the index the `searcher` reads: its segments and how many
+/// of their documents are deleted.
+pub fn generation(searcher: &tantivy::Searcher) -> String {
+    let mut segments = searcher
+        .segment_readers()
+        .iter()
+        .map(|segment| format!("{}:{}", segment.segment_id().uuid_string(), segment.num_deleted_docs()))
+        .collect::<Vec<_>>();
+    segments.sort();
+    segments.join(",")
+}
+
+/// Rebuild the cache of `index` unless it is already up to date with the last commit.
+pub fn refresh(index: &Index, symbols_field: Field, path_field: Field) -> Result<()> {
+    let searcher = index.reader()?.searcher();
+    let generation = generation(&searcher);
+
+    if let Some(cache) = SymbolCache::open(index.directory())? {
+        if cache.generation == generation {
+            return Ok(());
+        }
+    }
+
+    let mut symbols: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for segment_reader in searcher.segment_readers() {
+        let store_reader = segment_reader.get_store_reader(0)?;
+        for doc in store_reader.iter(segment_reader.alive_bitset()) {
+            let doc = doc?;
+            let Some(path) = doc.get_first(path_field).and_then(|f| f.as_text()) else {
+                continue;
+            };
+            let names = doc.get_first(symbols_field).and_then(|f| f.as_text()).unwrap_or_default();
+            for name in names.lines().filter(|name| !name.is_empty()) {
+                symbols
+                    .entry(format!("{}\0{}", name.to_lowercase(), name))
+                    .or_default()
+                    .insert(path.to_owned());
+            }
+        }
+    }
+
+    index
+        .directory()
+        .atomic_write(Path::new(SYMBOL_CACHE_FILE), &encode(&generation, &symbols)?)?;
+    Ok(())
+}
+
+fn encode(generation: &str, symbols: &BTreeMap<String, BTreeSet<String>>) -> Result<Vec<u8>> {
+    let mut path_ids = BTreeMap::new();
+    for path in symbols.values().flatten() {
+        let id = path_ids.len() as u32;
+        path_ids.entry(path.as_str()).or_insert(id);
+    }
+
+    let mut map = MapBuilder::memory();
+    let mut postings = Vec::new();
+    for (key, paths) in symbols {
+        map.insert(key, postings.len() as u64)?;
+        postings.extend_from_slice(&(paths.len() as u32).to_le_bytes());
+        for path in paths {
+            postings.extend_from_slice(&path_ids[path.as_str()].to_le_bytes());
+        }
+    }
+    let map = map.into_inner()?;
+
+    let mut ordered_paths = path_ids.into_iter().collect::<Vec<_>>();
+    ordered_paths.sort_by_key(|(_, id)| *id);
+    let mut paths = (ordered_paths.len() as u32).to_le_bytes().to_vec();
+    let mut offset = 0u32;
+    paths.extend_from_slice(&offset.to_le_bytes());
+    for (path, _) in &ordered_paths {
+        offset += path.len() as u32;
+        paths.extend_from_slice(&offset.to_le_bytes());
+    }
+    for (path, _) in &ordered_paths {
+        paths.extend_from_slice(path.as_bytes());
+    }
+
+    let mut bytes = MAGIC.to_vec();
+    for section in [generation.as_bytes(), &map, &postings, &paths] {
+        bytes.extend_from_slice(&(section.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(section);
+    }
+    Ok(bytes)
+}
+
+impl SymbolCache {
+    /// Memory-map the cache of the index in `directory`, if there is one.
+    pub fn open(directory: &dyn Directory) -> Result<Option<Self>> {
+        let file = match directory.open_read(Path::new(SYMBOL_CACHE_FILE)) {
+            Ok(file) => file,
+            Err(OpenReadError::FileDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let bytes = file.read_bytes()?;
+
+        if !bytes.as_slice().starts_with(MAGIC) {
+            return Ok(None);
+        }
+        let mut sections = Vec::with_capacity(4);
+        let mut offset = MAGIC.len();
+        for _ in 0..4 {
+            let len = read_u64(&bytes, offset).context("truncated symbol cache")? as usize;
+            offset += 8;
+            if offset + len > bytes.len() {
+                return Err(anyhow::anyhow!("truncated symbol cache"));
+            }
+            sections.push(offset..offset + len);
+            offset += len;
+        }
+
+        let generation = String::from_utf8(bytes.slice(sections[0].clone()).as_slice().to_vec())?;
+        Ok(Some(Self {
+            generation,
+            map: Map::new(bytes.slice(sections[1].clone()))?,
+            postings: bytes.slice(sections[2].clone()),
+            paths: bytes.slice(sections[3].clone()),
+        }))
+    }
+
+    /// Whether the cache describes the index as `searcher` sees it.
+    pub fn is_current(&self, searcher: &tantivy::Searcher) -> bool {
+        self.generation == generation(searcher)
+    }
+
+    /// The symbols whose name starts with `prefix`, ignoring case, with the sorted
+    /// paths of the files defining them.
+    pub fn complete(&self, prefix: &str) -> Result<Vec<(String, Vec<String>)>> {
+        let lowercase_prefix = prefix.to_lowercase();
+        let mut stream = self.map.range().ge(lowercase_prefix.as_bytes()).into_stream();
+
+        let mut completions = Vec::new();
+        while let Some((key, offset)) = stream.next() {
+            let key = std::str::from_utf8(key)?;
+            let Some((lowercase_name, name)) = key.split_once('\0') else {
+                continue;
+            };
+            if !lowercase_name.starts_with(&lowercase_prefix) {
+                break;
+            }
+            completions.push((name.to_owned(), self.postings(offset as usize)?));
+        }
+        Ok(completions)
+    }
+
+    fn postings(&self, offset: usize) -> Result<Vec<String>> {
+        let count = read_u32(&self.postings, offset).context("truncated symbol postings")?;
+        (0..count as usize)
+            .map(|i| {
+                let id = read_u32(&self.postings, offset + 4 * (i + 1)).context("truncated symbol postings")?;
+                self.path(id as usize)
+            })
+            .collect()
+    }
+
+    fn path(&self, id: usize) -> Result<String> {
+        let count = read_u32(&self.paths, 0).context("truncated symbol paths")? as usize;
+        let offset = |i: usize| read_u32(&self.paths, 4 * (i + 1)).map(|offset| offset as usize);
+        let data_start = 4 * (count + 2);
+        let (Some(start), Some(end)) = (offset(id), offset(id + 1)) else {
+            return Err(anyhow::anyhow!("invalid path id {id} in symbol cache"));
+        };
+        let range: Range<usize> = data_start + start..data_start + end;
+        let bytes = self.paths.as_slice().get(range).context("truncated symbol paths")?;
+        Ok(std::str::from_utf8(bytes)?.to_owned())
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::directory::RamDirectory;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let mut symbols: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for (name, path) in [
+            ("format_results", "/repo/a.rs"),
+            ("Format", "/repo/b.rs"),
+            ("format_results", "/repo/c.rs"),
+            ("parse", "/repo/a.rs"),
+        ] {
+            symbols
+                .entry(format!("{}\0{}", name.to_lowercase(), name))
+                .or_default()
+                .insert(path.to_owned());
+        }
+
+        let directory = RamDirectory::create();
+        directory.atomic_write(Path::new(SYMBOL_CACHE_FILE), &encode("gen", &symbols)?)?;
+        let cache = SymbolCache::open(&directory)?.unwrap();
+
+        assert_eq!(cache.generation, "gen");
+        assert_eq!(
+            cache.complete("FORM")?,
+            vec![
+                ("Format".to_owned(), vec!["/repo/b.rs".to_owned()]),
+                (
+                    "format_results".to_owned(),
+                    vec!["/repo/a.rs".to_owned(), "/repo/c.rs".to_owned()]
+                ),
+            ]
+        );
+        assert_eq!(cache.complete("parse")?.len(), 1);
+        assert!(cache.complete("zzz")?.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,2 +1,175 @@
+//! The progress of an indexing run, such as a refresh started with
+//! [`crate::IndexHandle::start_refresh`], readable from any thread while it runs, so
+//! that a caller can orchestrate other work rather than block on it.
+//!
+//! A run is [`Queued`](SyncState::Queued) until it takes the write lock of the index,
+//! then walks the repository, [`Walking`](SyncState::Walking) while it lists the files
+//! and compares them with the index, and [`Parsing`](SyncState::Parsing) while it
+//! parses a new or changed file into a document. It ends up
+//! [`Committing`](SyncState::Committing) the documents, then
+//! [`Done`](SyncState::Done), or [`Failed`](SyncState::Failed) at any point.
+
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::indexes::IndexReport;
+
+/// What an indexing run is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+    /// Waiting for the run before it to finish
+    Queued,
+    /// Listing the files of the repository and comparing them with the index
+    Walking,
+    /// Parsing a new or changed file
+    Parsing,
+    /// Writing the documents to the index, and refreshing what is derived from them
+    Committing,
+    Done,
+    Failed,
+}
+
+impl SyncState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Walking => "walking",
+            Self::Parsing => "parsing",
+            Self::Committing => "committing",
+            Self::Done => "done",
+            Self::Failed => "failed",
+        }
+    }
+
+    /// Whether the run is over, done or failed.
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Done | Self::Failed)
+    }
+
+    const ALL: [Self; 6] = [Self::Queued, Self::Walking, Self::Parsing, Self::Committing, Self::Done, Self::Failed];
+}
+
+/// Where an indexing run is at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncStatus {
+    pub state: SyncState,
+    /// Files the walk found so far, changed or not
+    pub walked: usize,
+    /// Files parsed so far, the one being parsed included
+    pub parsed: usize,
+    /// Why the run failed, once it is [`SyncState::Failed`]
+    pub error: Option<String>,
+}
+
+/// The progress of one indexing run. Cloning is cheap and every clone follows the same
+/// run.
+#[derive(Clone, Default)]
+pub struct SyncHandle {
+    inner: Arc<Progress>,
+}
+
 #[derive(Default)]
-pub struct SyncHandle;
+struct Progress {
+    state: AtomicU8,
+    walked: AtomicUsize,
+    parsed: AtomicUsize,
+    /// The report of the run, or its error, once it is finished
+    outcome: Mutex<Option<Result<IndexReport, String>>>,
+    finished: Condvar,
+}
+
+impl SyncHandle {
+    /// The progress of a run that hasn't started yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> SyncState {
+        SyncState::ALL[self.inner.state.load(Ordering::Acquire) as usize]
+    }
+
+    /// The state of the run and its counts, as of now.
+    pub fn status(&self) -> SyncStatus {
+        let outcome = self.inner.outcome.lock().unwrap_or_else(|e| e.into_inner());
+        SyncStatus {
+            state: self.state(),
+            walked: self.inner.walked.load(Ordering::Relaxed),
+            parsed: self.inner.parsed.load(Ordering::Relaxed),
+            error: outcome.as_ref().and_then(|outcome| outcome.as_ref().err().cloned()),
+        }
+    }
+
+    /// Wait for the run to finish, returning what it changed.
+    pub fn wait(&self) -> Result<IndexReport> {
+        let outcome = self.inner.outcome.lock().unwrap_or_else(|e| e.into_inner());
+        let outcome = self.inner.finished.wait_while(outcome, |outcome| outcome.is_none()).unwrap_or_else(|e| e.into_inner());
+        outcome.clone().unwrap().map_err(|e| anyhow!(e))
+    }
+
+    /// Like [`Self::wait`], waiting at most `timeout`, `None` if the run is still going.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<Result<IndexReport>> {
+        let outcome = self.inner.outcome.lock().unwrap_or_else(|e| e.into_inner());
+        let (outcome, _) = self
+            .inner
+            .finished
+            .wait_timeout_while(outcome, timeout, |outcome| outcome.is_none())
+            .unwrap_or_else(|e| e.into_inner());
+        outcome.clone().map(|outcome| outcome.map_err(|e| anyhow!(e)))
+    }
+
+    pub(crate) fn enter(&self, state: SyncState) {
+        self.inner.state.store(state as u8, Ordering::Release);
+    }
+
+    /// Count a file the walk found.
+    pub(crate) fn walked(&self) {
+        self.inner.walked.fetch_add(1, Ordering::Relaxed);
+        self.enter(SyncState::Walking);
+    }
+
+    /// Count a file about to be parsed.
+    pub(crate) fn parsing(&self) {
+        self.inner.parsed.fetch_add(1, Ordering::Relaxed);
+        self.enter(SyncState::Parsing);
+    }
+
+    /// End the run with `indexed`, waking up those waiting for it.
+    pub(crate) fn finish(&self, indexed: &Result<IndexReport>) {
+        let mut outcome = self.inner.outcome.lock().unwrap_or_else(|e| e.into_inner());
+        *outcome = Some(indexed.as_ref().map(Clone::clone).map_err(|e| format!("{e:#}")));
+        self.enter(if indexed.is_ok() { SyncState::Done } else { SyncState::Failed });
+        self.inner.finished.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_a_run() {
+        let progress = SyncHandle::new();
+        assert_eq!(progress.state(), SyncState::Queued);
+        assert!(progress.wait_timeout(Duration::ZERO).is_none());
+
+        progress.enter(SyncState::Walking);
+        progress.walked();
+        progress.walked();
+        progress.parsing();
+        progress.enter(SyncState::Committing);
+        let status = progress.status();
+        assert_eq!((status.state, status.walked, status.parsed), (SyncState::Committing, 2, 1));
+
+        let waiter = {
+            let progress = progress.clone();
+            std::thread::spawn(move || progress.wait())
+        };
+        progress.finish(&Err(anyhow!("disk full")));
+        assert_eq!(waiter.join().unwrap().unwrap_err().to_string(), "disk full");
+        assert_eq!(progress.status().error.as_deref(), Some("disk full"));
+        assert!(progress.state().is_finished());
+    }
+}
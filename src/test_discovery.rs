@@ -0,0 +1,287 @@
+//! Finding the tests to run after editing a file or a symbol.
+//!
+//! Nothing records which tests cover what, so they are guessed from the evidence the
+//! index holds: test files named after the file, e.g. `tests/test_parser.py` or
+//! `parser_test.go` for `parser.py` and `parser.go`, test files importing its
+//! symbols, and test functions referencing them or named after them. The inline
+//! tests of the file itself, such as a Rust `mod tests`, are its tests too.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ops::Range;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::content_document::ContentDocument;
+use crate::intelligence::code_navigation::CodeNavigationContext;
+use crate::intelligence::file_summary::FUNCTION_KINDS;
+use crate::repository::{self, Repository};
+use crate::text_range::{LineBase, TextRange};
+
+/// Directories whose files are all tests
+const TEST_DIRS: &[&str] = &["test", "tests", "__tests__", "spec", "specs"];
+
+/// Prefixes of the names of test files, before the name of the file they test
+const TEST_PREFIXES: &[&str] = &["test_", "tests_"];
+
+/// Suffixes of the names of test files, after the name of the file they test
+const TEST_SUFFIXES: &[&str] = &["_test", "_tests", ".test", "_spec", ".spec", "-test", "-spec", "Test", "Tests", "Spec"];
+
+/// Test functions are named after target names at least this long, shorter ones are
+/// too likely to be part of unrelated words
+const MIN_NAMED_LENGTH: usize = 3;
+
+/// What to find the tests of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestTarget {
+    /// The file at this path, relative or absolute
+    Path(String),
+    /// The symbol of this name, wherever it is defined
+    Symbol(String),
+}
+
+/// Why a file is taken for a test of the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestEvidence {
+    /// The test file is named after the file of the target, or one of its test
+    /// functions after the target
+    Name,
+    /// The test file imports a symbol defined in the file of the target
+    Import,
+    /// Test functions of the file reference the target
+    Reference,
+    /// The tests are in the file of the target itself
+    SameFile,
+}
+
+impl TestEvidence {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Import => "import",
+            Self::Reference => "reference",
+            Self::SameFile => "same_file",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestFunction {
+    pub name: String,
+    /// The range of the name, numbered from the [`LineBase::current`]
+    pub range: TextRange,
+}
+
+/// A file likely to test the target
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestMatch {
+    pub path: String,
+    /// `path` relative to the root it was indexed from
+    pub relative_path: String,
+    pub evidence: Vec<TestEvidence>,
+    /// The test functions of the file named after or referencing the target, all of
+    /// them for inline tests. Empty when only the file as a whole is evidence.
+    pub functions: Vec<TestFunction>,
+}
+
+/// Whether the file at `relative_path` is a test file, from its name or a directory
+/// it is in.
+pub fn is_test_file(relative_path: &str) -> bool {
+    let mut parts = relative_path.rsplit('/');
+    let name = parts.next().unwrap_or_default();
+    tested_stem(name).is_some() || parts.any(|dir| TEST_DIRS.contains(&dir))
+}
+
+/// The name of the file tested by the test file `name`, without its extension:
+/// `parser` for `test_parser.py`, `parser_test.go` or `parser.spec.ts`.
+fn tested_stem(name: &str) -> Option<&str> {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let tested = TEST_PREFIXES
+        .iter()
+        .find_map(|prefix| stem.strip_prefix(prefix))
+        .or_else(|| TEST_SUFFIXES.iter().find_map(|suffix| stem.strip_suffix(suffix)))?;
+    (!tested.is_empty()).then_some(tested)
+}
+
+/// `name` compared regardless of case and word separators, `parse_config` matching
+/// `TestParseConfig`.
+fn normalized(name: &str) -> String {
+    name.chars().filter(|c| !matches!(c, '_' | '-' | '.')).flat_map(char::to_lowercase).collect()
+}
+
+/// The files of `docs` likely to test `target`, whose path is that of a document of
+/// `docs` if it is a file, those with the most kinds of evidence first.
+pub(crate) fn find(docs: &[ContentDocument], target: &TestTarget, repositories: &[Repository]) -> Vec<TestMatch> {
+    let relative = |path: &str| repository::relative_path(repositories, path).unwrap_or(path).to_owned();
+    let (sources, names) = match target {
+        TestTarget::Path(path) => {
+            let sources = docs.iter().position(|doc| &doc.relative_path == path).into_iter().collect::<Vec<_>>();
+            let names = sources.iter().flat_map(|&i| top_level_names(&docs[i])).collect::<Vec<_>>();
+            (sources, names)
+        }
+        TestTarget::Symbol(name) => {
+            let sources = (0..docs.len()).filter(|&i| top_level_names(&docs[i]).contains(name)).collect::<Vec<_>>();
+            (sources, vec![name.clone()])
+        }
+    };
+
+    let stems = sources
+        .iter()
+        .map(|&i| relative(&docs[i].relative_path))
+        .map(|path| normalized(path.rsplit('/').next().and_then(|name| name.split('.').next()).unwrap_or_default()))
+        .collect::<HashSet<_>>();
+    let importing = sources
+        .iter()
+        .flat_map(|&i| CodeNavigationContext::files_importing(docs, i))
+        .map(|doc| doc.relative_path.as_str())
+        .collect::<HashSet<_>>();
+    let named = names.iter().map(|name| normalized(name)).filter(|name| name.len() >= MIN_NAMED_LENGTH).collect::<Vec<_>>();
+    let references = (!names.is_empty())
+        .then(|| Regex::new(&format!(r"\b(?:{})\b", names.iter().map(|name| regex::escape(name)).collect::<Vec<_>>().join("|"))))
+        .and_then(Result::ok);
+
+    let base = LineBase::current();
+    let mut matches = Vec::new();
+    for (i, doc) in docs.iter().enumerate() {
+        let relative_path = relative(&doc.relative_path);
+        let is_source = sources.contains(&i);
+        if !is_source && !is_test_file(&relative_path) {
+            continue;
+        }
+
+        let mut evidence = Vec::new();
+        let name = relative_path.rsplit('/').next().unwrap_or_default();
+        if !is_source && tested_stem(name).is_some_and(|stem| stems.contains(&normalized(stem))) {
+            evidence.push(TestEvidence::Name);
+        }
+        if !is_source && importing.contains(doc.relative_path.as_str()) {
+            evidence.push(TestEvidence::Import);
+        }
+
+        let inline = is_source && matches!(target, TestTarget::Path(_));
+        let mut functions = Vec::new();
+        for (function, body) in test_functions(doc) {
+            let is_named = named.iter().any(|name| normalized(&function.name).contains(name.as_str()));
+            let is_referencing = references.as_ref().is_some_and(|references| references.is_match(&doc.content[body]));
+            if is_named {
+                evidence.push(TestEvidence::Name);
+            }
+            if is_referencing {
+                evidence.push(TestEvidence::Reference);
+            }
+            if is_named || is_referencing || inline {
+                functions.push(TestFunction {
+                    name: function.name,
+                    range: base.numbered(function.range),
+                });
+            }
+        }
+        if inline && !functions.is_empty() {
+            evidence.push(TestEvidence::SameFile);
+        }
+
+        evidence.sort();
+        evidence.dedup();
+        if !evidence.is_empty() {
+            matches.push(TestMatch {
+                path: doc.relative_path.clone(),
+                relative_path,
+                evidence,
+                functions,
+            });
+        }
+    }
+
+    matches.sort_by(|a, b| (Reverse(a.evidence.len()), &a.path).cmp(&(Reverse(b.evidence.len()), &b.path)));
+    matches
+}
+
+/// The names of the top-level definitions of `doc`.
+fn top_level_names(doc: &ContentDocument) -> Vec<String> {
+    let Some(scope_graph) = doc.symbol_locations.scope_graph() else {
+        return Vec::new();
+    };
+    scope_graph
+        .graph
+        .node_indices()
+        .filter(|&idx| scope_graph.is_definition(idx) && scope_graph.is_top_level(idx))
+        .filter_map(|idx| {
+            let range = scope_graph.graph[idx].range();
+            doc.content.get(range.start.byte..range.end.byte).map(str::to_owned)
+        })
+        .collect()
+}
+
+/// The test functions of `doc`, with the byte range of their bodies: the functions
+/// named `test…`, or with a test attribute or annotation such as `#[test]` or `@Test`
+/// on the lines above.
+fn test_functions(doc: &ContentDocument) -> Vec<(TestFunction, Range<usize>)> {
+    let Some(scope_graph) = doc.symbol_locations.scope_graph() else {
+        return Vec::new();
+    };
+    let lines = doc.content.lines().collect::<Vec<_>>();
+    let bodies = doc.function_ranges().unwrap_or_default();
+
+    let mut functions = scope_graph
+        .graph
+        .node_indices()
+        .filter(|&idx| scope_graph.is_definition(idx))
+        .filter(|&idx| scope_graph.symbol_name_of(idx).is_some_and(|kind| FUNCTION_KINDS.contains(&kind)))
+        .filter_map(|idx| {
+            let range = scope_graph.graph[idx].range();
+            let name = doc.content.get(range.start.byte..range.end.byte)?;
+            if !name.to_lowercase().starts_with("test") && !has_test_attribute(&lines, range.start.line) {
+                return None;
+            }
+            // the innermost function around its name
+            let body = bodies
+                .iter()
+                .filter(|body| body.contains(&range.start.byte))
+                .min_by_key(|body| body.len())
+                .cloned()
+                .unwrap_or(range.start.byte..range.end.byte);
+            Some((TestFunction { name: name.to_owned(), range }, body))
+        })
+        .collect::<Vec<_>>();
+
+    functions.sort_by_key(|(function, _)| function.range.start.byte);
+    functions
+}
+
+/// Whether the attributes, annotations or decorators right above `line` mark a test.
+fn has_test_attribute(lines: &[&str], line: usize) -> bool {
+    lines
+        .get(..line)
+        .unwrap_or_default()
+        .iter()
+        .rev()
+        .map(|line| line.trim())
+        .take_while(|line| line.starts_with("#[") || line.starts_with('@') || line.starts_with('['))
+        .any(|line| line.to_lowercase().contains("test") || line.starts_with("[Fact") || line.starts_with("[Theory"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_by_their_names() {
+        assert_eq!(tested_stem("test_parser.py"), Some("parser"));
+        assert_eq!(tested_stem("parser_test.go"), Some("parser"));
+        assert_eq!(tested_stem("parser.spec.ts"), Some("parser"));
+        assert_eq!(tested_stem("ParserTest.java"), Some("Parser"));
+        assert_eq!(tested_stem("latest.rs"), None);
+        assert_eq!(tested_stem("test.py"), None);
+
+        assert!(is_test_file("tests/pipeline.rs"));
+        assert!(is_test_file("src/__tests__/app.js"));
+        assert!(!is_test_file("src/contest.rs"));
+        assert_eq!(normalized("TestParseConfig"), "testparseconfig");
+
+        let lines = ["#[tokio::test]", "#[ignore]", "async fn fetches() {}"];
+        assert!(has_test_attribute(&lines, 2));
+        assert!(!has_test_attribute(&["#[inline]", "fn fetches() {}"], 1));
+    }
+}
@@ -1,7 +1,84 @@
+use std::cell::Cell;
 use std::cmp::{Ord, Ordering};
 
 use serde::{Deserialize, Serialize};
 
+use crate::error::{ErrorCode, NavError};
+
+thread_local! {
+    /// The numbering of the calls made on this thread, see [`LineBase::current`]
+    static LINE_BASE: Cell<LineBase> = const { Cell::new(LineBase::One) };
+}
+
+/// How the lines given to and returned by the public functions are numbered.
+///
+/// Everything is 0-indexed inside the crate, and converted with [`LineBase::number`]
+/// and [`LineBase::index`] where lines come in and go out. Columns always count from 0.
+///
+/// The base is the calling thread's, so that threads sharing a searcher or an
+/// [`crate::IndexHandle`] each number lines their own way, and a call keeps the base it
+/// started with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LineBase {
+    /// The first line is line 0
+    Zero,
+    /// The first line is line 1
+    #[default]
+    One,
+}
+
+impl LineBase {
+    /// The numbering of the calls made on this thread, `One` unless set otherwise.
+    pub fn current() -> Self {
+        LINE_BASE.with(Cell::get)
+    }
+
+    /// Number the lines of the calls made on this thread from this base from now on.
+    pub fn set_current(self) {
+        LINE_BASE.with(|base| base.set(self));
+    }
+
+    /// Run `f` with the lines numbered from this base on this thread, then go back to
+    /// the base the thread had.
+    pub fn scope<T>(self, f: impl FnOnce() -> T) -> T {
+        struct Restore(LineBase);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                self.0.set_current();
+            }
+        }
+
+        let _restore = Restore(Self::current());
+        self.set_current();
+        f()
+    }
+
+    /// The number of the first line.
+    pub fn first(self) -> usize {
+        match self {
+            Self::Zero => 0,
+            Self::One => 1,
+        }
+    }
+
+    /// The number of the 0-indexed `line`.
+    pub fn number(self, line: usize) -> usize {
+        line + self.first()
+    }
+
+    /// The 0-indexed line numbered `number`, `None` below the first line.
+    pub fn index(self, number: usize) -> Option<usize> {
+        number.checked_sub(self.first())
+    }
+
+    /// `range` with its lines numbered from this base.
+    pub fn numbered(self, mut range: TextRange) -> TextRange {
+        range.start.line = self.number(range.start.line);
+        range.end.line = self.number(range.end.line);
+        range
+    }
+}
+
 /// A singular position in a text document
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Point {
@@ -32,22 +109,182 @@ impl Point {
         Self { byte, line, column }
     }
 
+    /// The position of `byte`, given the [`line_end_indices`] of the text. The newline
+    /// ending a line belongs to that line, bytes past the end land on the last line.
     pub fn from_byte(byte: usize, line_end_indices: &[u32]) -> Self {
         let line = line_end_indices
             .iter()
-            .position(|&line_end_byte| (line_end_byte as usize) > byte)
-            .unwrap_or(0);
+            .position(|&line_end_byte| (line_end_byte as usize) >= byte)
+            .unwrap_or(line_end_indices.len().saturating_sub(1));
 
         let column = line
             .checked_sub(1)
             .and_then(|idx| line_end_indices.get(idx))
-            .map(|&prev_line_end| byte.saturating_sub(prev_line_end as usize))
+            .map(|&prev_line_end| byte.saturating_sub(prev_line_end as usize + 1))
             .unwrap_or(byte);
 
         Self::new(byte, line, column)
     }
 }
 
+/// The byte the 0-indexed `line` starts at, given the [`line_end_indices`] of the text.
+/// Lines past the last one start at the end of the text.
+pub fn line_start(line: usize, line_end_indices: &[u32]) -> usize {
+    let text_end = line_end_indices.last().map_or(0, |&end| end as usize);
+    match line.checked_sub(1) {
+        Some(previous) => line_end_indices.get(previous).map_or(text_end, |&end| end as usize + 1).min(text_end),
+        None => 0,
+    }
+}
+
+/// Byte index of every newline in `content`, followed by the length of `content`.
+///
+/// This is the line model of the crate, shared by indexing and queries, and the layout
+/// of [`crate::content_document::ContentDocument::line_end_indices`], stored in the
+/// index by [`encode_line_ends`]: line `n` (0-indexed) spans from just after entry
+/// `n - 1` up to entry `n`, its newline left out, see [`line_text`].
+///
+/// A text has one more entry than newlines: the last line, ending the text without a
+/// newline, or the empty line after the newline that ends it, which is where the end of
+/// the text is and isn't counted by [`line_count`]. An empty text has an empty line.
+pub fn line_end_indices(content: &str) -> Vec<u32> {
+    content
+        .match_indices('\n')
+        .map(|(i, _)| i as u32)
+        .chain(std::iter::once(content.len() as u32))
+        .collect()
+}
+
+/// The text of the 0-indexed `line`, without its newline, given the [`line_end_indices`]
+/// of `content`. `None` past the last line.
+pub fn line_text<'a>(content: &'a str, line_end_indices: &[u32], line: usize) -> Option<&'a str> {
+    let end = *line_end_indices.get(line)? as usize;
+    content.get(line_start(line, line_end_indices)..end)
+}
+
+/// The number of lines of `content` as an editor shows them: the newline ending the text
+/// ends its last line rather than starting one, and an empty text has none.
+pub fn line_count(content: &str) -> usize {
+    content.matches('\n').count() + usize::from(!content.is_empty() && !content.ends_with('\n'))
+}
+
+/// The end of the last line of `content` that [`line_count`] counts, before the newline
+/// ending the text if it has one.
+pub fn last_line_end(content: &str) -> usize {
+    content.strip_suffix('\n').unwrap_or(content).len()
+}
+
+/// The `line_end_indices` as stored in the index: the first one, then the difference
+/// of every one to the previous one, the length of its line, each as a LEB128 varint.
+/// Most lines take a byte or two rather than four.
+pub fn encode_line_ends(line_end_indices: &[u32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(line_end_indices.len() * 2);
+    let mut previous = 0;
+    for &end in line_end_indices {
+        let mut delta = end.wrapping_sub(previous);
+        previous = end;
+        loop {
+            let byte = (delta & 0x7f) as u8;
+            delta >>= 7;
+            if delta == 0 {
+                bytes.push(byte);
+                break;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+    bytes
+}
+
+/// The `line_end_indices` encoded by [`encode_line_ends`]. A truncated last entry is
+/// dropped.
+pub fn decode_line_ends(bytes: &[u8]) -> Vec<u32> {
+    let mut line_end_indices = Vec::with_capacity(bytes.len());
+    let (mut previous, mut delta, mut shift) = (0u32, 0u32, 0);
+    for &byte in bytes {
+        delta |= u32::from(byte & 0x7f).checked_shl(shift).unwrap_or(0);
+        if byte & 0x80 != 0 {
+            shift += 7;
+            continue;
+        }
+        previous = previous.wrapping_add(delta);
+        line_end_indices.push(previous);
+        (delta, shift) = (0, 0);
+    }
+    line_end_indices
+}
+
+/// Convert a `line_number`, counted from the [`LineBase::current`], and a range of
+/// char columns on that line into a byte range of `content`.
+pub fn line_word_to_byte_range(
+    content: &str,
+    line_end_indices: &[u32],
+    line_number: usize,
+    word_start_index: usize,
+    word_end_index: usize,
+) -> anyhow::Result<(usize, usize)> {
+    let (start_of_line, line) = numbered_line(content, line_end_indices, line_number)?;
+
+    // Validate word start and end indices
+    let line_length = line.chars().count();
+    if word_start_index >= word_end_index || word_end_index > line_length {
+        let position = if word_start_index >= word_end_index { word_start_index } else { word_end_index };
+        return Err(NavError::new(ErrorCode::InvalidRange, "Invalid word indices")
+            .at(position)
+            .suggest(format!(
+                "The start column must be before the end column, and line {line_number} has {line_length} characters"
+            ))
+            .into());
+    }
+
+    let start_byte = start_of_line + byte_of_column(line, word_start_index);
+    let end_byte = start_of_line + byte_of_column(line, word_end_index);
+
+    Ok((start_byte, end_byte))
+}
+
+/// Convert a `line_number`, counted from the [`LineBase::current`], and a char column
+/// on that line, up to its end, into a byte of `content`.
+pub fn line_column_to_byte(content: &str, line_end_indices: &[u32], line_number: usize, column: usize) -> anyhow::Result<usize> {
+    let (start_of_line, line) = numbered_line(content, line_end_indices, line_number)?;
+    let line_length = line.chars().count();
+    if column > line_length {
+        return Err(NavError::new(ErrorCode::InvalidRange, "Invalid column")
+            .at(column)
+            .suggest(format!("Line {line_number} has {line_length} characters"))
+            .into());
+    }
+    Ok(start_of_line + byte_of_column(line, column))
+}
+
+/// The number of chars before the byte `byte` of `line`.
+pub fn column_of_byte(line: &str, byte: usize) -> usize {
+    line.get(..byte).map_or(0, |before| before.chars().count())
+}
+
+fn byte_of_column(line: &str, column: usize) -> usize {
+    line.chars().take(column).map(char::len_utf8).sum()
+}
+
+/// The first byte and the text of the line `line_number`, counted from the
+/// [`LineBase::current`].
+fn numbered_line<'a>(content: &'a str, line_end_indices: &[u32], line_number: usize) -> anyhow::Result<(usize, &'a str)> {
+    let base = LineBase::current();
+    let invalid_line = || {
+        NavError::new(ErrorCode::InvalidRange, "Invalid line number")
+            .at(line_number)
+            .suggest(format!(
+                "Lines are numbered from {} and the file has {} lines",
+                base.first(),
+                line_end_indices.len()
+            ))
+    };
+
+    let line = base.index(line_number).ok_or_else(invalid_line)?;
+    let text = line_text(content, line_end_indices, line).ok_or_else(invalid_line)?;
+    Ok((line_start(line, line_end_indices), text))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TextRange {
     pub start: Point,
@@ -119,3 +356,178 @@ impl From<TextRange> for std::ops::Range<usize> {
         r.start.byte..r.end.byte
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Text made of a few lines, with multi-byte chars and empty lines
+    fn text() -> impl Strategy<Value = String> {
+        prop::collection::vec("[a-z é中🦀_ ]{0,12}", 1..8).prop_map(|lines| lines.join("\n"))
+    }
+
+    /// Byte offsets of `content` that fall on char boundaries, the end included
+    fn char_boundaries(content: &str) -> Vec<usize> {
+        content.char_indices().map(|(i, _)| i).chain(std::iter::once(content.len())).collect()
+    }
+
+    proptest! {
+        #[test]
+        fn line_end_indices_layout(content in text()) {
+            let indices = line_end_indices(&content);
+
+            prop_assert_eq!(indices.len(), content.matches('\n').count() + 1);
+            prop_assert_eq!(*indices.last().unwrap() as usize, content.len());
+            prop_assert!(indices.windows(2).all(|w| w[0] < w[1]));
+            for &i in &indices[..indices.len() - 1] {
+                prop_assert_eq!(content.as_bytes()[i as usize], b'\n');
+            }
+        }
+
+        #[test]
+        fn line_ends_round_trip(ends in prop::collection::vec(any::<u32>(), 0..16)) {
+            let mut ends = ends;
+            ends.sort_unstable();
+            let encoded = encode_line_ends(&ends);
+
+            prop_assert_eq!(decode_line_ends(&encoded), ends.clone());
+            // at most five bytes an entry, the varint of a full u32
+            prop_assert!(encoded.len() <= ends.len() * 5);
+        }
+
+        #[test]
+        fn point_from_byte(content in text(), pick in any::<prop::sample::Index>()) {
+            let indices = line_end_indices(&content);
+            let boundaries = char_boundaries(&content);
+            let byte = boundaries[pick.index(boundaries.len())];
+
+            let point = Point::from_byte(byte, &indices);
+            let before = &content[..byte];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+            prop_assert_eq!(point.byte, byte);
+            prop_assert_eq!(point.line, before.matches('\n').count());
+            prop_assert_eq!(point.column, byte - line_start);
+        }
+
+        #[test]
+        fn byte_range_round_trip(content in text(), a in any::<prop::sample::Index>(), b in any::<prop::sample::Index>()) {
+            let indices = line_end_indices(&content);
+            let boundaries = char_boundaries(&content);
+            let (start, end) = {
+                let (a, b) = (boundaries[a.index(boundaries.len())], boundaries[b.index(boundaries.len())]);
+                (a.min(b), a.max(b))
+            };
+
+            let range = TextRange::from_byte_range(start..end, &indices);
+
+            prop_assert!(range.start <= range.end);
+            prop_assert!(range.contains(&range));
+            prop_assert_eq!(range.size(), end - start);
+            prop_assert_eq!(std::ops::Range::from(range), start..end);
+        }
+
+        #[test]
+        fn line_word_round_trip(content in text(), line in any::<prop::sample::Index>(), a in any::<prop::sample::Index>(), b in any::<prop::sample::Index>()) {
+            let indices = line_end_indices(&content);
+            let lines = content.split('\n').collect::<Vec<_>>();
+            let line_idx = line.index(lines.len());
+            let chars = lines[line_idx].chars().collect::<Vec<_>>();
+            prop_assume!(!chars.is_empty());
+
+            let (start, end) = {
+                let (a, b) = (a.index(chars.len() + 1), b.index(chars.len() + 1));
+                (a.min(b), a.max(b))
+            };
+            let result = line_word_to_byte_range(&content, &indices, line_idx + 1, start, end);
+
+            if start == end {
+                prop_assert!(result.is_err());
+            } else {
+                let (start_byte, end_byte) = result.unwrap();
+                prop_assert_eq!(&content[start_byte..end_byte], chars[start..end].iter().collect::<String>());
+
+                // the byte range maps back to the same line and columns, in bytes
+                let point = Point::from_byte(start_byte, &indices);
+                prop_assert_eq!(point.line, line_idx);
+                prop_assert_eq!(point.column, chars[..start].iter().map(|c| c.len_utf8()).sum::<usize>());
+            }
+        }
+
+        #[test]
+        fn line_word_out_of_bounds(content in text(), extra in 1usize..4) {
+            let indices = line_end_indices(&content);
+
+            prop_assert!(line_word_to_byte_range(&content, &indices, 0, 0, 1).is_err());
+
+            let error = line_word_to_byte_range(&content, &indices, indices.len() + extra, 0, 1).unwrap_err();
+            let error = error.downcast_ref::<NavError>().unwrap();
+            prop_assert_eq!(error.code, ErrorCode::InvalidRange);
+            prop_assert_eq!(error.position, Some(indices.len() + extra));
+        }
+    }
+
+    #[test]
+    fn line_bases() {
+        assert_eq!(LineBase::default(), LineBase::One);
+        assert_eq!(LineBase::One.number(0), 1);
+        assert_eq!(LineBase::One.index(1), Some(0));
+        assert_eq!(LineBase::One.index(0), None);
+        assert_eq!(LineBase::Zero.number(0), 0);
+        assert_eq!(LineBase::Zero.index(0), Some(0));
+
+        let indices = line_end_indices("ab\ncd");
+        assert_eq!(line_start(0, &indices), 0);
+        assert_eq!(line_start(1, &indices), 3);
+        assert_eq!(line_start(2, &indices), 5);
+        assert_eq!(line_start(9, &indices), 5);
+        let range = TextRange::from_byte_range(3..5, &indices);
+        assert_eq!(LineBase::One.numbered(range).start.line, 2);
+        assert_eq!(LineBase::Zero.numbered(range), range);
+
+        assert_eq!(LineBase::current(), LineBase::One);
+        assert_eq!(LineBase::Zero.scope(LineBase::current), LineBase::Zero);
+        assert_eq!(LineBase::current(), LineBase::One);
+    }
+
+    #[test]
+    fn line_model() {
+        // (text, line count, the text of every entry)
+        let cases: &[(&str, usize, &[&str])] = &[
+            ("", 0, &[""]),
+            ("one", 1, &["one"]),
+            ("one\n", 1, &["one", ""]),
+            ("one\ntwo", 2, &["one", "two"]),
+            ("one\r\ntwo\n", 2, &["one\r", "two", ""]),
+            ("\n\n", 2, &["", "", ""]),
+        ];
+        for &(content, count, lines) in cases {
+            let indices = line_end_indices(content);
+            assert_eq!(indices.len(), lines.len(), "{content:?}");
+            assert_eq!(line_count(content), count, "{content:?}");
+            assert_eq!(line_count(content), content.lines().count(), "{content:?}");
+            for (line, text) in lines.iter().enumerate() {
+                assert_eq!(line_text(content, &indices, line), Some(*text), "{content:?}");
+            }
+            assert_eq!(line_text(content, &indices, lines.len()), None);
+            assert_eq!(decode_line_ends(&encode_line_ends(&indices)), indices);
+        }
+
+        assert_eq!(last_line_end("one\ntwo\n"), 7);
+        assert_eq!(last_line_end("one\ntwo"), 7);
+        assert_eq!(last_line_end(""), 0);
+    }
+
+    #[test]
+    fn point_examples() {
+        let indices = line_end_indices("ab\ncd");
+        assert_eq!(indices, vec![2, 5]);
+
+        assert_eq!(Point::from_byte(0, &indices), Point::new(0, 0, 0));
+        // the newline belongs to the line it ends
+        assert_eq!(Point::from_byte(2, &indices), Point::new(2, 0, 2));
+        assert_eq!(Point::from_byte(3, &indices), Point::new(3, 1, 0));
+        assert_eq!(Point::from_byte(5, &indices), Point::new(5, 1, 2));
+    }
+}
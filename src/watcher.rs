@@ -0,0 +1,64 @@
+//! Keeps the index of an [`IndexHandle`] up to date while it is open, by reindexing the
+//! files of the repository as they change rather than walking it on every refresh.
+//!
+//! The root is watched recursively. Changes seen within the debounce delay of each other
+//! are indexed together once they settle, with [`IndexHandle::index_paths`]: created
+//! and modified files are read again, and the documents of deleted files, or of the
+//! files of a deleted directory, are deleted. What changed is published to the
+//! [`events`](IndexHandle::events) of the handle like a refresh, and so are the errors
+//! of the watch itself.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+
+use crate::handle::IndexHandle;
+
+/// How long changes must settle before they are indexed, unless
+/// [`IndexHandle::watch`] is given another delay
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// A watch on the root of a repository, stopped when dropped.
+pub struct Watcher {
+    _debouncer: Debouncer<RecommendedWatcher>,
+}
+
+impl Watcher {
+    /// Start watching the root of `handle`, indexing the files that change into it.
+    pub fn start(handle: IndexHandle, debounce: Duration) -> Result<Self> {
+        let root_path = handle
+            .root_path()
+            .canonicalize()
+            .with_context(|| format!("invalid root {}", handle.root_path().display()))?;
+        // an index kept inside the repository changes with every update
+        let index_path = handle.index_path().canonicalize().unwrap_or_else(|_| handle.index_path().to_path_buf());
+
+        let mut debouncer = new_debouncer(debounce, None, move |result: DebounceEventResult| match result {
+            Ok(events) => {
+                let mut paths = events
+                    .into_iter()
+                    .map(|event| event.path)
+                    .filter(|path| !path.starts_with(&index_path))
+                    .collect::<Vec<PathBuf>>();
+                if paths.is_empty() {
+                    return;
+                }
+                paths.sort();
+                paths.dedup();
+                // failures are published to the events of the handle
+                let _ = handle.index_paths(&paths);
+            }
+            Err(errors) => handle.publish_error(format!("failed to watch the repository: {errors:?}")),
+        })
+        .context("failed to create the file watcher")?;
+        debouncer
+            .watcher()
+            .watch(&root_path, RecursiveMode::Recursive)
+            .with_context(|| format!("failed to watch {}", root_path.display()))?;
+
+        Ok(Self { _debouncer: debouncer })
+    }
+}
@@ -0,0 +1,140 @@
+//! Synthetic repositories for the integration tests.
+//!
+//! Every generated repository has the same shape, scaled by [`RepoSpec`]:
+//!
+//! - `src/shared.rs` defines `shared_helper`, imported and called by the Rust files
+//!   in `src/rust/d0/d1/...`
+//! - `src/python/module_<i>.py` and `src/js/file_<i>.js` each define one function
+//! - every source file carries a `common_<lang>_token` comment on its second line
+//! - optionally ignored files, a non-UTF-8 file and symlinks, none of which may end up
+//!   in the index
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use code_nav_devon::{Indexes, Searcher};
+use tempdir::TempDir;
+
+pub struct RepoSpec {
+    /// Number of Rust callers, Python modules and JavaScript files
+    pub files_per_language: usize,
+    /// Nesting depth of the directory holding the Rust callers
+    pub depth: usize,
+    /// Add a root and a nested `.gitignore`, each hiding one source file
+    pub gitignores: bool,
+    /// Add a link to `src/shared.rs` and a link back to the root (unix only)
+    pub symlinks: bool,
+    /// Add a `.rs` file that isn't valid UTF-8
+    pub non_utf8: bool,
+}
+
+impl Default for RepoSpec {
+    fn default() -> Self {
+        Self {
+            files_per_language: 4,
+            depth: 2,
+            gitignores: true,
+            symlinks: true,
+            non_utf8: true,
+        }
+    }
+}
+
+pub struct SyntheticRepo {
+    _dir: TempDir,
+    pub root: PathBuf,
+    pub index: PathBuf,
+    pub spec: RepoSpec,
+}
+
+impl SyntheticRepo {
+    pub fn generate(spec: RepoSpec) -> Result<Self> {
+        let dir = TempDir::new("code_nav_devon")?;
+        // indexed paths are canonical, e.g. /private/var rather than /var on macOS
+        let base = dir.path().canonicalize()?;
+        let root = base.join("repo");
+        let index = base.join("index");
+        fs::create_dir_all(&index)?;
+
+        write(&root, "src/shared.rs", "pub fn shared_helper() {}\n// common_rust_token\n")?;
+
+        let rust_dir = (0..spec.depth).fold(PathBuf::from("src/rust"), |dir, level| dir.join(format!("d{level}")));
+        for i in 0..spec.files_per_language {
+            write(
+                &root,
+                &rust_dir.join(format!("caller_{i}.rs")).to_string_lossy(),
+                &format!(
+                    "use crate::shared::shared_helper;\n// common_rust_token\npub fn caller_{i}() {{\n    shared_helper();\n}}\n"
+                ),
+            )?;
+            write(
+                &root,
+                &format!("src/python/module_{i}.py"),
+                &format!("def py_helper_{i}():\n    # common_python_token\n    return {i}\n"),
+            )?;
+            write(
+                &root,
+                &format!("src/js/file_{i}.js"),
+                &format!("function jsHelper{i}() {{\n  // common_js_token\n  return {i};\n}}\n"),
+            )?;
+        }
+
+        if spec.gitignores {
+            write(&root, ".gitignore", "ignored\n")?;
+            write(&root, "ignored/secret.rs", "fn secret() {}\n// ignored_token\n")?;
+            write(&root, "src/python/.gitignore", "*_gen.py\n")?;
+            write(&root, "src/python/schema_gen.py", "def generated():\n    # nested_ignored_token\n    pass\n")?;
+        }
+
+        if spec.non_utf8 {
+            let mut bytes = b"fn broken() {}\n// non_utf8_token ".to_vec();
+            bytes.extend_from_slice(&[0xff, 0xfe, 0xfd, b'\n']);
+            fs::write(root.join("bad.rs"), bytes)?;
+        }
+
+        #[cfg(unix)]
+        if spec.symlinks {
+            std::os::unix::fs::symlink(root.join("src/shared.rs"), root.join("link.rs"))?;
+            std::os::unix::fs::symlink(&root, root.join("src/loop"))?;
+        }
+
+        Ok(Self {
+            _dir: dir,
+            root,
+            index,
+            spec,
+        })
+    }
+
+    /// The path of a repository file as it is stored in the index.
+    pub fn path(&self, relative: &str) -> String {
+        self.root.join(relative).to_string_lossy().replace('\\', "/")
+    }
+
+    pub fn shared_path(&self) -> String {
+        self.path("src/shared.rs")
+    }
+
+    /// Number of files expected in the index: the shared file plus every generated
+    /// source file, none of the ignored, non-UTF-8 or linked ones.
+    pub fn indexed_files(&self) -> u64 {
+        3 * self.spec.files_per_language as u64 + 1
+    }
+
+    /// Index the repository and open a searcher over it.
+    pub async fn index(&self) -> Result<Searcher> {
+        let indexes = Indexes::new(&self.index, 15_000_000, 2).await?;
+        indexes.index(&self.root).await?;
+        Searcher::new(&self.index)
+    }
+}
+
+fn write(root: &Path, relative: &str, content: &str) -> Result<()> {
+    let path = root.join(relative);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, content)?;
+    Ok(())
+}